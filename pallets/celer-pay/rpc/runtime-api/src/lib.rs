@@ -5,7 +5,7 @@
 use codec::{Codec, Decode, Encode};
 #[cfg(feature = "std")]
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use sp_runtime::traits::{MaybeDisplay, MaybeFromStr};
+use sp_runtime::{traits::{MaybeDisplay, MaybeFromStr}, RuntimeDebug};
 use sp_std::{prelude::*, vec::Vec};
 
 #[derive(Eq, PartialEq, Encode, Decode, Default)]
@@ -28,6 +28,21 @@ pub struct SeqNumInfo {
 	pub number: u128,
 }
 
+/// Hash function of a hash lock
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum HashType {
+	Blake2_256,
+	Sha256,
+	Keccak256,
+}
+
+impl Default for HashType {
+	fn default() -> Self {
+		HashType::Blake2_256
+	}
+}
+
 #[cfg(feature = "std")]
 fn serialize_as_string<S: Serializer, T: std::fmt::Display>(t: &T, serializer: S) -> Result<S::Ok, S::Error> {
 	serializer.serialize_str(&t.to_string())
@@ -41,6 +56,7 @@ fn deserialize_from_string<'de, D: Deserializer<'de>, T: std::str::FromStr>(dese
 }
 
 sp_api::decl_runtime_apis! {
+    #[api_version(2)]
     pub trait CelerPayModuleApi<AccountId, Hash, Balance, BlockNumber> where 
         AccountId: Codec,
         Hash: Codec,
@@ -61,6 +77,8 @@ sp_api::decl_runtime_apis! {
 
         fn get_dispute_time_out(channel_id: Hash) -> BlockNumber;
 
+        fn get_clear_safe_margin(channel_id: Hash) -> BlockNumber;
+
         fn get_state_seq_num_map(channel_id: Hash) -> (Vec<AccountId>, Vec<SeqNumInfo>);
 
         fn get_transfer_out_map(channel_id: Hash) -> (Vec<AccountId>, Vec<BalanceInfo<Balance>>);
@@ -94,14 +112,25 @@ sp_api::decl_runtime_apis! {
 
         fn get_wallet_balance(wallet_id: Hash) -> BalanceInfo<Balance>;
 
+        fn get_wallet_account(wallet_id: Hash) -> AccountId;
+
         fn get_pool_id() -> AccountId;
 
         fn get_pool_balance(owner: AccountId) -> BalanceInfo<Balance>;
 
+        #[changed_in(2)]
         fn get_allowance(owner: AccountId, spender: AccountId) -> BalanceInfo<Balance>;
 
+        fn get_allowance(owner: AccountId, spender: AccountId) -> (BalanceInfo<Balance>, BlockNumber);
+
+        fn get_asset_pool_balance(owner: AccountId, asset_id: u32) -> BalanceInfo<Balance>;
+
+        fn get_asset_allowance(owner: AccountId, spender: AccountId, asset_id: u32) -> (BalanceInfo<Balance>, BlockNumber);
+
         fn get_pay_resolver_id() -> AccountId;
 
         fn get_pay_info(pay_id: Hash) -> (BalanceInfo<Balance>, BlockNumber);
+
+        fn get_preimage_reveal_info(hash_lock: Hash, hash_type: HashType) -> (bool, Hash, BlockNumber);
     }
 }
\ No newline at end of file
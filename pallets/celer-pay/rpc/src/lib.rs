@@ -3,8 +3,8 @@
 use codec::Codec;
 use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
 use jsonrpc_derive::rpc;
-use celer_pay_module_rpc_runtime_api::{BalanceInfo, SeqNumInfo};
-use sp_api::ProvideRuntimeApi;
+use celer_pay_module_rpc_runtime_api::{BalanceInfo, HashType, SeqNumInfo};
+use sp_api::{ApiExt, ProvideRuntimeApi};
 use sp_blockchain::HeaderBackend;
 use sp_runtime::{
     generic::BlockId, 
@@ -34,6 +34,7 @@ pub trait CelerPayModuleApi<
     WithdrawIntentType,
     PeersMigrationInfoType,
     PayInfoType,
+    AllowanceType,
 > 
 where Balance: std::str::FromStr,
 {
@@ -43,6 +44,9 @@ where Balance: std::str::FromStr,
     #[rpc(name = "celerPayModule_getSettleFinalizedTime")]
     fn get_settle_finalized_time(&self, channel_id: Hash, at: Option<BlockHash>) -> Result<BlockNumber>;
 
+    #[rpc(name = "celerPayModule_getClearSafeMargin")]
+    fn get_clear_safe_margin(&self, channel_id: Hash, at: Option<BlockHash>) -> Result<BlockNumber>;
+
     #[rpc(name = "celerPayModule_getChannelStatus")]
     fn get_channel_status(&self, channel_id: Hash, at: Option<BlockHash>) -> Result<u8>;
     
@@ -94,6 +98,9 @@ where Balance: std::str::FromStr,
     #[rpc(name = "celerPayModule_getWalletBalance")]
     fn get_wallet_balance(&self, wallet_id: Hash, at: Option<BlockHash>) -> Result<BalanceType>;
 
+    #[rpc(name = "celerPayModule_getWalletAccount")]
+    fn get_wallet_account(&self, wallet_id: Hash, at: Option<BlockHash>) -> Result<AccountId>;
+
     #[rpc(name = "celerPayModule_getPoolId")]
     fn get_pool_id(&self, at: Option<BlockHash>) -> Result<AccountId>;
 
@@ -101,13 +108,22 @@ where Balance: std::str::FromStr,
     fn get_pool_balance(&self, owner: AccountId, at: Option<BlockHash>) -> Result<BalanceType>;
 
     #[rpc(name = "celerPayModule_getAllowance")]
-    fn get_allowance(&self, owner: AccountId, spender: AccountId, at: Option<BlockHash>) -> Result<BalanceType>;
+    fn get_allowance(&self, owner: AccountId, spender: AccountId, at: Option<BlockHash>) -> Result<AllowanceType>;
+
+    #[rpc(name = "celerPayModule_getAssetPoolBalance")]
+    fn get_asset_pool_balance(&self, owner: AccountId, asset_id: u32, at: Option<BlockHash>) -> Result<BalanceType>;
+
+    #[rpc(name = "celerPayModule_getAssetAllowance")]
+    fn get_asset_allowance(&self, owner: AccountId, spender: AccountId, asset_id: u32, at: Option<BlockHash>) -> Result<AllowanceType>;
 
     #[rpc(name = "celerPayModule_getPayResolverId")]
     fn get_pay_resolver_id(&self, at: Option<BlockHash>) -> Result<AccountId>;
 
     #[rpc(name = "celerPayModule_getPayInfo")]
     fn get_pay_info(&self, pay_id: Hash, at: Option<BlockHash>) -> Result<PayInfoType>;
+
+    #[rpc(name = "celerPayModule_getPreimageRevealInfo")]
+    fn get_preimage_reveal_info(&self, hash_lock: Hash, hash_type: HashType, at: Option<BlockHash>) -> Result<(bool, Hash, BlockNumber)>;
 }
 
 /// A struct that implements the `CelerPayModuleApi'
@@ -141,6 +157,7 @@ impl<C, Block, AccountId, Hash, Balance, BlockNumber>
         (AccountId, BalanceInfo<Balance>, BlockNumber, Hash),
         (Vec<AccountId>, Vec<BalanceInfo<Balance>>, Vec<BalanceInfo<Balance>>, Vec<SeqNumInfo>, Vec<BalanceInfo<Balance>>, Vec<BalanceInfo<Balance>>),
         (BalanceInfo<Balance>, BlockNumber),
+        (BalanceInfo<Balance>, BlockNumber),
     > for CelerPayModule<C, Block>
 where
     Block: BlockT,
@@ -150,7 +167,7 @@ where
     Hash: Codec,
     Balance: Codec + MaybeDisplay + MaybeFromStr + TryFrom<U256>,
     <Balance as TryFrom<U256>>::Error: sp_std::fmt::Debug,
-    BlockNumber: Codec,
+    BlockNumber: Codec + Default,
 {
     fn get_celer_ledger_id(&self, at: Option<<Block as BlockT>::Hash>) -> Result<AccountId> {
         let api = self.client.runtime_api();
@@ -180,6 +197,20 @@ where
         })
     }
 
+    fn get_clear_safe_margin(&self, channel_id: Hash, at: Option<<Block as BlockT>::Hash>) -> Result<BlockNumber> {
+        let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+
+        let runtime_api_result = api.get_clear_safe_margin(&at, channel_id);
+        runtime_api_result.map_err(|e| RpcError {
+            code: ErrorCode::ServerError(9876),
+            message: "Can't get clear safe margin".into(),
+            data: Some(format!("{:?}", e).into()),
+        })
+    }
+
     fn get_channel_status(&self, channel_id: Hash, at: Option<<Block as BlockT>::Hash>) -> Result<u8> {
         let api = self.client.runtime_api();
 		let at = BlockId::hash(at.unwrap_or_else(||
@@ -418,6 +449,20 @@ where
         })
     } 
 
+    fn get_wallet_account(&self, wallet_id: Hash, at: Option<<Block as BlockT>::Hash>) -> Result<AccountId> {
+        let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+
+        let runtime_api_result = api.get_wallet_account(&at, wallet_id);
+        runtime_api_result.map_err(|e| RpcError {
+            code: ErrorCode::ServerError(9876),
+            message: "Can't get wallet account".into(),
+            data: Some(format!("{:?}", e).into()),
+        })
+    }
+
     fn get_pool_id(&self, at: Option<<Block as BlockT>::Hash>) -> Result<AccountId> {
         let api = self.client.runtime_api();
 		let at = BlockId::hash(at.unwrap_or_else(||
@@ -446,13 +491,28 @@ where
         })
     }
 
-    fn get_allowance(&self, owner: AccountId, spender: AccountId, at: Option<<Block as BlockT>::Hash>) -> Result<BalanceInfo<Balance>> {
+    fn get_allowance(&self, owner: AccountId, spender: AccountId, at: Option<<Block as BlockT>::Hash>) -> Result<(BalanceInfo<Balance>, BlockNumber)> {
         let api = self.client.runtime_api();
 		let at = BlockId::hash(at.unwrap_or_else(||
 			// If the block hash is not supplied assume the best block.
 			self.client.info().best_hash));
 
-        let runtime_api_result = api.get_allowance(&at, owner, spender);
+        let has_allowance_expiry = api.has_api_with::<
+            dyn CelerPayModuleRuntimeApi<Block, AccountId, Hash, Balance, BlockNumber>, _
+        >(&at, |version| version >= 2).map_err(|e| RpcError {
+            code: ErrorCode::ServerError(9876),
+            message: "Can't get version of runtime api".into(),
+            data: Some(format!("{:?}", e).into()),
+        })?;
+
+        // runtime api of version 1 returns the allowance without expiry
+        let runtime_api_result = if has_allowance_expiry {
+            api.get_allowance(&at, owner, spender)
+        } else {
+            #[allow(deprecated)]
+            api.get_allowance_before_version_2(&at, owner, spender)
+                .map(|allowance| (allowance, BlockNumber::default()))
+        };
         runtime_api_result.map_err(|e| RpcError {
             code: ErrorCode::ServerError(9876),
             message: "Can't get allowed balance of spender".into(),
@@ -460,6 +520,34 @@ where
         })
     }
 
+    fn get_asset_pool_balance(&self, owner: AccountId, asset_id: u32, at: Option<<Block as BlockT>::Hash>) -> Result<BalanceInfo<Balance>> {
+        let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+
+        let runtime_api_result = api.get_asset_pool_balance(&at, owner, asset_id);
+        runtime_api_result.map_err(|e| RpcError {
+            code: ErrorCode::ServerError(9876),
+            message: "Can't get asset pool balance".into(),
+            data: Some(format!("{:?}", e).into()),
+        })
+    }
+
+    fn get_asset_allowance(&self, owner: AccountId, spender: AccountId, asset_id: u32, at: Option<<Block as BlockT>::Hash>) -> Result<(BalanceInfo<Balance>, BlockNumber)> {
+        let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+
+        let runtime_api_result = api.get_asset_allowance(&at, owner, spender, asset_id);
+        runtime_api_result.map_err(|e| RpcError {
+            code: ErrorCode::ServerError(9876),
+            message: "Can't get allowed asset balance of spender".into(),
+            data: Some(format!("{:?}", e).into()),
+        })
+    }
+
     fn get_pay_resolver_id(&self, at: Option<<Block as BlockT>::Hash>) -> Result<AccountId> {
         let api = self.client.runtime_api();
 		let at = BlockId::hash(at.unwrap_or_else(||
@@ -487,4 +575,18 @@ where
             data: Some(format!("{:?}", e).into()),
         })
     }
+
+    fn get_preimage_reveal_info(&self, hash_lock: Hash, hash_type: HashType, at: Option<<Block as BlockT>::Hash>) -> Result<(bool, Hash, BlockNumber)> {
+        let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+
+        let runtime_api_result = api.get_preimage_reveal_info(&at, hash_lock, hash_type);
+        runtime_api_result.map_err(|e| RpcError {
+            code: ErrorCode::ServerError(9876),
+            message: "Can't get preimage reveal info".into(),
+            data: Some(format!("{:?}", e).into()),
+        })
+    }
 }
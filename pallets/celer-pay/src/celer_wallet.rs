@@ -1,8 +1,9 @@
-use super::{Module as CelerPayModule, BalanceOf, Error, Wallets, RawEvent};
+use super::{Module as CelerPayModule, BalanceOf, Error, Wallets, WalletReserves, RawEvent};
 use crate::traits::Trait;
+use crate::custody::Custody;
+use crate::pay_resolver::TokenType;
 use codec::{Decode, Encode};
-use frame_support::traits::{Currency, ExistenceRequirement};
-use frame_support::{ensure, storage::StorageMap};
+use frame_support::{ensure, storage::{StorageDoubleMap, StorageMap}};
 use frame_system::{self as system, ensure_signed};
 use sp_std::{vec::Vec, vec};
 use sp_runtime::traits::{CheckedAdd, CheckedSub, Hash, Zero};
@@ -67,7 +68,17 @@ impl<T: Trait> CelerWallet<T> {
         wallet_id: T::Hash,
         msg_value: BalanceOf<T>,
     ) -> Result<(T::Hash, BalanceOf<T>), DispatchError> {
-        update_balance::<T>(caller, wallet_id.clone(), MathOperation::Add, msg_value.clone())?;
+        Self::deposit_token(caller, wallet_id, TokenType::Celer, msg_value)
+    }
+
+    // Deposit funds of given token to wallet
+    pub fn deposit_token(
+        caller: T::AccountId,
+        wallet_id: T::Hash,
+        token_type: TokenType,
+        msg_value: BalanceOf<T>,
+    ) -> Result<(T::Hash, BalanceOf<T>), DispatchError> {
+        update_balance::<T>(caller, wallet_id.clone(), &token_type, MathOperation::Add, msg_value.clone())?;
 
         // Emit DepositToWallet event
         CelerPayModule::<T>::deposit_event(RawEvent::DepositToWallet(
@@ -77,10 +88,35 @@ impl<T: Trait> CelerWallet<T> {
         return Ok((wallet_id, msg_value));
     }
 
+    // Deposit funds of given token from Pool balances of the owner to wallet.
+    // Pool balances of the owner are decreased by the caller.
+    pub fn deposit_from_pool(
+        owner: T::AccountId,
+        wallet_id: T::Hash,
+        token_type: TokenType,
+        amount: BalanceOf<T>,
+    ) -> Result<(T::Hash, BalanceOf<T>), DispatchError> {
+        let mut w: WalletOf<T> = match Wallets::<T>::get(wallet_id) {
+            Some(_w) => _w,
+            None => Err(Error::<T>::WalletNotExist)?,
+        };
+        w.balance = w.balance.checked_add(&amount).ok_or(Error::<T>::OverFlow)?;
+        Custody::<T>::pool_to_wallet(&token_type, &owner, wallet_id, amount)?;
+        Wallets::<T>::mutate(&wallet_id, |wallet| *wallet = Some(w));
+
+        // Emit DepositToWallet event
+        CelerPayModule::<T>::deposit_event(RawEvent::DepositToWallet(
+            wallet_id,
+            amount
+        ));
+        return Ok((wallet_id, amount));
+    }
+
     // Withdraw funds to an address
     pub fn withdraw(
         origin: T::Origin,
         wallet_id: T::Hash,
+        token_type: TokenType,
         receiver: T::AccountId,
         amount: BalanceOf<T>
     ) -> Result<(), DispatchError> {
@@ -88,7 +124,7 @@ impl<T: Trait> CelerWallet<T> {
         let celer_ledger_account = CelerPayModule::<T>::get_celer_ledger_id();
         ensure!(caller == celer_ledger_account, "Caler is not Celer Ledger module",);
 
-        update_balance::<T>(receiver.clone(), wallet_id, MathOperation::Sub, amount)?;
+        update_balance::<T>(receiver.clone(), wallet_id, &token_type, MathOperation::Sub, amount)?;
         
         // Emit WithdrawFromWallet Event
         CelerPayModule::<T>::deposit_event(RawEvent::WithdrawFromWallet(
@@ -104,6 +140,7 @@ impl<T: Trait> CelerWallet<T> {
         origin: T::Origin,
         from_wallet_id: T::Hash,
         to_wallet_id: T::Hash,
+        token_type: TokenType,
         receiver: T::AccountId,
         amount: BalanceOf<T>,
     ) -> Result<(), DispatchError> {
@@ -117,11 +154,29 @@ impl<T: Trait> CelerWallet<T> {
             "receiver is not wallet owner"
         );
 
-        update_balance::<T>(receiver.clone(), from_wallet_id, MathOperation::Sub, amount)?;
-        update_balance::<T>(receiver.clone(), to_wallet_id, MathOperation::Add, amount)?;
+        update_balance::<T>(receiver.clone(), from_wallet_id, &token_type, MathOperation::Sub, amount)?;
+        update_balance::<T>(receiver.clone(), to_wallet_id, &token_type, MathOperation::Add, amount)?;
 
         Ok(())
     }
+
+    // Return id of the wallet created for the peers with the nonce
+    pub fn wallet_id(peers: Vec<T::AccountId>, nonce: T::Hash) -> T::Hash {
+        return create_wallet_id::<T>(peers, nonce);
+    }
+
+    // Remove an empty wallet
+    pub fn remove_wallet(wallet_id: T::Hash) -> Result<(), DispatchError> {
+        let w: WalletOf<T> = match Wallets::<T>::get(wallet_id) {
+            Some(_w) => _w,
+            None => Err(Error::<T>::WalletNotExist)?,
+        };
+        ensure!(w.balance.is_zero(), "Wallet balance is not zero");
+
+        Wallets::<T>::remove(wallet_id);
+        WalletReserves::<T>::remove_prefix(wallet_id);
+        Ok(())
+    }
 }
 
 fn create_wallet_id<T: Trait>(
@@ -142,6 +197,7 @@ fn create_wallet_id<T: Trait>(
 fn update_balance<T: Trait>(
     caller: T::AccountId,
     wallet_id: T::Hash,
+    token_type: &TokenType,
     op: MathOperation,
     amount: BalanceOf<T>,
 ) -> Result<(), DispatchError> {
@@ -150,34 +206,21 @@ fn update_balance<T: Trait>(
         None => Err(Error::<T>::WalletNotExist)?,
     };
 
-    let celer_wallet_account = CelerPayModule::<T>::get_celer_wallet_id();
     if op == MathOperation::Sub {
         ensure!(w.balance >= amount, "balance of amount is not deposited");
         
         w.balance = w.balance.checked_sub(&amount).ok_or(Error::<T>::UnderFlow)?;
+        Custody::<T>::withdraw_from_wallet(token_type, wallet_id, &caller, amount)?;
         Wallets::<T>::mutate(&wallet_id, |wallet| *wallet = Some(w));
-
-        <T as Trait>::Currency::transfer(
-            &celer_wallet_account,
-            &caller,
-            amount,
-            ExistenceRequirement::AllowDeath,
-        )?;
     } else if op == MathOperation::Add {
         ensure!(
-            <T as Trait>::Currency::free_balance(&caller) >= amount,
+            CelerPayModule::<T>::token_free_balance(token_type, &caller)? >= amount,
             "caller does not have enough balances"
         );
         
         w.balance = w.balance.checked_add(&amount).ok_or(Error::<T>::OverFlow)?;
+        Custody::<T>::deposit_to_wallet(token_type, &caller, wallet_id, amount)?;
         Wallets::<T>::mutate(&wallet_id, |wallet| *wallet = Some(w));
-
-        <T as Trait>::Currency::transfer(
-            &caller,
-            &celer_wallet_account,
-            amount,
-            ExistenceRequirement::AllowDeath,
-        )?;
     } else {
         Err(Error::<T>::Error)?
     }
@@ -201,6 +244,8 @@ mod tests {
     use crate::tests::ledger_operation::test_ledger_operation::*;
     use crate::ledger_operation::LedgerOperation;
     use crate::mock::*;
+    use frame_support::storage::StorageValue;
+    use frame_support::traits::{Currency, ExistenceRequirement};
 
     #[test]
     fn test_pass_deposit_native_token() {
@@ -251,4 +296,47 @@ mod tests {
             );
         })
     }
+
+    #[test]
+    fn test_pass_migrate_wallet_funds_to_wallet_account() {
+        ExtBuilder::build().execute_with(|| {
+            let celer_wallet_account = CelerPayModule::get_celer_wallet_id();
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+
+            let open_channel_request = get_open_channel_request(false, 0, 500001, 10, true, channel_peers.clone(), 1, peers_pair);
+            let wallet_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[1]),
+                open_channel_request.clone(),
+                0,
+            ).unwrap();
+            CelerWallet::<TestRuntime>::deposit_native_token(channel_peers[0], wallet_id, 100).unwrap();
+            let wallet_account = CelerPayModule::get_wallet_account(&wallet_id);
+            assert_eq!(Balances::free_balance(&wallet_account), 100);
+
+            // funds of wallets were held by the CelerWallet module account before V3_0_0
+            <Balances as Currency<AccountId>>::transfer(
+                &wallet_account,
+                &celer_wallet_account,
+                100,
+                ExistenceRequirement::AllowDeath,
+            ).unwrap();
+            crate::StorageVersion::put(crate::Releases::V2_0_0);
+
+            // funds are moved in the following blocks, until then the wallet keeps using
+            // the CelerWallet module account
+            crate::migration::on_runtime_upgrade::<TestRuntime>();
+            assert_eq!(Balances::free_balance(&celer_wallet_account), 100);
+            CelerWallet::<TestRuntime>::deposit_native_token(channel_peers[0], wallet_id, 10).unwrap();
+            assert_eq!(Balances::free_balance(&celer_wallet_account), 110);
+            assert_eq!(Balances::free_balance(&wallet_account), 0);
+
+            crate::migration::on_initialize::<TestRuntime>();
+            assert_eq!(CelerPayModule::wallet_migration_cursor(), None);
+            assert_eq!(Balances::free_balance(&wallet_account), 110);
+            assert_eq!(Balances::free_balance(&celer_wallet_account), 0);
+            assert_eq!(CelerPayModule::get_wallet_balance(wallet_id).amount, 110);
+        })
+    }
 }
@@ -0,0 +1,173 @@
+use super::{BalanceOf, BooleanAppIds, Error, LegacyCallAppIds, Module as CelerPayModule, NumericAppIds};
+use crate::traits::{legacy_call_app_ids, AppId, BooleanConditions, ConditionContracts, NumericConditions, Trait};
+use codec::{Decode, Encode};
+use frame_support::{
+    ensure,
+    storage::{with_transaction, IterableStorageMap, StorageMap, TransactionOutcome},
+    traits::Filter,
+};
+use sp_runtime::DispatchError;
+use sp_std::{self, vec::Vec};
+
+pub struct ConditionCaller<T>(sp_std::marker::PhantomData<T>);
+
+impl<T: Trait> ConditionCaller<T> {
+    // Call a condition contract as the pay resolver account and decode its output.
+    // The input data is the message selector followed by the encoded query,
+    // and all state changes of the call are rolled back.
+    pub fn call_contract<R: Decode>(
+        contract_address: T::AccountId,
+        gas_limit: u64,
+        selector: [u8; 4],
+        query: Option<Vec<u8>>,
+    ) -> Result<R, DispatchError> {
+        let mut input_data = selector.to_vec();
+        input_data.extend(query.unwrap_or_default().encode());
+
+        let pay_resolver_account = CelerPayModule::<T>::get_pay_resolver_id();
+        let output = with_transaction(|| {
+            let output = T::ConditionContracts::bare_call(
+                pay_resolver_account,
+                contract_address,
+                gas_limit,
+                input_data
+            );
+            TransactionOutcome::Rollback(output)
+        })?;
+
+        match R::decode(&mut &output[..]) {
+            Ok(value) => Ok(value),
+            Err(_) => Err(Error::<T>::InvalidContractOutput)?,
+        }
+    }
+
+    // Return the id of the boolean app referenced by its id or its legacy app number.
+    // A legacy app number is mapped by the app numbers recorded in runtime upgrades, or by the
+    // position of the app in BooleanConditions if no app number has been recorded yet.
+    pub fn boolean_app_id(
+        app_id: Option<AppId>,
+        app_num: Option<u32>,
+    ) -> Result<AppId, DispatchError> {
+        if let Some(id) = app_id {
+            return Ok(id);
+        }
+        let app_num = match app_num {
+            Some(num) => num,
+            None => Err(Error::<T>::BooleanAppNotExist)?,
+        };
+        if let Some(id) = BooleanAppIds::get(app_num) {
+            return Ok(id);
+        }
+        let is_recorded = BooleanAppIds::iter().next().is_some();
+        match T::BooleanConditions::app_id(app_num) {
+            Some(id) if !is_recorded => Ok(id),
+            _ => Err(Error::<T>::BooleanAppNotExist)?,
+        }
+    }
+
+    // Return the id of the numeric app referenced by its id or its legacy app number.
+    pub fn numeric_app_id(
+        app_id: Option<AppId>,
+        app_num: Option<u32>,
+    ) -> Result<AppId, DispatchError> {
+        if let Some(id) = app_id {
+            return Ok(id);
+        }
+        let app_num = match app_num {
+            Some(num) => num,
+            None => Err(Error::<T>::NumericAppNotExist)?,
+        };
+        if let Some(id) = NumericAppIds::get(app_num) {
+            return Ok(id);
+        }
+        let is_recorded = NumericAppIds::iter().next().is_some();
+        match T::NumericConditions::app_id(app_num) {
+            Some(id) if !is_recorded => Ok(id),
+            _ => Err(Error::<T>::NumericAppNotExist)?,
+        }
+    }
+
+    // Return the boolean app answering a legacy condition call of a boolean runtime module, with the
+    // session id and the query decoded from the arguments of the call. The call is not dispatched.
+    // Its app is looked up by its pallet and call indices as recorded in runtime upgrades, or in
+    // LegacyConditionCalls if no call has been recorded yet.
+    pub fn legacy_call_query(
+        call: <T as Trait>::Call,
+    ) -> Result<(AppId, T::Hash, Vec<u8>), DispatchError> {
+        ensure!(
+            T::ConditionCallFilter::filter(&call),
+            Error::<T>::ConditionCallNotAllowed
+        );
+
+        let encoded_call = call.encode();
+        ensure!(encoded_call.len() >= 2, Error::<T>::BooleanAppNotExist);
+        let call_index: [u8; 2] = [encoded_call[0], encoded_call[1]];
+        let app_id = match LegacyCallAppIds::get(call_index) {
+            Some(id) => id,
+            None => {
+                let is_recorded = LegacyCallAppIds::iter().next().is_some();
+                match legacy_call_app_ids::<T>().into_iter().find(|(index, _)| *index == call_index) {
+                    Some((_, id)) if !is_recorded => id,
+                    _ => Err(Error::<T>::BooleanAppNotExist)?,
+                }
+            }
+        };
+
+        // the arguments of the call are the session id followed by the query
+        let mut args = &encoded_call[2..];
+        let session_id = match T::Hash::decode(&mut args) {
+            Ok(id) => id,
+            Err(_) => Err(Error::<T>::BooleanSessionIdNotExist)?,
+        };
+        return Ok((app_id, session_id, args.to_vec()));
+    }
+
+    // Query a condition app and roll back all of its state changes.
+    fn query_app<R>(query: impl FnOnce() -> Option<Result<R, DispatchError>>) -> Option<Result<R, DispatchError>> {
+        with_transaction(|| TransactionOutcome::Rollback(query()))
+    }
+
+    pub fn call_boolean_is_finalized(
+        boolean_app_id: &AppId,
+        session_id: &T::Hash,
+        args_query_finalization: Option<Vec<u8>>
+    ) -> Result<bool, DispatchError> {
+        match Self::query_app(|| T::BooleanConditions::is_finalized(boolean_app_id, session_id, args_query_finalization)) {
+            Some(is_finalized) => is_finalized,
+            None => Err(Error::<T>::BooleanAppNotExist)?,
+        }
+    }
+
+    pub fn call_boolean_get_outcome(
+        boolean_app_id: &AppId,
+        session_id: &T::Hash,
+        args_query_outcome: Option<Vec<u8>>
+    ) -> Result<bool, DispatchError> {
+        match Self::query_app(|| T::BooleanConditions::get_outcome(boolean_app_id, session_id, args_query_outcome)) {
+            Some(outcome) => outcome,
+            None => Err(Error::<T>::BooleanAppNotExist)?,
+        }
+    }
+
+    pub fn call_numeric_is_finalized(
+        numeric_app_id: &AppId, 
+        session_id: &T::Hash, 
+        args_query_finalization: Option<Vec<u8>>
+    ) -> Result<bool, DispatchError> {
+        match Self::query_app(|| T::NumericConditions::is_finalized(numeric_app_id, session_id, args_query_finalization)) {
+            Some(is_finalized) => is_finalized,
+            None => Err(Error::<T>::NumericAppNotExist)?,
+        }
+    }
+
+    pub fn call_numeric_get_outcome(
+        numeric_app_id: &AppId, 
+        session_id: &T::Hash, 
+        args_query_outcome: Option<Vec<u8>>
+    ) -> Result<BalanceOf<T>, DispatchError> {
+        match Self::query_app(|| T::NumericConditions::get_outcome(numeric_app_id, session_id, args_query_outcome)) {
+            Some(outcome) => outcome,
+            None => Err(Error::<T>::NumericAppNotExist)?,
+        }
+    }
+}
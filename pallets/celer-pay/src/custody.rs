@@ -0,0 +1,503 @@
+use super::{
+    Module as CelerPayModule, BalanceOf, CustodyReserves, LegacyCustodyWallets, NativeCustodyMode,
+    WalletEndowments, WalletMigrationCursor, WalletReserves, Wallets
+};
+use crate::traits::{CustodyMode, Trait};
+use crate::pay_resolver::TokenType;
+use frame_support::{
+    debug, ensure,
+    storage::{IterableStorageDoubleMap, StorageDoubleMap, StorageMap, StorageValue},
+    traits::{BalanceStatus, Currency, ExistenceRequirement, Get, ReservableCurrency},
+};
+use ExistenceRequirement::AllowDeath;
+use sp_runtime::traits::{Saturating, Zero};
+use sp_runtime::DispatchError;
+use sp_std::{vec, vec::Vec};
+
+// Custody of funds in Pool and wallets. Ledger accounting (PoolBalances, Wallets) is kept
+// by the callers. In `CustodyMode::ModuleAccount`, funds are held by the Pool module account
+// and the sub-account of each wallet. In `CustodyMode::Reserve`, native token is reserved on
+// the account of each owner instead.
+pub struct Custody<T>(sp_std::marker::PhantomData<T>);
+
+impl<T: Trait> Custody<T> {
+    // Move funds of `from` into Pool of `receiver`
+    pub fn deposit_to_pool(
+        token_type: &TokenType,
+        from: &T::AccountId,
+        receiver: &T::AccountId,
+        amount: BalanceOf<T>,
+    ) -> Result<(), DispatchError> {
+        fix_custody_mode::<T>();
+        if !is_reserved::<T>(token_type) {
+            let pool_account = CelerPayModule::<T>::get_pool_id();
+            return CelerPayModule::<T>::transfer_token(token_type, from, &pool_account, amount, AllowDeath);
+        }
+
+        if from != receiver {
+            <T as Trait>::Currency::transfer(from, receiver, amount, ExistenceRequirement::AllowDeath)?;
+        }
+        reserve::<T>(receiver, amount)
+    }
+
+    // Move funds out of Pool of `owner` to free balance of `dest`
+    pub fn withdraw_from_pool(
+        token_type: &TokenType,
+        owner: &T::AccountId,
+        dest: &T::AccountId,
+        amount: BalanceOf<T>,
+    ) -> Result<(), DispatchError> {
+        if !is_reserved::<T>(token_type) {
+            let pool_account = CelerPayModule::<T>::get_pool_id();
+            return CelerPayModule::<T>::transfer_token(token_type, &pool_account, dest, amount, AllowDeath);
+        }
+
+        release_reserved::<T>(owner, dest, amount)
+    }
+
+    // Move funds from Pool of `owner` into the wallet
+    pub fn pool_to_wallet(
+        token_type: &TokenType,
+        owner: &T::AccountId,
+        wallet_id: T::Hash,
+        amount: BalanceOf<T>,
+    ) -> Result<(), DispatchError> {
+        if !is_reserved::<T>(token_type) {
+            let pool_account = CelerPayModule::<T>::get_pool_id();
+            let (wallet_account, _) = wallet_custody_account::<T>(&wallet_id);
+            return CelerPayModule::<T>::transfer_token(token_type, &pool_account, &wallet_account, amount, AllowDeath);
+        }
+
+        // Funds stay reserved on the owner account
+        WalletReserves::<T>::mutate(wallet_id, owner, |reserved| *reserved = reserved.saturating_add(amount));
+        Ok(())
+    }
+
+    // Move funds of `from` into the wallet
+    pub fn deposit_to_wallet(
+        token_type: &TokenType,
+        from: &T::AccountId,
+        wallet_id: T::Hash,
+        amount: BalanceOf<T>,
+    ) -> Result<(), DispatchError> {
+        fix_custody_mode::<T>();
+        if !is_reserved::<T>(token_type) {
+            let (wallet_account, _) = wallet_custody_account::<T>(&wallet_id);
+            return CelerPayModule::<T>::transfer_token(token_type, from, &wallet_account, amount, AllowDeath);
+        }
+
+        reserve::<T>(from, amount)?;
+        WalletReserves::<T>::mutate(wallet_id, from, |reserved| *reserved = reserved.saturating_add(amount));
+        Ok(())
+    }
+
+    // Return the existential deposit endowed to the sub-account of a new wallet, which is zero
+    // if native token of the wallet is not held by its sub-account
+    pub fn wallet_endowment(token_type: &TokenType, wallet_id: &T::Hash) -> BalanceOf<T> {
+        if *token_type != TokenType::Celer || is_reserved::<T>(token_type) {
+            return Zero::zero();
+        }
+        let (_, is_sub_account) = wallet_custody_account::<T>(wallet_id);
+        if !is_sub_account {
+            return Zero::zero();
+        }
+        return <T as Trait>::Currency::minimum_balance();
+    }
+
+    // Endow the sub-account of a new wallet from free balance of `depositor`, so that the
+    // sub-account is kept alive whatever the balance of the wallet is
+    pub fn endow_wallet(
+        wallet_id: T::Hash,
+        depositor: &T::AccountId,
+        endowment: BalanceOf<T>,
+    ) -> Result<(), DispatchError> {
+        fix_custody_mode::<T>();
+        if endowment.is_zero() {
+            return Ok(());
+        }
+
+        let wallet_account = CelerPayModule::<T>::get_wallet_account(&wallet_id);
+        <T as Trait>::Currency::transfer(depositor, &wallet_account, endowment, AllowDeath)?;
+        WalletEndowments::<T>::insert(wallet_id, endowment);
+        Ok(())
+    }
+
+    // Sweep the sub-account of a removed wallet to `depositor` of its endowment,
+    // including funds sent to the sub-account directly, and return the endowment
+    pub fn sweep_wallet(wallet_id: T::Hash, depositor: &T::AccountId) -> BalanceOf<T> {
+        let endowment = match WalletEndowments::<T>::take(wallet_id) {
+            Some(_endowment) => _endowment,
+            None => return Zero::zero(),
+        };
+
+        let wallet_account = CelerPayModule::<T>::get_wallet_account(&wallet_id);
+        let swept = <T as Trait>::Currency::free_balance(&wallet_account);
+        if let Err(e) = <T as Trait>::Currency::transfer(&wallet_account, depositor, swept, AllowDeath) {
+            debug::error!("Sub-account of wallet {:?} can not be swept: {:?}", wallet_id, e);
+        }
+        return endowment;
+    }
+
+    // Move funds out of the wallet to free balance of `receiver`.
+    // Funds reserved by the receiver are released first, then funds reserved by other depositors.
+    pub fn withdraw_from_wallet(
+        token_type: &TokenType,
+        wallet_id: T::Hash,
+        receiver: &T::AccountId,
+        amount: BalanceOf<T>,
+    ) -> Result<(), DispatchError> {
+        if !is_reserved::<T>(token_type) {
+            let (wallet_account, is_sub_account) = wallet_custody_account::<T>(&wallet_id);
+            // the CelerWallet module account holds funds of other wallets, and an endowed
+            // sub-account is not reaped by withdrawing the wallet balance
+            if !is_sub_account
+                || *token_type != TokenType::Celer
+                || WalletEndowments::<T>::contains_key(wallet_id)
+            {
+                return CelerPayModule::<T>::transfer_token(token_type, &wallet_account, receiver, amount, AllowDeath);
+            }
+
+            // Sub-accounts of wallets migrated to V3_0_0 are not endowed. Funds of such a wallet are
+            // moved back to the CelerWallet module account before its sub-account would be reaped.
+            let free_balance = <T as Trait>::Currency::free_balance(&wallet_account);
+            ensure!(free_balance >= amount, "wallet account does not have enough balances");
+            if free_balance - amount >= <T as Trait>::Currency::minimum_balance() {
+                return CelerPayModule::<T>::transfer_token(token_type, &wallet_account, receiver, amount, AllowDeath);
+            }
+
+            let celer_wallet_account = CelerPayModule::<T>::get_celer_wallet_id();
+            <T as Trait>::Currency::transfer(&wallet_account, &celer_wallet_account, free_balance, AllowDeath)?;
+            LegacyCustodyWallets::<T>::insert(wallet_id, true);
+            return <T as Trait>::Currency::transfer(&celer_wallet_account, receiver, amount, AllowDeath);
+        }
+
+        let mut depositors = vec![receiver.clone()];
+        depositors.extend(
+            WalletReserves::<T>::iter_prefix(wallet_id)
+                .map(|(depositor, _)| depositor)
+                .filter(|depositor| depositor != receiver)
+                .collect::<Vec<T::AccountId>>()
+        );
+
+        let mut remaining = amount;
+        for depositor in depositors.iter() {
+            if remaining.is_zero() {
+                break;
+            }
+            let reserved = WalletReserves::<T>::get(wallet_id, depositor);
+            let released = if reserved < remaining { reserved } else { remaining };
+            if released.is_zero() {
+                continue;
+            }
+
+            if reserved == released {
+                WalletReserves::<T>::remove(wallet_id, depositor);
+            } else {
+                WalletReserves::<T>::insert(wallet_id, depositor, reserved.saturating_sub(released));
+            }
+            release_reserved::<T>(depositor, receiver, released)?;
+            remaining = remaining.saturating_sub(released);
+        }
+        ensure!(remaining.is_zero(), "wallet does not have enough reserved balances");
+
+        Ok(())
+    }
+}
+
+// Return true if funds of the token are reserved on the account of each owner.
+// Once fixed, the custody mode is read from storage rather than from the runtime config.
+pub(crate) fn is_reserved<T: Trait>(token_type: &TokenType) -> bool {
+    let custody_mode = NativeCustodyMode::get().unwrap_or_else(T::NativeCustody::get);
+    return *token_type == TokenType::Celer && custody_mode == CustodyMode::Reserve;
+}
+
+// Return the account holding funds of the wallet in `CustodyMode::ModuleAccount`, and whether it is
+// the sub-account of the wallet. Funds of wallets which have not been or failed to be migrated to
+// V3_0_0 are held by the CelerWallet module account.
+fn wallet_custody_account<T: Trait>(wallet_id: &T::Hash) -> (T::AccountId, bool) {
+    let is_migrating = match WalletMigrationCursor::get() {
+        Some(cursor) => Wallets::<T>::hashed_key_for(wallet_id) > cursor,
+        None => false,
+    };
+    if is_migrating || LegacyCustodyWallets::<T>::get(wallet_id) {
+        return (CelerPayModule::<T>::get_celer_wallet_id(), false);
+    }
+    return (CelerPayModule::<T>::get_wallet_account(wallet_id), true);
+}
+
+// Fix the custody mode before the first funds are deposited
+fn fix_custody_mode<T: Trait>() {
+    if NativeCustodyMode::get().is_none() {
+        NativeCustodyMode::put(T::NativeCustody::get());
+    }
+}
+
+// Reserve native token of `owner` for Pool, escrows and wallets
+fn reserve<T: Trait>(
+    owner: &T::AccountId,
+    amount: BalanceOf<T>,
+) -> Result<(), DispatchError> {
+    <T as Trait>::Currency::reserve(owner, amount)?;
+    CustodyReserves::<T>::mutate(owner, |reserved| *reserved = reserved.saturating_add(amount));
+    Ok(())
+}
+
+// Release reserved native token of `owner` to free balance of `dest`.
+// Reserves of an account are shared with storage deposits and other modules, so only funds
+// reserved by the custody are released, and nothing is released once reserves of the account
+// have been slashed below them.
+fn release_reserved<T: Trait>(
+    owner: &T::AccountId,
+    dest: &T::AccountId,
+    amount: BalanceOf<T>,
+) -> Result<(), DispatchError> {
+    let custody_reserved = CustodyReserves::<T>::get(owner);
+    ensure!(custody_reserved >= amount, "owner does not have enough reserved balances");
+    ensure!(
+        <T as Trait>::Currency::reserved_balance(owner) >= custody_reserved,
+        "owner reserved balance is below custody reserves"
+    );
+
+    let remaining = if owner == dest {
+        <T as Trait>::Currency::unreserve(owner, amount)
+    } else {
+        <T as Trait>::Currency::repatriate_reserved(owner, dest, amount, BalanceStatus::Free)?
+    };
+    ensure!(remaining.is_zero(), "owner does not have enough reserved balances");
+
+    if custody_reserved == amount {
+        CustodyReserves::<T>::remove(owner);
+    } else {
+        CustodyReserves::<T>::insert(owner, custody_reserved.saturating_sub(amount));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::mock::*;
+    use crate::celer_wallet::CelerWallet;
+    use crate::pool::Pool;
+    use crate::pool::tests::{approve, deposit_pool};
+    use frame_support::assert_noop;
+    use sp_core::H256;
+
+    #[test]
+    fn test_pass_pool_with_reserve_custody() {
+        ExtBuilder::build().execute_with(|| {
+            MockNativeCustody::set(CustodyMode::Reserve);
+            let pool_account = CelerPayModule::get_pool_id();
+            let alice = account_key("Alice");
+            let bob = account_key("Bob");
+            let risa = account_key("Risa");
+
+            Pool::<TestRuntime>::deposit_pool(Origin::signed(alice), bob, 100).unwrap();
+            deposit_pool(bob, 100);
+            assert_eq!(CelerPayModule::get_pool_balance(bob).amount, 200);
+            assert_eq!(Balances::reserved_balance(bob), 200);
+            assert_eq!(Balances::free_balance(bob), 900);
+            assert_eq!(Balances::free_balance(alice), 900);
+            assert_eq!(Balances::free_balance(pool_account), 0);
+
+            approve(bob, risa, 150);
+            Pool::<TestRuntime>::transfer_from(Origin::signed(risa), bob, alice, 150).unwrap();
+            assert_eq!(Balances::reserved_balance(bob), 50);
+            assert_eq!(Balances::free_balance(alice), 1050);
+
+            Pool::<TestRuntime>::withdraw(Origin::signed(bob), 50).unwrap();
+            assert_eq!(Balances::reserved_balance(bob), 0);
+            assert_eq!(Balances::free_balance(bob), 950);
+        })
+    }
+
+    #[test]
+    fn test_pass_wallet_with_reserve_custody() {
+        ExtBuilder::build().execute_with(|| {
+            MockNativeCustody::set(CustodyMode::Reserve);
+            let celer_ledger_account = CelerPayModule::get_celer_ledger_id();
+            let alice = account_key("Alice");
+            let bob = account_key("Bob");
+
+            let wallet_id = CelerWallet::<TestRuntime>::create_wallet(
+                Origin::signed(celer_ledger_account),
+                vec![alice, bob],
+                H256::from_low_u64_be(1)
+            ).unwrap();
+            CelerWallet::<TestRuntime>::deposit_token(alice, wallet_id, TokenType::Celer, 100).unwrap();
+            CelerWallet::<TestRuntime>::deposit_token(bob, wallet_id, TokenType::Celer, 50).unwrap();
+            assert_eq!(CelerPayModule::get_wallet_balance(wallet_id).amount, 150);
+            assert_eq!(Balances::reserved_balance(alice), 100);
+            assert_eq!(Balances::free_balance(CelerPayModule::get_wallet_account(&wallet_id)), 0);
+
+            // reserve of bob is released first, then reserve of alice
+            CelerWallet::<TestRuntime>::withdraw(
+                Origin::signed(celer_ledger_account),
+                wallet_id,
+                TokenType::Celer,
+                bob,
+                120
+            ).unwrap();
+            assert_eq!(CelerPayModule::get_wallet_balance(wallet_id).amount, 30);
+            assert_eq!(CelerPayModule::wallet_reserves(wallet_id, alice), 30);
+            assert_eq!(CelerPayModule::wallet_reserves(wallet_id, bob), 0);
+            assert_eq!(Balances::reserved_balance(alice), 30);
+            assert_eq!(Balances::reserved_balance(bob), 0);
+            assert_eq!(Balances::free_balance(bob), 1070);
+        })
+    }
+
+    #[test]
+    fn test_fail_release_reserved_below_custody_reserves() {
+        ExtBuilder::build().execute_with(|| {
+            MockNativeCustody::set(CustodyMode::Reserve);
+            let bob = account_key("Bob");
+
+            // reserves of another module are not released by the custody
+            deposit_pool(bob, 200);
+            Balances::reserve(&bob, 30).unwrap();
+            assert_eq!(CelerPayModule::custody_reserves(bob), 200);
+            Pool::<TestRuntime>::withdraw(Origin::signed(bob), 200).unwrap();
+            assert_eq!(CelerPayModule::custody_reserves(bob), 0);
+            assert_eq!(Balances::reserved_balance(bob), 30);
+
+            // nothing is released once the reserves have been slashed below the custody reserves
+            deposit_pool(bob, 100);
+            let _ = Balances::slash_reserved(&bob, 80);
+            assert_eq!(Balances::reserved_balance(bob), 50);
+            assert_noop!(
+                Pool::<TestRuntime>::withdraw(Origin::signed(bob), 50),
+                DispatchError::Other("owner reserved balance is below custody reserves")
+            );
+            assert_eq!(CelerPayModule::custody_reserves(bob), 100);
+        })
+    }
+
+    #[test]
+    fn test_pass_keep_endowed_wallet_account_alive_until_swept() {
+        ExtBuilder::build().execute_with(|| {
+            MockExistentialDeposit::set(10);
+            let celer_ledger_account = CelerPayModule::get_celer_ledger_id();
+            let alice = account_key("Alice");
+            let bob = account_key("Bob");
+
+            let wallet_id = CelerWallet::<TestRuntime>::create_wallet(
+                Origin::signed(celer_ledger_account),
+                vec![alice, bob],
+                H256::from_low_u64_be(1)
+            ).unwrap();
+            let wallet_account = CelerPayModule::get_wallet_account(&wallet_id);
+            let endowment = Custody::<TestRuntime>::wallet_endowment(&TokenType::Celer, &wallet_id);
+            assert_eq!(endowment, 10);
+            Custody::<TestRuntime>::endow_wallet(wallet_id, &alice, endowment).unwrap();
+            assert_eq!(CelerPayModule::wallet_endowments(wallet_id), Some(10));
+
+            // a deposit below the existential deposit is accepted by the endowed sub-account
+            CelerWallet::<TestRuntime>::deposit_token(alice, wallet_id, TokenType::Celer, 5).unwrap();
+            CelerWallet::<TestRuntime>::deposit_token(alice, wallet_id, TokenType::Celer, 95).unwrap();
+            assert_eq!(Balances::free_balance(wallet_account), 110);
+
+            // withdrawals leaving less than the existential deposit in the wallet do not reap the sub-account
+            for amount in vec![95, 5] {
+                CelerWallet::<TestRuntime>::withdraw(
+                    Origin::signed(celer_ledger_account),
+                    wallet_id,
+                    TokenType::Celer,
+                    bob,
+                    amount
+                ).unwrap();
+            }
+            assert_eq!(CelerPayModule::get_wallet_balance(wallet_id).amount, 0);
+            assert_eq!(Balances::free_balance(wallet_account), 10);
+            assert_eq!(Balances::free_balance(bob), 1100);
+
+            // the sub-account is swept to the depositor of the endowment including funds sent to it directly
+            <Balances as Currency<AccountId>>::transfer(&alice, &wallet_account, 3, AllowDeath).unwrap();
+            CelerWallet::<TestRuntime>::remove_wallet(wallet_id).unwrap();
+            assert_eq!(Custody::<TestRuntime>::sweep_wallet(wallet_id, &alice), 10);
+            assert_eq!(CelerPayModule::wallet_endowments(wallet_id), None);
+            assert_eq!(Balances::free_balance(wallet_account), 0);
+            assert_eq!(Balances::free_balance(alice), 900);
+        })
+    }
+
+    #[test]
+    fn test_pass_move_funds_of_unendowed_wallet_account_to_celer_wallet_account() {
+        ExtBuilder::build().execute_with(|| {
+            MockExistentialDeposit::set(10);
+            let celer_ledger_account = CelerPayModule::get_celer_ledger_id();
+            let celer_wallet_account = CelerPayModule::get_celer_wallet_id();
+            let alice = account_key("Alice");
+            let bob = account_key("Bob");
+
+            // funds of another wallet held by the CelerWallet module account
+            <Balances as Currency<AccountId>>::transfer(&alice, &celer_wallet_account, 50, AllowDeath).unwrap();
+
+            // the sub-account of a migrated wallet is not endowed
+            let wallet_id = CelerWallet::<TestRuntime>::create_wallet(
+                Origin::signed(celer_ledger_account),
+                vec![alice, bob],
+                H256::from_low_u64_be(1)
+            ).unwrap();
+            let wallet_account = CelerPayModule::get_wallet_account(&wallet_id);
+            CelerWallet::<TestRuntime>::deposit_token(alice, wallet_id, TokenType::Celer, 100).unwrap();
+
+            CelerWallet::<TestRuntime>::withdraw(
+                Origin::signed(celer_ledger_account),
+                wallet_id,
+                TokenType::Celer,
+                bob,
+                85
+            ).unwrap();
+            assert_eq!(Balances::free_balance(wallet_account), 15);
+            assert_eq!(CelerPayModule::legacy_custody_wallets(wallet_id), false);
+
+            // funds are moved to the CelerWallet module account before the sub-account would be reaped
+            CelerWallet::<TestRuntime>::withdraw(
+                Origin::signed(celer_ledger_account),
+                wallet_id,
+                TokenType::Celer,
+                bob,
+                10
+            ).unwrap();
+            assert_eq!(CelerPayModule::legacy_custody_wallets(wallet_id), true);
+            assert_eq!(Balances::free_balance(wallet_account), 0);
+            assert_eq!(Balances::free_balance(celer_wallet_account), 55);
+
+            CelerWallet::<TestRuntime>::withdraw(
+                Origin::signed(celer_ledger_account),
+                wallet_id,
+                TokenType::Celer,
+                bob,
+                5
+            ).unwrap();
+            assert_eq!(CelerPayModule::get_wallet_balance(wallet_id).amount, 0);
+            assert_eq!(Balances::free_balance(celer_wallet_account), 50);
+            assert_eq!(Balances::free_balance(bob), 1100);
+        })
+    }
+
+    #[test]
+    fn test_pass_keep_custody_mode_of_first_deposit_after_config_changed() {
+        ExtBuilder::build().execute_with(|| {
+            let pool_account = CelerPayModule::get_pool_id();
+            let bob = account_key("Bob");
+            assert_eq!(CelerPayModule::native_custody_mode(), None);
+            deposit_pool(bob, 100);
+            assert_eq!(CelerPayModule::native_custody_mode(), Some(CustodyMode::ModuleAccount));
+            assert_eq!(Balances::free_balance(pool_account), 100);
+
+            // funds deposited to the Pool module account are not stranded by a runtime upgrade
+            // which switches NativeCustody
+            MockNativeCustody::set(CustodyMode::Reserve);
+            crate::migration::on_runtime_upgrade::<TestRuntime>();
+            assert_eq!(CelerPayModule::native_custody_mode(), Some(CustodyMode::ModuleAccount));
+            deposit_pool(bob, 50);
+            assert_eq!(Balances::free_balance(pool_account), 150);
+            Pool::<TestRuntime>::withdraw(Origin::signed(bob), 150).unwrap();
+            assert_eq!(Balances::free_balance(pool_account), 0);
+            assert_eq!(Balances::reserved_balance(bob), 0);
+            assert_eq!(Balances::free_balance(bob), 1000);
+        })
+    }
+}
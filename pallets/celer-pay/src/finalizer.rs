@@ -0,0 +1,434 @@
+use super::{Module as CelerPayModule, ChannelMap, Error, RawEvent, ScheduledSettles, ScheduledWithdraws, weight_for};
+use crate::traits::Trait;
+use crate::ledger_operation::{settle_finalize_block, ChannelStatus, LedgerOperation};
+use frame_support::{
+    storage::{with_transaction, StorageMap, TransactionOutcome},
+    traits::Get,
+    weights::Weight,
+};
+use sp_runtime::traits::{One, Saturating, Zero};
+use sp_std::vec::Vec;
+
+// Automatic finalization of settles and withdraw intents. Channels are queued by the block
+// from which they can be finalized, and confirmed in `on_initialize` within `AutoFinalizeWeight`.
+// Queued channels which are finalized manually, vetoed or re-intended are skipped or requeued.
+// At most `MaxScheduledFinalizations` channels are queued at a block, and channels beyond it
+// spill to the following blocks. A channel which finds no room within `MAX_SPILLED_BLOCKS`
+// is left to be finalized manually, and an event is emitted for it.
+pub struct Finalizer<T>(sp_std::marker::PhantomData<T>);
+
+// The maximum number of blocks searched for room in their queues to schedule a channel
+pub const MAX_SPILLED_BLOCKS: u32 = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Finalization {
+    Settle,
+    Withdraw,
+}
+
+impl<T: Trait> Finalizer<T> {
+    // Queue the channel to be confirmed settle at the block or a following one
+    pub fn schedule_settle(channel_id: T::Hash, block_number: T::BlockNumber) {
+        if T::AutoFinalizeWeight::get().is_zero() {
+            return;
+        }
+        schedule::<T>(Finalization::Settle, channel_id, block_number);
+    }
+
+    // Queue the channel to be confirmed withdraw at the block or a following one
+    pub fn schedule_withdraw(channel_id: T::Hash, block_number: T::BlockNumber) {
+        if T::AutoFinalizeWeight::get().is_zero() {
+            return;
+        }
+        schedule::<T>(Finalization::Withdraw, channel_id, block_number);
+    }
+
+    // Finalize channels queued at the block within `AutoFinalizeWeight`,
+    // and defer the remaining ones to the next block
+    pub fn finalize_scheduled(block_number: T::BlockNumber) -> Weight {
+        let budget = T::AutoFinalizeWeight::get();
+        let next_block = block_number.saturating_add(One::one());
+        // Take the queues at the block, and put the deferred channels ahead of the queues at the next block
+        let mut weight = T::DbWeight::get().reads_writes(4, 4);
+
+        let mut deferred_settles: Vec<T::Hash> = Vec::new();
+        for channel_id in ScheduledSettles::<T>::take(block_number).into_iter() {
+            let settle_weight = weight_for::confirm_settle::<T>();
+            if weight.saturating_add(settle_weight) > budget {
+                deferred_settles.push(channel_id);
+                continue;
+            }
+            try_confirm_settle::<T>(channel_id, block_number);
+            weight = weight.saturating_add(settle_weight);
+        }
+
+        let mut deferred_withdraws: Vec<T::Hash> = Vec::new();
+        for channel_id in ScheduledWithdraws::<T>::take(block_number).into_iter() {
+            let withdraw_weight = weight_for::confirm_withdraw::<T>();
+            if weight.saturating_add(withdraw_weight) > budget {
+                deferred_withdraws.push(channel_id);
+                continue;
+            }
+            try_confirm_withdraw::<T>(channel_id, block_number);
+            weight = weight.saturating_add(withdraw_weight);
+        }
+
+        let spilled = defer::<T>(Finalization::Settle, next_block, deferred_settles)
+            + defer::<T>(Finalization::Withdraw, next_block, deferred_withdraws);
+        // Each spilled channel searches at most MAX_SPILLED_BLOCKS queues
+        weight = weight.saturating_add(
+            T::DbWeight::get().reads_writes(MAX_SPILLED_BLOCKS as Weight, 1).saturating_mul(spilled as Weight)
+        );
+
+        return weight;
+    }
+}
+
+fn get_queue<T: Trait>(finalization: Finalization, block_number: T::BlockNumber) -> Vec<T::Hash> {
+    match finalization {
+        Finalization::Settle => ScheduledSettles::<T>::get(block_number),
+        Finalization::Withdraw => ScheduledWithdraws::<T>::get(block_number),
+    }
+}
+
+fn put_queue<T: Trait>(finalization: Finalization, block_number: T::BlockNumber, channel_ids: Vec<T::Hash>) {
+    match finalization {
+        Finalization::Settle => ScheduledSettles::<T>::insert(block_number, channel_ids),
+        Finalization::Withdraw => ScheduledWithdraws::<T>::insert(block_number, channel_ids),
+    }
+}
+
+// Push the channel to the queue at the first block from `block_number` which has room for it,
+// unless it is already queued there
+fn schedule<T: Trait>(finalization: Finalization, channel_id: T::Hash, block_number: T::BlockNumber) {
+    let max_channels = T::MaxScheduledFinalizations::get() as usize;
+    let mut block_number = block_number;
+    for _ in 0..MAX_SPILLED_BLOCKS {
+        let mut channel_ids = get_queue::<T>(finalization, block_number);
+        if channel_ids.contains(&channel_id) {
+            return;
+        }
+        if channel_ids.len() < max_channels {
+            channel_ids.push(channel_id);
+            put_queue::<T>(finalization, block_number, channel_ids);
+            return;
+        }
+        block_number = block_number.saturating_add(One::one());
+    }
+
+    match finalization {
+        // Emit AutoConfirmSettleNotScheduled event
+        Finalization::Settle => CelerPayModule::<T>::deposit_event(
+            RawEvent::AutoConfirmSettleNotScheduled(channel_id)
+        ),
+        // Emit AutoConfirmWithdrawNotScheduled event
+        Finalization::Withdraw => CelerPayModule::<T>::deposit_event(
+            RawEvent::AutoConfirmWithdrawNotScheduled(channel_id)
+        ),
+    }
+}
+
+// Put the deferred channels ahead of the queue at the block, so that they are finalized first,
+// and spill the channels beyond `MaxScheduledFinalizations` to the following blocks.
+// Return the number of spilled channels.
+fn defer<T: Trait>(finalization: Finalization, block_number: T::BlockNumber, mut deferred: Vec<T::Hash>) -> usize {
+    if deferred.is_empty() {
+        return 0;
+    }
+
+    for channel_id in get_queue::<T>(finalization, block_number).into_iter() {
+        if !deferred.contains(&channel_id) {
+            deferred.push(channel_id);
+        }
+    }
+    let max_channels = T::MaxScheduledFinalizations::get() as usize;
+    let spilled = if deferred.len() > max_channels {
+        deferred.split_off(max_channels)
+    } else {
+        Vec::new()
+    };
+    put_queue::<T>(finalization, block_number, deferred);
+
+    let next_block = block_number.saturating_add(One::one());
+    for channel_id in spilled.iter() {
+        schedule::<T>(finalization, *channel_id, next_block);
+    }
+    return spilled.len();
+}
+
+// Confirm settle of the channel if it is finalized,
+// or requeue it at the block from which it is finalized
+fn try_confirm_settle<T: Trait>(channel_id: T::Hash, block_number: T::BlockNumber) {
+    let c = match ChannelMap::<T>::get(&channel_id) {
+        Some(_channel) => _channel,
+        None => return,
+    };
+    if c.status != ChannelStatus::Settling {
+        return;
+    }
+
+    let finalize_block = settle_finalize_block::<T>(channel_id, &c);
+    if finalize_block > block_number {
+        Finalizer::<T>::schedule_settle(channel_id, finalize_block);
+        return;
+    }
+
+    // Roll back all state changes of a failed finalization, except resetting the channel to
+    // Operable on an invalid settle balance as a manual confirm_settle does
+    let result = with_transaction(|| {
+        let result = LedgerOperation::<T>::confirm_settle(channel_id);
+        match result {
+            Ok(_) => TransactionOutcome::Commit(result),
+            Err(e) if e == Error::<T>::ConfirmSettleFail.into() => TransactionOutcome::Commit(result),
+            Err(_) => TransactionOutcome::Rollback(result),
+        }
+    });
+    if result.is_err() {
+        // Emit AutoConfirmSettleFail event
+        CelerPayModule::<T>::deposit_event(RawEvent::AutoConfirmSettleFail(channel_id));
+    }
+}
+
+// Confirm the pending withdraw intent of the channel if its dispute timeout is passed,
+// or requeue it at the block from which the dispute timeout is passed
+fn try_confirm_withdraw<T: Trait>(channel_id: T::Hash, block_number: T::BlockNumber) {
+    let c = match ChannelMap::<T>::get(&channel_id) {
+        Some(_channel) => _channel,
+        None => return,
+    };
+    let celer_ledger_account = CelerPayModule::<T>::get_celer_ledger_id();
+    if c.status != ChannelStatus::Operable || c.withdraw_intent.receiver == celer_ledger_account {
+        return;
+    }
+
+    let finalize_block = c.withdraw_intent.request_time.unwrap_or(Zero::zero())
+        .saturating_add(c.dispute_timeout);
+    if finalize_block > block_number {
+        Finalizer::<T>::schedule_withdraw(channel_id, finalize_block);
+        return;
+    }
+
+    // Roll back all state changes of a failed finalization
+    let result = with_transaction(|| {
+        let result = LedgerOperation::<T>::confirm_withdraw(channel_id);
+        match result {
+            Ok(_) => TransactionOutcome::Commit(result),
+            Err(_) => TransactionOutcome::Rollback(result),
+        }
+    });
+    if result.is_err() {
+        // Emit AutoConfirmWithdrawFail event
+        CelerPayModule::<T>::deposit_event(RawEvent::AutoConfirmWithdrawFail(channel_id));
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::mock::*;
+    use crate::ledger_operation::{PayIdList, SignedSimplexStateArray};
+    use crate::pool::Pool;
+    use crate::pool::tests::approve;
+    use crate::tests::ledger_operation::test_ledger_operation::*;
+    use frame_support::traits::OnInitialize;
+    use sp_core::H256;
+
+    #[test]
+    fn test_pass_auto_confirm_settle_at_settle_finalized_time() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let celer_ledger_account = CelerPayModule::get_celer_ledger_id();
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+            Pool::<TestRuntime>::deposit_pool(Origin::signed(channel_peers[0]), channel_peers[0], 100).unwrap();
+            approve(channel_peers[0], celer_ledger_account, 100);
+
+            let open_channel_request = get_open_channel_request(true, 10000, 500001, 10, false, channel_peers.clone(), 1, peers_pair.clone());
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[1]),
+                open_channel_request,
+                200,
+            ).unwrap();
+
+            let single_singed_null_state = get_single_signed_simplex_state(channel_id, channel_peers[0], peers_pair);
+            let signed_simplex_state_array = SignedSimplexStateArray {
+                signed_simplex_states: vec![single_singed_null_state],
+            };
+            LedgerOperation::<TestRuntime>::intend_settle(
+                Origin::signed(channel_peers[0]),
+                signed_simplex_state_array,
+            ).unwrap();
+            let settle_finalized_time = CelerPayModule::get_settle_finalized_time(channel_id);
+            assert_eq!(CelerPayModule::scheduled_settles(settle_finalized_time), vec![channel_id]);
+
+            System::set_block_number(settle_finalized_time);
+            CelerPayModule::on_initialize(settle_finalized_time);
+            assert_eq!(CelerPayModule::get_channel_status(channel_id), 3);
+            assert_eq!(CelerPayModule::scheduled_settles(settle_finalized_time), vec![]);
+        })
+    }
+
+    #[test]
+    fn test_pass_auto_confirm_withdraw_after_dispute_timeout() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+            let open_channel_request = get_open_channel_request(true, 800, 500001, 10, true, channel_peers.clone(), 1, peers_pair);
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[1]),
+                open_channel_request,
+                0,
+            ).unwrap();
+            LedgerOperation::<TestRuntime>::deposit(
+                Origin::signed(channel_peers[0]),
+                channel_id,
+                channel_peers[0],
+                300,
+                0
+            ).unwrap();
+
+            let zero_channel_id = CelerPayModule::get_zero_hash();
+            LedgerOperation::<TestRuntime>::intend_withdraw(
+                Origin::signed(channel_peers[0]),
+                channel_id,
+                200,
+                zero_channel_id,
+            ).unwrap();
+            assert_eq!(CelerPayModule::scheduled_withdraws(11), vec![channel_id]);
+
+            System::set_block_number(11);
+            CelerPayModule::on_initialize(11);
+            let (_, _, withdrawals) = CelerPayModule::get_balance_map(channel_id);
+            assert_eq!(withdrawals[0].amount, 200);
+
+            // a failed automatic finalization emits an event
+            LedgerOperation::<TestRuntime>::intend_withdraw(
+                Origin::signed(channel_peers[0]),
+                channel_id,
+                200,
+                zero_channel_id,
+            ).unwrap();
+            System::set_block_number(21);
+            CelerPayModule::on_initialize(21);
+            let expected_event = TestEvent::celer(RawEvent::AutoConfirmWithdrawFail(channel_id));
+            assert!(System::events().iter().any(|a| a.event == expected_event));
+        })
+    }
+
+    #[test]
+    fn test_pass_defer_auto_finalization_exceeding_weight_budget() {
+        ExtBuilder::build().execute_with(|| {
+            let channel_ids: Vec<H256> = (1..4).map(|i| H256::from_low_u64_be(i)).collect();
+            for channel_id in channel_ids.iter() {
+                Finalizer::<TestRuntime>::schedule_settle(*channel_id, 5);
+            }
+            Finalizer::<TestRuntime>::schedule_settle(channel_ids[0], 5);
+            assert_eq!(CelerPayModule::scheduled_settles(5), channel_ids);
+
+            // AutoFinalizeWeight of the mock runtime allows two finalizations in a block
+            Finalizer::<TestRuntime>::finalize_scheduled(5);
+            assert_eq!(CelerPayModule::scheduled_settles(5), vec![]);
+            assert_eq!(CelerPayModule::scheduled_settles(6), vec![channel_ids[2]]);
+        })
+    }
+
+    #[test]
+    fn test_pass_auto_confirm_settle_fail_resets_channel_to_operable() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+            let open_channel_request = get_open_channel_request(true, 800, 500001, 10, true, channel_peers.clone(), 1, peers_pair.clone());
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap();
+            LedgerOperation::<TestRuntime>::deposit(
+                Origin::signed(channel_peers[0]),
+                channel_id,
+                channel_peers[0],
+                5,
+                0
+            ).unwrap();
+
+            // peer 0 transfers more than its deposit
+            let signed_simplex_state_array = get_signed_simplex_state_array(
+                vec![channel_id],
+                vec![1],
+                vec![10],
+                vec![1],
+                vec![PayIdList { pay_ids: vec![], next_list_hash: None }],
+                vec![channel_peers[0]],
+                vec![0],
+                channel_peers[0],
+                peers_pair,
+            );
+            LedgerOperation::<TestRuntime>::intend_settle(
+                Origin::signed(channel_peers[0]),
+                signed_simplex_state_array,
+            ).unwrap();
+            let settle_finalized_time = CelerPayModule::get_settle_finalized_time(channel_id);
+
+            System::set_block_number(settle_finalized_time);
+            CelerPayModule::on_initialize(settle_finalized_time);
+            assert_eq!(CelerPayModule::get_channel_status(channel_id), ChannelStatus::Operable as u8);
+            let expected_events = vec![
+                TestEvent::celer(RawEvent::ConfirmSettleFail(channel_id)),
+                TestEvent::celer(RawEvent::AutoConfirmSettleFail(channel_id)),
+            ];
+            for expected_event in expected_events.iter() {
+                assert!(System::events().iter().any(|a| a.event == *expected_event));
+            }
+        })
+    }
+
+    #[test]
+    fn test_pass_spill_auto_finalizations_beyond_a_block_to_following_blocks() {
+        ExtBuilder::build().execute_with(|| {
+            let channel_ids: Vec<H256> = (1..7).map(|i| H256::from_low_u64_be(i)).collect();
+            // MaxScheduledFinalizations of the mock runtime is 3
+            for channel_id in channel_ids[0..4].iter() {
+                Finalizer::<TestRuntime>::schedule_settle(*channel_id, 5);
+            }
+            Finalizer::<TestRuntime>::schedule_settle(channel_ids[0], 5);
+            assert_eq!(CelerPayModule::scheduled_settles(5), channel_ids[0..3].to_vec());
+            assert_eq!(CelerPayModule::scheduled_settles(6), vec![channel_ids[3]]);
+
+            Finalizer::<TestRuntime>::schedule_settle(channel_ids[4], 6);
+            Finalizer::<TestRuntime>::schedule_settle(channel_ids[5], 6);
+            assert_eq!(CelerPayModule::scheduled_settles(6), channel_ids[3..6].to_vec());
+
+            // deferred channels are put ahead of the queue at the next block,
+            // and the channels beyond the bound spill to the following block
+            Finalizer::<TestRuntime>::finalize_scheduled(5);
+            assert_eq!(CelerPayModule::scheduled_settles(5), vec![]);
+            assert_eq!(CelerPayModule::scheduled_settles(6), vec![channel_ids[2], channel_ids[3], channel_ids[4]]);
+            assert_eq!(CelerPayModule::scheduled_settles(7), vec![channel_ids[5]]);
+        })
+    }
+
+    #[test]
+    fn test_pass_emit_event_for_auto_finalization_not_scheduled() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let max_channels = MAX_SPILLED_BLOCKS as u64 * 3;
+            for i in 0..max_channels {
+                Finalizer::<TestRuntime>::schedule_withdraw(H256::from_low_u64_be(i + 1), 10);
+            }
+            let last_block = 10 + MAX_SPILLED_BLOCKS as u64 - 1;
+            assert_eq!(CelerPayModule::scheduled_withdraws(last_block).len(), 3);
+
+            // a channel finds no room in the queues within MAX_SPILLED_BLOCKS
+            let channel_id = H256::from_low_u64_be(max_channels + 1);
+            Finalizer::<TestRuntime>::schedule_withdraw(channel_id, 10);
+            assert_eq!(CelerPayModule::scheduled_withdraws(last_block + 1), vec![]);
+            let expected_event = TestEvent::celer(RawEvent::AutoConfirmWithdrawNotScheduled(channel_id));
+            assert!(System::events().iter().any(|a| a.event == expected_event));
+        })
+    }
+}
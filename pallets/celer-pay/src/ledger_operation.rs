@@ -1,17 +1,19 @@
 use super::{
-    BalanceOf, ChannelMap, ChannelStatusNums, Error, 
-    Module as CelerPayModule, RawEvent
+    BalanceOf, ChannelClearSafeMargins, ChannelClosedBlocks, ChannelMap, ChannelStatusNums, ClearedPayIds, Error, 
+    Module as CelerPayModule, PrunedChannels, RawEvent, SettleResetNums
 };
 use crate::traits::Trait;
 use crate::celer_wallet::CelerWallet;
+use crate::custody::Custody;
 use crate::pay_registry::PayRegistry;
 use crate::pay_resolver::{AccountAmtPair, TokenInfo, TokenTransfer, TokenType};
 use crate::pool::Pool;
+use crate::storage_deposit::StorageDeposit;
+use crate::finalizer::Finalizer;
 use codec::{Decode, Encode};
-use frame_support::traits::Currency;
-use frame_support::{ensure, storage::StorageMap};
+use frame_support::{ensure, storage::{StorageDoubleMap, StorageMap}, traits::Get};
 use frame_system::{self as system, ensure_signed};
-use sp_runtime::traits::{CheckedAdd, CheckedSub, Hash, Zero};
+use sp_runtime::traits::{CheckedAdd, CheckedSub, Hash, One, Saturating, Zero};
 use sp_runtime::{ModuleId, RuntimeDebug, DispatchError};
 use sp_std::{vec, vec::Vec};
 use celer_pay_module_rpc_runtime_api::BalanceInfo;
@@ -25,23 +27,31 @@ pub enum ChannelStatus {
 }
 
 #[derive(Clone, Eq, PartialEq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
-pub struct PeerState<Hash, BlockNumber, Balance> {
+pub struct PeerState<AccountId, Hash, BlockNumber, Balance> {
     pub seq_num: u128,
     pub transfer_out: Balance,
     pub next_pay_id_list_hash: Option<Hash>,
     pub last_pay_resolve_deadline: BlockNumber,
     pub pending_pay_out: Balance,
+    // cumulative amount transferred to each receiver, transfer_out is the total of them
+    pub transfer_outs: Vec<(AccountId, Balance)>,
+    // Merkle root of pending pay ids, set instead of next_pay_id_list_hash by a Merkle-root state
+    pub pending_pay_root: Option<Hash>,
 }
 
-pub type PeerStateOf<T> =
-    PeerState<<T as system::Trait>::Hash, <T as system::Trait>::BlockNumber, BalanceOf<T>>;
+pub type PeerStateOf<T> = PeerState<
+    <T as system::Trait>::AccountId,
+    <T as system::Trait>::Hash,
+    <T as system::Trait>::BlockNumber,
+    BalanceOf<T>,
+>;
 
 #[derive(Clone, Eq, PartialEq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
 pub struct PeerProfile<AccountId, Balance, BlockNumber, Hash> {
     pub peer_addr: AccountId,
     pub deposit: Balance,
     pub withdrawal: Option<Balance>,
-    pub state: PeerState<Hash, BlockNumber, Balance>,
+    pub state: PeerState<AccountId, Hash, BlockNumber, Balance>,
 }
 
 pub type PeerProfileOf<T> = PeerProfile<
@@ -101,6 +111,7 @@ pub struct PaymentChannelInitializer<AccountId, BlockNumber, Balance> {
     pub open_deadline: BlockNumber,
     pub dispute_timeout: BlockNumber,
     pub msg_value_receiver: u8,
+    pub clear_safe_margin: Option<BlockNumber>, // overrides ClearPaysSafeMargin of the runtime if set
 }
 
 pub type PaymentChannelInitializerOf<T> = PaymentChannelInitializer<
@@ -128,6 +139,13 @@ pub struct PayIdList<Hash> {
     pub next_list_hash: Option<Hash>,
 }
 
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
+pub struct PayIdProof<Hash, AccountId> {
+    pub pay_id: Hash,
+    pub receiver: Option<AccountId>, // receiver of the pay, set only in channels of more than two peers
+    pub proof: Vec<Hash>, // sibling hashes from the leaf of pay_id up to the pending pay root
+}
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
 pub struct SimplexPaymentChannel<Hash, AccountId, BlockNumber, Balance> {
     pub channel_id: Hash,
@@ -137,6 +155,9 @@ pub struct SimplexPaymentChannel<Hash, AccountId, BlockNumber, Balance> {
     pub pending_pay_ids: Option<PayIdList<Hash>>,
     pub last_pay_resolve_deadline: Option<BlockNumber>,
     pub total_pending_amount: Option<Balance>,
+    pub pending_pay_root: Option<Hash>, // Merkle root of pending pay ids, used instead of pending_pay_ids
+    // cumulative amount transferred to each receiver, required in channels of more than two peers
+    pub transfer_to_peers: Option<Vec<AccountAmtPair<AccountId, Balance>>>,
 }
 
 pub type SimplexPaymentChannelOf<T> = SimplexPaymentChannel<
@@ -313,15 +334,16 @@ impl<T: Trait> LedgerOperation<T> {
         msg_value: BalanceOf<T>,
     ) -> Result<T::Hash, DispatchError> {
         let caller = ensure_signed(origin.clone())?;
+        let channel_initializer = open_request.channel_initializer;
+        let token = channel_initializer.init_distribution.token.clone();
         ensure!(
-            <T as Trait>::Currency::free_balance(&caller) >= msg_value,
+            CelerPayModule::<T>::token_free_balance(&token.token_type, &caller)? >= msg_value,
             "caller does not have enough balances."
         );
 
-        let channel_initializer = open_request.channel_initializer;
-
+        let peer_num = channel_initializer.init_distribution.distribution.len();
         ensure!(
-            channel_initializer.init_distribution.distribution.len() == 2,
+            peer_num >= 2 && peer_num <= T::MaxChannelPeers::get() as usize,
             "Wrong length",
         );
 
@@ -330,41 +352,54 @@ impl<T: Trait> LedgerOperation<T> {
             "Open deadline passed"
         );
 
-        let token = channel_initializer.init_distribution.token.clone();
-        let amounts: Vec<BalanceOf<T>> = vec![
-            channel_initializer.init_distribution.distribution[0].amt.clone(),
-            channel_initializer.init_distribution.distribution[1].amt.clone(),
-        ];
+        let amounts: Vec<BalanceOf<T>> = channel_initializer.init_distribution.distribution
+            .iter()
+            .map(|pair| pair.amt.clone())
+            .collect();
 
         let mut peer_addrs: Vec<T::AccountId> = vec![];
-        match channel_initializer.init_distribution.distribution[0].account.clone() {
-            Some(account) => peer_addrs.push(account),
-            None => return Err(Error::<T>::PeerNotExist)?,
-        };
-        match channel_initializer.init_distribution.distribution[1].account.clone() {
-            Some(account) => peer_addrs.push(account),
-            None => return Err(Error::<T>::PeerNotExist)?,
-        };
+        for pair in channel_initializer.init_distribution.distribution.iter() {
+            match pair.account.clone() {
+                Some(account) => peer_addrs.push(account),
+                None => return Err(Error::<T>::PeerNotExist)?,
+            };
+        }
 
         // Enforce asceding order of peer's addresses to simplyfy contract code
-        ensure!(
-            peer_addrs[0] < peer_addrs[1],
-            "Peer addrs are not ascending"
-        );
+        for i in 1..peer_num {
+            ensure!(
+                peer_addrs[i - 1] < peer_addrs[i],
+                "Peer addrs are not ascending"
+            );
+        }
 
         let encoded = encode_channel_initializer::<T>(channel_initializer.clone());
         CelerPayModule::<T>::valid_signers(open_request.sigs, &encoded, peer_addrs.clone())?;
 
-        let celer_ledger_account = CelerPayModule::<T>::get_celer_ledger_id();
+        // A pruned channel can not be opened again by replaying its OpenChannelRequest
         let h = T::Hashing::hash(&encoded);
+        let wallet_id = CelerWallet::<T>::wallet_id(peer_addrs.clone(), h);
+        ensure!(!PrunedChannels::<T>::get(wallet_id), "Channel is pruned");
+
+        // Reserve the storage deposit of the channel and its wallet from the caller,
+        // out of which the sub-account of the wallet is endowed
+        let endowment = Custody::<T>::wallet_endowment(&token.token_type, &wallet_id);
+        let channel_deposit = StorageDeposit::<T>::reserve_channel_deposit(&caller, endowment)?;
+
+        let celer_ledger_account = CelerPayModule::<T>::get_celer_ledger_id();
         let channel_id = CelerWallet::<T>::create_wallet(
             frame_system::RawOrigin::Signed(celer_ledger_account).into(),
             peer_addrs.clone(),
             h
         )?;
+        Custody::<T>::endow_wallet(channel_id, &caller, endowment)?;
+        StorageDeposit::<T>::set_channel_deposit(channel_id, caller.clone(), channel_deposit);
+        if let Some(clear_safe_margin) = channel_initializer.clear_safe_margin {
+            ChannelClearSafeMargins::<T>::insert(channel_id, clear_safe_margin);
+        }
 
         let mut peer_profiles: Vec<PeerProfileOf<T>> = vec![];
-        for i in 0..2 {
+        for i in 0..peer_num {
             peer_profiles.push(PeerProfileOf::<T> {
                 peer_addr: peer_addrs[i].clone(),
                 deposit: amounts[i],
@@ -385,7 +420,10 @@ impl<T: Trait> LedgerOperation<T> {
             withdraw_intent: default_withdraw_intent::<T>(),
         };
 
-        let amt_sum: BalanceOf<T> = amounts[0].checked_add(&amounts[1]).ok_or(Error::<T>::OverFlow)?;
+        let mut amt_sum: BalanceOf<T> = Zero::zero();
+        for amount in amounts.iter() {
+            amt_sum = amt_sum.checked_add(amount).ok_or(Error::<T>::OverFlow)?;
+        }
         // if total deposit is 0
         if amt_sum.is_zero() {
             ensure!(msg_value.is_zero(), "msg_value is not 0");
@@ -395,8 +433,8 @@ impl<T: Trait> LedgerOperation<T> {
             // Emit OpenChannel event
             CelerPayModule::<T>::deposit_event(RawEvent::OpenChannel(
                 channel_id,
-                vec![channel.peer_profiles[0].peer_addr.clone(), channel.peer_profiles[1].peer_addr.clone()],
-                vec![Zero::zero(), Zero::zero()]
+                peer_addrs,
+                vec![Zero::zero(); peer_num]
             ));
             return Ok(channel_id);
         }
@@ -423,26 +461,26 @@ impl<T: Trait> LedgerOperation<T> {
             ));
         }
 
-        if token.token_type == TokenType::Celer {
-            let msg_value_receiver = channel_initializer.msg_value_receiver as usize;
-            ensure!(msg_value == amounts[msg_value_receiver], "amount mismatch");
-            if amounts[msg_value_receiver] > Zero::zero() {
-                CelerWallet::<T>::deposit_native_token(caller, channel_id, msg_value)?;
-            }
+        let msg_value_receiver = channel_initializer.msg_value_receiver as usize;
+        ensure!(msg_value_receiver < peer_num, "Invalid msg_value_receiver");
+        ensure!(msg_value == amounts[msg_value_receiver], "amount mismatch");
+        if amounts[msg_value_receiver] > Zero::zero() {
+            CelerWallet::<T>::deposit_token(caller, channel_id, token.token_type.clone(), msg_value)?;
+        }
 
-            // peer ID of non-msg_value_receiver
-            let pid: usize = 1 - msg_value_receiver;
-            if amounts[pid] > Zero::zero() {
-                let celer_ledger_account = CelerPayModule::<T>::get_celer_ledger_id();
-                Pool::<T>::transfer_to_celer_wallet_by_ledger(
-                    frame_system::RawOrigin::Signed(celer_ledger_account).into(),
-                    peer_addrs[pid].clone(),
-                    channel_id,
-                    amounts[pid],
-                )?;
+        // peer IDs of non-msg_value_receiver
+        for pid in 0..peer_num {
+            if pid == msg_value_receiver || amounts[pid].is_zero() {
+                continue;
             }
-        } else {
-            Err(Error::<T>::Error)?
+            let celer_ledger_account = CelerPayModule::<T>::get_celer_ledger_id();
+            Pool::<T>::transfer_to_celer_wallet_by_ledger(
+                frame_system::RawOrigin::Signed(celer_ledger_account).into(),
+                peer_addrs[pid].clone(),
+                channel_id,
+                token.token_type.clone(),
+                amounts[pid],
+            )?;
         }
 
         ChannelMap::<T>::insert(channel_id, channel.clone());
@@ -450,13 +488,13 @@ impl<T: Trait> LedgerOperation<T> {
         // Emit OpenChannel event
         CelerPayModule::<T>::deposit_event(RawEvent::OpenChannel(
             channel_id,
-            vec![channel.peer_profiles[0].peer_addr.clone(), channel.peer_profiles[1].peer_addr.clone()],
-            vec![channel.peer_profiles[0].deposit, channel.peer_profiles[1].deposit]
+            peer_addrs,
+            amounts
         ));
         return Ok(channel_id);
     }
 
-    // Deposit native token into the channel
+    // Deposit funds of the channel's token into the channel
     pub fn deposit(
         origin: T::Origin,
         channel_id: T::Hash,
@@ -470,27 +508,24 @@ impl<T: Trait> LedgerOperation<T> {
             None => Err(Error::<T>::ChannelNotExist)?,
         };
         ensure!(
-            <T as Trait>::Currency::free_balance(&caller) >= msg_value,
+            CelerPayModule::<T>::token_free_balance(&c.token.token_type, &caller)? >= msg_value,
             "caller does not have enough balances."
         );
         let deposit_amount: BalanceOf<T> = msg_value.checked_add(&transfer_from_amount).ok_or(Error::<T>::OverFlow)?;
         add_deposit::<T>(channel_id, receiver.clone(), deposit_amount)?;
 
-        if c.token.token_type == TokenType::Celer {
-            if msg_value > Zero::zero() {
-                CelerWallet::<T>::deposit_native_token(caller.clone(), channel_id, msg_value)?;
-            }
-            let celer_ledger_account = CelerPayModule::<T>::get_celer_ledger_id();
-            if transfer_from_amount > Zero::zero() {
-                Pool::<T>::transfer_to_celer_wallet_by_ledger(
-                    frame_system::RawOrigin::Signed(celer_ledger_account).into(),
-                    caller,
-                    channel_id,
-                    transfer_from_amount,
-                )?;
-            }
-        } else {
-            Err(Error::<T>::Error)?
+        if msg_value > Zero::zero() {
+            CelerWallet::<T>::deposit_token(caller.clone(), channel_id, c.token.token_type.clone(), msg_value)?;
+        }
+        let celer_ledger_account = CelerPayModule::<T>::get_celer_ledger_id();
+        if transfer_from_amount > Zero::zero() {
+            Pool::<T>::transfer_to_celer_wallet_by_ledger(
+                frame_system::RawOrigin::Signed(celer_ledger_account).into(),
+                caller,
+                channel_id,
+                c.token.token_type.clone(),
+                transfer_from_amount,
+            )?;
         }
 
         Ok(())
@@ -517,24 +552,28 @@ impl<T: Trait> LedgerOperation<T> {
             // Check Co-Signatures.
             let encoded = encode_signed_simplex_state_array::<T>(simplex_state.clone());
             let sigs = signed_simplex_state_array.signed_simplex_states[i].sigs.clone();
-            let channel_peer = vec![
-                c.peer_profiles[0].peer_addr.clone(),
-                c.peer_profiles[1].peer_addr.clone(),
-            ];
-            CelerPayModule::<T>::valid_signers(sigs, &encoded, channel_peer)?;
+            CelerPayModule::<T>::valid_signers(sigs, &encoded, get_peer_addrs::<T>(&c))?;
+            ensure!(
+                simplex_state.transfer_to_peer.clone().unwrap().token.token_type == c.token.token_type,
+                "Token mismatch of channel"
+            );
 
             let pid = get_peer_id::<T>(c.clone(), simplex_state.peer_from.clone().unwrap())?;
             let state = c.peer_profiles[pid].clone().state;
 
             ensure!(simplex_state.seq_num > c.peer_profiles[pid].state.seq_num, "seq_num error");
 
+            let (transfer_out, transfer_outs) = get_transfer_outs::<T>(&c, pid, &simplex_state)?;
+
             // No need to update nextPayIdListHash and lastPayResolveDeadline for snapshot purpose
             let new_state = PeerStateOf::<T> {
                 seq_num: simplex_state.seq_num,
-                transfer_out: simplex_state.transfer_to_peer.clone().unwrap().receiver.amt,
+                transfer_out: transfer_out,
                 next_pay_id_list_hash: state.next_pay_id_list_hash,
                 last_pay_resolve_deadline: state.last_pay_resolve_deadline,
                 pending_pay_out: simplex_state.total_pending_amount.unwrap_or(Zero::zero()),
+                transfer_outs: transfer_outs,
+                pending_pay_root: state.pending_pay_root,
             };
             c.peer_profiles[pid].state = new_state;
             ChannelMap::<T>::mutate(&current_channel_id, |channel| {*channel = Some(c)});
@@ -595,14 +634,17 @@ impl<T: Trait> LedgerOperation<T> {
             "Receirver is not channel peer."
         );
 
+        let request_time = frame_system::Module::<T>::block_number();
+        let dispute_timeout = request_time.checked_add(&c.dispute_timeout).ok_or(Error::<T>::OverFlow)?;
         let new_withdraw_intent = WithdrawIntentOf::<T> {
             receiver: receiver.clone(),
             amount: Some(amount),
-            request_time: Some(frame_system::Module::<T>::block_number()),
+            request_time: Some(request_time),
             recipient_channel_id: Some(recipient_channel_id),
         };
         c.withdraw_intent = new_withdraw_intent;
         ChannelMap::<T>::mutate(&channel_id, |channel| *channel = Some(c));
+        Finalizer::<T>::schedule_withdraw(channel_id, dispute_timeout);
 
         // Emit IntendWithdraw event
         CelerPayModule::<T>::deposit_event(RawEvent::IntendWithdraw(
@@ -640,20 +682,11 @@ impl<T: Trait> LedgerOperation<T> {
         let rid = get_peer_id::<T>(c.clone(), receiver.clone())?;
 
         // check withdraw limit
-        let mut withdraw_limit: BalanceOf<T> = Zero::zero();
-        if rid == 0 {
-            withdraw_limit = withdraw_limit.checked_add(&c.peer_profiles[0].deposit).ok_or(Error::<T>::OverFlow)?;
-            withdraw_limit = withdraw_limit.checked_add(&c.peer_profiles[1].state.transfer_out).ok_or(Error::<T>::OverFlow)?;
-            withdraw_limit = withdraw_limit.checked_sub(&c.peer_profiles[0].clone().withdrawal.unwrap_or(Zero::zero())).ok_or(Error::<T>::UnderFlow)?;
-            withdraw_limit = withdraw_limit.checked_sub(&c.peer_profiles[0].state.transfer_out).ok_or(Error::<T>::UnderFlow)?;
-            withdraw_limit = withdraw_limit.checked_sub(&c.peer_profiles[0].state.pending_pay_out).ok_or(Error::<T>::UnderFlow)?;
-        } else {
-            withdraw_limit = withdraw_limit.checked_add(&c.peer_profiles[1].deposit).ok_or(Error::<T>::OverFlow)?;
-            withdraw_limit = withdraw_limit.checked_add(&c.peer_profiles[0].state.transfer_out).ok_or(Error::<T>::OverFlow)?;
-            withdraw_limit = withdraw_limit.checked_sub(&c.peer_profiles[1].clone().withdrawal.unwrap_or(Zero::zero())).ok_or(Error::<T>::UnderFlow)?;
-            withdraw_limit = withdraw_limit.checked_sub(&c.peer_profiles[1].state.transfer_out).ok_or(Error::<T>::UnderFlow)?;
-            withdraw_limit = withdraw_limit.checked_sub(&c.peer_profiles[1].state.pending_pay_out).ok_or(Error::<T>::UnderFlow)?;
-        }
+        let mut withdraw_limit: BalanceOf<T> = c.peer_profiles[rid].deposit;
+        withdraw_limit = withdraw_limit.checked_add(&get_transfer_in::<T>(&c, rid)?).ok_or(Error::<T>::OverFlow)?;
+        withdraw_limit = withdraw_limit.checked_sub(&c.peer_profiles[rid].clone().withdrawal.unwrap_or(Zero::zero())).ok_or(Error::<T>::UnderFlow)?;
+        withdraw_limit = withdraw_limit.checked_sub(&c.peer_profiles[rid].state.transfer_out).ok_or(Error::<T>::UnderFlow)?;
+        withdraw_limit = withdraw_limit.checked_sub(&c.peer_profiles[rid].state.pending_pay_out).ok_or(Error::<T>::UnderFlow)?;
         ensure!(amount <= withdraw_limit, "Exceed withdraw limit");
 
         // Update record of one peer's withdrawal amount
@@ -682,8 +715,8 @@ impl<T: Trait> LedgerOperation<T> {
             amount,
             receiver.clone(),
             recipient_channel_id,
-            vec![deposits[0].amount, deposits[1].amount],
-            vec![withdrawals[0].amount, withdrawals[1].amount]
+            deposits.into_iter().map(|deposit| deposit.amount).collect(),
+            withdrawals.into_iter().map(|withdrawal| withdrawal.amount).collect()
         ));
         return Ok((amount, receiver, recipient_channel_id));
     }
@@ -730,11 +763,7 @@ impl<T: Trait> LedgerOperation<T> {
 
         // check signatures.
         let encoded = encode_withdraw_info::<T>(withdraw_info.clone());
-        let signers = vec![
-            c.peer_profiles[0].peer_addr.clone(),
-            c.peer_profiles[1].peer_addr.clone(),
-        ];
-        CelerPayModule::<T>::valid_signers(cooperative_withdraw_request.sigs, &encoded, signers)?;
+        CelerPayModule::<T>::valid_signers(cooperative_withdraw_request.sigs, &encoded, get_peer_addrs::<T>(&c))?;
 
         // require an increment of exactly 1 for seq_num of each cooperative withdraw request
         let cal_seq = withdraw_info.seq_num
@@ -772,8 +801,8 @@ impl<T: Trait> LedgerOperation<T> {
             amount,
             receiver.clone(),
             recipient_channel_id,
-            vec![deposits[0].amount, deposits[1].amount],
-            vec![withdrawals[0].amount, withdrawals[1].amount],
+            deposits.into_iter().map(|deposit| deposit.amount).collect(),
+            withdrawals.into_iter().map(|withdrawal| withdrawal.amount).collect(),
             withdraw_info.seq_num
         ));
 
@@ -828,11 +857,11 @@ impl<T: Trait> LedgerOperation<T> {
                 // Check signatures
                 let encoded = encode_signed_simplex_state_array::<T>(simplex_state.clone());
                 let sigs = signed_simplex_state_array.signed_simplex_states[i].sigs.clone();
-                let channel_peer = vec![
-                    c.peer_profiles[0].peer_addr.clone(),
-                    c.peer_profiles[1].peer_addr.clone(),
-                ];
-                CelerPayModule::<T>::valid_signers(sigs, &encoded, channel_peer)?;
+                CelerPayModule::<T>::valid_signers(sigs, &encoded, get_peer_addrs::<T>(&c))?;
+                ensure!(
+                    simplex_state.transfer_to_peer.clone().unwrap().token.token_type == c.token.token_type,
+                    "Token mismatch of channel"
+                );
 
                 let pid = get_peer_id::<T>(c.clone(), simplex_state.peer_from.clone().unwrap())?;
                 let state = c.peer_profiles[pid].clone().state;
@@ -846,38 +875,63 @@ impl<T: Trait> LedgerOperation<T> {
                     Err(Error::<T>::Error)?
                 }
 
-                let zero_hash = CelerPayModule::<T>::get_zero_hash();
-                let next_pay_id_list_hash = simplex_state.pending_pay_ids.clone().unwrap().next_list_hash.unwrap_or(zero_hash);
-                
-                let new_state: PeerStateOf<T>;
-                if next_pay_id_list_hash == zero_hash {
-                    // Update simplex_state-dependent fields
-                    new_state = PeerStateOf::<T> {
+                let (transfer_out, transfer_outs) = get_transfer_outs::<T>(&c, pid, &simplex_state)?;
+
+                if simplex_state.pending_pay_root.is_some() {
+                    // pending pays are cleared later by clear_pays_by_proofs in any order
+                    let new_state = PeerStateOf::<T> {
                         seq_num: simplex_state.seq_num,
-                        transfer_out: simplex_state.transfer_to_peer.clone().unwrap().receiver.amt.clone(),
+                        transfer_out: transfer_out,
                         next_pay_id_list_hash: None,
                         last_pay_resolve_deadline: simplex_state.last_pay_resolve_deadline.unwrap_or(Zero::zero()).clone(),
-                        pending_pay_out: state.pending_pay_out,
-                    };
-                } else {
-                    // Update simplex_state-dependent fields
-                    new_state = PeerStateOf::<T> {
-                        seq_num: simplex_state.seq_num,
-                        transfer_out: simplex_state.transfer_to_peer.clone().unwrap().receiver.amt.clone(),
-                        next_pay_id_list_hash: Some(next_pay_id_list_hash),
-                        last_pay_resolve_deadline: simplex_state.last_pay_resolve_deadline.unwrap_or(Zero::zero()).clone(),
                         pending_pay_out: simplex_state.total_pending_amount.clone().unwrap_or(Zero::zero()),
+                        transfer_outs: transfer_outs,
+                        pending_pay_root: simplex_state.pending_pay_root,
                     };
+                    c.peer_profiles[pid].state = new_state;
+                    ChannelMap::<T>::mutate(&current_channel_id, |channel| *channel = Some(c));
+                } else {
+                    let zero_hash = CelerPayModule::<T>::get_zero_hash();
+                    let next_pay_id_list_hash = simplex_state.pending_pay_ids.clone().unwrap().next_list_hash.unwrap_or(zero_hash);
+
+                    let new_state: PeerStateOf<T>;
+                    if next_pay_id_list_hash == zero_hash {
+                        // Update simplex_state-dependent fields
+                        new_state = PeerStateOf::<T> {
+                            seq_num: simplex_state.seq_num,
+                            transfer_out: transfer_out,
+                            next_pay_id_list_hash: None,
+                            last_pay_resolve_deadline: simplex_state.last_pay_resolve_deadline.unwrap_or(Zero::zero()).clone(),
+                            pending_pay_out: state.pending_pay_out,
+                            transfer_outs: transfer_outs,
+                            pending_pay_root: None,
+                        };
+                    } else {
+                        // Update simplex_state-dependent fields
+                        new_state = PeerStateOf::<T> {
+                            seq_num: simplex_state.seq_num,
+                            transfer_out: transfer_out,
+                            next_pay_id_list_hash: Some(next_pay_id_list_hash),
+                            last_pay_resolve_deadline: simplex_state.last_pay_resolve_deadline.unwrap_or(Zero::zero()).clone(),
+                            pending_pay_out: simplex_state.total_pending_amount.clone().unwrap_or(Zero::zero()),
+                            transfer_outs: transfer_outs,
+                            pending_pay_root: None,
+                        };
+                    }
+
+                    c.peer_profiles[pid].state = new_state;
+
+                    let pay_id_list = simplex_state.pending_pay_ids.clone().unwrap();
+                    let is_last_list = pay_id_list.next_list_hash.unwrap_or(zero_hash) == zero_hash;
+                    let pays = get_listed_pays::<T>(&c, pid, pay_id_list.pay_ids)?;
+                    _clear_pays::<T>(
+                        c,
+                        current_channel_id,
+                        pid,
+                        pays,
+                        is_last_list,
+                    )?;
                 }
-
-                c.peer_profiles[pid].state = new_state;
-            
-                _clear_pays::<T>(
-                    c,
-                    current_channel_id,
-                    pid,
-                    simplex_state.pending_pay_ids.clone().unwrap(),
-                )?;
             } else if simplex_state.seq_num == 0 {
                 // null state
                 // Check signautre
@@ -885,7 +939,16 @@ impl<T: Trait> LedgerOperation<T> {
                     simplex_state.clone()
                 );
                 let sigs = signed_simplex_state_array.signed_simplex_states[i].sigs.clone();
-                CelerPayModule::<T>::check_single_signature(sigs[0].clone(),&encoded,c.peer_profiles[0].peer_addr.clone())?;
+                ensure!(
+                    c.peer_profiles.iter().any(|peer_profile| {
+                        CelerPayModule::<T>::check_single_signature(
+                            sigs[0].clone(),
+                            &encoded,
+                            peer_profile.peer_addr.clone()
+                        ).is_ok()
+                    }),
+                    "Check sig failed"
+                );
                 
                 let zero_blocknumber = Zero::zero();
                 // This implies both stored seq_nums are 0
@@ -947,7 +1010,62 @@ impl<T: Trait> LedgerOperation<T> {
         
         // Update next_pay_id_list_hash
         c.peer_profiles[pid].state.next_pay_id_list_hash = pay_id_list.next_list_hash;
-        _clear_pays::<T>(c, channel_id, pid, pay_id_list)?;
+        let is_last_list = pay_id_list.next_list_hash.unwrap_or(zero_hash) == zero_hash;
+        let pays = get_listed_pays::<T>(&c, pid, pay_id_list.pay_ids)?;
+        _clear_pays::<T>(c, channel_id, pid, pays, is_last_list)?;
+
+        Ok(())
+    }
+
+    // Read payment results of any subset of pays committed by the pending pay root of
+    // the intended settle state, and add results to correspond simplex payment channel.
+    // Pays already cleared by another batch are skipped.
+    pub fn clear_pays_by_proofs(
+        channel_id: T::Hash,
+        peer_from: T::AccountId,
+        pay_id_proofs: Vec<PayIdProof<T::Hash, T::AccountId>>,
+    ) -> Result<(), DispatchError> {
+        let c = match ChannelMap::<T>::get(&channel_id) {
+            Some(_channel) => _channel,
+            None => Err(Error::<T>::ChannelNotExist)?,
+        };
+        ensure!(c.status == ChannelStatus::Settling, "Channel status error");
+
+        let pid = get_peer_id::<T>(c.clone(), peer_from.clone())?;
+        let pending_pay_root = match c.peer_profiles[pid].state.pending_pay_root {
+            Some(root) => root,
+            None => Err(Error::<T>::PendingPayRootNotExist)?,
+        };
+
+        // pays cleared for former states of the peer are not cleared for this state
+        let cleared_key = (
+            channel_id,
+            peer_from,
+            SettleResetNums::<T>::get(&channel_id),
+            c.peer_profiles[pid].state.seq_num
+        );
+        let mut pays: Vec<(T::Hash, T::AccountId)> = vec![];
+        for pay_id_proof in pay_id_proofs.iter() {
+            ensure!(
+                verify_pay_id_proof::<T>(
+                    &pending_pay_root,
+                    &pay_id_proof.pay_id,
+                    &pay_id_proof.receiver,
+                    &pay_id_proof.proof
+                ),
+                "Invalid pay id proof"
+            );
+            let receiver = get_pay_receiver::<T>(&c, pid, &pay_id_proof.receiver)?;
+            if ClearedPayIds::<T>::get(&cleared_key, &pay_id_proof.pay_id) == false
+                && !pays.iter().any(|(pay_id, _)| *pay_id == pay_id_proof.pay_id) {
+                pays.push((pay_id_proof.pay_id, receiver));
+            }
+        }
+
+        for (pay_id, _) in pays.iter() {
+            ClearedPayIds::<T>::insert(&cleared_key, pay_id, true);
+        }
+        _clear_pays::<T>(c, channel_id, pid, pays, false)?;
 
         Ok(())
     }
@@ -971,23 +1089,21 @@ impl<T: Trait> LedgerOperation<T> {
         );
 
         // require channel status of current intend_settle has been finalized,
-        // namely all payment have already been either cleared or expired
-        // Note: this last PayResolveDeadline should use
-        //      (the actual last resolve deadline of all pays + clear_pays safe margin)
-        //      to ensure that peers have enough time to clear_pays before confirm_settle.
-        //      However this only matters if there are multiple blocks of pending pay list
-        //      i.e. the next_pay_id_list_hash after intend_settle is not zero_hash (defined in get_zero_hash()).
-        // TODO: add an additonal clear_safe_margin param or change the semantics of
-        // last_pay_resolve_deadline to also include clear_pays safe margin and rename it.
-        let state_0 = c.peer_profiles[0].state.clone();
-        let state_1 = c.peer_profiles[1].state.clone();
+        // namely all payment have already been either cleared or expired.
+        // Pending pays are expired only after (last_pay_resolve_deadline + clear_pays safe margin)
+        // to ensure that peers have enough time to clear_pays before confirm_settle.
+        // This only matters if there are multiple blocks of pending pay list
+        // i.e. the next_pay_id_list_hash after intend_settle is not zero_hash (defined in get_zero_hash()).
+        // A Merkle-root state never records that all of its pays are cleared,
+        // so it is finalized only after the margin as well.
         let zero_hash = CelerPayModule::<T>::get_zero_hash();
-
+        let clear_safe_margin = CelerPayModule::<T>::get_clear_safe_margin(channel_id);
         ensure!(
-            (state_0.next_pay_id_list_hash.unwrap_or(zero_hash) == zero_hash
-                || block_number > state_0.last_pay_resolve_deadline)
-                && (state_1.next_pay_id_list_hash.unwrap_or(zero_hash) == zero_hash
-                    || block_number > state_1.last_pay_resolve_deadline),
+            c.peer_profiles.iter().all(|peer_profile| {
+                (peer_profile.state.next_pay_id_list_hash.unwrap_or(zero_hash) == zero_hash
+                    && peer_profile.state.pending_pay_root.is_none())
+                    || block_number > peer_profile.state.last_pay_resolve_deadline.saturating_add(clear_safe_margin)
+            }),
             "Payments are not finalized"
         );
 
@@ -1000,19 +1116,18 @@ impl<T: Trait> LedgerOperation<T> {
             Err(Error::<T>::ConfirmSettleFail)?
         }
 
-        update_channel_status::<T>(c.clone(), channel_id, ChannelStatus::Closed)?;
-
         // Withdrawal from Contracts pattern is needles here,
         // because peers need sign messages which implies that they cannot be contracts
         batch_transfer_out::<T>(
             channel_id,
-            vec![
-                c.peer_profiles[0].peer_addr.clone(),
-                c.peer_profiles[1].peer_addr.clone(),
-            ],
+            c.token.token_type.clone(),
+            get_peer_addrs::<T>(&c),
             settle_balance.clone(),
         )?;
 
+        // The channel is closed only after its funds are transferred out
+        update_channel_status::<T>(c.clone(), channel_id, ChannelStatus::Closed)?;
+
         // Emit ConfirmSettle event
         CelerPayModule::<T>::deposit_event(RawEvent::ConfirmSettle(
             channel_id,
@@ -1038,47 +1153,52 @@ impl<T: Trait> LedgerOperation<T> {
 
         // Check whether cooperative settle info contains all data
         check_settle_info::<T>(settle_info.clone())?;
-        // Check co-signature
-        let encoded = encode_settle_info::<T>(settle_info.clone());
-        let signers = vec![
-            settle_info.settle_balance[0].account.clone().unwrap(),
-            settle_info.settle_balance[1].account.clone().unwrap(),
-        ];
-        CelerPayModule::<T>::valid_signers(settle_request.sigs, &encoded, signers)?;
-
-        let state_0 = c.peer_profiles[0].state.clone();
-        let state_1 = c.peer_profiles[1].state.clone();
-
+        // Check the settle accounts before verifying any signature
         ensure!(
-            settle_info.seq_num > state_0.seq_num && settle_info.seq_num > state_1.seq_num,
-            "seq_num error"
+            settle_info.settle_balance.len() == c.peer_profiles.len(),
+            "Settle accounts mismatch"
         );
+        let signers: Vec<T::AccountId> = settle_info.settle_balance
+            .iter()
+            .map(|pair| pair.account.clone().unwrap())
+            .collect();
         ensure!(
-            settle_info.settle_balance[0].clone().account.unwrap() == c.peer_profiles[0].peer_addr
-                && settle_info.settle_balance[1].clone().account.unwrap() == c.peer_profiles[1].peer_addr,
+            signers == get_peer_addrs::<T>(&c),
             "Settle accounts mismatch"
         );
+        ensure!(
+            c.peer_profiles.iter().all(|peer_profile| settle_info.seq_num > peer_profile.state.seq_num),
+            "seq_num error"
+        );
+        // Check co-signature
+        let encoded = encode_settle_info::<T>(settle_info.clone());
+        CelerPayModule::<T>::valid_signers(settle_request.sigs, &encoded, signers)?;
 
-        let settle_balance = vec![
-            settle_info.settle_balance[0].amt,
-            settle_info.settle_balance[1].amt,
-        ];
-        let total_settle_balance = settle_balance[0]
-                .checked_add(&settle_balance[1]).ok_or(Error::<T>::OverFlow)?;
+        let settle_balance: Vec<BalanceOf<T>> = settle_info.settle_balance
+            .iter()
+            .map(|pair| pair.amt)
+            .collect();
+        let mut total_settle_balance: BalanceOf<T> = Zero::zero();
+        for amount in settle_balance.iter() {
+            total_settle_balance = total_settle_balance
+                .checked_add(amount).ok_or(Error::<T>::OverFlow)?;
+        }
         let total_balance = get_total_balance::<T>(channel_id)?;
         ensure!(
             total_settle_balance == total_balance,
             "Balance sum mismatch"
         );
 
-        update_channel_status::<T>(c.clone(), channel_id, ChannelStatus::Closed)?;
-
         batch_transfer_out::<T>(
             channel_id, 
-            vec![c.peer_profiles[0].peer_addr.clone(), c.peer_profiles[1].peer_addr.clone()], 
+            c.token.token_type.clone(),
+            get_peer_addrs::<T>(&c), 
             settle_balance.clone()
         )?;
 
+        // The channel is closed only after its funds are transferred out
+        update_channel_status::<T>(c.clone(), channel_id, ChannelStatus::Closed)?;
+
         // Emit CooperativeSettle event
         CelerPayModule::<T>::deposit_event(RawEvent::CooperativeSettle(
             channel_id,
@@ -1087,9 +1207,53 @@ impl<T: Trait> LedgerOperation<T> {
         return Ok((channel_id, settle_balance));
     }
 
+    // Remove a closed channel and its wallet after `ChannelPruneDelay`,
+    // and refund the storage deposit of the channel.
+    // A tombstone of the channel id is kept, since its OpenChannelRequest may be replayed
+    // before the open deadline.
+    pub fn prune_channel(
+        origin: T::Origin,
+        channel_id: T::Hash,
+    ) -> Result<BalanceOf<T>, DispatchError> {
+        let caller = ensure_signed(origin)?;
+        let c = match ChannelMap::<T>::get(&channel_id) {
+            Some(_channel) => _channel,
+            None => Err(Error::<T>::ChannelNotExist)?,
+        };
+        ensure!(c.status == ChannelStatus::Closed, "Channel status error");
+
+        // A channel closed before closed blocks were recorded can be pruned at once
+        let closed_block = ChannelClosedBlocks::<T>::get(&channel_id).unwrap_or(Zero::zero());
+        let prune_block = closed_block.checked_add(&T::ChannelPruneDelay::get())
+            .ok_or(Error::<T>::OverFlow)?;
+        ensure!(
+            frame_system::Module::<T>::block_number() >= prune_block,
+            "Channel prune delay not passed"
+        );
+
+        CelerWallet::<T>::remove_wallet(channel_id)?;
+        ChannelMap::<T>::remove(&channel_id);
+        ChannelClosedBlocks::<T>::remove(&channel_id);
+        ChannelClearSafeMargins::<T>::remove(&channel_id);
+        PrunedChannels::<T>::insert(&channel_id, true);
+        SettleResetNums::<T>::remove(&channel_id);
+        let status_nums = CelerPayModule::<T>::channel_status_nums(ChannelStatus::Closed as u8).unwrap_or(0);
+        ChannelStatusNums::mutate(ChannelStatus::Closed as u8, |num| *num = Some(status_nums.saturating_sub(1)));
+
+        let reward = StorageDeposit::<T>::refund_channel_deposit(&channel_id, &caller);
+
+        // Emit PruneChannel event
+        CelerPayModule::<T>::deposit_event(RawEvent::PruneChannel(
+            channel_id,
+            caller,
+            reward
+        ));
+        return Ok(reward);
+    }
+
     // Check if addr is one of the peers in channel c
     pub fn is_peer(c: ChannelOf<T>, addr: T::AccountId) -> bool {
-        return addr == c.peer_profiles[0].peer_addr || addr == c.peer_profiles[1].peer_addr;
+        return c.peer_profiles.iter().any(|peer_profile| peer_profile.peer_addr == addr);
     }
 }
 
@@ -1099,7 +1263,9 @@ fn default_peer_state<T: Trait>() -> PeerStateOf<T> {
         transfer_out: Zero::zero(),
         next_pay_id_list_hash: None,
         last_pay_resolve_deadline: Zero::zero(),
-        pending_pay_out: Zero::zero()
+        pending_pay_out: Zero::zero(),
+        transfer_outs: vec![],
+        pending_pay_root: None,
     }
 }
 
@@ -1117,15 +1283,120 @@ fn get_peer_id<T: Trait>(
     c: ChannelOf<T>, 
     peer: T::AccountId
 ) -> Result<usize, DispatchError> {
-    if c.peer_profiles[0].peer_addr == peer {
-        Ok(0 as usize)
-    } else if c.peer_profiles[1].peer_addr == peer {
-        Ok(1 as usize)
-    } else {
-        Err(Error::<T>::NotChannelPeer)?
+    match c.peer_profiles.iter().position(|peer_profile| peer_profile.peer_addr == peer) {
+        Some(pid) => Ok(pid),
+        None => Err(Error::<T>::NotChannelPeer)?,
     }
 }
 
+// get addresses of all peers in ascending order
+fn get_peer_addrs<T: Trait>(c: &ChannelOf<T>) -> Vec<T::AccountId> {
+    c.peer_profiles.iter().map(|peer_profile| peer_profile.peer_addr.clone()).collect()
+}
+
+// Return transfer_out and the cumulative amount transferred to each receiver by a simplex state of peer pid.
+// In a two-peer channel the state transfers to the counterparty, whose account may be omitted.
+// In a channel of more than two peers the state lists every receiver in transfer_to_peers and
+// transfer_to_peer carries their total, so that a newer state to one receiver keeps the amounts
+// transferred to the others.
+fn get_transfer_outs<T: Trait>(
+    c: &ChannelOf<T>,
+    pid: usize,
+    simplex_state: &SimplexPaymentChannelOf<T>,
+) -> Result<(BalanceOf<T>, Vec<(T::AccountId, BalanceOf<T>)>), DispatchError> {
+    let transfer_to_peer = simplex_state.transfer_to_peer.clone().unwrap().receiver;
+    if c.peer_profiles.len() == 2 {
+        ensure!(
+            simplex_state.transfer_to_peers.is_none(),
+            Error::<T>::InvalidSignedSimplexStateArray
+        );
+        if let Some(account) = transfer_to_peer.account {
+            ensure!(get_peer_id::<T>(c.clone(), account)? != pid, "Transfer receiver is peer_from");
+        }
+        let counterparty = c.peer_profiles[1 - pid].peer_addr.clone();
+        return Ok((transfer_to_peer.amt, vec![(counterparty, transfer_to_peer.amt)]));
+    }
+
+    let transfer_to_peers = match simplex_state.transfer_to_peers.clone() {
+        Some(transfer_to_peers) => transfer_to_peers,
+        None => Err(DispatchError::Other("Transfer receiver is not specified"))?,
+    };
+    ensure!(transfer_to_peer.account.is_none(), Error::<T>::InvalidSignedSimplexStateArray);
+    // pending pays of a channel with more than two peers are committed by a pending pay root
+    if let Some(pay_id_list) = simplex_state.pending_pay_ids.clone() {
+        ensure!(
+            pay_id_list.pay_ids.is_empty() && pay_id_list.next_list_hash.is_none(),
+            "Pay id list in multi-peer channel"
+        );
+    }
+
+    let mut transfer_out: BalanceOf<T> = Zero::zero();
+    let mut transfer_outs: Vec<(T::AccountId, BalanceOf<T>)> = vec![];
+    for pair in transfer_to_peers.into_iter() {
+        let receiver = get_pay_receiver::<T>(c, pid, &pair.account)?;
+        ensure!(
+            !transfer_outs.iter().any(|(account, _)| *account == receiver),
+            "Duplicate transfer receiver"
+        );
+        transfer_out = transfer_out.checked_add(&pair.amt).ok_or(Error::<T>::OverFlow)?;
+        transfer_outs.push((receiver, pair.amt));
+    }
+    ensure!(transfer_out == transfer_to_peer.amt, "Transfer sum mismatch");
+
+    Ok((transfer_out, transfer_outs))
+}
+
+// Resolve the receiver of a pay or a transfer from peer pid.
+// The receiver is omitted in a two-peer channel and must be specified otherwise.
+fn get_pay_receiver<T: Trait>(
+    c: &ChannelOf<T>,
+    pid: usize,
+    receiver: &Option<T::AccountId>,
+) -> Result<T::AccountId, DispatchError> {
+    if c.peer_profiles.len() == 2 {
+        ensure!(receiver.is_none(), Error::<T>::InvalidSignedSimplexStateArray);
+        return Ok(c.peer_profiles[1 - pid].peer_addr.clone());
+    }
+
+    let receiver = match receiver {
+        Some(account) => account.clone(),
+        None => Err(DispatchError::Other("Transfer receiver is not specified"))?,
+    };
+    ensure!(get_peer_id::<T>(c.clone(), receiver.clone())? != pid, "Transfer receiver is peer_from");
+    Ok(receiver)
+}
+
+// Pair pay ids of a pay id list with their receiver, the counterparty in a two-peer channel
+fn get_listed_pays<T: Trait>(
+    c: &ChannelOf<T>,
+    pid: usize,
+    pay_ids: Vec<T::Hash>,
+) -> Result<Vec<(T::Hash, T::AccountId)>, DispatchError> {
+    if pay_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    ensure!(c.peer_profiles.len() == 2, "Pay id list in multi-peer channel");
+    let receiver = c.peer_profiles[1 - pid].peer_addr.clone();
+    Ok(pay_ids.into_iter().map(|pay_id| (pay_id, receiver.clone())).collect())
+}
+
+// get total amount transferred to peer rid by the other peers
+fn get_transfer_in<T: Trait>(
+    c: &ChannelOf<T>,
+    rid: usize,
+) -> Result<BalanceOf<T>, DispatchError> {
+    let receiver = c.peer_profiles[rid].peer_addr.clone();
+    let mut transfer_in: BalanceOf<T> = Zero::zero();
+    for peer_profile in c.peer_profiles.iter() {
+        for (account, amount) in peer_profile.state.transfer_outs.iter() {
+            if *account == receiver {
+                transfer_in = transfer_in.checked_add(amount).ok_or(Error::<T>::OverFlow)?;
+            }
+        }
+    }
+    Ok(transfer_in)
+}
+
 fn get_total_balance<T: Trait>(
     channel_id: T::Hash
 ) -> Result<BalanceOf<T>, DispatchError> {
@@ -1133,13 +1404,15 @@ fn get_total_balance<T: Trait>(
         Some(_channel) => _channel,
         None => Err(Error::<T>::ChannelNotExist)?,
     };
-    let mut balance: BalanceOf<T> = c.peer_profiles[0].deposit;
-    balance = balance.checked_add(&c.peer_profiles[1].deposit)
-        .ok_or(Error::<T>::OverFlow)?;
-    balance = balance.checked_sub(&c.peer_profiles[0].clone().withdrawal.unwrap_or(Zero::zero()))
-        .ok_or(Error::<T>::UnderFlow)?;
-    balance = balance.checked_sub(&c.peer_profiles[1].clone().withdrawal.unwrap_or(Zero::zero()))
-        .ok_or(Error::<T>::UnderFlow)?;
+    let mut balance: BalanceOf<T> = Zero::zero();
+    for peer_profile in c.peer_profiles.iter() {
+        balance = balance.checked_add(&peer_profile.deposit)
+            .ok_or(Error::<T>::OverFlow)?;
+    }
+    for peer_profile in c.peer_profiles.iter() {
+        balance = balance.checked_sub(&peer_profile.withdrawal.unwrap_or(Zero::zero()))
+            .ok_or(Error::<T>::UnderFlow)?;
+    }
     return Ok(balance);
 }
 
@@ -1174,18 +1447,9 @@ fn add_deposit<T: Trait>(
     // Emit Deposit event
     CelerPayModule::<T>::deposit_event(RawEvent::DepositToChannel(
         channel_id,
-        vec![
-            c.peer_profiles[0].peer_addr.clone(),
-            c.peer_profiles[1].peer_addr.clone(),
-        ],
-        vec![
-            c.peer_profiles[0].deposit,
-            c.peer_profiles[1].deposit,
-        ],
-        vec![
-            c.peer_profiles[0].clone().withdrawal.unwrap_or(Zero::zero()),
-            c.peer_profiles[1].clone().withdrawal.unwrap_or(Zero::zero()),
-        ],
+        get_peer_addrs::<T>(&c),
+        c.peer_profiles.iter().map(|peer_profile| peer_profile.deposit).collect(),
+        c.peer_profiles.iter().map(|peer_profile| peer_profile.withdrawal.unwrap_or(Zero::zero())).collect(),
     ));
 
     Ok(())
@@ -1194,17 +1458,19 @@ fn add_deposit<T: Trait>(
 // Internal function to transfer funds out in batch
 fn batch_transfer_out<T: Trait>(
     channel_id: T::Hash,
+    token_type: TokenType,
     receivers: Vec<T::AccountId>,
     amounts: Vec<BalanceOf<T>>,
 ) -> Result<(), DispatchError> {
     let celer_ledger_account = CelerPayModule::<T>::get_celer_ledger_id();
-    for i in 0..2 {
+    for i in 0..receivers.len() {
         if amounts[i] == Zero::zero() {
             continue;
         }
         CelerWallet::<T>::withdraw(
             frame_system::RawOrigin::Signed(celer_ledger_account.clone()).into(),
             channel_id, 
+            token_type.clone(),
             receivers[i].clone(), 
             amounts[i]
         )?;
@@ -1231,6 +1497,7 @@ fn withdraw_funds<T: Trait>(
         CelerWallet::<T>::withdraw(
             frame_system::RawOrigin::Signed(celer_ledger_account).into(),
             channel_id, 
+            c.token.token_type,
             receiver,
             amount,
         )?;
@@ -1247,6 +1514,7 @@ fn withdraw_funds<T: Trait>(
             frame_system::RawOrigin::Signed(celer_ledger_account).into(),
             channel_id, 
             recipient_channel_id, 
+            c.token.token_type,
             receiver, 
             amount
         )?;
@@ -1263,8 +1531,11 @@ fn reset_duplex_state<T: Trait>(mut c: ChannelOf<T>, channel_id: T::Hash) -> Res
     update_channel_status::<T>(c.clone(), channel_id, ChannelStatus::Operable)?;
     
     // initialize peer_state
-    c.peer_profiles[0].state = default_peer_state::<T>();
-    c.peer_profiles[1].state = default_peer_state::<T>();
+    for peer_profile in c.peer_profiles.iter_mut() {
+        peer_profile.state = default_peer_state::<T>();
+    }
+    // pays cleared for the reset states are not cleared for the states intended later
+    SettleResetNums::<T>::mutate(&channel_id, |num| *num = num.wrapping_add(1));
     
     // reset possibly remaining WithdrawIntent freezed by previous intendSettle()
     c.withdraw_intent = default_withdraw_intent::<T>();
@@ -1274,26 +1545,36 @@ fn reset_duplex_state<T: Trait>(mut c: ChannelOf<T>, channel_id: T::Hash) -> Res
     Ok(())
 }
 
-// Clear payments by their hash array
+// Clear payments by their hash array and receivers, is_last_list is true if there are no more uncleared pays in the state
 fn _clear_pays<T: Trait>(
     mut c: ChannelOf<T>,
     channel_id: T::Hash,
     pid: usize,
-    pay_id_list: PayIdList<T::Hash>,
+    pays: Vec<(T::Hash, T::AccountId)>,
+    is_last_list: bool,
 ) -> Result<(), DispatchError> { 
     let state = c.peer_profiles[pid].state.clone();
     let out_amts = PayRegistry::<T>::get_pay_amounts(
-        pay_id_list.pay_ids.clone(),
+        pays.iter().map(|(pay_id, _)| *pay_id).collect(),
         state.last_pay_resolve_deadline,
     )?;
+    // cleared pays are transferred to their receivers
     let mut total_amt_out: BalanceOf<T> = Zero::zero();
+    let mut new_transfer_outs = state.transfer_outs.clone();
     for i in 0..out_amts.len() {
         total_amt_out = total_amt_out.checked_add(&out_amts[i])
             .ok_or(Error::<T>::OverFlow)?;
+        let receiver = &pays[i].1;
+        match new_transfer_outs.iter_mut().find(|(account, _)| account == receiver) {
+            Some(transfer_out) => {
+                transfer_out.1 = transfer_out.1.checked_add(&out_amts[i]).ok_or(Error::<T>::OverFlow)?;
+            },
+            None => new_transfer_outs.push((receiver.clone(), out_amts[i])),
+        }
         // Emit ClearOnePay event
         CelerPayModule::<T>::deposit_event(RawEvent::ClearOnePay(
             channel_id,
-            pay_id_list.pay_ids[i].clone(),
+            pays[i].0.clone(),
             c.peer_profiles[pid].clone().peer_addr,
             out_amts[i],
         ));
@@ -1302,8 +1583,7 @@ fn _clear_pays<T: Trait>(
     // updating pending_pay_out is only needed when migrating ledger during settling phrase,
     // which will affect the withdraw limit after the migration.
     let new_transfer_out = state.transfer_out.checked_add(&total_amt_out).ok_or(Error::<T>::OverFlow)?;
-    let zero_hash = CelerPayModule::<T>::get_zero_hash();
-    if pay_id_list.next_list_hash.unwrap_or(zero_hash) == zero_hash {
+    if is_last_list == true {
         // if there are not more uncleared pays in this state, the pending_pay_out must be 0
         let new_state = PeerStateOf::<T> {
             seq_num: state.seq_num,
@@ -1311,6 +1591,8 @@ fn _clear_pays<T: Trait>(
             next_pay_id_list_hash: state.next_pay_id_list_hash,
             last_pay_resolve_deadline: state.last_pay_resolve_deadline,
             pending_pay_out: Zero::zero(),
+            transfer_outs: new_transfer_outs,
+            pending_pay_root: state.pending_pay_root,
         };
         c.peer_profiles[pid].state = new_state;
         ChannelMap::<T>::mutate(&channel_id, |channel| *channel = Some(c));
@@ -1327,6 +1609,8 @@ fn _clear_pays<T: Trait>(
             next_pay_id_list_hash: state.next_pay_id_list_hash,
             last_pay_resolve_deadline: state.last_pay_resolve_deadline,
             pending_pay_out: new_pending_pay_out,
+            transfer_outs: new_transfer_outs,
+            pending_pay_root: state.pending_pay_root,
         };
         c.peer_profiles[pid].state = new_state;
         ChannelMap::<T>::mutate(&channel_id, |channel| *channel = Some(c));
@@ -1349,6 +1633,7 @@ fn update_overall_states_by_intend_state<T: Trait>(
     c.settle_finalized_time = Some(new_setttle_finalized_time);
     ChannelMap::<T>::mutate(channel_id, |channel| *channel = Some(c.clone()));
     update_channel_status::<T>(c, channel_id, ChannelStatus::Settling)?;
+    Finalizer::<T>::schedule_settle(channel_id, new_setttle_finalized_time);
 
     let seq_nums = get_state_seq_nums::<T>(channel_id);
     // Emit IntendSettle event
@@ -1357,6 +1642,27 @@ fn update_overall_states_by_intend_state<T: Trait>(
     Ok(())
 }
 
+/// Return the block from which the settle of a settling channel can be confirmed,
+/// namely settle_finalized_time is passed and all pending pays are cleared or expired
+pub(crate) fn settle_finalize_block<T: Trait>(channel_id: T::Hash, c: &ChannelOf<T>) -> T::BlockNumber {
+    let zero_hash = CelerPayModule::<T>::get_zero_hash();
+    let clear_safe_margin = CelerPayModule::<T>::get_clear_safe_margin(channel_id);
+    let mut finalize_block = c.settle_finalized_time.unwrap_or(Zero::zero());
+    for peer_profile in c.peer_profiles.iter() {
+        if peer_profile.state.next_pay_id_list_hash.unwrap_or(zero_hash) == zero_hash
+            && peer_profile.state.pending_pay_root.is_none() {
+            continue;
+        }
+        let expire_block = peer_profile.state.last_pay_resolve_deadline
+            .saturating_add(clear_safe_margin)
+            .saturating_add(One::one());
+        if expire_block > finalize_block {
+            finalize_block = expire_block;
+        }
+    }
+    return finalize_block;
+}
+
 /// Update status of a channel
 fn update_channel_status<T: Trait>(
     mut c: ChannelOf<T>,
@@ -1392,6 +1698,10 @@ fn update_channel_status<T: Trait>(
     let new_nums = new_status_nums + 1;
     ChannelStatusNums::mutate(new_status.clone() as u8, |num| *num = Some(new_nums));
 
+    if new_status == ChannelStatus::Closed {
+        ChannelClosedBlocks::<T>::insert(channel_id, frame_system::Module::<T>::block_number());
+    }
+
     c.status = new_status;
     ChannelMap::<T>::mutate(channel_id, |channel| *channel = Some(c));
 
@@ -1402,32 +1712,63 @@ fn update_channel_status<T: Trait>(
 fn validate_settle_balance<T: Trait>(
     c: ChannelOf<T>,
 ) -> Result<(bool, Vec<BalanceOf<T>>), DispatchError> {
-    let mut settle_balance: Vec<BalanceOf<T>> = vec![
-        c.peer_profiles[0].deposit.checked_add(&c.peer_profiles[1].clone().state.transfer_out).ok_or(Error::<T>::OverFlow)?,
-        c.peer_profiles[1].deposit.checked_add(&c.peer_profiles[0].clone().state.transfer_out).ok_or(Error::<T>::OverFlow)?,
-    ];
-
-    for i in 0..2 {
-        let sub_amt = c.peer_profiles[i as usize].clone().state.transfer_out
-                .checked_add(&c.peer_profiles[i as usize].withdrawal.unwrap_or(Zero::zero())).ok_or(Error::<T>::OverFlow)?;
-        if settle_balance[i as usize] < sub_amt {
-            return Ok((false, vec![Zero::zero(), Zero::zero()]));
+    let peer_num = c.peer_profiles.len();
+    let mut settle_balance: Vec<BalanceOf<T>> = vec![];
+    for i in 0..peer_num {
+        settle_balance.push(
+            c.peer_profiles[i].deposit.checked_add(&get_transfer_in::<T>(&c, i)?).ok_or(Error::<T>::OverFlow)?
+        );
+    }
+
+    for i in 0..peer_num {
+        let sub_amt = c.peer_profiles[i].clone().state.transfer_out
+                .checked_add(&c.peer_profiles[i].withdrawal.unwrap_or(Zero::zero())).ok_or(Error::<T>::OverFlow)?;
+        if settle_balance[i] < sub_amt {
+            return Ok((false, vec![Zero::zero(); peer_num]));
         }
 
-        settle_balance[i as usize] = settle_balance[i as usize]
+        settle_balance[i] = settle_balance[i]
             .checked_sub(&sub_amt).ok_or(Error::<T>::UnderFlow)?;
     }
 
     return Ok((true, settle_balance));
 }
 
-/// Get the seqNums of two simplex channel states
+/// Get the seqNums of all simplex channel states
 fn get_state_seq_nums<T: Trait>(channel_id: T::Hash) -> Vec<u128> {
     let c = ChannelMap::<T>::get(channel_id).unwrap();
-    return vec![
-        c.peer_profiles[0].clone().state.seq_num,
-        c.peer_profiles[1].state.seq_num
-    ];
+    return c.peer_profiles.iter().map(|peer_profile| peer_profile.state.seq_num).collect();
+}
+
+/// Get the Merkle leaf of a pending pay id, which commits to the receiver of the pay
+/// in a channel of more than two peers
+pub fn get_pay_id_leaf<T: Trait>(pay_id: &T::Hash, receiver: &Option<T::AccountId>) -> T::Hash {
+    match receiver {
+        Some(receiver) => T::Hashing::hash(&(pay_id, receiver).encode()),
+        None => T::Hashing::hash(&pay_id.encode()),
+    }
+}
+
+/// Hash a pair of Merkle nodes in sorted order so that proofs need no position bits
+pub fn hash_pay_id_nodes<T: Trait>(a: &T::Hash, b: &T::Hash) -> T::Hash {
+    let (first, second) = if a <= b { (a, b) } else { (b, a) };
+    let mut encoded = first.encode();
+    encoded.extend(second.encode());
+    return T::Hashing::hash(&encoded);
+}
+
+/// Verify that pay_id is committed by the pending pay root
+pub fn verify_pay_id_proof<T: Trait>(
+    root: &T::Hash,
+    pay_id: &T::Hash,
+    receiver: &Option<T::AccountId>,
+    proof: &Vec<T::Hash>,
+) -> bool {
+    let mut node = get_pay_id_leaf::<T>(pay_id, receiver);
+    for sibling in proof.iter() {
+        node = hash_pay_id_nodes::<T>(&node, sibling);
+    }
+    return node == *root;
 }
 
 pub fn encode_channel_initializer<T: Trait>(
@@ -1436,13 +1777,17 @@ pub fn encode_channel_initializer<T: Trait>(
     let mut encoded = channel_initializer.balance_limits_enabled.encode();
     encoded.extend(channel_initializer.balance_limits.encode());
     encoded.extend(channel_initializer.init_distribution.token.token_type.encode());
-    encoded.extend(channel_initializer.init_distribution.distribution[0].account.encode());
-    encoded.extend(channel_initializer.init_distribution.distribution[0].amt.encode());
-    encoded.extend(channel_initializer.init_distribution.distribution[1].account.encode());
-    encoded.extend(channel_initializer.init_distribution.distribution[1].amt.encode());
+    channel_initializer.init_distribution.distribution.iter().for_each(|pair| {
+        encoded.extend(pair.account.encode());
+        encoded.extend(pair.amt.encode());
+    });
     encoded.extend(channel_initializer.open_deadline.encode());
     encoded.extend(channel_initializer.dispute_timeout.encode());
     encoded.extend(channel_initializer.msg_value_receiver.encode());
+    // encoded only if set, so that initializers without a margin are signed as before
+    if let Some(clear_safe_margin) = channel_initializer.clear_safe_margin {
+        encoded.extend(clear_safe_margin.encode());
+    }
 
     return encoded;
 }
@@ -1450,10 +1795,11 @@ pub fn encode_channel_initializer<T: Trait>(
 pub fn check_signed_simplex_state_array<T: Trait>(
     simplex_state: SimplexPaymentChannelOf<T>,
 ) -> Result<(), DispatchError> {
+    // pending pays are committed either by a pay id list or by a Merkle root
     ensure!(
         simplex_state.peer_from.is_some()
         && simplex_state.transfer_to_peer.is_some()
-        && simplex_state.pending_pay_ids.is_some(),
+        && simplex_state.pending_pay_ids.is_some() != simplex_state.pending_pay_root.is_some(),
         Error::<T>::InvalidSignedSimplexStateArray
     );
 
@@ -1469,12 +1815,22 @@ pub fn encode_signed_simplex_state_array<T: Trait>(
     encoded.extend(simplex_state.transfer_to_peer.clone().unwrap().token.token_type.encode());
     encoded.extend(simplex_state.transfer_to_peer.clone().unwrap().receiver.account.encode());
     encoded.extend(simplex_state.transfer_to_peer.unwrap().receiver.amt.encode());
-    simplex_state.pending_pay_ids.clone().unwrap().pay_ids.into_iter().for_each(|pay_id| {
-        encoded.extend(pay_id.encode());
-    });
-    encoded.extend(simplex_state.pending_pay_ids.unwrap().next_list_hash.encode());
+    if let Some(pending_pay_ids) = simplex_state.pending_pay_ids {
+        pending_pay_ids.pay_ids.into_iter().for_each(|pay_id| {
+            encoded.extend(pay_id.encode());
+        });
+        encoded.extend(pending_pay_ids.next_list_hash.encode());
+    }
     encoded.extend(simplex_state.last_pay_resolve_deadline.encode());
     encoded.extend(simplex_state.total_pending_amount.encode());
+    // encoded only if set, so that states listing pending pay ids are signed as before
+    if let Some(pending_pay_root) = simplex_state.pending_pay_root {
+        encoded.extend(pending_pay_root.encode());
+    }
+    // encoded only if set, so that states of two-peer channels are signed as before
+    if let Some(transfer_to_peers) = simplex_state.transfer_to_peers {
+        encoded.extend(transfer_to_peers.encode());
+    }
 
     return encoded;
 }
@@ -1489,6 +1845,14 @@ pub fn encode_signed_simplex_null_state<T: Trait>(
     encoded.extend(simplex_state.pending_pay_ids.encode());
     encoded.extend(simplex_state.last_pay_resolve_deadline.encode());
     encoded.extend(simplex_state.total_pending_amount.encode());
+    // encoded only if set, so that states listing pending pay ids are signed as before
+    if let Some(pending_pay_root) = simplex_state.pending_pay_root {
+        encoded.extend(pending_pay_root.encode());
+    }
+    // encoded only if set, so that states of two-peer channels are signed as before
+    if let Some(transfer_to_peers) = simplex_state.transfer_to_peers {
+        encoded.extend(transfer_to_peers.encode());
+    }
 
     return encoded;
 }
@@ -1506,8 +1870,8 @@ pub fn encode_withdraw_info<T: Trait>(withdraw_info: CooperativeWithdrawInfoOf<T
 
 pub fn check_settle_info<T: Trait>(settle_info: CooperativeSettleInfoOf<T>) -> Result<(), DispatchError> {
     ensure!(
-        settle_info.settle_balance[0].clone().account.is_some()
-        && settle_info.settle_balance[1].clone().account.is_some(),
+        settle_info.settle_balance.len() >= 2
+        && settle_info.settle_balance.iter().all(|pair| pair.account.is_some()),
         Error::<T>::InvalidCooperativeSettle
     );
 
@@ -1517,10 +1881,10 @@ pub fn check_settle_info<T: Trait>(settle_info: CooperativeSettleInfoOf<T>) -> R
 pub fn encode_settle_info<T: Trait>(settle_info: CooperativeSettleInfoOf<T>) -> Vec<u8> {
     let mut encoded = settle_info.channel_id.encode();
     encoded.extend(settle_info.seq_num.encode());
-    encoded.extend(settle_info.settle_balance[0].clone().account.encode());
-    encoded.extend(settle_info.settle_balance[0].clone().amt.encode());
-    encoded.extend(settle_info.settle_balance[1].clone().account.encode());
-    encoded.extend(settle_info.settle_balance[1].clone().amt.encode());
+    settle_info.settle_balance.iter().for_each(|pair| {
+        encoded.extend(pair.account.encode());
+        encoded.extend(pair.amt.encode());
+    });
     encoded.extend(settle_info.settle_deadline.encode());
 
     return encoded;
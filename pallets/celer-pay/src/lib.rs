@@ -3,11 +3,17 @@
 
 mod celer_wallet;
 mod ledger_operation;
+mod migration;
 mod mock;
+mod pay_escrow;
 mod pay_registry;
 mod pay_resolver;
 mod pool;
-mod numeric_condition_caller;
+mod preimage_registry;
+mod condition_caller;
+mod custody;
+mod storage_deposit;
+mod finalizer;
 pub mod traits;
 
 #[cfg(test)]
@@ -16,22 +22,28 @@ mod tests;
 use codec::{Decode, Encode};
 use frame_support::{
     decl_error, decl_event, decl_module, decl_storage, ensure,
-    traits::{Currency, Get},
+    traits::{Currency, ExistenceRequirement, Get},
     dispatch::DispatchResultWithPostInfo,
     weights::{Weight, DispatchClass},
 };
 use frame_system::{self as system, ensure_signed};
 use ledger_operation::{
     ChannelOf, CooperativeSettleRequestOf, CooperativeWithdrawRequestOf,
-    LedgerOperation, OpenChannelRequestOf, PayIdList, SignedSimplexStateArrayOf, CELER_LEDGER_ID,
+    LedgerOperation, OpenChannelRequestOf, PayIdList, PayIdProof, SignedSimplexStateArrayOf, CELER_LEDGER_ID,
 };
 use celer_wallet::{WalletOf, WALLET_ID};
-use pay_registry::{PayInfoOf};
-use pay_resolver::{PayResolver, ResolvePaymentConditionsRequestOf, VouchedCondPayResultOf, PAY_RESOLVER_ID};
-use pool::{Pool, POOL_ID};
-pub use traits::Trait;
+use pay_escrow::{EscrowInfoOf, PayEscrow};
+use pay_registry::{PayInfoOf, PayRegistry};
+use pay_resolver::{
+    boolean_circuit_gates_len, contract_conditions_gas_limit, AssetId, ConditionalPayOf, HashType, PayResolver, ResolvePaymentConditionsRequestOf, TokenType, VouchedCondPayResultOf, PAY_RESOLVER_ID,
+};
+use pool::{Pool, PermitRequestOf, SubscriptionOf, POOL_ID};
+use preimage_registry::{PreimageInfoOf, PreimageRegistry};
+use storage_deposit::StorageDeposit;
+use finalizer::Finalizer;
+pub use traits::{AppId, BooleanCondition, BooleanConditions, ConditionContracts, CustodyMode, Fungibles, NumericCondition, NumericConditions, Trait};
 use sp_runtime::traits::{AccountIdConversion, CheckedAdd, CheckedSub, Hash, Zero, Verify};
-use sp_runtime::{RuntimeDebug, DispatchResult, DispatchError};
+use sp_runtime::{Perbill, RuntimeDebug, DispatchResult, DispatchError};
 use sp_std::{prelude::*, vec, vec::Vec};
 use celer_pay_module_rpc_runtime_api::{BalanceInfo, SeqNumInfo};
 
@@ -44,6 +56,7 @@ pub type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>
 enum Releases {
     V1_0_0,
     V2_0_0,
+    V3_0_0,
 }
 
 impl Default for Releases {
@@ -61,12 +74,65 @@ decl_storage! {
         /// Mapping the channel id to Channel
         pub ChannelMap get(fn channel_map):
                 map hasher(blake2_128_concat) T::Hash => Option<ChannelOf<T>>;
+        /// Mapping the channel id to clear_pays safe margin overriding `ClearPaysSafeMargin`
+        pub ChannelClearSafeMargins get(fn channel_clear_safe_margins):
+                map hasher(blake2_128_concat) T::Hash => Option<T::BlockNumber>;
+        /// Mapping the channel id to block number at which the channel is closed
+        pub ChannelClosedBlocks get(fn channel_closed_block):
+                map hasher(blake2_128_concat) T::Hash => Option<T::BlockNumber>;
+        /// Mapping the channel id to whether the channel has been pruned, kept as a tombstone
+        /// so that the OpenChannelRequest of the channel can not be replayed to open it again
+        pub PrunedChannels get(fn pruned_channels):
+                map hasher(blake2_128_concat) T::Hash => bool;
+        /// Raw storage key of the last channel translated to the current layout
+        /// while the V2_0_0 migration is in progress
+        pub ChannelMigrationCursor get(fn channel_migration_cursor): Option<Vec<u8>>;
+        /// Mapping the channel id to (depositor, amount) of the storage deposit of the channel and its wallet
+        pub ChannelDeposits get(fn channel_deposits):
+                map hasher(blake2_128_concat) T::Hash => Option<(T::AccountId, BalanceOf<T>)>;
+        /// Mapping the channel id to the number of times the settle states of the channel have been reset
+        pub SettleResetNums get(fn settle_reset_nums):
+                map hasher(blake2_128_concat) T::Hash => u32;
+        /// Mapping ((channel id, peer from, settle reset number, seq num), pay id) to whether the pay committed
+        /// by the pending pay root of the intended settle state has been cleared. Every intended settle state
+        /// has its own key, so pays cleared for former states are never read again and are not swept.
+        pub ClearedPayIds get(fn cleared_pay_ids):
+                double_map hasher(blake2_128_concat) (T::Hash, T::AccountId, u32, u128), hasher(blake2_128_concat) T::Hash => bool;
+        /// Mapping block number to at most MaxScheduledFinalizations channel ids
+        /// to be confirmed settle automatically at the block
+        pub ScheduledSettles get(fn scheduled_settles):
+                map hasher(twox_64_concat) T::BlockNumber => Vec<T::Hash>;
+        /// Mapping block number to at most MaxScheduledFinalizations channel ids
+        /// to be confirmed withdraw automatically at the block
+        pub ScheduledWithdraws get(fn scheduled_withdraws):
+                map hasher(twox_64_concat) T::BlockNumber => Vec<T::Hash>;
 
         /// Celer Wallet
         /// Number of wallet
         pub WalletNum get(fn wallet_num): u128;
         /// Mapping the wallet id(channel id) to Wallet
         pub Wallets get(fn wallet): map hasher(blake2_128_concat) T::Hash => Option<WalletOf<T>>;
+        /// Mapping (wallet id, depositor) to amount of native token reserved on the depositor account
+        /// for the wallet, used in `CustodyMode::Reserve`
+        pub WalletReserves get(fn wallet_reserves):
+                double_map hasher(blake2_128_concat) T::Hash, hasher(blake2_128_concat) T::AccountId => BalanceOf<T>;
+        /// Mapping owner to amount of native token reserved on the owner account for Pool, escrows
+        /// and wallets, used in `CustodyMode::Reserve` to tell them from reserves of storage deposits
+        /// and other modules
+        pub CustodyReserves get(fn custody_reserves):
+                map hasher(blake2_128_concat) T::AccountId => BalanceOf<T>;
+        /// Raw storage key of the last wallet whose funds are moved to its sub-account
+        /// while the V3_0_0 migration is in progress
+        pub WalletMigrationCursor get(fn wallet_migration_cursor): Option<Vec<u8>>;
+        /// Mapping the wallet id to whether funds of the wallet are still held by the CelerWallet
+        /// module account, since they failed to move to its sub-account in the V3_0_0 migration
+        /// or its sub-account was not endowed
+        pub LegacyCustodyWallets get(fn legacy_custody_wallets):
+                map hasher(blake2_128_concat) T::Hash => bool;
+        /// Mapping the wallet id to existential deposit endowed to its sub-account from the storage
+        /// deposit of the channel, which keeps the sub-account alive until the wallet is removed
+        pub WalletEndowments get(fn wallet_endowments):
+                map hasher(blake2_128_concat) T::Hash => Option<BalanceOf<T>>;
 
         /// Pool
         /// Mapping owner to amount of funds in Pool
@@ -75,14 +141,64 @@ decl_storage! {
         /// Mapping (owner, spender) to amount of funds to be allowed by owner
         pub Allowed get(fn allowed):
                 double_map hasher(blake2_128_concat) T::AccountId, hasher(blake2_128_concat) T::AccountId => Option<BalanceOf<T>>;
+        /// Mapping (owner, asset id) to amount of fungible asset in Pool
+        pub AssetPoolBalances get(fn asset_balances):
+                double_map hasher(blake2_128_concat) T::AccountId, hasher(blake2_128_concat) AssetId => Option<BalanceOf<T>>;
+        /// Mapping ((owner, spender), asset id) to amount of fungible asset to be allowed by owner
+        pub AssetAllowed get(fn asset_allowed):
+                double_map hasher(blake2_128_concat) (T::AccountId, T::AccountId), hasher(blake2_128_concat) AssetId => Option<BalanceOf<T>>;
+        /// Mapping ((owner, spender), token type) to block number from which the allowance is expired
+        pub AllowanceExpiries get(fn allowance_expiries):
+                double_map hasher(blake2_128_concat) (T::AccountId, T::AccountId), hasher(blake2_128_concat) TokenType => Option<T::BlockNumber>;
+        /// Mapping block number to ((owner, spender), token type) of allowances to be removed at the block
+        pub ExpiringAllowances get(fn expiring_allowances):
+                map hasher(twox_64_concat) T::BlockNumber => Vec<((T::AccountId, T::AccountId), TokenType)>;
+        /// Mapping owner to nonce of the next permit signed by owner
+        pub PermitNonces get(fn permit_nonces):
+                map hasher(blake2_128_concat) T::AccountId => u64;
+        /// Mapping (owner, spender) to subscription of recurring pulls authorized by owner
+        pub Subscriptions get(fn subscriptions):
+                double_map hasher(blake2_128_concat) T::AccountId, hasher(blake2_128_concat) T::AccountId => Option<SubscriptionOf<T>>;
 
         // PayRegistry
         /// Mapping pay id to PayInfo
         pub PayInfoMap get(fn info_map):
                 map hasher(blake2_128_concat) T::Hash => Option<PayInfoOf<T>>;
-
-        // Storage version of the pallet
-        StorageVersion build(|_| Releases::V1_0_0): Releases;
+        /// Mapping pay id to (depositor, amount) of the storage deposit of PayInfo
+        pub PayInfoDeposits get(fn pay_info_deposits):
+                map hasher(blake2_128_concat) T::Hash => Option<(T::AccountId, BalanceOf<T>)>;
+
+        // PayEscrow
+        /// Mapping pay id to escrow locked from Pool balances of the pay source
+        pub EscrowMap get(fn escrow_map):
+                map hasher(blake2_128_concat) T::Hash => Option<EscrowInfoOf<T>>;
+
+        // PreimageRegistry
+        /// Mapping (hash type, hash lock) to revealed preimage
+        pub PreimageMap get(fn preimage_map):
+                double_map hasher(twox_64_concat) HashType, hasher(blake2_128_concat) T::Hash => Option<PreimageInfoOf<T>>;
+        /// Mapping (hash type, hash lock) to (depositor, amount) of the storage deposit of revealed preimage
+        pub PreimageDeposits get(fn preimage_deposits):
+                double_map hasher(twox_64_concat) HashType, hasher(blake2_128_concat) T::Hash => Option<(T::AccountId, BalanceOf<T>)>;
+
+        // Condition apps
+        /// Mapping legacy boolean app number to app id, recorded by the storage migration
+        pub BooleanAppIds get(fn boolean_app_ids):
+                map hasher(twox_64_concat) u32 => Option<AppId>;
+        /// Mapping legacy numeric app number to app id, recorded by the storage migration
+        pub NumericAppIds get(fn numeric_app_ids):
+                map hasher(twox_64_concat) u32 => Option<AppId>;
+        /// Mapping pallet and call indices of legacy condition calls to boolean app id, recorded by runtime upgrades
+        pub LegacyCallAppIds get(fn legacy_call_app_ids):
+                map hasher(twox_64_concat) [u8; 2] => Option<AppId>;
+
+        /// Custody of native token in Pool and wallets, fixed to `NativeCustody` at genesis or
+        /// at the first deposit. Account reserves are shared with storage deposits, so the mode
+        /// is never switched after funds are deposited.
+        pub NativeCustodyMode get(fn native_custody_mode) build(|_| Some(T::NativeCustody::get())): Option<CustodyMode>;
+
+        // Storage version of the pallet, new chains start with the latest layout
+        StorageVersion build(|_| Releases::V3_0_0): Releases;
     }
 }
 
@@ -90,6 +206,32 @@ mod weight_for {
     use frame_support::{traits::Get, weights::Weight};
     use super::Trait;
 
+    /// Calculate the weight for `confirm_withdraw`
+    pub(crate) fn confirm_withdraw<T: Trait>() -> Weight {
+        100_000_000 + T::DbWeight::get().reads_writes(4, 3)
+    }
+
+    /// Calculate the weight for `confirm_settle`
+    pub(crate) fn confirm_settle<T: Trait>() -> Weight {
+        100_000_000 + T::DbWeight::get().reads_writes(5, 5)
+    }
+
+    /// Calculate the weight for `open_channel`
+    pub(crate) fn open_channel<T: Trait>(
+        peers_len: u64,
+    ) -> Weight {
+        T::DbWeight::get().reads_writes(1 + 2 * peers_len, 2 + 2 * peers_len)
+            .saturating_add(peers_len.saturating_mul(50_000_000))
+    }
+
+    /// Calculate the weight for `cooperative_settle`
+    pub(crate) fn cooperative_settle<T: Trait>(
+        peers_len: u64,
+    ) -> Weight {
+        T::DbWeight::get().reads_writes(2 + 2 * peers_len, 1 + 2 * peers_len)
+            .saturating_add(peers_len.saturating_mul(50_000_000))
+    }
+
     /// Calculate the weight for `deposit_in_batch`
     pub(crate) fn deposit_in_batch<T: Trait>(
         channel_id_len: u64,
@@ -118,13 +260,27 @@ mod weight_for {
             .saturating_add(signed_simplex_states_len_weight.saturating_mul(100_000_000))
     }
 
+    /// Calculate the weight for `clear_pays_by_proofs`
+    pub(crate) fn clear_pays_by_proofs<T: Trait>(
+        pay_ids_len: u64,
+        proof_nodes_len: Weight,
+    ) -> Weight {
+        T::DbWeight::get().reads_writes(1 + 2 * pay_ids_len, 1 + pay_ids_len)
+            .saturating_add(100_000_000)
+            .saturating_add(proof_nodes_len.saturating_mul(1_000_000))
+    }
+
     /// Calculate the weight for `resolve_payment_by_conditions`
     pub(crate) fn resolve_payment_by_conditions<T: Trait>(
-        conditions_len: Weight
+        conditions_len: Weight,
+        gates_len: Weight,
+        gas_limit: Weight,
     ) -> Weight {
         T::DbWeight::get().reads_writes(1, 1)
             .saturating_add(100_000_000)
             .saturating_add(conditions_len.saturating_mul(50_000_000))
+            .saturating_add(gates_len.saturating_mul(1_000_000))
+            .saturating_add(gas_limit)
     }
 
     /// Calculate the weight for `resolve_payment_vouched_result`
@@ -140,6 +296,42 @@ mod weight_for {
 decl_module! {
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
         type Error = Error<T>;
+
+        /// The maximum number of peers in a channel
+        const MaxChannelPeers: u32 = T::MaxChannelPeers::get();
+
+        /// The maximum number of gates in a boolean circuit transfer function
+        const MaxBooleanCircuitGates: u32 = T::MaxBooleanCircuitGates::get();
+
+        /// The storage deposit of a channel and its wallet
+        const ChannelDeposit: BalanceOf<T> = T::ChannelDeposit::get();
+
+        /// The storage deposit of a pay info
+        const PayInfoDeposit: BalanceOf<T> = T::PayInfoDeposit::get();
+
+        /// The number of blocks after which a closed channel can be pruned
+        const ChannelPruneDelay: T::BlockNumber = T::ChannelPruneDelay::get();
+
+        /// The number of blocks after the onchain resolve deadline from which a pay info can be pruned
+        const PayInfoPruneDelay: T::BlockNumber = T::PayInfoPruneDelay::get();
+
+        /// The storage deposit of a revealed preimage
+        const PreimageDeposit: BalanceOf<T> = T::PreimageDeposit::get();
+
+        /// The number of blocks after the reveal block from which a revealed preimage can be pruned
+        const PreimagePruneDelay: T::BlockNumber = T::PreimagePruneDelay::get();
+
+        /// The portion of the storage deposit of a pruned entry rewarded to the pruner
+        const PruneReward: Perbill = T::PruneReward::get();
+
+        /// The maximum weight of automatic finalizations of settles and withdraw intents in a block
+        const AutoFinalizeWeight: Weight = T::AutoFinalizeWeight::get();
+
+        /// The maximum number of channels queued to be finalized automatically at a block
+        const MaxScheduledFinalizations: u32 = T::MaxScheduledFinalizations::get();
+
+        /// The number of blocks after last pay resolve deadlines for peers to clear pays before confirm settle
+        const ClearPaysSafeMargin: T::BlockNumber = T::ClearPaysSafeMargin::get();
         
         fn deposit_event() = default;
 
@@ -163,6 +355,7 @@ decl_module! {
             channel_id: T::Hash,
             #[compact] limits: BalanceOf<T>
         ) -> DispatchResult {
+            migration::ensure_channels_migrated::<T>()?;
             LedgerOperation::<T>::set_balance_limits(origin, channel_id, limits)?;
             Ok(())
         }
@@ -184,6 +377,7 @@ decl_module! {
             origin,
             channel_id: T::Hash
         ) -> DispatchResult {
+            migration::ensure_channels_migrated::<T>()?;
             LedgerOperation::<T>::disable_balance_limits(origin, channel_id)?;
             Ok(())
         }
@@ -205,6 +399,7 @@ decl_module! {
             origin,
             channel_id: T::Hash
         ) -> DispatchResult {
+            migration::ensure_channels_migrated::<T>()?;
             LedgerOperation::<T>::enable_balance_limits(origin, channel_id)?;
             Ok(())
         }
@@ -217,22 +412,29 @@ decl_module! {
         /// 
         /// # <weight>
         /// ## Weight
-        /// - Complexity: `O(1)`
+        /// - Complexity: `O(N)`
+        ///     - N: number of channel peers
+        ///   - 1 storage read `PrunedChannels`
         ///   - 1 storage write `ChannelMap`
-        ///   - 1 storage reads `Wallets`
-        ///   - 1 storage mutation `Wallets`
-        ///   - 1 storage reads `PoolBalances`
-        ///   - 1 storage mutation `PoolBalances`
-        ///   - 2 storage reads `Allowed`
-        ///   - 1 storage mutation `Allowed`
+        ///   - N storage reads `Wallets`
+        ///   - N storage mutation `Wallets`
+        ///   - N-1 storage reads `PoolBalances`
+        ///   - N-1 storage mutation `PoolBalances`
+        ///   - N storage reads `Allowed`
+        ///   - N-1 storage mutation `Allowed`
         ///   - 1 storage write `WalletNum`
+        ///   - 1 storage write `ChannelDeposits`
+        ///   - 1 storage write `WalletEndowments`
         /// # </weight>
-        #[weight = 100_000_000 + T::DbWeight::get().reads_writes(4, 5)]
+        #[weight = weight_for::open_channel::<T>(
+            open_request.channel_initializer.init_distribution.distribution.len() as u64 // N
+        )]
         fn open_channel(
             origin,
             open_request: OpenChannelRequestOf<T>,
             msg_value: BalanceOf<T>
         ) -> DispatchResult {
+            migration::ensure_channels_migrated::<T>()?;
             LedgerOperation::<T>::open_channel(origin, open_request, msg_value)?;            
             let wallet_num = Self::wallet_num() + 1;
             WalletNum::put(wallet_num);
@@ -269,6 +471,7 @@ decl_module! {
             msg_value: BalanceOf<T>,
             transfer_from_amount: BalanceOf<T>
         ) -> DispatchResult {
+            migration::ensure_channels_migrated::<T>()?;
             LedgerOperation::<T>::deposit(origin, channel_id, receiver, msg_value, transfer_from_amount)?;
             Ok(())
         }
@@ -309,6 +512,7 @@ decl_module! {
             msg_values: Vec<BalanceOf<T>>,
             transfer_from_amounts: Vec<BalanceOf<T>>
         ) -> DispatchResultWithPostInfo {
+            migration::ensure_channels_migrated::<T>()?;
             ensure!(
                 channel_ids.len() == receivers.len() &&
                 receivers.len() == msg_values.len() &&
@@ -355,6 +559,7 @@ decl_module! {
             origin,
             signed_simplex_state_array: SignedSimplexStateArrayOf<T>
         ) -> DispatchResultWithPostInfo {
+            migration::ensure_channels_migrated::<T>()?;
             ensure_signed(origin)?;
             LedgerOperation::<T>::snapshot_states(signed_simplex_state_array.clone())?;
             
@@ -388,6 +593,7 @@ decl_module! {
             amount: BalanceOf<T>,
             recipient_channel_id: T::Hash
         ) -> DispatchResult {
+            migration::ensure_channels_migrated::<T>()?;
             LedgerOperation::<T>::intend_withdraw(origin, channel_id, amount, recipient_channel_id)?;
             Ok(())
         }
@@ -408,11 +614,12 @@ decl_module! {
         ///   - 2 storage reads `Wallets`
         ///   - 2 storage mutation `Wallets`
         /// # </weight>
-        #[weight = 100_000_000 + T::DbWeight::get().reads_writes(4, 3)]
+        #[weight = weight_for::confirm_withdraw::<T>()]
         fn confirm_withdraw(
             origin,
             channel_id: T::Hash
         ) -> DispatchResult {
+            migration::ensure_channels_migrated::<T>()?;
             ensure_signed(origin)?;
             LedgerOperation::<T>::confirm_withdraw(channel_id)?;
             Ok(())
@@ -438,6 +645,7 @@ decl_module! {
             origin,
             channel_id: T::Hash
         ) -> DispatchResult {
+            migration::ensure_channels_migrated::<T>()?;
             LedgerOperation::<T>::veto_withdraw(origin, channel_id)?;
             Ok(())
         }
@@ -461,6 +669,7 @@ decl_module! {
             origin,
             cooperative_withdraw_request: CooperativeWithdrawRequestOf<T>
         ) -> DispatchResult {
+            migration::ensure_channels_migrated::<T>()?;
             ensure_signed(origin)?;
             LedgerOperation::<T>::cooperative_withdraw(cooperative_withdraw_request)?;
             Ok(())
@@ -472,6 +681,8 @@ decl_module! {
         ///      which means intendSettle natively supports multi-channel batch processing.
         ///      A simplex state with non-zero seqNum (non-null state) must be co-signed by both peers,
         ///      while a simplex state with seqNum = 0 (null state) only needs to be signed by one peer.
+        ///      In a channel of more than two peers, a non-null state lists the cumulative amount
+        ///      transferred to every receiver and commits its pending pays by a pending pay root.
         ///
         /// Parameter:
         /// `signed_simplex_state_array`: SignedSimplexStateArray message
@@ -498,6 +709,7 @@ decl_module! {
             origin,
             signed_simplex_state_array: SignedSimplexStateArrayOf<T>
         ) -> DispatchResultWithPostInfo {
+            migration::ensure_channels_migrated::<T>()?;
             LedgerOperation::<T>::intend_settle(origin, signed_simplex_state_array.clone())?;
 
             Ok(Some(weight_for::intend_settle::<T>(
@@ -528,11 +740,51 @@ decl_module! {
             peer_from: T::AccountId,
             pay_id_list: PayIdList<T::Hash>
         ) -> DispatchResult {
+            migration::ensure_channels_migrated::<T>()?;
             ensure_signed(origin)?;
             LedgerOperation::<T>::clear_pays(channel_id, peer_from, pay_id_list)?;
             Ok(())
         }
 
+        /// Read payment results of pays committed by a pending pay root and add results
+        /// to corresponding simplex payment channel
+        ///
+        /// Dev: Pays can be cleared in batches of any order, already cleared pays are skipped
+        ///
+        /// Parameters:
+        /// `channel_id`: Id of channel
+        /// `peer_from`: address of the peer who send out funds
+        /// `pay_id_proofs`: pay ids with Merkle proofs against the pending pay root,
+        ///     with the receivers of the pays in a channel of more than two peers
+        ///
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(N * M)`
+        ///     - `N` pay_id_proofs-len
+        ///     - `M` proof-len
+        /// - DB:
+        ///   - 1 storage reads `ChannelMap`
+        ///   - N storage reads `ClearedPayIds`
+        ///   - N storage reads `PayInfoMap`
+        ///   - N storage writes `ClearedPayIds`
+        ///   - 1 storage mutation `ChannelMap`
+        /// # </weight>
+        #[weight = weight_for::clear_pays_by_proofs::<T>(
+            pay_id_proofs.len() as u64, // N
+            pay_id_proofs.iter().map(|pay_id_proof| pay_id_proof.proof.len() as Weight).sum::<Weight>() // N * M
+        )]
+        fn clear_pays_by_proofs(
+            origin,
+            channel_id: T::Hash,
+            peer_from: T::AccountId,
+            pay_id_proofs: Vec<PayIdProof<T::Hash, T::AccountId>>
+        ) -> DispatchResult {
+            migration::ensure_channels_migrated::<T>()?;
+            ensure_signed(origin)?;
+            LedgerOperation::<T>::clear_pays_by_proofs(channel_id, peer_from, pay_id_proofs)?;
+            Ok(())
+        }
+
         /// Confirm channel settlement
         ///
         /// Dev: This must be called after settle_finalized_time
@@ -551,11 +803,12 @@ decl_module! {
         ///   - 2 storage reads `Wallets`
         ///   - 2 storage mutation `Wallets`
         /// # </weight>
-        #[weight = 100_000_000 + T::DbWeight::get().reads_writes(5, 5)]
+        #[weight = weight_for::confirm_settle::<T>()]
         fn confirm_settle(
             origin,
             channel_id: T::Hash
         ) -> DispatchResult {
+            migration::ensure_channels_migrated::<T>()?;
             ensure_signed(origin)?;
             LedgerOperation::<T>::confirm_settle(channel_id)?;
             Ok(())
@@ -568,25 +821,64 @@ decl_module! {
         /// 
         /// # <weight>
         /// ## Weight
-        /// - Complexity: `O(1)`
+        /// - Complexity: `O(N)`
+        ///     - N: number of channel peers
         /// - DB:
         ///   - 2 storage reads `ChannelMap`
         ///   - 1 storage mutation `ChannelMap`
         ///   - 2 storage reads `ChannelStatusNums`
         ///   - 2 storage mutation `ChannelStatusNums`
-        ///   - 2 storage reads `Wallets`
-        ///   - 2 storage mutation `Wallets`
+        ///   - N storage reads `Wallets`
+        ///   - N storage mutation `Wallets`
         /// # </weight>
-        #[weight = 100_000_000 + T::DbWeight::get().reads_writes(6, 5)]
+        #[weight = weight_for::cooperative_settle::<T>(
+            settle_request.settle_info.settle_balance.len() as u64 // N
+        )]
         fn cooperative_settle(
             origin,
             settle_request: CooperativeSettleRequestOf<T>
         ) -> DispatchResult {
+            migration::ensure_channels_migrated::<T>()?;
             ensure_signed(origin)?;
             LedgerOperation::<T>::cooperative_settle(settle_request)?;
             Ok(())
         }
 
+        /// Remove a closed channel and its wallet
+        ///
+        /// Dev: Anyone can prune a channel after `ChannelPruneDelay` from its closing,
+        ///      and is rewarded `PruneReward` of the storage deposit of the channel.
+        ///
+        /// Parameters:
+        /// `channel_id`: Id of the channel
+        ///
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(1)`
+        /// - DB:
+        ///   - 1 storage read `ChannelMap`
+        ///   - 1 storage write `ChannelMap`
+        ///   - 1 storage read `ChannelClosedBlocks`
+        ///   - 1 storage write `ChannelClosedBlocks`
+        ///   - 1 storage read `Wallets`
+        ///   - 1 storage write `Wallets`
+        ///   - 1 storage write `WalletReserves`
+        ///   - 1 storage write `SettleResetNums`
+        ///   - 1 storage mutation `ChannelStatusNums`
+        ///   - 1 storage mutation `ChannelDeposits`
+        ///   - 1 storage mutation `WalletEndowments`
+        ///   - 1 storage write `PrunedChannels`
+        /// # </weight>
+        #[weight = 50_000_000 + T::DbWeight::get().reads_writes(6, 9)]
+        fn prune_channel(
+            origin,
+            channel_id: T::Hash
+        ) -> DispatchResult {
+            migration::ensure_channels_migrated::<T>()?;
+            LedgerOperation::<T>::prune_channel(origin, channel_id)?;
+            Ok(())
+        }
+
  /// ========================= Pool ===================================================
         /// Deposit native token into Pool
         ///
@@ -643,8 +935,10 @@ decl_module! {
         /// - Complexity: `O(1)`
         /// - DB:
         ///   - 1 storage write `Allowed`
+        ///   - 1 storage take `AllowanceExpiries`
+        ///   - 1 storage mutation `ExpiringAllowances` if the allowance was expiring
         /// # </weight>
-        #[weight = 500_000 + T::DbWeight::get().writes(1)]
+        #[weight = 500_000 + T::DbWeight::get().reads_writes(2, 3)]
         fn approve(
             origin,
             spender: T::AccountId,
@@ -727,6 +1021,213 @@ decl_module! {
             Ok(())
         }
 
+        /// Deposit fungible asset into Pool
+        ///
+        /// Parameters:
+        /// `receiver`: the address fungible asset is deposited to pool
+        /// `asset_id`: Id of fungible asset
+        /// `msg_value`: amount of asset to deposit to pool
+        /// 
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(1)`
+        /// - DB:
+        ///   - 1 storage reads `AssetPoolBalances`
+        ///   - 1 storage mutation `AssetPoolBalances`
+        /// #</weight>
+        #[weight = 100_000_000 + T::DbWeight::get().reads_writes(1, 1)]
+        fn deposit_asset_pool(
+            origin,
+            receiver: T::AccountId,
+            asset_id: AssetId,
+            msg_value: BalanceOf<T>
+        ) -> DispatchResult {
+            Pool::<T>::deposit_asset_pool(origin, receiver, asset_id, msg_value)?;
+            Ok(())
+        }
+
+        /// Withdraw fungible asset from Pool
+        ///
+        /// Parameters:
+        /// `asset_id`: Id of fungible asset
+        /// `value`: amount of asset to withdraw from pool
+        /// 
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(1)`
+        /// - DB:
+        ///   - 1 storage reads `AssetPoolBalances`
+        ///   - 1 storage mutation `AssetPoolBalances`
+        /// # </weight>
+        #[weight = 100_000_000 + T::DbWeight::get().reads_writes(1, 1)]
+        fn withdraw_asset_from_pool(
+            origin,
+            asset_id: AssetId,
+            value: BalanceOf<T>
+        ) -> DispatchResult {
+            Pool::<T>::withdraw_asset(origin, asset_id, value)?;
+            Ok(())
+        }
+
+        /// Approve the passed address the spend the specified amount of fungible asset on behalf of caller.
+        ///
+        /// Parameters:
+        /// `spender`: the address which will spend the asset
+        /// `asset_id`: Id of fungible asset
+        /// `value`: amount of asset to spent
+        /// 
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(1)`
+        /// - DB:
+        ///   - 1 storage write `AssetAllowed`
+        ///   - 1 storage take `AllowanceExpiries`
+        ///   - 1 storage mutation `ExpiringAllowances` if the allowance was expiring
+        /// # </weight>
+        #[weight = 500_000 + T::DbWeight::get().reads_writes(2, 3)]
+        fn approve_asset(
+            origin,
+            spender: T::AccountId,
+            asset_id: AssetId,
+            value: BalanceOf<T>
+        ) -> DispatchResult {
+            Pool::<T>::approve_asset(origin, spender, asset_id, value)?;
+            Ok(())
+        }
+
+        /// Approve the passed address to spend the specified amount of token on behalf of caller until expiry.
+        ///
+        /// Dev: The allowance is removed at the expiry block,
+        ///      at which at most MaxExpiringAllowances allowances can expire.
+        ///
+        /// Parameters:
+        /// `spender`: the address which will spend the token
+        /// `token_type`: type of token, native token or fungible asset
+        /// `value`: amount of token to spent
+        /// `expiry`: block number from which the allowance can not be spent
+        ///
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(M)` where `M` is MaxExpiringAllowances
+        /// - DB:
+        ///   - 1 storage read `AllowanceExpiries`
+        ///   - 1 storage read length of `ExpiringAllowances`
+        ///   - 1 storage write `Allowed` or `AssetAllowed`
+        ///   - 1 storage write `AllowanceExpiries`
+        ///   - 1 storage mutation `ExpiringAllowances` of the previous expiry
+        ///   - 1 storage append `ExpiringAllowances`
+        /// # </weight>
+        #[weight = 500_000 + T::DbWeight::get().reads_writes(4, 4)]
+        fn approve_with_expiry(
+            origin,
+            spender: T::AccountId,
+            token_type: TokenType,
+            value: BalanceOf<T>,
+            expiry: T::BlockNumber
+        ) -> DispatchResult {
+            Pool::<T>::approve_with_expiry(origin, spender, token_type, value, expiry)?;
+            Ok(())
+        }
+
+        /// Approve a spender by a permit signed by owner, so that anyone can submit the approval
+        ///
+        /// Dev: The permit sets the allowance of native token or fungible asset
+        ///      which is spent by transfer_from or opening a channel.
+        ///
+        /// Parameters:
+        /// `permit_request`: PermitRequest message
+        ///
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(1)`
+        /// - DB:
+        ///   - 1 storage reads `PermitNonces`
+        ///   - 1 storage mutation `PermitNonces`
+        ///   - 1 storage write `Allowed` or `AssetAllowed`
+        ///   - 1 storage take `AllowanceExpiries`
+        ///   - 1 storage mutation `ExpiringAllowances` if the allowance was expiring
+        /// # </weight>
+        #[weight = 50_000_000 + T::DbWeight::get().reads_writes(3, 4)]
+        fn permit(
+            origin,
+            permit_request: PermitRequestOf<T>
+        ) -> DispatchResult {
+            Pool::<T>::permit(origin, permit_request)?;
+            Ok(())
+        }
+
+        /// Authorize a spender to pull up to amount of native token from Pool in every period blocks until expiry.
+        ///
+        /// Parameters:
+        /// `spender`: the address which will pull the funds
+        /// `amount`: maximum amount of funds to be pulled in a period
+        /// `period`: number of blocks of a period
+        /// `expiry`: block number from which the subscription can not be pulled
+        ///
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(1)`
+        /// - DB:
+        ///   - 1 storage write `Subscriptions`
+        /// # </weight>
+        #[weight = 500_000 + T::DbWeight::get().writes(1)]
+        fn authorize_subscription(
+            origin,
+            spender: T::AccountId,
+            amount: BalanceOf<T>,
+            period: T::BlockNumber,
+            expiry: T::BlockNumber
+        ) -> DispatchResult {
+            Pool::<T>::authorize_subscription(origin, spender, amount, period, expiry)?;
+            Ok(())
+        }
+
+        /// Pull funds of the current period of a subscription authorized by owner.
+        ///
+        /// Parameters:
+        /// `owner`: the address which authorized the subscription
+        /// `value`: amount of funds to be pulled
+        ///
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(1)`
+        /// - DB:
+        ///   - 1 storage reads `Subscriptions`
+        ///   - 1 storage mutation `Subscriptions`
+        ///   - 2 storage reads `PoolBalances`
+        ///   - 1 storage mutation `PoolBalances`
+        /// # </weight>
+        #[weight = 100_000_000 + T::DbWeight::get().reads_writes(3, 2)]
+        fn pull_subscription(
+            origin,
+            owner: T::AccountId,
+            value: BalanceOf<T>
+        ) -> DispatchResult {
+            Pool::<T>::pull_subscription(origin, owner, value)?;
+            Ok(())
+        }
+
+        /// Cancel a subscription authorized to a spender.
+        ///
+        /// Parameters:
+        /// `spender`: the address which pulls the funds
+        ///
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(1)`
+        /// - DB:
+        ///   - 1 storage reads `Subscriptions`
+        ///   - 1 storage write `Subscriptions`
+        /// # </weight>
+        #[weight = 500_000 + T::DbWeight::get().reads_writes(1, 1)]
+        fn cancel_subscription(
+            origin,
+            spender: T::AccountId
+        ) -> DispatchResult {
+            Pool::<T>::cancel_subscription(origin, spender)?;
+            Ok(())
+        }
+
  /// ==================================== PayResolver =============================================
         /// Resolve a payment by onchain getting its conditons outcomes
         ///
@@ -739,15 +1240,20 @@ decl_module! {
         /// 
         /// # <weight>
         /// ## Weight
-        /// - Complexity: `O(N)`
+        /// - Complexity: `O(N + G)`
         ///     - N: condtions-len
+        ///     - G: gates-len of boolean circuit, bounded by `MaxBooleanCircuitGates`
+        /// - Gas limits of contract conditions, each contract is queried twice
         /// - DB:
         ///   - 1 storage reads `PayRegistry`
         ///   - 1 storage mutation `PayRegistry`
+        ///   - 1 storage write `PayInfoDeposits`
         /// # </weight>
         #[weight = (
             weight_for::resolve_payment_by_conditions::<T>(
-                resolve_pay_request.cond_pay.conditions.len() as Weight
+                resolve_pay_request.cond_pay.conditions.len() as Weight,
+                boolean_circuit_gates_len::<T>(&resolve_pay_request.cond_pay.transfer_func),
+                contract_conditions_gas_limit::<T>(&resolve_pay_request.cond_pay)
             ),
             DispatchClass::Operational
         )]
@@ -755,11 +1261,15 @@ decl_module! {
             origin,
             resolve_pay_request: ResolvePaymentConditionsRequestOf<T>
         ) -> DispatchResultWithPostInfo {
-            ensure_signed(origin)?;
+            let caller = ensure_signed(origin)?;
+            let new_pay_id = StorageDeposit::<T>::check_pay_info_deposit(&caller, &resolve_pay_request.cond_pay)?;
             PayResolver::<T>::resolve_payment_by_conditions(resolve_pay_request.clone())?;
+            StorageDeposit::<T>::reserve_pay_info_deposit(&caller, new_pay_id)?;
             
             Ok(Some(weight_for::resolve_payment_by_conditions::<T>(
                 resolve_pay_request.cond_pay.conditions.len() as Weight, // N
+                boolean_circuit_gates_len::<T>(&resolve_pay_request.cond_pay.transfer_func), // G
+                contract_conditions_gas_limit::<T>(&resolve_pay_request.cond_pay),
             )).into())
         }
 
@@ -775,6 +1285,7 @@ decl_module! {
         /// - DB:
         ///   - 1 storage reads `PayRegistry`
         ///   - 1 storage mutation `PayRegistry`
+        ///   - 1 storage write `PayInfoDeposits`
         /// # </weight>
         #[weight = (
             weight_for::resolve_payment_by_vouched_result::<T>(
@@ -786,17 +1297,182 @@ decl_module! {
             origin,
             vouched_pay_result: VouchedCondPayResultOf<T>
         ) -> DispatchResultWithPostInfo {
-            ensure_signed(origin)?;
+            let caller = ensure_signed(origin)?;
+            let new_pay_id = StorageDeposit::<T>::check_pay_info_deposit(
+                &caller,
+                &vouched_pay_result.cond_pay_result.cond_pay
+            )?;
             PayResolver::<T>::resolve_payment_vouched_result(vouched_pay_result.clone())?;
+            StorageDeposit::<T>::reserve_pay_info_deposit(&caller, new_pay_id)?;
             
             Ok(Some(weight_for::resolve_payment_by_vouched_result::<T>(
                 vouched_pay_result.cond_pay_result.cond_pay.conditions.len() as Weight, // N
             )).into())
         }
+
+        /// Remove a resolved pay info and refund its storage deposit
+        ///
+        /// Dev: Anyone can prune a pay info after the resolve deadline of the pay and
+        ///      `PayInfoPruneDelay` from its onchain resolve deadline, and is rewarded `PruneReward`
+        ///      of the storage deposit of the pay info. Channels should clear the pay before then,
+        ///      since a pruned pay is cleared as 0.
+        ///      A pay info of an unreleased escrow can not be pruned.
+        ///
+        /// Parameters:
+        /// `cond_pay`: ConditionalPay of the pay info
+        ///
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(N)`
+        ///     - N: conditions-len
+        /// - DB:
+        ///   - 1 storage read `PayInfoMap`
+        ///   - 1 storage write `PayInfoMap`
+        ///   - 1 storage read `EscrowMap`
+        ///   - 1 storage mutation `PayInfoDeposits`
+        ///   - 2 account mutations of the depositor and the pruner
+        /// # </weight>
+        #[weight = 50_000_000
+            + (cond_pay.conditions.len() as Weight).saturating_mul(1_000_000)
+            + T::DbWeight::get().reads_writes(3, 4)]
+        fn prune_pay_info(
+            origin,
+            cond_pay: ConditionalPayOf<T>
+        ) -> DispatchResult {
+            PayRegistry::<T>::prune_pay_info(origin, cond_pay)?;
+            Ok(())
+        }
+
+ /// =================================== PreimageRegistry ==========================================
+        /// Reveal a preimage of hash lock so that payments locked by it can be resolved
+        /// without submitting the preimage again
+        ///
+        /// Parameters:
+        /// `preimage`: preimage of hash lock
+        /// `hash_type`: hash function of hash lock
+        ///
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(1)`
+        /// - DB:
+        ///   - 1 storage read `PreimageMap`
+        ///   - 1 storage write `PreimageMap`
+        ///   - 1 storage write `PreimageDeposits`
+        /// # </weight>
+        #[weight = 10_000_000 + T::DbWeight::get().reads_writes(1, 2)]
+        fn reveal_preimage(
+            origin,
+            preimage: T::Hash,
+            hash_type: HashType
+        ) -> DispatchResult {
+            PreimageRegistry::<T>::reveal_preimage(origin, preimage, hash_type)?;
+            Ok(())
+        }
+
+        /// Remove a revealed preimage
+        ///
+        /// Dev: Anyone can prune a revealed preimage after `PreimagePruneDelay` from its reveal block,
+        ///      and is rewarded `PruneReward` of the storage deposit of the preimage.
+        ///
+        /// Parameters:
+        /// `hash_lock`: hash lock of the revealed preimage
+        /// `hash_type`: hash function of hash lock
+        ///
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(1)`
+        /// - DB:
+        ///   - 1 storage read `PreimageMap`
+        ///   - 1 storage write `PreimageMap`
+        ///   - 1 storage mutation `PreimageDeposits`
+        /// # </weight>
+        #[weight = 30_000_000 + T::DbWeight::get().reads_writes(2, 2)]
+        fn prune_preimage(
+            origin,
+            hash_lock: T::Hash,
+            hash_type: HashType
+        ) -> DispatchResult {
+            PreimageRegistry::<T>::prune_preimage(origin, hash_lock, hash_type)?;
+            Ok(())
+        }
+
+ /// ===================================== PayEscrow ==============================================
+        /// Lock the max transfer amount of a conditional pay from Pool balances of caller
+        ///
+        /// Dev: The caller must be the source of the pay. The pay is resolved by
+        ///      resolve_payment_by_conditions or resolve_payment_by_vouched_result as usual.
+        ///
+        /// Parameters:
+        /// `cond_pay`: ConditionalPay message
+        ///
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(N)`
+        ///     - N: conditions-len
+        /// - DB:
+        ///   - 1 storage read `EscrowMap`
+        ///   - 1 storage write `EscrowMap`
+        ///   - 1 storage mutation `PoolBalances` or `AssetPoolBalances`
+        /// # </weight>
+        #[weight = 50_000_000
+            + (cond_pay.conditions.len() as Weight).saturating_mul(1_000_000)
+            + T::DbWeight::get().reads_writes(2, 2)]
+        fn lock_escrow(
+            origin,
+            cond_pay: ConditionalPayOf<T>
+        ) -> DispatchResult {
+            PayEscrow::<T>::lock_escrow(origin, cond_pay)?;
+            Ok(())
+        }
+
+        /// Release the resolved amount of a finalized pay to its destination,
+        /// and the remainder back to Pool balances of its source
+        ///
+        /// Dev: The pay is finalized after its onchain resolve deadline,
+        ///      or after the resolve deadline in the cond_pay msg if it is never resolved.
+        ///
+        /// Parameters:
+        /// `pay_id`: Id of the escrowed payment
+        ///
+        /// # <weight>
+        /// ## Weight
+        /// - Complexity: `O(1)`
+        /// - DB:
+        ///   - 1 storage read `EscrowMap`
+        ///   - 1 storage read `PayInfoMap`
+        ///   - 1 storage write `EscrowMap`
+        ///   - 1 storage mutation `PoolBalances` or `AssetPoolBalances`
+        /// # </weight>
+        #[weight = 50_000_000 + T::DbWeight::get().reads_writes(3, 2)]
+        fn release_escrow(
+            origin,
+            pay_id: T::Hash
+        ) -> DispatchResult {
+            ensure_signed(origin)?;
+            PayEscrow::<T>::release_escrow(pay_id)?;
+            Ok(())
+        }
+
+        fn on_initialize(block_number: T::BlockNumber) -> Weight {
+            Pool::<T>::remove_expired_allowances(block_number)
+                .saturating_add(Finalizer::<T>::finalize_scheduled(block_number))
+                .saturating_add(migration::on_initialize::<T>())
+        }
       
         fn on_runtime_upgrade() -> Weight {
-            //migration::on_runtime_upgrade::<T>();
-            500_000
+            migration::on_runtime_upgrade::<T>()
+        }
+
+        fn integrity_test() {
+            // conditions reference apps by app id, so an app id must not be registered twice
+            assert!(
+                !traits::has_duplicate_app_ids(&traits::boolean_app_ids::<T::Hash, T::BooleanConditions>()),
+                "BooleanConditions must have unique APP_IDs"
+            );
+            assert!(
+                !traits::has_duplicate_app_ids(&traits::numeric_app_ids::<T::Hash, BalanceOf<T>, T::NumericConditions>()),
+                "NumericConditions must have unique APP_IDs"
+            );
         }
     }
 }
@@ -823,6 +1499,10 @@ decl_event! (
         SnapshotStates(Hash, Vec<u128>),
         /// IntendWithdraw(channel_id, receiver, amount)
         IntendWithdraw(Hash, AccountId, Balance),
+        /// AutoConfirmWithdrawFail(channel_id)
+        AutoConfirmWithdrawFail(Hash),
+        /// AutoConfirmWithdrawNotScheduled(channel_id)
+        AutoConfirmWithdrawNotScheduled(Hash),
         /// ConfirmWithdraw(channel_id, withdrawn_amount, receiver, recipient_channel_id, deposits, withdrawals)
         ConfirmWithdraw(Hash, Balance, AccountId, Hash, Vec<Balance>, Vec<Balance>),
         /// VetoWithdraw(channel_id)
@@ -837,8 +1517,14 @@ decl_event! (
         ConfirmSettle(Hash, Vec<Balance>),
         /// ConfirmSettleFail(channel_id)
         ConfirmSettleFail(Hash),
+        /// AutoConfirmSettleFail(channel_id)
+        AutoConfirmSettleFail(Hash),
+        /// AutoConfirmSettleNotScheduled(channel_id)
+        AutoConfirmSettleNotScheduled(Hash),
         /// CooperativeSettle(channel_id, settle_balances)
         CooperativeSettle(Hash, Vec<Balance>),
+        /// PruneChannel(channel_id, pruner, reward)
+        PruneChannel(Hash, AccountId, Balance),
 
         /// Celer Wallet
         /// CreateWallet(channel_id, channel_peers)
@@ -855,12 +1541,40 @@ decl_event! (
         WithdrawFromPool(AccountId, Balance),
         /// Approval(owner, spender, amount)
         Approval(AccountId, AccountId, Balance),
+        /// DepositAssetToPool(receiver, asset_id, amount)
+        DepositAssetToPool(AccountId, AssetId, Balance),
+        /// WithdrawAssetFromPool(receiver, asset_id, amount)
+        WithdrawAssetFromPool(AccountId, AssetId, Balance),
+        /// AssetApproval(owner, spender, asset_id, amount)
+        AssetApproval(AccountId, AccountId, AssetId, Balance),
+        /// ApprovalExpiry(owner, spender, token_type, expiry)
+        ApprovalExpiry(AccountId, AccountId, TokenType, BlockNumber),
+        /// AuthorizeSubscription(owner, spender, amount, period, expiry)
+        AuthorizeSubscription(AccountId, AccountId, Balance, BlockNumber, BlockNumber),
+        /// PullSubscription(owner, spender, amount)
+        PullSubscription(AccountId, AccountId, Balance),
+        /// CancelSubscription(owner, spender)
+        CancelSubscription(AccountId, AccountId),
 
         /// PayRegsitry
         /// PayInfoUpdate(pay_id, amount, resolve_deadline)
         PayInfoUpdate(Hash, Balance, BlockNumber),
         /// ResolvePayment(pay_id, amount, resolve_deadline)
         ResolvePayment(Hash, Balance, BlockNumber),
+        /// PrunePayInfo(pay_id, pruner, reward)
+        PrunePayInfo(Hash, AccountId, Balance),
+
+        /// PreimageRegistry
+        /// PreimageRevealed(hash_type, hash_lock, preimage, reveal_block)
+        PreimageRevealed(HashType, Hash, Hash, BlockNumber),
+        /// PrunePreimage(hash_type, hash_lock, pruner, reward)
+        PrunePreimage(HashType, Hash, AccountId, Balance),
+
+        /// PayEscrow
+        /// LockEscrow(pay_id, src, dest, amount)
+        LockEscrow(Hash, AccountId, AccountId, Balance),
+        /// ReleaseEscrow(pay_id, dest_amount, src_amount)
+        ReleaseEscrow(Hash, Balance, Balance),
     }   
 );
 
@@ -907,7 +1621,49 @@ decl_error! {
         // numeric_app is not exist
         NumericAppNotExist,
         // numeric_session_id is not exist
-        NumericSessionIdNotExist
+        NumericSessionIdNotExist,
+        // token type is invalid
+        InvalidTokenType,
+        // boolean_app is not exist
+        BooleanAppNotExist,
+        // boolean_session_id is not exist
+        BooleanSessionIdNotExist,
+        // condition call is not allowed by ConditionCallFilter
+        ConditionCallNotAllowed,
+        // gas_limit of contract condition is not exist
+        GasLimitNotExist,
+        // output of condition contract can not be decoded
+        InvalidContractOutput,
+        // oracle of OracleSigned condition is not exist
+        OracleNotExist,
+        // question hash of OracleSigned condition is not exist
+        QuestionHashNotExist,
+        // oracle signed outcome of OracleSigned condition is not submitted
+        OracleOutcomeNotExist,
+        // oracle result type does not match transfer function
+        InvalidOracleResult,
+        // block number of BlockAfter or BlockBefore condition is not exist
+        BlockNumberNotExist,
+        // timestamp of TimeAfter or TimeBefore condition is not exist
+        TimestampNotExist,
+        // dependent pay id of PayResult condition is not exist
+        DependentPayIdNotExist,
+        // args_query_outcome of PayResult condition is not an encoded amount
+        InvalidPayResultQuery,
+        // pending pay root of the intended settle state is not exist
+        PendingPayRootNotExist,
+        // ticket of Lottery payment is not exist
+        LotteryTicketNotExist,
+        // payer's secret of Lottery ticket is not submitted
+        LotterySecretNotExist,
+        // stream of Linear payment is not exist
+        LinearStreamNotExist,
+        // escrow of payment is not exist
+        EscrowNotExist,
+        // subscription is not exist
+        SubscriptionNotExist,
+        // channels are not translated to the current layout yet
+        ChannelMigrationInProgress
     }
 }
 
@@ -954,10 +1710,13 @@ impl<T: Trait> Module<T> {
     /// `channel_id`: Id of channel
     pub fn get_total_balance(channel_id: T::Hash) -> BalanceInfo<BalanceOf<T>> {
         let c: ChannelOf<T> = Self::channel_map(channel_id).unwrap();
-        let mut balance: BalanceOf<T> = c.peer_profiles[0].deposit;
-        balance = balance.checked_add(&c.peer_profiles[1].deposit).unwrap();
-        balance = balance.checked_sub(&c.peer_profiles[0].clone().withdrawal.unwrap_or(Zero::zero())).unwrap();
-        balance = balance.checked_sub(&c.peer_profiles[1].clone().withdrawal.unwrap_or(Zero::zero())).unwrap();
+        let mut balance: BalanceOf<T> = Zero::zero();
+        for peer_profile in c.peer_profiles.iter() {
+            balance = balance.checked_add(&peer_profile.deposit).unwrap();
+        }
+        for peer_profile in c.peer_profiles.iter() {
+            balance = balance.checked_sub(&peer_profile.withdrawal.unwrap_or(Zero::zero())).unwrap();
+        }
 
         return BalanceInfo { amount: balance };
     }
@@ -971,18 +1730,9 @@ impl<T: Trait> Module<T> {
     ) -> (Vec<T::AccountId>, Vec<BalanceInfo<BalanceOf<T>>>, Vec<BalanceInfo<BalanceOf<T>>>) {
         let c = Self::channel_map(channel_id).unwrap();
         return (
-            vec![
-                c.peer_profiles[0].peer_addr.clone(),
-                c.peer_profiles[1].peer_addr.clone(),
-            ],
-            vec![
-                BalanceInfo { amount: c.peer_profiles[0].deposit }, 
-                BalanceInfo { amount: c.peer_profiles[1].deposit }
-            ],
-            vec![
-                BalanceInfo { amount: c.peer_profiles[0].clone().withdrawal.unwrap_or(Zero::zero()) },
-                BalanceInfo { amount: c.peer_profiles[1].clone().withdrawal.unwrap_or(Zero::zero()) }
-            ],
+            c.peer_profiles.iter().map(|p| p.peer_addr.clone()).collect(),
+            c.peer_profiles.iter().map(|p| BalanceInfo { amount: p.deposit }).collect(),
+            c.peer_profiles.iter().map(|p| BalanceInfo { amount: p.withdrawal.unwrap_or(Zero::zero()) }).collect(),
         );
     }
 
@@ -995,25 +1745,28 @@ impl<T: Trait> Module<T> {
         return c.dispute_timeout;
     }
 
-    /// Return state seq_num map of a duplex channel
+    /// Return channel's clear_pays safe margin after last pay resolve deadlines
+    ///
+    /// Parameter:
+    /// `channel_id`: Id of channel
+    pub fn get_clear_safe_margin(channel_id: T::Hash) -> T::BlockNumber {
+        return Self::channel_clear_safe_margins(channel_id)
+            .unwrap_or(T::ClearPaysSafeMargin::get());
+    }
+
+    /// Return state seq_num map of a channel
     ///
     /// Parameter:
     /// `channel_id`: Id of channel
     pub fn get_state_seq_num_map(channel_id: T::Hash) -> (Vec<T::AccountId>, Vec<SeqNumInfo>) {
         let c = Self::channel_map(channel_id).unwrap();
         return (
-            vec![
-                c.peer_profiles[0].peer_addr.clone(),
-                c.peer_profiles[1].peer_addr.clone(),
-            ],
-            vec![
-                SeqNumInfo { number: c.peer_profiles[0].state.seq_num },
-                SeqNumInfo { number: c.peer_profiles[1].state.seq_num },
-            ],
+            c.peer_profiles.iter().map(|p| p.peer_addr.clone()).collect(),
+            c.peer_profiles.iter().map(|p| SeqNumInfo { number: p.state.seq_num }).collect(),
         );
     }
 
-    /// Return transfer_out map of a duplex channel
+    /// Return transfer_out map of a channel
     ///
     /// Parameter:
     /// `channel_id`: Id of channel
@@ -1022,18 +1775,12 @@ impl<T: Trait> Module<T> {
     ) -> (Vec<T::AccountId>, Vec<BalanceInfo<BalanceOf<T>>>) {
         let c = Self::channel_map(channel_id).unwrap();
         return (
-            vec![
-                c.peer_profiles[0].peer_addr.clone(),
-                c.peer_profiles[1].peer_addr.clone(),
-            ],
-            vec![
-                BalanceInfo { amount: c.peer_profiles[0].state.transfer_out },
-                BalanceInfo { amount: c.peer_profiles[1].state.transfer_out },
-            ],
+            c.peer_profiles.iter().map(|p| p.peer_addr.clone()).collect(),
+            c.peer_profiles.iter().map(|p| BalanceInfo { amount: p.state.transfer_out }).collect(),
         );
     }
 
-    /// Return next_pay_id_list_hash map of a duplex channel
+    /// Return next_pay_id_list_hash map of a channel
     ///
     /// Parameter:
     /// `channel_id`: Id of channel
@@ -1044,18 +1791,12 @@ impl<T: Trait> Module<T> {
 
         let zero_hash = Self::get_zero_hash();
         return (
-            vec![
-                c.peer_profiles[0].peer_addr.clone(),
-                c.peer_profiles[1].peer_addr.clone(),
-            ],
-            vec![
-                c.peer_profiles[0].state.next_pay_id_list_hash.unwrap_or(zero_hash),
-                c.peer_profiles[1].state.next_pay_id_list_hash.unwrap_or(zero_hash),
-            ],
+            c.peer_profiles.iter().map(|p| p.peer_addr.clone()).collect(),
+            c.peer_profiles.iter().map(|p| p.state.next_pay_id_list_hash.unwrap_or(zero_hash)).collect(),
         );
     }
 
-    /// Return last_pay_resolve_deadline map of a duplex channel
+    /// Return last_pay_resolve_deadline map of a channel
     ///
     /// Parameter:
     /// `channel_id`: Id of channel
@@ -1065,18 +1806,12 @@ impl<T: Trait> Module<T> {
         let c = Self::channel_map(channel_id).unwrap();
 
         return (
-            vec![
-                c.peer_profiles[0].peer_addr.clone(),
-                c.peer_profiles[1].peer_addr.clone(),
-            ],
-            vec![
-                c.peer_profiles[0].state.last_pay_resolve_deadline,
-                c.peer_profiles[1].state.last_pay_resolve_deadline,
-            ],
+            c.peer_profiles.iter().map(|p| p.peer_addr.clone()).collect(),
+            c.peer_profiles.iter().map(|p| p.state.last_pay_resolve_deadline).collect(),
         );
     }
 
-    /// Return pending_pay_out map of a duplex channel
+    /// Return pending_pay_out map of a channel
     ///
     /// Parameter:
     /// `channel_id`: Id of channel
@@ -1086,14 +1821,8 @@ impl<T: Trait> Module<T> {
         let c = Self::channel_map(channel_id).unwrap();
 
         return (
-            vec![
-                c.peer_profiles[0].peer_addr.clone(),
-                c.peer_profiles[1].peer_addr.clone(),
-            ],
-            vec![
-                BalanceInfo { amount: c.peer_profiles[0].state.pending_pay_out },
-                BalanceInfo { amount: c.peer_profiles[1].state.pending_pay_out },
-            ],
+            c.peer_profiles.iter().map(|p| p.peer_addr.clone()).collect(),
+            c.peer_profiles.iter().map(|p| BalanceInfo { amount: p.state.pending_pay_out }).collect(),
         );
     }
 
@@ -1163,30 +1892,12 @@ impl<T: Trait> Module<T> {
         let c = Self::channel_map(channel_id).unwrap();
 
         return (
-            vec![
-                c.peer_profiles[0].peer_addr.clone(),
-                c.peer_profiles[1].peer_addr.clone(),
-            ],
-            vec![
-                BalanceInfo { amount: c.peer_profiles[0].deposit }, 
-                BalanceInfo { amount: c.peer_profiles[1].deposit }
-            ],
-            vec![
-                BalanceInfo { amount: c.peer_profiles[0].withdrawal.unwrap_or(Zero::zero()) },
-                BalanceInfo { amount: c.peer_profiles[1].withdrawal.unwrap_or(Zero::zero()) },
-            ],
-            vec![
-                SeqNumInfo { number: c.peer_profiles[0].state.seq_num },
-                SeqNumInfo { number: c.peer_profiles[1].state.seq_num },
-            ],
-            vec![
-                BalanceInfo { amount: c.peer_profiles[0].state.transfer_out },
-                BalanceInfo { amount: c.peer_profiles[1].state.transfer_out },
-            ],
-            vec![
-                BalanceInfo { amount: c.peer_profiles[0].state.pending_pay_out },
-                BalanceInfo { amount: c.peer_profiles[1].state.pending_pay_out },
-            ],
+            c.peer_profiles.iter().map(|p| p.peer_addr.clone()).collect(),
+            c.peer_profiles.iter().map(|p| BalanceInfo { amount: p.deposit }).collect(),
+            c.peer_profiles.iter().map(|p| BalanceInfo { amount: p.withdrawal.unwrap_or(Zero::zero()) }).collect(),
+            c.peer_profiles.iter().map(|p| SeqNumInfo { number: p.state.seq_num }).collect(),
+            c.peer_profiles.iter().map(|p| BalanceInfo { amount: p.state.transfer_out }).collect(),
+            c.peer_profiles.iter().map(|p| BalanceInfo { amount: p.state.pending_pay_out }).collect(),
         );
     }
 
@@ -1196,6 +1907,16 @@ impl<T: Trait> Module<T> {
         return WALLET_ID.into_account();
     }
 
+    /// Return AccountId of the sub-account holding funds of the wallet
+    ///
+    /// Dev: Native token is held by the sub-account only in `CustodyMode::ModuleAccount`.
+    ///
+    /// Parameter:
+    /// `wallet_id`: Id of the wallet
+    pub fn get_wallet_account(wallet_id: &T::Hash) -> T::AccountId {
+        return WALLET_ID.into_sub_account(wallet_id);
+    }
+
     /// Return wallet owner conrresponding tp wallet_id
     ///
     /// Parameter:
@@ -1228,13 +1949,44 @@ impl<T: Trait> Module<T> {
         return BalanceInfo { amount: Self::balances(owner).unwrap_or(Zero::zero()) };
     }
 
-    /// Return amount of funds which owner allowed to a spender
+    /// Return amount of funds which owner allowed to a spender and expiry of the allowance.
+    /// Expiry is zero if the allowance does not expire.
     ///
     /// Parameters:
     /// `owner`: the address which owns the funds
     /// `spender`: the address which will spend the funds
-    pub fn get_allowance(owner: T::AccountId, spender: T::AccountId) -> BalanceInfo<BalanceOf<T>> {
-        return BalanceInfo { amount: Self::allowed(owner, spender).unwrap_or(Zero::zero()) };
+    pub fn get_allowance(owner: T::AccountId, spender: T::AccountId) -> (BalanceInfo<BalanceOf<T>>, T::BlockNumber) {
+        return (
+            BalanceInfo { amount: Self::allowed(&owner, &spender).unwrap_or(Zero::zero()) },
+            Self::allowance_expiries((owner, spender), TokenType::Celer).unwrap_or(Zero::zero())
+        );
+    }
+
+    /// Return amount of fungible asset which is pooled of specified address
+    ///
+    /// Parameters:
+    /// `owner`: the address of query balance of
+    /// `asset_id`: Id of fungible asset
+    pub fn get_asset_pool_balance(owner: T::AccountId, asset_id: AssetId) -> BalanceInfo<BalanceOf<T>> {
+        return BalanceInfo { amount: Self::asset_balances(owner, asset_id).unwrap_or(Zero::zero()) };
+    }
+
+    /// Return amount of fungible asset which owner allowed to a spender and expiry of the allowance.
+    /// Expiry is zero if the allowance does not expire.
+    ///
+    /// Parameters:
+    /// `owner`: the address which owns the asset
+    /// `spender`: the address which will spend the asset
+    /// `asset_id`: Id of fungible asset
+    pub fn get_asset_allowance(
+        owner: T::AccountId,
+        spender: T::AccountId,
+        asset_id: AssetId
+    ) -> (BalanceInfo<BalanceOf<T>>, T::BlockNumber) {
+        return (
+            BalanceInfo { amount: Self::asset_allowed((&owner, &spender), asset_id).unwrap_or(Zero::zero()) },
+            Self::allowance_expiries((owner, spender), TokenType::Asset(asset_id)).unwrap_or(Zero::zero())
+        );
     }
 
 /// ================================ PayResolver =============================================
@@ -1263,22 +2015,47 @@ impl<T: Trait> Module<T> {
         };
     }
 
+/// ================================= PreimageRegistry ============================================
+    /// Return (is revealed, preimage, reveal block) of hash lock
+    ///
+    /// Parameters:
+    /// `hash_lock`: hash lock of payment condition
+    /// `hash_type`: hash function of hash lock
+    pub fn get_preimage_reveal_info(hash_lock: T::Hash, hash_type: HashType) -> (bool, T::Hash, T::BlockNumber) {
+        match PreimageMap::<T>::get(hash_type, &hash_lock) {
+            Some(preimage_info) => {
+                return (true, preimage_info.preimage, preimage_info.reveal_block);
+            },
+            None => return (false, T::Hash::default(), Zero::zero())
+        };
+    }
+
 /// =================================== Helper ===============================================
+    /// Check that `signatures` are the N-of-N co-signatures of `signers`,
+    /// the i-th signature being made by the i-th signer
     pub fn valid_signers(
         signatures: Vec<<T as Trait>::Signature>,
         encoded: &[u8],
         signers: Vec<T::AccountId>,
     ) -> Result<(), DispatchError> {
-        let signature_0 = &signatures[0];
-        let signature_1 = &signatures[1];
-        ensure!(
-            (signature_0.verify(encoded, &signers[0]) && signature_1.verify(encoded, &signers[1]))
-                || (signature_0.verify(encoded, &signers[1])
-                    && signature_1.verify(encoded, &signers[0])),
-            "Check co-sigs failed"
-        );
+        ensure!(signatures.len() == signers.len(), "Check co-sigs failed");
 
-        Ok(())
+        let aligned = |signatures: &[<T as Trait>::Signature], signers: &[T::AccountId]| {
+            signatures.iter().zip(signers.iter()).all(|(signature, signer)| signature.verify(encoded, signer))
+        };
+        if aligned(&signatures, &signers) {
+            return Ok(());
+        }
+        // two-peer co-signatures were accepted in either order
+        if signers.len() == 2 {
+            ensure!(
+                aligned(&signatures, &[signers[1].clone(), signers[0].clone()]),
+                "Check co-sigs failed"
+            );
+            return Ok(());
+        }
+
+        Err(DispatchError::Other("Check co-sigs failed"))
     }
 
     pub fn check_single_signature(
@@ -1293,4 +2070,50 @@ impl<T: Trait> Module<T> {
     pub fn get_zero_hash() -> T::Hash {
         T::Hashing::hash_of(&0)
     }
+
+    /// Fail if the token is invalid or is an asset while the runtime provides no assets
+    pub fn ensure_token_supported(token_type: &TokenType) -> Result<(), DispatchError> {
+        match token_type {
+            TokenType::Celer => Ok(()),
+            TokenType::Asset(_) => {
+                ensure!(<T as Trait>::Assets::is_enabled(), "Assets are not supported");
+                Ok(())
+            },
+            TokenType::Invalid => Err(Error::<T>::InvalidTokenType)?,
+        }
+    }
+
+    /// Return free balance of `who` in the given token
+    pub fn token_free_balance(
+        token_type: &TokenType,
+        who: &T::AccountId,
+    ) -> Result<BalanceOf<T>, DispatchError> {
+        Self::ensure_token_supported(token_type)?;
+        match token_type {
+            TokenType::Celer => Ok(<T as Trait>::Currency::free_balance(who)),
+            TokenType::Asset(asset_id) => Ok(<T as Trait>::Assets::balance(*asset_id, who)),
+            TokenType::Invalid => Err(Error::<T>::InvalidTokenType)?,
+        }
+    }
+
+    /// Transfer `amount` of the given token from `source` to `dest`.
+    /// `existence` applies to native token only.
+    pub fn transfer_token(
+        token_type: &TokenType,
+        source: &T::AccountId,
+        dest: &T::AccountId,
+        amount: BalanceOf<T>,
+        existence: ExistenceRequirement,
+    ) -> Result<(), DispatchError> {
+        match token_type {
+            TokenType::Celer => <T as Trait>::Currency::transfer(
+                source,
+                dest,
+                amount,
+                existence
+            ),
+            TokenType::Asset(asset_id) => <T as Trait>::Assets::transfer(*asset_id, source, dest, amount),
+            TokenType::Invalid => Err(Error::<T>::InvalidTokenType)?,
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,547 @@
+use super::{
+    BooleanAppIds, ChannelMap, ChannelMigrationCursor, Error, LegacyCallAppIds, LegacyCustodyWallets,
+    Module as CelerPayModule, NativeCustodyMode, NumericAppIds, PoolBalances, Releases,
+    StorageVersion, WalletMigrationCursor, WalletNum, Wallets
+};
+use crate::celer_wallet::WalletOf;
+use crate::traits::CustodyMode;
+use crate::traits::{boolean_app_ids, legacy_call_app_ids, numeric_app_ids, AppId, Trait};
+use crate::custody::is_reserved;
+use crate::ledger_operation::{Channel, ChannelOf, PeerProfile, PeerState};
+use crate::pay_resolver::TokenType;
+use frame_support::{
+    debug,
+    storage::{unhashed, IterableStorageMap, StorageMap, StoragePrefixedMap, StorageValue},
+    traits::{ExistenceRequirement, Get},
+    weights::Weight,
+};
+use sp_runtime::{traits::Zero, DispatchResult};
+use codec::Decode;
+use sp_std::{vec, vec::Vec};
+
+// Channels translated to the current layout in a block during the V2_0_0 migration
+const MIGRATED_CHANNELS_PER_BLOCK: u32 = 64;
+
+// Wallets whose funds are moved to their sub-accounts in a block during the V3_0_0 migration
+const MIGRATED_WALLETS_PER_BLOCK: u32 = 64;
+
+// Layout of ChannelMap entries before V2_0_0, when channels had exactly two peers
+mod deprecated {
+    use crate::ledger_operation::{ChannelStatus, WithdrawIntent};
+    use crate::pay_resolver::TokenInfo;
+    use codec::Decode;
+    use sp_std::vec::Vec;
+
+    #[derive(Decode)]
+    pub struct PeerState<Hash, BlockNumber, Balance> {
+        pub seq_num: u128,
+        pub transfer_out: Balance,
+        pub next_pay_id_list_hash: Option<Hash>,
+        pub last_pay_resolve_deadline: BlockNumber,
+        pub pending_pay_out: Balance,
+    }
+
+    #[derive(Decode)]
+    pub struct PeerProfile<AccountId, Balance, BlockNumber, Hash> {
+        pub peer_addr: AccountId,
+        pub deposit: Balance,
+        pub withdrawal: Option<Balance>,
+        pub state: PeerState<Hash, BlockNumber, Balance>,
+    }
+
+    #[derive(Decode)]
+    pub struct Channel<AccountId, BlockNumber, Balance, Hash> {
+        pub balance_limits_enabled: bool,
+        pub balance_limits: Option<Balance>,
+        pub settle_finalized_time: Option<BlockNumber>,
+        pub dispute_timeout: BlockNumber,
+        pub token: TokenInfo,
+        pub status: ChannelStatus,
+        pub peer_profiles: Vec<PeerProfile<AccountId, Balance, BlockNumber, Hash>>,
+        pub cooperative_withdraw_seq_num: Option<u128>,
+        pub withdraw_intent: WithdrawIntent<AccountId, Balance, BlockNumber, Hash>,
+    }
+}
+
+type DeprecatedChannelOf<T> = deprecated::Channel<
+    <T as frame_system::Trait>::AccountId,
+    <T as frame_system::Trait>::BlockNumber,
+    crate::BalanceOf<T>,
+    <T as frame_system::Trait>::Hash,
+>;
+
+pub fn on_runtime_upgrade<T: Trait>() -> Weight {
+    let mut weight = T::DbWeight::get().reads(1);
+    if StorageVersion::get() == Releases::V1_0_0 {
+        weight = weight.saturating_add(migrate_to_v2::<T>());
+    }
+    if StorageVersion::get() == Releases::V2_0_0 {
+        weight = weight.saturating_add(migrate_to_v3::<T>());
+    }
+    weight = weight.saturating_add(record_app_nums::<T>());
+    weight = weight.saturating_add(record_legacy_call_app_ids::<T>());
+
+    weight = weight.saturating_add(fix_legacy_custody_mode::<T>());
+    return weight;
+}
+
+// Fix the custody mode of chains holding funds deposited before the custody mode was introduced,
+// when native token was always held by module accounts. A switched NativeCustody is ignored
+// once the custody mode is fixed.
+fn fix_legacy_custody_mode<T: Trait>() -> Weight {
+    let custody_mode = match NativeCustodyMode::get() {
+        Some(_custody_mode) => _custody_mode,
+        None => {
+            let has_funds = WalletNum::get() > 0 || PoolBalances::<T>::iter().next().is_some();
+            if !has_funds {
+                return T::DbWeight::get().reads(3);
+            }
+            NativeCustodyMode::put(CustodyMode::ModuleAccount);
+            CustodyMode::ModuleAccount
+        }
+    };
+    if custody_mode != T::NativeCustody::get() {
+        debug::warn!(
+            "NativeCustody {:?} is ignored, native token stays in custody mode {:?}",
+            T::NativeCustody::get(),
+            custody_mode
+        );
+    }
+
+    return T::DbWeight::get().reads_writes(3, 1);
+}
+
+// Record legacy app numbers of condition apps registered in the runtime.
+//
+// Legacy app numbers are positions in the BooleanConditions and NumericConditions tuples, which
+// change whenever the tuples are reordered. Once recorded, the app number of an app never changes,
+// and apps which are not recorded yet are numbered after the recorded ones. The runtime upgrading
+// from V1_0_0 must list the apps of existing legacy app numbers first, in the order of their numbers.
+fn record_app_nums<T: Trait>() -> Weight {
+    let mut reads: Weight = 0;
+    let mut writes: Weight = 0;
+
+    let recorded: Vec<(u32, AppId)> = BooleanAppIds::iter().collect();
+    reads += recorded.len() as Weight + 1;
+    let mut app_num: u32 = recorded.iter().map(|(num, _)| num + 1).max().unwrap_or(0);
+    for app_id in boolean_app_ids::<T::Hash, T::BooleanConditions>() {
+        if !recorded.iter().any(|(_, id)| *id == app_id) {
+            BooleanAppIds::insert(app_num, app_id);
+            app_num += 1;
+            writes += 1;
+        }
+    }
+
+    let recorded: Vec<(u32, AppId)> = NumericAppIds::iter().collect();
+    reads += recorded.len() as Weight + 1;
+    let mut app_num: u32 = recorded.iter().map(|(num, _)| num + 1).max().unwrap_or(0);
+    for app_id in numeric_app_ids::<T::Hash, crate::BalanceOf<T>, T::NumericConditions>() {
+        if !recorded.iter().any(|(_, id)| *id == app_id) {
+            NumericAppIds::insert(app_num, app_id);
+            app_num += 1;
+            writes += 1;
+        }
+    }
+
+    return T::DbWeight::get().reads_writes(reads, writes);
+}
+
+// Record the boolean apps answering legacy condition calls by the pallet and call indices of the calls.
+//
+// The indices of a call change whenever construct_runtime is reordered, while signed pays keep the
+// calls encoded by the runtime signing them. Once recorded, the indices of a call keep their app,
+// and indices which are not recorded yet are mapped by LegacyConditionCalls of the current runtime.
+fn record_legacy_call_app_ids<T: Trait>() -> Weight {
+    let mut reads: Weight = 0;
+    let mut writes: Weight = 0;
+    for (call_index, app_id) in legacy_call_app_ids::<T>() {
+        reads += 1;
+        if !LegacyCallAppIds::contains_key(call_index) {
+            LegacyCallAppIds::insert(call_index, app_id);
+            writes += 1;
+        }
+    }
+
+    return T::DbWeight::get().reads_writes(reads, writes);
+}
+
+// Migrate storage from V1_0_0 to V2_0_0.
+//
+// Peer states of channels gained the cumulative amounts transferred to each receiver and the
+// Merkle root of pending pays. Channels had exactly two peers, so transfer_out of a peer is
+// transferred to the other peer. Channels are translated in on_initialize of the following blocks,
+// at most MIGRATED_CHANNELS_PER_BLOCK channels per block, and the storage version is bumped once
+// all of them are translated. Channel operations are rejected until then.
+fn migrate_to_v2<T: Trait>() -> Weight {
+    if ChannelMigrationCursor::get().is_some() {
+        return T::DbWeight::get().reads(1);
+    }
+    ChannelMigrationCursor::put(ChannelMap::<T>::final_prefix().to_vec());
+    return T::DbWeight::get().reads_writes(1, 1);
+}
+
+// Translate at most `max_channels` channels following the migration cursor in key order.
+// Once all channels are translated, the storage version is bumped and the V3_0_0 migration starts.
+pub(crate) fn migrate_channels<T: Trait>(max_channels: u32) -> Weight {
+    let prefix = ChannelMap::<T>::final_prefix();
+    let mut key = match ChannelMigrationCursor::get() {
+        Some(_key) => _key,
+        None => return T::DbWeight::get().reads(1),
+    };
+    let mut reads: Weight = 1;
+    let mut writes: Weight = 0;
+
+    for _ in 0..max_channels {
+        key = match sp_io::storage::next_key(&key) {
+            Some(next_key) if next_key.starts_with(&prefix) => next_key,
+            _ => {
+                ChannelMigrationCursor::kill();
+                StorageVersion::put(Releases::V2_0_0);
+                return T::DbWeight::get().reads_writes(reads, writes + 2)
+                    .saturating_add(migrate_to_v3::<T>());
+            }
+        };
+        reads += 1;
+
+        match unhashed::get::<DeprecatedChannelOf<T>>(&key) {
+            Some(c) => {
+                unhashed::put(&key, &translate_channel::<T>(c));
+                writes += 1;
+            },
+            None => debug::error!("Channel at key {:?} can not be decoded in the layout before V2_0_0", key),
+        }
+    }
+
+    ChannelMigrationCursor::put(key);
+    return T::DbWeight::get().reads_writes(reads, writes + 1);
+}
+
+// Reject channel operations until all channels are translated by the V2_0_0 migration.
+// The cursor is read by on_initialize of every block, so that reading it again is cheap.
+pub fn ensure_channels_migrated<T: Trait>() -> DispatchResult {
+    if ChannelMigrationCursor::get().is_some() {
+        Err(Error::<T>::ChannelMigrationInProgress)?
+    }
+    Ok(())
+}
+
+fn translate_channel<T: Trait>(c: DeprecatedChannelOf<T>) -> ChannelOf<T> {
+    let peer_addrs: Vec<T::AccountId> = c.peer_profiles.iter()
+        .map(|peer_profile| peer_profile.peer_addr.clone())
+        .collect();
+    let peer_profiles = c.peer_profiles.into_iter().enumerate().map(|(i, peer_profile)| {
+        let state = peer_profile.state;
+        // channels of the deprecated layout have two peers, transferring to each other
+        let transfer_outs = if peer_addrs.len() == 2 && !state.transfer_out.is_zero() {
+            vec![(peer_addrs[1 - i].clone(), state.transfer_out)]
+        } else {
+            vec![]
+        };
+        PeerProfile {
+            peer_addr: peer_profile.peer_addr,
+            deposit: peer_profile.deposit,
+            withdrawal: peer_profile.withdrawal,
+            state: PeerState {
+                seq_num: state.seq_num,
+                transfer_out: state.transfer_out,
+                next_pay_id_list_hash: state.next_pay_id_list_hash,
+                last_pay_resolve_deadline: state.last_pay_resolve_deadline,
+                pending_pay_out: state.pending_pay_out,
+                transfer_outs: transfer_outs,
+                pending_pay_root: None,
+            },
+        }
+    }).collect();
+
+    Channel {
+        balance_limits_enabled: c.balance_limits_enabled,
+        balance_limits: c.balance_limits,
+        settle_finalized_time: c.settle_finalized_time,
+        dispute_timeout: c.dispute_timeout,
+        token: c.token,
+        status: c.status,
+        peer_profiles: peer_profiles,
+        cooperative_withdraw_seq_num: c.cooperative_withdraw_seq_num,
+        withdraw_intent: c.withdraw_intent,
+    }
+}
+
+// Migrate storage from V2_0_0 to V3_0_0.
+//
+// Funds of all wallets were held by the CelerWallet module account. The balance of each wallet
+// is moved to the sub-account derived from its wallet id in on_initialize of the following blocks,
+// at most MIGRATED_WALLETS_PER_BLOCK wallets per block. Until its funds are moved, a wallet keeps
+// using the CelerWallet module account.
+fn migrate_to_v3<T: Trait>() -> Weight {
+    WalletMigrationCursor::put(Wallets::<T>::final_prefix().to_vec());
+    StorageVersion::put(Releases::V3_0_0);
+    return T::DbWeight::get().writes(2);
+}
+
+// Continue translating channels, and then moving funds of wallets to their sub-accounts.
+// This function is called in on_initialize.
+pub fn on_initialize<T: Trait>() -> Weight {
+    if ChannelMigrationCursor::get().is_some() {
+        return migrate_channels::<T>(MIGRATED_CHANNELS_PER_BLOCK);
+    }
+    if WalletMigrationCursor::get().is_none() {
+        return T::DbWeight::get().reads(2);
+    }
+    return migrate_wallet_funds::<T>(MIGRATED_WALLETS_PER_BLOCK)
+        .saturating_add(T::DbWeight::get().reads(1));
+}
+
+// Move funds of at most `max_wallets` wallets following the migration cursor in key order.
+// A wallet whose funds fail to move keeps using the CelerWallet module account.
+pub(crate) fn migrate_wallet_funds<T: Trait>(max_wallets: u32) -> Weight {
+    let celer_wallet_account = CelerPayModule::<T>::get_celer_wallet_id();
+    let prefix = Wallets::<T>::final_prefix();
+    let mut key = match WalletMigrationCursor::get() {
+        Some(_key) => _key,
+        None => return T::DbWeight::get().reads(1),
+    };
+    let mut reads: Weight = 1;
+    let mut writes: Weight = 1;
+
+    for _ in 0..max_wallets {
+        key = match sp_io::storage::next_key(&key) {
+            Some(next_key) if next_key.starts_with(&prefix) => next_key,
+            _ => {
+                WalletMigrationCursor::kill();
+                return T::DbWeight::get().reads_writes(reads, writes);
+            }
+        };
+        reads += 1;
+
+        // the key is the prefix followed by blake2_128_concat of the wallet id
+        let wallet_id = match T::Hash::decode(&mut &key[prefix.len() + 16..]) {
+            Ok(_wallet_id) => _wallet_id,
+            Err(_) => continue,
+        };
+        let wallet: WalletOf<T> = match unhashed::get(&key) {
+            Some(_wallet) => _wallet,
+            None => continue,
+        };
+        if wallet.balance.is_zero() {
+            continue;
+        }
+
+        reads += 1;
+        let token_type = match ChannelMap::<T>::get(&wallet_id) {
+            Some(c) => c.token.token_type,
+            None => TokenType::Celer,
+        };
+        if is_reserved::<T>(&token_type) {
+            continue;
+        }
+
+        // the CelerWallet module account is kept alive for funds of the remaining wallets
+        let wallet_account = CelerPayModule::<T>::get_wallet_account(&wallet_id);
+        let existence = match CelerPayModule::<T>::token_free_balance(&token_type, &celer_wallet_account) {
+            Ok(balance) if balance == wallet.balance => ExistenceRequirement::AllowDeath,
+            _ => ExistenceRequirement::KeepAlive,
+        };
+        reads += 1;
+        writes += 2;
+        if let Err(e) = CelerPayModule::<T>::transfer_token(
+            &token_type,
+            &celer_wallet_account,
+            &wallet_account,
+            wallet.balance,
+            existence
+        ) {
+            debug::error!(
+                "Funds of wallet {:?} stay in CelerWallet module account, failed to move them: {:?}",
+                wallet_id,
+                e
+            );
+            LegacyCustodyWallets::<T>::insert(&wallet_id, true);
+        }
+    }
+
+    WalletMigrationCursor::put(key);
+    return T::DbWeight::get().reads_writes(reads, writes + 1);
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::mock::*;
+    use crate::ledger_operation::{ChannelStatus, WithdrawIntent};
+    use crate::celer_wallet::CelerWallet;
+    use crate::pay_resolver::TokenInfo;
+    use frame_support::{assert_noop, traits::Currency};
+    use sp_core::H256;
+
+    // Put a channel encoded in the layout before V2_0_0, peer 0 having transferred 30 to peer 1
+    fn put_deprecated_channel(channel_id: H256, alice: AccountId, bob: AccountId) {
+        let old_peer_profile = |peer: AccountId, transfer_out: Balance| {
+            (peer, 100 as Balance, None::<Balance>, (1u128, transfer_out, None::<H256>, 0 as BlockNumber, 0 as Balance))
+        };
+        let old_channel = (
+            false,
+            None::<Balance>,
+            None::<BlockNumber>,
+            10 as BlockNumber,
+            TokenInfo { token_type: TokenType::Celer },
+            ChannelStatus::Operable,
+            vec![old_peer_profile(alice, 30), old_peer_profile(bob, 0)],
+            None::<u128>,
+            WithdrawIntent {
+                receiver: alice,
+                amount: None::<Balance>,
+                request_time: None::<BlockNumber>,
+                recipient_channel_id: None::<H256>,
+            },
+        );
+        frame_support::storage::unhashed::put(
+            &ChannelMap::<TestRuntime>::hashed_key_for(channel_id),
+            &old_channel
+        );
+    }
+
+    #[test]
+    fn test_pass_migrate_two_peer_channel_to_v2() {
+        ExtBuilder::build().execute_with(|| {
+            let alice = account_key("Alice");
+            let bob = account_key("Bob");
+            let channel_id = H256::from_low_u64_be(1);
+
+            put_deprecated_channel(channel_id, alice, bob);
+
+            StorageVersion::put(Releases::V1_0_0);
+            on_runtime_upgrade::<TestRuntime>();
+            assert_eq!(StorageVersion::get(), Releases::V1_0_0);
+            assert_noop!(
+                CelerPayModule::confirm_settle(Origin::signed(alice), channel_id),
+                Error::<TestRuntime>::ChannelMigrationInProgress
+            );
+
+            on_initialize::<TestRuntime>();
+            assert_eq!(CelerPayModule::channel_migration_cursor(), None);
+            assert_eq!(StorageVersion::get(), Releases::V3_0_0);
+            let c = CelerPayModule::channel_map(channel_id).unwrap();
+            assert_eq!(c.peer_profiles[0].state.transfer_outs, vec![(bob, 30)]);
+            assert_eq!(c.peer_profiles[1].state.transfer_outs, vec![]);
+            assert_eq!(c.peer_profiles[1].state.pending_pay_root, None);
+            assert_eq!(c.withdraw_intent.receiver, alice);
+        })
+    }
+
+    #[test]
+    fn test_pass_migrate_channels_to_v2_in_bounded_batches() {
+        ExtBuilder::build().execute_with(|| {
+            let alice = account_key("Alice");
+            let bob = account_key("Bob");
+            let channel_ids = vec![H256::from_low_u64_be(1), H256::from_low_u64_be(2)];
+            for channel_id in channel_ids.iter() {
+                put_deprecated_channel(*channel_id, alice, bob);
+            }
+
+            StorageVersion::put(Releases::V1_0_0);
+            on_runtime_upgrade::<TestRuntime>();
+            let cursor = CelerPayModule::channel_migration_cursor();
+            assert!(cursor.is_some());
+            // an upgrade during the migration keeps the cursor
+            on_runtime_upgrade::<TestRuntime>();
+            assert_eq!(CelerPayModule::channel_migration_cursor(), cursor);
+
+            // at most max_channels channels are translated at a time
+            migrate_channels::<TestRuntime>(1);
+            assert_eq!(
+                channel_ids.iter().filter(|channel_id| CelerPayModule::channel_map(*channel_id).is_some()).count(),
+                1
+            );
+            assert_eq!(StorageVersion::get(), Releases::V1_0_0);
+            migrate_channels::<TestRuntime>(1);
+            assert!(CelerPayModule::channel_migration_cursor().is_some());
+            migrate_channels::<TestRuntime>(1);
+            assert_eq!(CelerPayModule::channel_migration_cursor(), None);
+            assert_eq!(StorageVersion::get(), Releases::V3_0_0);
+
+            for channel_id in channel_ids.iter() {
+                let c = CelerPayModule::channel_map(channel_id).unwrap();
+                assert_eq!(c.peer_profiles[0].state.transfer_outs, vec![(bob, 30)]);
+            }
+        })
+    }
+
+    #[test]
+    fn test_pass_migrate_wallet_funds_in_bounded_batches() {
+        ExtBuilder::build().execute_with(|| {
+            MockExistentialDeposit::set(10);
+            let celer_ledger_account = CelerPayModule::get_celer_ledger_id();
+            let celer_wallet_account = CelerPayModule::get_celer_wallet_id();
+            let alice = account_key("Alice");
+            let bob = account_key("Bob");
+
+            // funds of wallets were held by the CelerWallet module account before V3_0_0
+            let mut wallet_ids: Vec<H256> = vec![];
+            for (nonce, balance) in vec![(1, 100), (2, 5), (3, 50)] {
+                let wallet_id = CelerWallet::<TestRuntime>::create_wallet(
+                    Origin::signed(celer_ledger_account),
+                    vec![alice, bob],
+                    H256::from_low_u64_be(nonce)
+                ).unwrap();
+                Wallets::<TestRuntime>::mutate(&wallet_id, |wallet| {
+                    wallet.as_mut().unwrap().balance = balance;
+                });
+                wallet_ids.push(wallet_id);
+            }
+            <Balances as Currency<AccountId>>::transfer(
+                &alice,
+                &celer_wallet_account,
+                165,
+                ExistenceRequirement::AllowDeath,
+            ).unwrap();
+            StorageVersion::put(Releases::V2_0_0);
+            on_runtime_upgrade::<TestRuntime>();
+            assert_eq!(StorageVersion::get(), Releases::V3_0_0);
+
+            // at most max_wallets wallets are migrated at a time
+            migrate_wallet_funds::<TestRuntime>(2);
+            assert!(CelerPayModule::wallet_migration_cursor().is_some());
+            migrate_wallet_funds::<TestRuntime>(2);
+            assert_eq!(CelerPayModule::wallet_migration_cursor(), None);
+
+            assert_eq!(Balances::free_balance(CelerPayModule::get_wallet_account(&wallet_ids[0])), 100);
+            assert_eq!(Balances::free_balance(CelerPayModule::get_wallet_account(&wallet_ids[2])), 50);
+
+            // a balance below the existential deposit stays in the CelerWallet module account
+            assert_eq!(Balances::free_balance(CelerPayModule::get_wallet_account(&wallet_ids[1])), 0);
+            assert_eq!(CelerPayModule::legacy_custody_wallets(wallet_ids[1]), true);
+            assert_eq!(Balances::free_balance(&celer_wallet_account), 15);
+            CelerWallet::<TestRuntime>::withdraw(
+                Origin::signed(celer_ledger_account),
+                wallet_ids[1],
+                TokenType::Celer,
+                bob,
+                5
+            ).unwrap();
+            assert_eq!(Balances::free_balance(&celer_wallet_account), 10);
+            assert_eq!(Balances::free_balance(bob), 1005);
+        })
+    }
+
+    #[test]
+    fn test_pass_fix_custody_mode_of_legacy_pool_balances() {
+        ExtBuilder::build().execute_with(|| {
+            // Pool balance deposited before the custody mode was introduced
+            PoolBalances::<TestRuntime>::insert(account_key("Bob"), 100);
+            MockNativeCustody::set(CustodyMode::Reserve);
+
+            on_runtime_upgrade::<TestRuntime>();
+            assert_eq!(CelerPayModule::native_custody_mode(), Some(CustodyMode::ModuleAccount));
+        })
+    }
+
+    #[test]
+    fn test_pass_leave_custody_mode_unfixed_without_funds() {
+        ExtBuilder::build().execute_with(|| {
+            MockNativeCustody::set(CustodyMode::Reserve);
+
+            on_runtime_upgrade::<TestRuntime>();
+            assert_eq!(CelerPayModule::native_custody_mode(), None);
+        })
+    }
+}
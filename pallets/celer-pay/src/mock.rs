@@ -5,7 +5,7 @@ use crate::{Module};
 use crate::traits::Trait;
 use frame_support::{
     impl_outer_event, impl_outer_origin, impl_outer_dispatch,
-    parameter_types, weights::Weight
+    parameter_types, traits::Filter, weights::Weight
 };
 use frame_system as system;
 use pallet_balances;
@@ -13,6 +13,7 @@ use sp_core::{sr25519, Pair, H256};
 use sp_runtime::testing::Header;
 use sp_runtime::traits::{BlakeTwo256, IdentityLookup};
 use sp_runtime::Perbill;
+use std::{cell::RefCell, collections::BTreeMap};
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct TestRuntime;
@@ -53,7 +54,6 @@ parameter_types! {
     pub const MaximumBlockWeight: Weight = 1024;
     pub const MaximumBlockLength: u32 = 2 * 1024;
     pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
-    pub const ExistentialDeposit: u64 = 1; // should be greater than zero
 }
 
 impl frame_system::Trait for TestRuntime {
@@ -124,7 +124,7 @@ impl pallet_balances::Trait for TestRuntime {
     type Balance = u64;
     type Event = TestEvent;
     type DustRemoval = ();
-    type ExistentialDeposit = ExistentialDeposit;
+    type ExistentialDeposit = MockExistentialDeposit;
     type AccountStore = frame_system::Module<TestRuntime>;
     type WeightInfo = ();
 }
@@ -140,16 +140,262 @@ impl mock_boolean_condition::Trait for TestRuntime {}
 
 impl mock_numeric_condition::Trait for TestRuntime {}
 
+thread_local! {
+    static ASSET_BALANCES: RefCell<BTreeMap<(AssetId, AccountId), Balance>> = RefCell::new(BTreeMap::new());
+    static CONTRACTS: RefCell<BTreeMap<AccountId, (bool, Vec<u8>)>> = RefCell::new(BTreeMap::new());
+    static VIRTUAL_CONTRACTS: RefCell<BTreeMap<H256, AccountId>> = RefCell::new(BTreeMap::new());
+    static NATIVE_CUSTODY: RefCell<CustodyMode> = RefCell::new(CustodyMode::ModuleAccount);
+    static STORAGE_DEPOSITS: RefCell<(Balance, Balance)> = RefCell::new((0, 0));
+    static PREIMAGE_DEPOSIT: RefCell<Balance> = RefCell::new(0);
+    static CLEAR_PAYS_SAFE_MARGIN: RefCell<BlockNumber> = RefCell::new(0);
+    static EXISTENTIAL_DEPOSIT: RefCell<Balance> = RefCell::new(1);
+}
+
+/// Existential deposit of native token, 1 unless set by a test (should be greater than zero)
+pub struct MockExistentialDeposit;
+impl MockExistentialDeposit {
+    pub fn set(existential_deposit: Balance) {
+        EXISTENTIAL_DEPOSIT.with(|d| *d.borrow_mut() = existential_deposit);
+    }
+}
+
+impl Get<Balance> for MockExistentialDeposit {
+    fn get() -> Balance {
+        EXISTENTIAL_DEPOSIT.with(|d| *d.borrow())
+    }
+}
+
+/// Custody of native token, `CustodyMode::ModuleAccount` unless set by a test
+pub struct MockNativeCustody;
+impl MockNativeCustody {
+    pub fn set(mode: CustodyMode) {
+        NATIVE_CUSTODY.with(|c| *c.borrow_mut() = mode);
+    }
+}
+
+impl Get<CustodyMode> for MockNativeCustody {
+    fn get() -> CustodyMode {
+        NATIVE_CUSTODY.with(|c| *c.borrow())
+    }
+}
+
+/// Storage deposits of a channel and of a pay info, zero unless set by a test
+pub struct MockStorageDeposits;
+impl MockStorageDeposits {
+    pub fn set(channel_deposit: Balance, pay_info_deposit: Balance) {
+        STORAGE_DEPOSITS.with(|d| *d.borrow_mut() = (channel_deposit, pay_info_deposit));
+    }
+}
+
+pub struct MockChannelDeposit;
+impl Get<Balance> for MockChannelDeposit {
+    fn get() -> Balance {
+        STORAGE_DEPOSITS.with(|d| d.borrow().0)
+    }
+}
+
+pub struct MockPayInfoDeposit;
+impl Get<Balance> for MockPayInfoDeposit {
+    fn get() -> Balance {
+        STORAGE_DEPOSITS.with(|d| d.borrow().1)
+    }
+}
+
+/// Storage deposit of a revealed preimage, zero unless set by a test
+pub struct MockPreimageDeposit;
+impl MockPreimageDeposit {
+    pub fn set(preimage_deposit: Balance) {
+        PREIMAGE_DEPOSIT.with(|d| *d.borrow_mut() = preimage_deposit);
+    }
+}
+impl Get<Balance> for MockPreimageDeposit {
+    fn get() -> Balance {
+        PREIMAGE_DEPOSIT.with(|d| *d.borrow())
+    }
+}
+
+/// Clear pays safe margin of the runtime, zero unless set by a test
+pub struct MockClearPaysSafeMargin;
+impl MockClearPaysSafeMargin {
+    pub fn set(clear_safe_margin: BlockNumber) {
+        CLEAR_PAYS_SAFE_MARGIN.with(|m| *m.borrow_mut() = clear_safe_margin);
+    }
+}
+impl Get<BlockNumber> for MockClearPaysSafeMargin {
+    fn get() -> BlockNumber {
+        CLEAR_PAYS_SAFE_MARGIN.with(|m| *m.borrow())
+    }
+}
+
+/// Id of the fungible asset endowed in `ExtBuilder`
+pub(crate) const TEST_ASSET_ID: AssetId = 1;
+
+/// In-memory fungible assets module
+pub struct MockAssets;
+impl MockAssets {
+    pub fn set_balance(asset_id: AssetId, who: &AccountId, amount: Balance) {
+        ASSET_BALANCES.with(|b| b.borrow_mut().insert((asset_id, *who), amount));
+    }
+}
+
+impl Fungibles<AccountId, Balance> for MockAssets {
+    fn is_enabled() -> bool {
+        true
+    }
+
+    fn balance(asset_id: AssetId, who: &AccountId) -> Balance {
+        ASSET_BALANCES.with(|b| *b.borrow().get(&(asset_id, *who)).unwrap_or(&0))
+    }
+
+    fn transfer(
+        asset_id: AssetId,
+        source: &AccountId,
+        dest: &AccountId,
+        amount: Balance,
+    ) -> DispatchResult {
+        let source_balance = Self::balance(asset_id, source);
+        ensure!(source_balance >= amount, "asset balance too low");
+        Self::set_balance(asset_id, source, source_balance - amount);
+        Self::set_balance(asset_id, dest, Self::balance(asset_id, dest) + amount);
+        Ok(())
+    }
+}
+
+/// Storage key written by every query of MockBooleanApp, to check that queries are rolled back
+pub const MOCK_BOOLEAN_QUERIED_KEY: &[u8] = b":mock_boolean_app:queried";
+
+/// Boolean condition app whose session is finalized and true iff the query is 1
+pub struct MockBooleanApp;
+impl BooleanCondition<H256> for MockBooleanApp {
+    const APP_ID: AppId = *b"mockbool";
+
+    fn is_finalized(_session_id: &H256, query: Option<Vec<u8>>) -> Result<bool, DispatchError> {
+        frame_support::storage::unhashed::put(MOCK_BOOLEAN_QUERIED_KEY, &true);
+        let number: u8 = Decode::decode(&mut &query.unwrap_or_default()[..])
+            .map_err(|_| DispatchError::Other("MustBeDecodable"))?;
+        Ok(number == 1)
+    }
+
+    fn get_outcome(_session_id: &H256, query: Option<Vec<u8>>) -> Result<bool, DispatchError> {
+        let number: u8 = Decode::decode(&mut &query.unwrap_or_default()[..])
+            .map_err(|_| DispatchError::Other("MustBeDecodable"))?;
+        Ok(number == 1)
+    }
+}
+
+/// Numeric condition app backed by `mock_numeric_condition`
+pub struct MockNumericApp;
+impl NumericCondition<H256, Balance> for MockNumericApp {
+    const APP_ID: AppId = *b"mock_num";
+
+    fn is_finalized(session_id: &H256, query: Option<Vec<u8>>) -> Result<bool, DispatchError> {
+        mock_numeric_condition::Module::<TestRuntime>::is_finalized(session_id, query)
+    }
+
+    fn get_outcome(session_id: &H256, query: Option<Vec<u8>>) -> Result<Balance, DispatchError> {
+        let outcome = mock_numeric_condition::Module::<TestRuntime>::get_outcome(session_id, query)?;
+        Ok(outcome.into())
+    }
+}
+
+/// In-memory contracts module answering the condition queries with fixed results
+pub struct MockContracts;
+impl MockContracts {
+    pub fn deploy(address: &AccountId, is_finalized: bool, encoded_outcome: Vec<u8>) {
+        CONTRACTS.with(|c| c.borrow_mut().insert(*address, (is_finalized, encoded_outcome)));
+    }
+
+    pub fn register_virtual(virt_addr: H256, address: &AccountId) {
+        VIRTUAL_CONTRACTS.with(|v| v.borrow_mut().insert(virt_addr, *address));
+    }
+}
+
+impl ConditionContracts<AccountId, H256> for MockContracts {
+    fn resolve(virt_addr: &H256) -> Result<AccountId, DispatchError> {
+        VIRTUAL_CONTRACTS.with(|v| v.borrow().get(virt_addr).cloned())
+            .ok_or(DispatchError::Other("Virtual contract is not deployed"))
+    }
+
+    fn bare_call(
+        _origin: AccountId,
+        dest: AccountId,
+        gas_limit: u64,
+        input_data: Vec<u8>,
+    ) -> Result<Vec<u8>, DispatchError> {
+        ensure!(gas_limit > 0, "OutOfGas");
+        let (is_finalized, encoded_outcome) = CONTRACTS.with(|c| c.borrow().get(&dest).cloned())
+            .ok_or(DispatchError::Other("NotCallable"))?;
+        if input_data.starts_with(&crate::pay_resolver::IS_FINALIZED_SELECTOR) {
+            Ok(is_finalized.encode())
+        } else if input_data.starts_with(&crate::pay_resolver::GET_OUTCOME_SELECTOR) {
+            Ok(encoded_outcome)
+        } else {
+            Err(DispatchError::Other("ContractTrapped"))
+        }
+    }
+}
+
+/// Only calls of the mock boolean condition module can be referenced by legacy conditions
+pub struct MockConditionCallFilter;
+impl Filter<Call> for MockConditionCallFilter {
+    fn filter(call: &Call) -> bool {
+        match call {
+            Call::MockBooleanCondition(_) => true,
+            _ => false,
+        }
+    }
+}
+
+parameter_types! {
+    pub MockLegacyConditionCalls: Vec<(Call, AppId)> = vec![
+        (Call::MockBooleanCondition(mock_boolean_condition::Call::is_finalized(H256::zero(), 0)), *b"mockbool"),
+        (Call::MockBooleanCondition(mock_boolean_condition::Call::get_outcome(H256::zero(), 0)), *b"mockbool"),
+    ];
+}
+
+parameter_types! {
+    pub const MaxChannelPeers: u32 = 4;
+    pub const MaxBooleanCircuitGates: u32 = 16;
+    pub const MaxExpiringAllowances: u32 = 2;
+    pub const ChannelPruneDelay: BlockNumber = 10;
+    pub const PayInfoPruneDelay: BlockNumber = 10;
+    pub const PreimagePruneDelay: BlockNumber = 10;
+    pub const PruneReward: Perbill = Perbill::from_percent(10);
+    pub const AutoFinalizeWeight: Weight = 250_000_000;
+    pub const MaxScheduledFinalizations: u32 = 3;
+}
+
 impl Trait for TestRuntime {
     type Currency = pallet_balances::Module<Self>;
+    type NativeCustody = MockNativeCustody;
+    type ChannelDeposit = MockChannelDeposit;
+    type PayInfoDeposit = MockPayInfoDeposit;
+    type ChannelPruneDelay = ChannelPruneDelay;
+    type PayInfoPruneDelay = PayInfoPruneDelay;
+    type PreimageDeposit = MockPreimageDeposit;
+    type PreimagePruneDelay = PreimagePruneDelay;
+    type PruneReward = PruneReward;
+    type ClearPaysSafeMargin = MockClearPaysSafeMargin;
+    type AutoFinalizeWeight = AutoFinalizeWeight;
+    type MaxScheduledFinalizations = MaxScheduledFinalizations;
+    type Assets = MockAssets;
     type Event = TestEvent;
     type Public = sr25519::Public;
     type Signature = sr25519::Signature;
+    type MaxChannelPeers = MaxChannelPeers;
+    type MaxBooleanCircuitGates = MaxBooleanCircuitGates;
+    type MaxExpiringAllowances = MaxExpiringAllowances;
+    type BooleanConditions = (MockBooleanApp,);
+    type NumericConditions = (MockNumericApp,);
+    type ConditionContracts = MockContracts;
+    type ConditionCallFilter = MockConditionCallFilter;
+    type LegacyConditionCalls = MockLegacyConditionCalls;
     type Call = Call;
 }
 
 pub type CelerPayModule = Module<TestRuntime>;
 pub type System = frame_system::Module<TestRuntime>;
+pub type Balances = pallet_balances::Module<TestRuntime>;
 pub type Timestamp = pallet_timestamp::Module<TestRuntime>;
 type MockBooleanCondition = mock_boolean_condition::Module<TestRuntime>;
 
@@ -161,11 +407,24 @@ impl ExtBuilder {
         let risa: sr25519::Public = account_key("Risa");
         let carl: sr25519::Public = account_key("Carl");
 
+        // genesis balances are checked against the existential deposit
+        MockExistentialDeposit::set(1);
         let mut t = system::GenesisConfig::default()
             .build_storage::<TestRuntime>().unwrap();
         pallet_balances::GenesisConfig::<TestRuntime> {
             balances: vec![(alice, 1000), (bob, 1000), (risa, 1000), (carl, 100000)],
         }.assimilate_storage(&mut t).unwrap();
+
+        ASSET_BALANCES.with(|b| b.borrow_mut().clear());
+        CONTRACTS.with(|c| c.borrow_mut().clear());
+        VIRTUAL_CONTRACTS.with(|v| v.borrow_mut().clear());
+        MockNativeCustody::set(CustodyMode::ModuleAccount);
+        MockStorageDeposits::set(0, 0);
+        MockPreimageDeposit::set(0);
+        MockClearPaysSafeMargin::set(0);
+        for who in [alice, bob, risa].iter() {
+            MockAssets::set_balance(TEST_ASSET_ID, who, 1000);
+        }
         sp_io::TestExternalities::new(t)
     }
 }
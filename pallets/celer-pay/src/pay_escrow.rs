@@ -0,0 +1,256 @@
+use super::{
+    AssetPoolBalances, BalanceOf, EscrowMap, Error,
+    Module as CelerPayModule, PoolBalances, RawEvent
+};
+use crate::traits::Trait;
+use crate::custody::Custody;
+use crate::pay_registry::PayRegistry;
+use crate::pay_resolver::{calculate_pay_id, encode_conditional_pay, ConditionalPayOf, TokenType};
+use codec::{Decode, Encode};
+use frame_support::{
+    ensure,
+    storage::{StorageDoubleMap, StorageMap},
+};
+use frame_system::{self as system, ensure_signed};
+use sp_runtime::traits::{CheckedAdd, CheckedSub, Hash, Zero};
+use sp_runtime::{RuntimeDebug, DispatchError};
+use sp_std::vec;
+
+#[derive(Clone, Eq, PartialEq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
+pub struct EscrowInfo<AccountId, BlockNumber, Balance> {
+    pub src: AccountId,
+    pub dest: AccountId,
+    pub token_type: TokenType,
+    pub amount: Balance, // max transfer amount of the pay locked from Pool balances of src
+    pub resolve_deadline: BlockNumber, // resolve deadline in the cond_pay msg
+}
+
+pub type EscrowInfoOf<T> = EscrowInfo<
+    <T as system::Trait>::AccountId,
+    <T as system::Trait>::BlockNumber,
+    BalanceOf<T>,
+>;
+
+pub struct PayEscrow<T>(sp_std::marker::PhantomData<T>);
+
+impl<T: Trait> PayEscrow<T> {
+    // Lock the max transfer amount of a conditional pay from Pool balances of its source,
+    // so that the pay is settled on-chain without a channel
+    pub fn lock_escrow(
+        origin: T::Origin,
+        cond_pay: ConditionalPayOf<T>,
+    ) -> Result<T::Hash, DispatchError> {
+        let caller = ensure_signed(origin)?;
+        ensure!(caller == cond_pay.src, "Caller is not pay source");
+        ensure!(
+            frame_system::Module::<T>::block_number() <= cond_pay.resolve_deadline,
+            "Passed pay resolve deadline in cond_pay msg"
+        );
+
+        let encoded = encode_conditional_pay::<T>(cond_pay.clone());
+        let pay_id = calculate_pay_id::<T>(T::Hashing::hash(&encoded));
+        ensure!(EscrowMap::<T>::contains_key(&pay_id) == false, "Escrow already exists");
+
+        let token_type = cond_pay.transfer_func.max_transfer.token.token_type.clone();
+        CelerPayModule::<T>::ensure_token_supported(&token_type)?;
+        let amount = cond_pay.transfer_func.max_transfer.receiver.amt;
+        let balances = match get_pool_balances::<T>(&token_type, &caller)? {
+            Some(_balances) => _balances,
+            None => Err(Error::<T>::PoolBalancesNotExist)?,
+        };
+        ensure!(balances >= amount, "caller does not have enough balances");
+        let new_balances = balances.checked_sub(&amount).ok_or(Error::<T>::UnderFlow)?;
+        set_pool_balances::<T>(&token_type, &caller, new_balances);
+
+        let escrow = EscrowInfoOf::<T> {
+            src: caller.clone(),
+            dest: cond_pay.dest.clone(),
+            token_type: token_type,
+            amount: amount,
+            resolve_deadline: cond_pay.resolve_deadline,
+        };
+        EscrowMap::<T>::insert(&pay_id, escrow);
+
+        // Emit LockEscrow event
+        CelerPayModule::<T>::deposit_event(RawEvent::LockEscrow(
+            pay_id,
+            caller,
+            cond_pay.dest,
+            amount
+        ));
+        return Ok(pay_id);
+    }
+
+    // Release the resolved amount of a finalized pay to its destination,
+    // and the remainder back to Pool balances of its source
+    pub fn release_escrow(
+        pay_id: T::Hash,
+    ) -> Result<(BalanceOf<T>, BalanceOf<T>), DispatchError> {
+        let escrow = match EscrowMap::<T>::get(&pay_id) {
+            Some(_escrow) => _escrow,
+            None => Err(Error::<T>::EscrowNotExist)?,
+        };
+
+        // Fail if the pay is not finalized, an unresolved pay is finalized with zero amount
+        // after the resolve deadline in the cond_pay msg
+        let out_amts = PayRegistry::<T>::get_pay_amounts(vec![pay_id], escrow.resolve_deadline)?;
+        let dest_amount = if out_amts[0] < escrow.amount { out_amts[0] } else { escrow.amount };
+        let src_amount = escrow.amount.checked_sub(&dest_amount).ok_or(Error::<T>::UnderFlow)?;
+
+        EscrowMap::<T>::remove(&pay_id);
+
+        if src_amount > Zero::zero() {
+            let balances = get_pool_balances::<T>(&escrow.token_type, &escrow.src)?
+                .unwrap_or(Zero::zero());
+            let new_balances = balances.checked_add(&src_amount).ok_or(Error::<T>::OverFlow)?;
+            set_pool_balances::<T>(&escrow.token_type, &escrow.src, new_balances);
+        }
+        if dest_amount > Zero::zero() {
+            Custody::<T>::withdraw_from_pool(
+                &escrow.token_type,
+                &escrow.src,
+                &escrow.dest,
+                dest_amount,
+            )?;
+        }
+
+        // Emit ReleaseEscrow event
+        CelerPayModule::<T>::deposit_event(RawEvent::ReleaseEscrow(
+            pay_id,
+            dest_amount,
+            src_amount
+        ));
+        return Ok((dest_amount, src_amount));
+    }
+}
+
+fn get_pool_balances<T: Trait>(
+    token_type: &TokenType,
+    owner: &T::AccountId,
+) -> Result<Option<BalanceOf<T>>, DispatchError> {
+    match token_type {
+        TokenType::Celer => Ok(PoolBalances::<T>::get(owner)),
+        TokenType::Asset(asset_id) => Ok(AssetPoolBalances::<T>::get(owner, asset_id)),
+        TokenType::Invalid => Err(Error::<T>::InvalidTokenType)?,
+    }
+}
+
+fn set_pool_balances<T: Trait>(
+    token_type: &TokenType,
+    owner: &T::AccountId,
+    balances: BalanceOf<T>,
+) {
+    match token_type {
+        TokenType::Asset(asset_id) => AssetPoolBalances::<T>::insert(owner, asset_id, balances),
+        _ => PoolBalances::<T>::insert(owner, balances),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::mock::*;
+    use crate::mock::Call as MockCall;
+    use crate::pay_resolver::{
+        Condition, ConditionalPay, PayResolver, ResolvePaymentConditionsRequest, TransferFunctionType
+    };
+    use crate::pool::Pool;
+    use crate::pool::tests::deposit_pool;
+    use crate::tests::pay_resolver::test_pay_resolver::{get_condition, get_transfer_func};
+    use frame_support::assert_noop;
+    use sp_core::H256;
+
+    #[test]
+    fn test_pass_release_escrow_after_pay_is_resolved() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let alice = account_key("Alice");
+            let bob = account_key("Bob");
+            deposit_pool(alice, 100);
+
+            let cond_pay = get_escrow_cond_pay(
+                alice,
+                bob,
+                vec![get_condition(0), get_condition(3)],
+                TransferFunctionType::NumericAdd
+            );
+            let pay_id = PayEscrow::<TestRuntime>::lock_escrow(Origin::signed(alice), cond_pay.clone()).unwrap();
+            assert_eq!(CelerPayModule::get_pool_balance(alice).amount, 70);
+            let err = PayEscrow::<TestRuntime>::lock_escrow(Origin::signed(alice), cond_pay.clone()).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Escrow already exists"));
+
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+
+            // pay is not finalized until its onchain resolve deadline is passed
+            System::set_block_number(11);
+            let err = PayEscrow::<TestRuntime>::release_escrow(pay_id).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Payment is not finalized"));
+
+            System::set_block_number(12);
+            let bob_balance = Balances::free_balance(bob);
+            let (dest_amount, src_amount) = PayEscrow::<TestRuntime>::release_escrow(pay_id).unwrap();
+            assert_eq!((dest_amount, src_amount), (10, 20));
+            assert_eq!(Balances::free_balance(bob), bob_balance + 10);
+            assert_eq!(CelerPayModule::get_pool_balance(alice).amount, 90);
+
+            assert_noop!(
+                PayEscrow::<TestRuntime>::release_escrow(pay_id),
+                Error::<TestRuntime>::EscrowNotExist
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_release_escrow_of_unresolved_pay_after_resolve_deadline() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let alice = account_key("Alice");
+            let bob = account_key("Bob");
+            Pool::<TestRuntime>::deposit_asset_pool(Origin::signed(alice), alice, TEST_ASSET_ID, 100).unwrap();
+
+            let mut cond_pay = get_escrow_cond_pay(alice, bob, vec![], TransferFunctionType::BooleanAnd);
+            cond_pay.transfer_func.max_transfer.token.token_type = TokenType::Asset(TEST_ASSET_ID);
+            let err = PayEscrow::<TestRuntime>::lock_escrow(Origin::signed(bob), cond_pay.clone()).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Caller is not pay source"));
+
+            let pay_id = PayEscrow::<TestRuntime>::lock_escrow(Origin::signed(alice), cond_pay).unwrap();
+            assert_eq!(CelerPayModule::get_asset_pool_balance(alice, TEST_ASSET_ID).amount, 70);
+
+            System::set_block_number(20);
+            let err = PayEscrow::<TestRuntime>::release_escrow(pay_id).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Payment is not finalized"));
+
+            System::set_block_number(21);
+            let (dest_amount, src_amount) = PayEscrow::<TestRuntime>::release_escrow(pay_id).unwrap();
+            assert_eq!((dest_amount, src_amount), (0, 30));
+            assert_eq!(CelerPayModule::get_asset_pool_balance(alice, TEST_ASSET_ID).amount, 100);
+        })
+    }
+
+    fn get_escrow_cond_pay(
+        src: AccountId,
+        dest: AccountId,
+        conditions: Vec<Condition<Moment, BlockNumber, AccountId, H256, MockCall>>,
+        logic_type: TransferFunctionType,
+    ) -> ConditionalPay<Moment, BlockNumber, AccountId, H256, MockCall, Balance> {
+        let mut transfer_func = get_transfer_func(dest, 30, 0);
+        transfer_func.logic_type = logic_type;
+        return ConditionalPay {
+            pay_timestamp: Timestamp::get(),
+            src: src,
+            dest: dest,
+            conditions: conditions,
+            transfer_func: transfer_func,
+            resolve_deadline: 20,
+            resolve_timeout: 10,
+            lottery: None,
+            stream: None,
+        };
+    }
+}
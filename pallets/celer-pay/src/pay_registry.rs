@@ -1,9 +1,11 @@
-use super::{BalanceOf, PayInfoMap, Module as CelerPayModule, RawEvent};
+use super::{BalanceOf, Error, EscrowMap, PayInfoMap, Module as CelerPayModule, RawEvent};
+use crate::pay_resolver::{encode_conditional_pay, ConditionalPayOf};
 use crate::traits::Trait;
+use crate::storage_deposit::StorageDeposit;
 use codec::{Decode, Encode};
-use frame_support::{ensure, storage::StorageMap};
-use frame_system::{self as system};
-use sp_runtime::traits::{Hash, Zero};
+use frame_support::{ensure, storage::StorageMap, traits::Get};
+use frame_system::{self as system, ensure_signed};
+use sp_runtime::traits::{CheckedAdd, Hash, Zero};
 use sp_runtime::{RuntimeDebug, DispatchError};
 use sp_std::{vec, vec::Vec};
 
@@ -288,4 +290,42 @@ impl<T: Trait> PayRegistry<T> {
             }
         }
     }
+
+    // Remove a resolved pay info and refund its storage deposit after the resolve deadline of the pay
+    // and `PayInfoPruneDelay` from its onchain resolve deadline.
+    // The pay can not be resolved again once its resolve deadline has passed.
+    pub fn prune_pay_info(
+        origin: T::Origin,
+        cond_pay: ConditionalPayOf<T>,
+    ) -> Result<BalanceOf<T>, DispatchError> {
+        let caller = ensure_signed(origin)?;
+        let encoded = encode_conditional_pay::<T>(cond_pay.clone());
+        let pay_id = Self::calculate_pay_id(T::Hashing::hash(&encoded));
+        let pay_info = match PayInfoMap::<T>::get(&pay_id) {
+            Some(_pay_info) => _pay_info,
+            None => Err(Error::<T>::PayInfoNotExist)?,
+        };
+        let resolve_deadline = pay_info.resolve_deadline.unwrap_or(Zero::zero());
+        ensure!(!resolve_deadline.is_zero(), "Payment is not resolved");
+
+        // the pay can not be resolved again after its resolve deadline
+        let block_number = frame_system::Module::<T>::block_number();
+        ensure!(block_number > cond_pay.resolve_deadline, "Pay resolve deadline not passed");
+        let prune_block = resolve_deadline.checked_add(&T::PayInfoPruneDelay::get())
+            .ok_or(Error::<T>::OverFlow)?;
+        ensure!(block_number > prune_block, "Pay info prune delay not passed");
+        // escrow is released by the resolved amount of the pay
+        ensure!(EscrowMap::<T>::contains_key(&pay_id) == false, "Escrow is not released");
+
+        PayInfoMap::<T>::remove(&pay_id);
+        let reward = StorageDeposit::<T>::refund_pay_info_deposit(&pay_id, &caller);
+
+        // Emit PrunePayInfo event
+        CelerPayModule::<T>::deposit_event(RawEvent::PrunePayInfo(
+            pay_id,
+            caller,
+            reward
+        ));
+        return Ok(reward);
+    }
 }
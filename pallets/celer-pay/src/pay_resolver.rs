@@ -1,34 +1,62 @@
-use super::{BalanceOf, Error, Module as CelerPayModule, RawEvent};
-use crate::traits::Trait;
+use super::{BalanceOf, Error, Module as CelerPayModule, PayInfoMap, RawEvent};
+use crate::traits::{AppId, Trait};
 use crate::pay_registry::PayRegistry;
-use crate::numeric_condition_caller::NumericConditionCaller;
-use codec::{Decode, Encode};
-use frame_support::{ensure};
+use crate::condition_caller::ConditionCaller;
+use crate::preimage_registry::PreimageRegistry;
+use codec::{Compact, Decode, Encode};
+use frame_support::{ensure, storage::StorageMap, traits::Get, weights::Weight};
 use frame_system::{self as system};
 use pallet_timestamp;
-use sp_runtime::traits::{CheckedAdd, Hash, Zero, Dispatchable};
-use sp_runtime::{ModuleId, RuntimeDebug, DispatchError};
+use sp_runtime::traits::{CheckedAdd, Hash, Saturating, Zero};
+use sp_runtime::{ModuleId, Perbill, PerThing, RuntimeDebug, DispatchError, SaturatedConversion};
 use sp_std::{vec::Vec, boxed::Box};
+pub use celer_pay_module_rpc_runtime_api::HashType;
 
 pub const PAY_RESOLVER_ID: ModuleId = ModuleId(*b"Resolver");
 
+// Selectors of `is_finalized(query: Vec<u8>) -> bool` and `get_outcome(query: Vec<u8>) -> bool / Balance`
+// messages of condition contracts, the first 4 bytes of blake2_256 of the message name
+pub const IS_FINALIZED_SELECTOR: [u8; 4] = [0xb5, 0xea, 0x21, 0xb9];
+pub const GET_OUTCOME_SELECTOR: [u8; 4] = [0x2c, 0x72, 0x9f, 0x82];
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
 pub enum ConditionType {
     HashLock,
     BooleanRuntimeModule,
     NumericRuntimeModule, 
+    DeployedContract,
+    VirtualContract,
+    OracleSigned,
+    BlockAfter,
+    BlockBefore,
+    TimeAfter,
+    TimeBefore,
+    PayResult,
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
-pub struct Condition<Hash, Call> {
+pub struct Condition<Moment, BlockNumber, AccountId, Hash, Call> {
     pub condition_type: ConditionType,
     pub hash_lock: Option<Hash>,
-    pub call_is_finalized: Option<Box<Call>>, // overarching call is_finalized of boolean runtime module
-    pub call_get_outcome: Option<Box<Call>>, // overarching call get_outcome of boolean runtime module
-    pub numeric_app_num: Option<u32>, // number of registered numeric app 
+    pub hash_type: Option<HashType>, // hash function of hash_lock, blake2_256 if None
+    pub call_is_finalized: Option<Box<Call>>, // deprecated and not dispatched, answered by the boolean app recorded for the call
+    pub call_get_outcome: Option<Box<Call>>, // deprecated and not dispatched, answered by the boolean app recorded for the call
+    pub boolean_app_num: Option<u32>, // legacy number of registered boolean app
+    pub boolean_session_id: Option<Hash>, // session id of boolean condition
+    pub numeric_app_num: Option<u32>, // legacy number of registered numeric app 
     pub numeric_session_id: Option<Hash>, // session id of numeric condition
-    pub args_query_finalzation: Option<Vec<u8>>, // the encoded query finalization of numeric runtime module
-    pub args_query_outcome: Option<Vec<u8>>, // the encoded query outcome of numeric runtime module
+    pub args_query_finalzation: Option<Vec<u8>>, // the encoded query finalization of registered app or contract
+    pub args_query_outcome: Option<Vec<u8>>, // the encoded query outcome of registered app or contract
+    pub deployed_contract_address: Option<AccountId>, // address of deployed condition contract
+    pub virtual_contract_address: Option<Hash>, // off-chain address of virtual condition contract
+    pub gas_limit: Option<u64>, // gas limit of each query of condition contract
+    pub oracle: Option<AccountId>, // public key of the oracle signing the outcome
+    pub question_hash: Option<Hash>, // hash of the question answered by the oracle
+    pub block_number: Option<BlockNumber>, // true from this block for BlockAfter, true until this block for BlockBefore
+    pub timestamp: Option<Moment>, // true from this time for TimeAfter, true until this time for TimeBefore
+    pub dependent_pay_id: Option<Hash>, // id of the pay whose resolved amount is the outcome of PayResult condition
+    pub boolean_app_id: Option<AppId>, // stable id of registered boolean app, used instead of boolean_app_num if set
+    pub numeric_app_id: Option<AppId>, // stable id of registered numeric app, used instead of numeric_app_num if set
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
@@ -39,13 +67,18 @@ pub enum TransferFunctionType {
     NumericAdd,
     NumericMax,
     NumericMin,
+    Lottery,
+    Linear,
 }
 
-// Currently native token is only supoorted.
+/// Identifier of a fungible asset held in `Trait::Assets`
+pub type AssetId = u32;
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
 pub enum TokenType {
     Invalid,
     Celer, // native token. If Kusama network,change from Celer to Ksm.
+    Asset(AssetId), // fungible asset of Assets module
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
@@ -65,10 +98,44 @@ pub struct TokenTransfer<AccountId, Balance> {
     pub receiver: AccountAmtPair<AccountId, Balance>,
 }
 
+// Gate of BooleanCircut transfer function.
+// Wires 0..N-1 are outcomes of the N conditions of the pay and
+// the output of the k-th gate is appended as wire N+k.
+// The output of the last gate is the outcome of the circuit.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
+pub enum BooleanGate {
+    And(u32, u32),
+    Or(u32, u32),
+    Not(u32),
+}
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
 pub struct TransferFunction<AccountId, Balance> {
     pub logic_type: TransferFunctionType,
     pub max_transfer: TokenTransfer<AccountId, Balance>,
+    pub boolean_circuit: Option<Vec<u8>>, // encoded Vec<BooleanGate> of BooleanCircut
+}
+
+// Ticket of Lottery transfer function.
+// The ticket wins max_transfer with probability win_prob,
+// so its expected value is the nominal amount win_prob * max_transfer.
+// The ticket is drawn from the payer's secret and the hash of draw_block, which are both fixed
+// before the block is produced, so that neither the payer nor the resolver can choose the draw.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
+pub struct LotteryTicket<Hash, BlockNumber> {
+    pub win_prob: Perbill,
+    pub secret_commitment: Hash, // hash of the payer's secret revealed to draw the ticket
+    pub draw_block: BlockNumber, // block whose hash draws the ticket, the resolve deadline must be at most BlockHashCount blocks later
+}
+
+// Stream of Linear transfer function.
+// The stream accrues rate_per_block in each block from start_block until end_block,
+// capped at max_transfer.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
+pub struct LinearStream<BlockNumber, Balance> {
+    pub rate_per_block: Balance,
+    pub start_block: BlockNumber,
+    pub end_block: BlockNumber,
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
@@ -76,12 +143,22 @@ pub struct ConditionalPay<Moment, BlockNumber, AccountId, Hash, Call, Balance> {
     pub pay_timestamp: Moment,
     pub src: AccountId,
     pub dest: AccountId,
-    pub conditions: Vec<Condition<Hash, Call>>,
+    pub conditions: Vec<Condition<Moment, BlockNumber, AccountId, Hash, Call>>,
     pub transfer_func: TransferFunction<AccountId, Balance>,
     pub resolve_deadline: BlockNumber,
     pub resolve_timeout: BlockNumber,
+    pub lottery: Option<LotteryTicket<Hash, BlockNumber>>, // ticket of Lottery transfer function
+    pub stream: Option<LinearStream<BlockNumber, Balance>>, // stream of Linear transfer function
 }
 
+pub type ConditionOf<T> = Condition<
+    <T as pallet_timestamp::Trait>::Moment,
+    <T as system::Trait>::BlockNumber,
+    <T as system::Trait>::AccountId,
+    <T as system::Trait>::Hash,
+    <T as Trait>::Call,
+>;
+
 pub type ConditionalPayOf<T> = ConditionalPay<
     <T as pallet_timestamp::Trait>::Moment,
     <T as system::Trait>::BlockNumber,
@@ -92,9 +169,30 @@ pub type ConditionalPayOf<T> = ConditionalPay<
 >;
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
-pub struct ResolvePaymentConditionsRequest<Moment, BlockNumber, AccountId, Hash, Call, Balance> {
+pub enum OracleResult<Balance> {
+    Boolean(bool),
+    Numeric(Balance),
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
+pub struct OracleOutcome<Hash, Balance, Signature> {
+    pub question_hash: Hash,
+    pub result: OracleResult<Balance>,
+    pub sig: Signature, // signature of the oracle over question_hash and result
+}
+
+pub type OracleOutcomeOf<T> = OracleOutcome<
+    <T as system::Trait>::Hash,
+    BalanceOf<T>,
+    <T as Trait>::Signature,
+>;
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
+pub struct ResolvePaymentConditionsRequest<Moment, BlockNumber, AccountId, Hash, Call, Balance, Signature> {
     pub cond_pay: ConditionalPay<Moment, BlockNumber, AccountId, Hash, Call, Balance>,
     pub hash_preimages: Vec<Hash>,
+    pub oracle_outcomes: Vec<OracleOutcome<Hash, Balance, Signature>>, // outcomes of OracleSigned conditions in order
+    pub lottery_secret: Option<Hash>, // payer's secret of the Lottery ticket
 }
 
 pub type ResolvePaymentConditionsRequestOf<T> = ResolvePaymentConditionsRequest<
@@ -104,6 +202,7 @@ pub type ResolvePaymentConditionsRequestOf<T> = ResolvePaymentConditionsRequest<
     <T as system::Trait>::Hash,
     <T as Trait>::Call,
     BalanceOf<T>,
+    <T as Trait>::Signature,
 >;
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
@@ -144,18 +243,54 @@ impl<T: Trait> PayResolver<T> {
             amount = calculate_boolean_and_payment::<T>(
                 pay.clone(),
                 resolve_pay_request.hash_preimages,
+                resolve_pay_request.oracle_outcomes,
             )?;
         } else if func_type == TransferFunctionType::BooleanOr {
             amount = calculate_boolean_or_payment::<T>(
                 pay.clone(), 
-                resolve_pay_request.hash_preimages
+                resolve_pay_request.hash_preimages,
+                resolve_pay_request.oracle_outcomes
+            )?;
+        } else if func_type == TransferFunctionType::BooleanCircut {
+            amount = calculate_boolean_circuit_payment::<T>(
+                pay.clone(),
+                resolve_pay_request.hash_preimages,
+                resolve_pay_request.oracle_outcomes
             )?;
         } else if is_numeric_logic::<T>(func_type.clone()) {
             amount = calculate_numeric_logic_payment::<T>(
                 pay.clone(),
                 resolve_pay_request.hash_preimages,
+                resolve_pay_request.oracle_outcomes,
                 func_type.clone(),
             )?;
+        } else if func_type == TransferFunctionType::Lottery {
+            // all conditions of the ticket must be met before it is drawn
+            let conditions_amount = calculate_boolean_and_payment::<T>(
+                pay.clone(),
+                resolve_pay_request.hash_preimages,
+                resolve_pay_request.oracle_outcomes,
+            )?;
+            ensure!(
+                conditions_amount == pay.transfer_func.max_transfer.receiver.amt,
+                "Lottery conditions are not met"
+            );
+            let encoded = encode_conditional_pay::<T>(pay.clone());
+            amount = draw_lottery_ticket::<T>(
+                &pay,
+                T::Hashing::hash(&encoded),
+                resolve_pay_request.lottery_secret,
+            )?;
+        } else if func_type == TransferFunctionType::Linear {
+            // the stream accrues only while all of its conditions are met
+            let conditions_amount = calculate_boolean_and_payment::<T>(
+                pay.clone(),
+                resolve_pay_request.hash_preimages,
+                resolve_pay_request.oracle_outcomes,
+            )?;
+            if conditions_amount == pay.transfer_func.max_transfer.receiver.amt {
+                amount = calculate_linear_payment::<T>(&pay)?;
+            }
         } else {
             Err(Error::<T>::Error)?
         }
@@ -205,6 +340,10 @@ fn resolve_payment<T: Trait>(
         block_number <= pay.resolve_deadline,
         "Passed pay resolve deadline in cond_pay msg"
     );
+    ensure!(
+        pay.transfer_func.max_transfer.token.token_type != TokenType::Invalid,
+        "Invalid token type"
+    );
 
     let pay_id = calculate_pay_id::<T>(pay_hash);
     let pay_info: (BalanceOf<T>, T::BlockNumber) = PayRegistry::<T>::get_pay_info(pay_id)?;
@@ -250,9 +389,17 @@ fn resolve_payment<T: Trait>(
         if amount == pay.transfer_func.max_transfer.receiver.amt {
             new_deadline = block_number.clone();
         } else {
-            let timeout = block_number.checked_add(&pay.resolve_timeout).ok_or(Error::<T>::OverFlow)?;
+            let mut timeout = block_number.checked_add(&pay.resolve_timeout).ok_or(Error::<T>::OverFlow)?;
+            // the stream of a Linear payment can be re-resolved until its end_block
+            if pay.transfer_func.logic_type == TransferFunctionType::Linear {
+                if let Some(ref stream) = pay.stream {
+                    if timeout < stream.end_block {
+                        timeout = stream.end_block;
+                    }
+                }
+            }
             if timeout < pay.resolve_deadline {
-                new_deadline = block_number.checked_add(&pay.resolve_timeout).ok_or(Error::<T>::OverFlow)?;
+                new_deadline = timeout;
             } else {
                 new_deadline = pay.resolve_deadline;
             }
@@ -272,43 +419,444 @@ fn resolve_payment<T: Trait>(
     }
 }
 
+// Verify that hash of the next supplied preimage matches hash_lock of the condition,
+// and move `j` to the following preimage. If it does not match, the preimage of hash_lock
+// must have been revealed in PreimageRegistry, and the supplied preimage is left for the next hash lock.
+fn verify_hash_lock<T: Trait>(
+    cond: &ConditionOf<T>,
+    preimages: &Vec<T::Hash>,
+    j: &mut usize,
+) -> Result<(), DispatchError> {
+    let hash_lock = match cond.hash_lock {
+        Some(lock) => lock,
+        None => Err(Error::<T>::HashLockNotExist)?,
+    };
+    let hash_type = cond.hash_type.unwrap_or_default();
+
+    if let Some(preimage) = preimages.get(*j) {
+        if PreimageRegistry::<T>::calculate_hash_lock(preimage, hash_type)? == hash_lock {
+            *j = *j + 1;
+            return Ok(());
+        }
+    }
+    if PreimageRegistry::<T>::is_revealed(&hash_lock, hash_type) {
+        return Ok(());
+    }
+
+    ensure!(preimages.get(*j).is_none(), "Wrong preimage");
+    Err(DispatchError::Other("Preimage not revealed"))
+}
+
+// Draw the ticket of a Lottery payment with the payer's secret mixed with the hash of its draw block.
+// The draw does not depend on the block in which the pay is resolved.
+// Return max_transfer amount if the ticket wins, otherwise 0.
+fn draw_lottery_ticket<T: Trait>(
+    pay: &ConditionalPayOf<T>,
+    pay_hash: T::Hash,
+    secret: Option<T::Hash>,
+) -> Result<BalanceOf<T>, DispatchError> {
+    let ticket = match pay.lottery {
+        Some(ref ticket) => ticket.clone(),
+        None => Err(Error::<T>::LotteryTicketNotExist)?,
+    };
+    let secret = match secret {
+        Some(secret) => secret,
+        None => Err(Error::<T>::LotterySecretNotExist)?,
+    };
+    ensure!(T::Hashing::hash(secret.as_ref()) == ticket.secret_commitment, "Wrong lottery secret");
+
+    // block hashes are kept for BlockHashCount blocks, so a ticket whose pay can be resolved later
+    // could not be drawn at the end of its resolve window
+    let hash_window_end = ticket.draw_block.checked_add(&<T as system::Trait>::BlockHashCount::get())
+        .ok_or(Error::<T>::OverFlow)?;
+    ensure!(
+        pay.resolve_deadline <= hash_window_end,
+        "Lottery resolve deadline beyond block hash window"
+    );
+    ensure!(
+        frame_system::Module::<T>::block_number() > ticket.draw_block,
+        "Lottery draw block not reached"
+    );
+    let draw_block_hash = frame_system::Module::<T>::block_hash(ticket.draw_block);
+    ensure!(draw_block_hash != T::Hash::default(), "Lottery draw block hash not available");
+
+    let mut encoded = secret.encode();
+    encoded.extend(draw_block_hash.encode());
+    encoded.extend(pay_hash.encode());
+    let draw = T::Hashing::hash(&encoded);
+    let mut draw_bytes = [0u8; 4];
+    draw_bytes.copy_from_slice(&draw.as_ref()[0..4]);
+    let draw_num = u32::from_le_bytes(draw_bytes) as u64;
+
+    // the ticket wins iff draw_num / 2^32 < win_prob
+    if draw_num * (Perbill::ACCURACY as u64) < (ticket.win_prob.deconstruct() as u64) << 32 {
+        return Ok(pay.transfer_func.max_transfer.receiver.amt);
+    } else {
+        return Ok(Zero::zero());
+    }
+}
+
+// Calculate the amount accrued by the stream of a Linear payment until the current block.
+// The destination can re-resolve the pay periodically, since resolve_payment accepts
+// monotone increasing amounts until the onchain resolve deadline.
+fn calculate_linear_payment<T: Trait>(
+    pay: &ConditionalPayOf<T>,
+) -> Result<BalanceOf<T>, DispatchError> {
+    let stream = match pay.stream {
+        Some(ref stream) => stream.clone(),
+        None => Err(Error::<T>::LinearStreamNotExist)?,
+    };
+    ensure!(stream.start_block < stream.end_block, "Invalid stream period");
+
+    let block_number = frame_system::Module::<T>::block_number();
+    if block_number <= stream.start_block {
+        return Ok(Zero::zero());
+    }
+    let end_block = if block_number < stream.end_block { block_number } else { stream.end_block };
+    let accrued_blocks: BalanceOf<T> = (end_block - stream.start_block).saturated_into::<u128>().saturated_into();
+    let amount = stream.rate_per_block.saturating_mul(accrued_blocks);
+
+    let max_amount = pay.transfer_func.max_transfer.receiver.amt;
+    if amount < max_amount {
+        return Ok(amount);
+    } else {
+        return Ok(max_amount);
+    }
+}
+
+// Verify the oracle signed outcome of an OracleSigned condition and return its result
+fn verify_oracle_outcome<T: Trait>(
+    cond: &ConditionOf<T>,
+    oracle_outcome: Option<&OracleOutcomeOf<T>>,
+) -> Result<OracleResult<BalanceOf<T>>, DispatchError> {
+    let oracle = match cond.oracle {
+        Some(ref oracle) => oracle.clone(),
+        None => Err(Error::<T>::OracleNotExist)?,
+    };
+    let question_hash = match cond.question_hash {
+        Some(hash) => hash,
+        None => Err(Error::<T>::QuestionHashNotExist)?,
+    };
+    let oracle_outcome = match oracle_outcome {
+        Some(outcome) => outcome.clone(),
+        None => Err(Error::<T>::OracleOutcomeNotExist)?,
+    };
+    ensure!(oracle_outcome.question_hash == question_hash, "Wrong question hash");
+
+    let encoded = encode_oracle_outcome::<T>(&oracle_outcome);
+    CelerPayModule::<T>::check_single_signature(oracle_outcome.sig, &encoded, oracle)?;
+    Ok(oracle_outcome.result)
+}
+
+// Return the boolean result of an OracleSigned condition
+fn get_oracle_boolean_outcome<T: Trait>(
+    cond: &ConditionOf<T>,
+    oracle_outcome: Option<&OracleOutcomeOf<T>>,
+) -> Result<bool, DispatchError> {
+    match verify_oracle_outcome::<T>(cond, oracle_outcome)? {
+        OracleResult::Boolean(outcome) => Ok(outcome),
+        OracleResult::Numeric(_) => Err(Error::<T>::InvalidOracleResult)?,
+    }
+}
+
+// Return the numeric result of an OracleSigned condition
+fn get_oracle_numeric_outcome<T: Trait>(
+    cond: &ConditionOf<T>,
+    oracle_outcome: Option<&OracleOutcomeOf<T>>,
+) -> Result<BalanceOf<T>, DispatchError> {
+    match verify_oracle_outcome::<T>(cond, oracle_outcome)? {
+        OracleResult::Numeric(outcome) => Ok(outcome),
+        OracleResult::Boolean(_) => Err(Error::<T>::InvalidOracleResult)?,
+    }
+}
+
+// Return whether the current block number or timestamp satisfies a time condition.
+// BlockAfter and TimeAfter hold from the given point on and are not finalized before it,
+// BlockBefore and TimeBefore hold until it.
+fn get_time_outcome<T: Trait>(
+    cond: &ConditionOf<T>,
+) -> Result<bool, DispatchError> {
+    match cond.condition_type {
+        ConditionType::BlockAfter | ConditionType::BlockBefore => {
+            let block_number = match cond.block_number {
+                Some(number) => number,
+                None => Err(Error::<T>::BlockNumberNotExist)?,
+            };
+            let current_block = frame_system::Module::<T>::block_number();
+            if cond.condition_type == ConditionType::BlockAfter {
+                ensure!(current_block >= block_number, "Condition is not finalized");
+                return Ok(true);
+            } else {
+                return Ok(current_block < block_number);
+            }
+        },
+        ConditionType::TimeAfter | ConditionType::TimeBefore => {
+            let timestamp = match cond.timestamp {
+                Some(time) => time,
+                None => Err(Error::<T>::TimestampNotExist)?,
+            };
+            let now = pallet_timestamp::Module::<T>::get();
+            if cond.condition_type == ConditionType::TimeAfter {
+                ensure!(now >= timestamp, "Condition is not finalized");
+                return Ok(true);
+            } else {
+                return Ok(now < timestamp);
+            }
+        },
+        _ => Err(Error::<T>::Error)?,
+    }
+}
+
+// Return the resolved amount of the pay which a PayResult condition depends on.
+// The dependent pay must have been resolved and passed its resolve deadline.
+fn get_pay_result_amount<T: Trait>(
+    cond: &ConditionOf<T>,
+) -> Result<BalanceOf<T>, DispatchError> {
+    let pay_id = match cond.dependent_pay_id {
+        Some(id) => id,
+        None => Err(Error::<T>::DependentPayIdNotExist)?,
+    };
+    // read the pay info without recording an unknown pay
+    let pay_info = match PayInfoMap::<T>::get(&pay_id) {
+        Some(_pay_info) => _pay_info,
+        None => Err(DispatchError::Other("Dependent pay is not finalized"))?,
+    };
+    let resolve_deadline = pay_info.resolve_deadline.unwrap_or(Zero::zero());
+    ensure!(
+        !resolve_deadline.is_zero()
+            && frame_system::Module::<T>::block_number() > resolve_deadline,
+        "Dependent pay is not finalized"
+    );
+    return Ok(pay_info.amount.unwrap_or(Zero::zero()));
+}
+
+// Return whether the dependent pay resolved to at least the amount encoded in args_query_outcome,
+// or to a non-zero amount if no amount is given
+fn get_pay_result_boolean_outcome<T: Trait>(
+    cond: &ConditionOf<T>,
+) -> Result<bool, DispatchError> {
+    let amount = get_pay_result_amount::<T>(cond)?;
+    match cond.args_query_outcome {
+        Some(ref query) => {
+            let min_amount = match BalanceOf::<T>::decode(&mut &query[..]) {
+                Ok(min_amount) => min_amount,
+                Err(_) => Err(Error::<T>::InvalidPayResultQuery)?,
+            };
+            return Ok(amount >= min_amount);
+        },
+        None => return Ok(amount > Zero::zero()),
+    }
+}
+
+// Return the outcome of a boolean runtime module, contract, time or pay result condition, which must be finalized
+fn get_boolean_outcome<T: Trait>(
+    cond: ConditionOf<T>,
+) -> Result<bool, DispatchError> {
+    if is_time_condition(&cond.condition_type) {
+        return get_time_outcome::<T>(&cond);
+    }
+    if cond.condition_type == ConditionType::PayResult {
+        return get_pay_result_boolean_outcome::<T>(&cond);
+    }
+
+    if is_contract_condition(&cond.condition_type) {
+        let (contract_address, gas_limit) = get_contract_address_and_gas_limit::<T>(&cond)?;
+        let is_finalized: bool = ConditionCaller::<T>::call_contract(
+            contract_address.clone(),
+            gas_limit,
+            IS_FINALIZED_SELECTOR,
+            cond.args_query_finalzation
+        )?;
+        ensure!(is_finalized == true, "Condition is not finalized");
+
+        return ConditionCaller::<T>::call_contract(
+            contract_address,
+            gas_limit,
+            GET_OUTCOME_SELECTOR,
+            cond.args_query_outcome
+        );
+    }
+
+    // a legacy condition referencing a boolean runtime module only by its calls
+    if cond.boolean_app_id.is_none() && cond.boolean_app_num.is_none() {
+        return get_legacy_call_outcome::<T>(cond);
+    }
+
+    // the id of registered boolean app
+    let boolean_app_id = ConditionCaller::<T>::boolean_app_id(cond.boolean_app_id, cond.boolean_app_num)?;
+    // session id of boolean condition
+    let session_id = match cond.boolean_session_id {
+        Some(id) => id,
+        None => Err(Error::<T>::BooleanSessionIdNotExist)?,
+    };
+
+    let is_finalized: bool = ConditionCaller::<T>::call_boolean_is_finalized(
+        &boolean_app_id,
+        &session_id,
+        cond.args_query_finalzation
+    )?;
+    ensure!(is_finalized == true, "Condition is not finalized");
+
+    return ConditionCaller::<T>::call_boolean_get_outcome(
+        &boolean_app_id,
+        &session_id,
+        cond.args_query_outcome
+    );
+}
+
+// Return the outcome of a legacy condition referencing a boolean runtime module by its calls, which must be finalized.
+// The calls are not dispatched, since their encoding changes with the pallet and call indices of the runtime.
+fn get_legacy_call_outcome<T: Trait>(
+    cond: ConditionOf<T>,
+) -> Result<bool, DispatchError> {
+    // call is_finalized of boolean condition
+    let call_is_finalized = match cond.call_is_finalized {
+        Some(call) => call,
+        None => Err(Error::<T>::CallIsFinalizedNotExist)?,
+    };
+    // call get_outcome of boolean condition
+    let call_get_outcome = match cond.call_get_outcome {
+        Some(call) => call,
+        None => Err(Error::<T>::CallGetOutcomeNotExist)?,
+    };
+
+    let (boolean_app_id, session_id, query) = ConditionCaller::<T>::legacy_call_query(*call_is_finalized)?;
+    let is_finalized: bool = ConditionCaller::<T>::call_boolean_is_finalized(
+        &boolean_app_id,
+        &session_id,
+        Some(query)
+    )?;
+    ensure!(is_finalized == true, "Condition is not finalized");
+
+    let (boolean_app_id, session_id, query) = ConditionCaller::<T>::legacy_call_query(*call_get_outcome)?;
+    return ConditionCaller::<T>::call_boolean_get_outcome(
+        &boolean_app_id,
+        &session_id,
+        Some(query)
+    );
+}
+
+// Return the outcome of a numeric runtime module, contract or pay result condition, which must be finalized
+fn get_numeric_outcome<T: Trait>(
+    cond: ConditionOf<T>,
+) -> Result<BalanceOf<T>, DispatchError> {
+    if cond.condition_type == ConditionType::PayResult {
+        return get_pay_result_amount::<T>(&cond);
+    }
+
+    if is_contract_condition(&cond.condition_type) {
+        let (contract_address, gas_limit) = get_contract_address_and_gas_limit::<T>(&cond)?;
+        let is_finalized: bool = ConditionCaller::<T>::call_contract(
+            contract_address.clone(),
+            gas_limit,
+            IS_FINALIZED_SELECTOR,
+            cond.args_query_finalzation
+        )?;
+        ensure!(is_finalized == true, "Condition is not finalized");
+
+        return ConditionCaller::<T>::call_contract(
+            contract_address,
+            gas_limit,
+            GET_OUTCOME_SELECTOR,
+            cond.args_query_outcome
+        );
+    }
+
+    // the id of registered numeric app
+    let numeric_app_id = ConditionCaller::<T>::numeric_app_id(cond.numeric_app_id, cond.numeric_app_num)?;
+    // session id of numeric condition
+    let session_id = match cond.numeric_session_id {
+        Some(id) => id,
+        None => Err(Error::<T>::NumericSessionIdNotExist)?,
+    };
+
+    let is_finalized: bool = ConditionCaller::<T>::call_numeric_is_finalized(
+        &numeric_app_id, 
+        &session_id, 
+        cond.args_query_finalzation
+    )?;
+    ensure!(is_finalized == true, "Condition is not finalized");
+
+    return ConditionCaller::<T>::call_numeric_get_outcome(
+        &numeric_app_id, 
+        &session_id, 
+        cond.args_query_outcome
+    );
+}
+
+// Return the deployed address and the gas limit of a contract condition.
+// The address of a virtual contract is resolved from its off-chain address.
+fn get_contract_address_and_gas_limit<T: Trait>(
+    cond: &ConditionOf<T>,
+) -> Result<(T::AccountId, u64), DispatchError> {
+    let contract_address = if cond.condition_type == ConditionType::DeployedContract {
+        match cond.deployed_contract_address {
+            Some(ref address) => address.clone(),
+            None => Err(Error::<T>::ConditionAddressNotExist)?,
+        }
+    } else {
+        match cond.virtual_contract_address {
+            Some(ref virt_addr) => T::ConditionContracts::resolve(virt_addr)?,
+            None => Err(Error::<T>::ConditionAddressNotExist)?,
+        }
+    };
+    let gas_limit = match cond.gas_limit {
+        Some(limit) => limit,
+        None => Err(Error::<T>::GasLimitNotExist)?,
+    };
+    return Ok((contract_address, gas_limit));
+}
+
+fn is_contract_condition(condition_type: &ConditionType) -> bool {
+    return *condition_type == ConditionType::DeployedContract
+        || *condition_type == ConditionType::VirtualContract;
+}
+
+fn is_time_condition(condition_type: &ConditionType) -> bool {
+    return *condition_type == ConditionType::BlockAfter
+        || *condition_type == ConditionType::BlockBefore
+        || *condition_type == ConditionType::TimeAfter
+        || *condition_type == ConditionType::TimeBefore;
+}
+
+fn is_boolean_condition(condition_type: &ConditionType) -> bool {
+    return *condition_type == ConditionType::BooleanRuntimeModule
+        || is_contract_condition(condition_type)
+        || is_time_condition(condition_type)
+        || *condition_type == ConditionType::PayResult;
+}
+
+// Return the gas limits of all contract conditions of the pay, each contract is queried twice
+pub fn contract_conditions_gas_limit<T: Trait>(pay: &ConditionalPayOf<T>) -> Weight {
+    let mut gas_limit: Weight = 0;
+    for cond in pay.conditions.iter() {
+        if is_contract_condition(&cond.condition_type) {
+            gas_limit = gas_limit.saturating_add(cond.gas_limit.unwrap_or(0).saturating_mul(2));
+        }
+    }
+    return gas_limit;
+}
+
 // Calculate the result amount of BooleanAnd payment
 fn calculate_boolean_and_payment<T: Trait>(
     pay: ConditionalPayOf<T>,
     preimages: Vec<T::Hash>,
+    oracle_outcomes: Vec<OracleOutcomeOf<T>>,
 ) -> Result<BalanceOf<T>, DispatchError> {
     let mut j: usize = 0;
+    let mut k: usize = 0;
     let mut has_false_contract_cond: bool = false;
     for i in 0..pay.conditions.len() {
         let cond = pay.conditions[i].clone();
         if cond.condition_type == ConditionType::HashLock {
-            let hash_lock = match cond.hash_lock {
-                Some(lock) => lock,
-                None => Err(Error::<T>::HashLockNotExist)?,
-            };
-            ensure!(preimages[j] == hash_lock, "Wrong preimage");
-            j = j + 1;
-        } else if cond.condition_type == ConditionType::BooleanRuntimeModule {
-            let pay_resolver_account = CelerPayModule::<T>::get_pay_resolver_id();
-            
-            // call is_finalized of boolean condition
-            let call_is_finalized = match cond.call_is_finalized {
-                Some(call) => call,
-                None => Err(Error::<T>::CallIsFinalizedNotExist)?,
-            };
-            let is_finalized = call_is_finalized.dispatch(frame_system::RawOrigin::Signed(pay_resolver_account.clone()).into());
-            ensure!(
-                is_finalized.is_ok(),
-                "Condition is not finalized"
-            );
-
-            // call get_outcome of boolean condition
-            let call_get_outcome = match cond.call_get_outcome {
-                Some(call) => call,
-                None => Err(Error::<T>::CallGetOutcomeNotExist)?,
-            };
-            let outcome = call_get_outcome.dispatch(frame_system::RawOrigin::Signed(pay_resolver_account).into());
-            if (!outcome.is_ok()) && (outcome.unwrap_err().error == DispatchError::Other("FalseOutcome")) {
+            verify_hash_lock::<T>(&cond, &preimages, &mut j)?;
+        } else if cond.condition_type == ConditionType::OracleSigned {
+            if get_oracle_boolean_outcome::<T>(&cond, oracle_outcomes.get(k))? == false {
+                has_false_contract_cond = true;
+            }
+            k = k + 1;
+        } else if is_boolean_condition(&cond.condition_type) {
+            if get_boolean_outcome::<T>(cond)? == false {
                 has_false_contract_cond = true;
             }
         } else {
@@ -327,42 +875,28 @@ fn calculate_boolean_and_payment<T: Trait>(
 fn calculate_boolean_or_payment<T: Trait>(
     pay: ConditionalPayOf<T>,
     preimages: Vec<T::Hash>,
+    oracle_outcomes: Vec<OracleOutcomeOf<T>>,
 ) -> Result<BalanceOf<T>, DispatchError> {
     let mut j: usize = 0;
+    let mut k: usize = 0;
     // Whether there are any contract based conditions, i.e. DEPLOYED_CONTRACT or VIRTUAL_CONTRACT
     let mut has_contract_cond = false;
     let mut has_true_contract_cond = false;
     for i in 0..pay.conditions.len() {
         let cond = pay.conditions[i].clone();
         if cond.condition_type == ConditionType::HashLock {
-            let hash_lock = match cond.hash_lock {
-                Some(lock) => lock,
-                None => Err(Error::<T>::HashLockNotExist)?,
-            };
-            ensure!(preimages[j] == hash_lock, "Wrong preimage");
-            j += 1;
-        } else if cond.condition_type == ConditionType::BooleanRuntimeModule {
-            let pay_resolver_account = CelerPayModule::<T>::get_pay_resolver_id();
-            
-            // call is_finalized of boolean_condition
-            let call_is_finalized = match cond.call_is_finalized {
-                Some(call) => call,
-                None => Err(Error::<T>::CallIsFinalizedNotExist)?,
-            };
-            let is_finalized = call_is_finalized.dispatch(frame_system::RawOrigin::Signed(pay_resolver_account.clone()).into());
-            ensure!(
-                is_finalized.is_ok(),
-                "Condition is not finalized"
-            );
+            verify_hash_lock::<T>(&cond, &preimages, &mut j)?;
+        } else if cond.condition_type == ConditionType::OracleSigned {
+            let outcome = get_oracle_boolean_outcome::<T>(&cond, oracle_outcomes.get(k))?;
+            k += 1;
             has_contract_cond = true;
-
-            // call get_outcome of boolean_condition
-            let call_get_outcome = match cond.call_get_outcome {
-                Some(call) => call,
-                None => Err(Error::<T>::CallGetOutcomeNotExist)?,
-            };
-            let outcome = call_get_outcome.dispatch(frame_system::RawOrigin::Signed(pay_resolver_account).into());
-            if outcome.is_ok() {
+            if outcome == true {
+                has_true_contract_cond = true;
+            }
+        } else if is_boolean_condition(&cond.condition_type) {
+            let outcome = get_boolean_outcome::<T>(cond)?;
+            has_contract_cond = true;
+            if outcome == true {
                 has_true_contract_cond = true;
             }
         } else {
@@ -381,46 +915,35 @@ fn calculate_boolean_or_payment<T: Trait>(
 fn calculate_numeric_logic_payment<T: Trait>(
     pay: ConditionalPayOf<T>,
     preimages: Vec<T::Hash>,
+    oracle_outcomes: Vec<OracleOutcomeOf<T>>,
     func_type: TransferFunctionType,
 ) -> Result<BalanceOf<T>, DispatchError> {
     let mut amount: BalanceOf<T> = <BalanceOf<T>>::zero();
     let mut j: usize = 0;
+    let mut k: usize = 0;
     let mut has_contract_cond: bool = false;
+    // an unsatisfied time condition makes the payment zero
+    let mut has_false_time_cond: bool = false;
     for i in 0..pay.conditions.len() {
         let cond = pay.conditions[i].clone();
         if cond.condition_type == ConditionType::HashLock {
-            let hash_lock = match cond.hash_lock {
-                Some(lock) => lock,
-                None => Err(Error::<T>::HashLockNotExist)?,
-            };
-            ensure!(preimages[j] == hash_lock, "Wrong preimage");
-            j = j + 1;
-        } else if cond.condition_type == ConditionType::NumericRuntimeModule {
-            // the number of registered numeric app
-            let numeric_app_number = match cond.numeric_app_num {
-                Some(app_num) => app_num,
-                None => Err(Error::<T>::NumericAppNotExist)?,
-            };
-            // session id of numeric condition
-            let session_id = match cond.numeric_session_id {
-                Some(id) => id,
-                None => Err(Error::<T>::NumericSessionIdNotExist)?,
+            verify_hash_lock::<T>(&cond, &preimages, &mut j)?;
+        } else if is_time_condition(&cond.condition_type) {
+            if get_time_outcome::<T>(&cond)? == false {
+                has_false_time_cond = true;
+            }
+        } else if cond.condition_type == ConditionType::NumericRuntimeModule
+            || is_contract_condition(&cond.condition_type)
+            || cond.condition_type == ConditionType::OracleSigned
+            || cond.condition_type == ConditionType::PayResult {
+            let outcome: BalanceOf<T> = if cond.condition_type == ConditionType::OracleSigned {
+                k = k + 1;
+                get_oracle_numeric_outcome::<T>(&cond, oracle_outcomes.get(k - 1))?
+            } else {
+                get_numeric_outcome::<T>(cond)?
             };
-
-            let is_finalized: bool = NumericConditionCaller::<T>::call_is_finalized(
-                numeric_app_number, 
-                &session_id, 
-                cond.args_query_finalzation
-            )?;
-            ensure!(is_finalized == true, "Condition is not finalized");
-
-            let outcome: BalanceOf<T> = NumericConditionCaller::<T>::call_get_outcome(
-                numeric_app_number, 
-                &session_id, 
-                cond.args_query_outcome
-            )?;
             if func_type == TransferFunctionType::NumericAdd {
-                amount = amount + outcome;
+                amount = amount.checked_add(&outcome).ok_or(Error::<T>::OverFlow)?;
             } else if func_type == TransferFunctionType::NumericMax {
                 if outcome > amount {
                     amount = outcome;
@@ -443,6 +966,9 @@ fn calculate_numeric_logic_payment<T: Trait>(
         }
     }
 
+    if has_false_time_cond == true {
+        return Ok(Zero::zero());
+    }
     if has_contract_cond == true {
         ensure!(
             amount <= pay.transfer_func.max_transfer.receiver.amt,
@@ -454,6 +980,97 @@ fn calculate_numeric_logic_payment<T: Trait>(
     }
 }
 
+// Calculate the result amount of BooleanCircut payment
+fn calculate_boolean_circuit_payment<T: Trait>(
+    pay: ConditionalPayOf<T>,
+    preimages: Vec<T::Hash>,
+    oracle_outcomes: Vec<OracleOutcomeOf<T>>,
+) -> Result<BalanceOf<T>, DispatchError> {
+    let gates = decode_boolean_circuit::<T>(&pay.transfer_func)?;
+
+    // outcomes of conditions
+    let mut wires: Vec<bool> = Vec::with_capacity(pay.conditions.len() + gates.len());
+    let mut j: usize = 0;
+    let mut k: usize = 0;
+    for i in 0..pay.conditions.len() {
+        let cond = pay.conditions[i].clone();
+        if cond.condition_type == ConditionType::HashLock {
+            // an unrevealed or wrong preimage fails the resolution as in other logic types,
+            // so that the circuit can not negate a hash lock
+            verify_hash_lock::<T>(&cond, &preimages, &mut j)?;
+            wires.push(true);
+        } else if cond.condition_type == ConditionType::OracleSigned {
+            wires.push(get_oracle_boolean_outcome::<T>(&cond, oracle_outcomes.get(k))?);
+            k = k + 1;
+        } else if is_boolean_condition(&cond.condition_type) {
+            wires.push(get_boolean_outcome::<T>(cond)?);
+        } else {
+            Err(Error::<T>::Error)?
+        }
+    }
+
+    // evaluate gates in order, a gate can only take conditions and preceding gates as inputs
+    for gate in gates.iter() {
+        let input = |wire: &u32| -> Result<bool, DispatchError> {
+            match wires.get(*wire as usize) {
+                Some(value) => Ok(*value),
+                None => Err(DispatchError::Other("Invalid boolean circuit")),
+            }
+        };
+        let output = match gate {
+            BooleanGate::And(a, b) => input(a)? && input(b)?,
+            BooleanGate::Or(a, b) => input(a)? || input(b)?,
+            BooleanGate::Not(a) => !input(a)?,
+        };
+        wires.push(output);
+    }
+
+    if wires[wires.len() - 1] == true {
+        return Ok(pay.transfer_func.max_transfer.receiver.amt);
+    } else {
+        return Ok(Zero::zero());
+    }
+}
+
+// Decode gate list of BooleanCircut transfer function.
+// The number of gates is checked against MaxBooleanCircuitGates before the gates are decoded.
+fn decode_boolean_circuit<T: Trait>(
+    transfer_func: &TransferFunction<T::AccountId, BalanceOf<T>>,
+) -> Result<Vec<BooleanGate>, DispatchError> {
+    let encoded = match transfer_func.boolean_circuit {
+        Some(ref circuit) => circuit,
+        None => Err(DispatchError::Other("Invalid boolean circuit"))?,
+    };
+    let gates_len: u32 = match Compact::<u32>::decode(&mut &encoded[..]) {
+        Ok(len) => len.0,
+        Err(_) => Err(DispatchError::Other("Invalid boolean circuit"))?,
+    };
+    ensure!(gates_len > 0, "Invalid boolean circuit");
+    ensure!(
+        gates_len <= T::MaxBooleanCircuitGates::get(),
+        "Exceed max boolean circuit gates"
+    );
+    match Vec::<BooleanGate>::decode(&mut &encoded[..]) {
+        Ok(gates) => Ok(gates),
+        Err(_) => Err(DispatchError::Other("Invalid boolean circuit")),
+    }
+}
+
+// Return number of gates of BooleanCircut transfer function, bounded by MaxBooleanCircuitGates.
+// Only the length prefix of the gate list is decoded.
+pub fn boolean_circuit_gates_len<T: Trait>(
+    transfer_func: &TransferFunction<T::AccountId, BalanceOf<T>>,
+) -> u64 {
+    if transfer_func.logic_type != TransferFunctionType::BooleanCircut {
+        return 0;
+    }
+    let gates_len: u32 = match transfer_func.boolean_circuit {
+        Some(ref circuit) => Compact::<u32>::decode(&mut &circuit[..]).map(|len| len.0).unwrap_or(0),
+        None => 0,
+    };
+    return sp_std::cmp::min(gates_len, T::MaxBooleanCircuitGates::get()) as u64;
+}
+
 fn is_numeric_logic<T: Trait>(func_type: TransferFunctionType) -> bool {
     return func_type == TransferFunctionType::NumericAdd
         || func_type == TransferFunctionType::NumericMax
@@ -469,6 +1086,8 @@ pub fn calculate_pay_id<T: Trait>(pay_hash: T::Hash) -> T::Hash {
     return pay_id;
 }
 
+// Fields added after the initial release are encoded only if set, so that pays using none
+// of them keep their pay ids. A condition or pay setting any of them appends all of them.
 pub fn encode_conditional_pay<T: Trait>(pay: ConditionalPayOf<T>) -> Vec<u8> {
     let mut encoded = pay.pay_timestamp.encode();
     encoded.extend(pay.src.encode());
@@ -482,6 +1101,21 @@ pub fn encode_conditional_pay<T: Trait>(pay: ConditionalPayOf<T>) -> Vec<u8> {
         encoded.extend(condition.numeric_session_id.encode());
         encoded.extend(condition.args_query_finalzation.encode());
         encoded.extend(condition.args_query_outcome.encode());
+        if is_extended_condition::<T>(&condition) {
+            encoded.extend(condition.hash_type.encode());
+            encoded.extend(condition.boolean_app_num.encode());
+            encoded.extend(condition.boolean_session_id.encode());
+            encoded.extend(condition.deployed_contract_address.encode());
+            encoded.extend(condition.virtual_contract_address.encode());
+            encoded.extend(condition.gas_limit.encode());
+            encoded.extend(condition.oracle.encode());
+            encoded.extend(condition.question_hash.encode());
+            encoded.extend(condition.block_number.encode());
+            encoded.extend(condition.timestamp.encode());
+            encoded.extend(condition.dependent_pay_id.encode());
+            encoded.extend(condition.boolean_app_id.encode());
+            encoded.extend(condition.numeric_app_id.encode());
+        }
     });
     encoded.extend(pay.transfer_func.logic_type.encode());
     encoded.extend(pay.transfer_func.max_transfer.token.token_type.encode());
@@ -489,7 +1123,37 @@ pub fn encode_conditional_pay<T: Trait>(pay: ConditionalPayOf<T>) -> Vec<u8> {
     encoded.extend(pay.transfer_func.max_transfer.receiver.amt.encode());
     encoded.extend(pay.resolve_deadline.encode());
     encoded.extend(pay.resolve_timeout.encode());
+    if pay.transfer_func.boolean_circuit.is_some() || pay.lottery.is_some() || pay.stream.is_some() {
+        encoded.extend(pay.transfer_func.boolean_circuit.encode());
+        encoded.extend(pay.lottery.encode());
+        encoded.extend(pay.stream.encode());
+    }
 
     return encoded;
 }
 
+// Return true if any field added to the condition after the initial release is set
+fn is_extended_condition<T: Trait>(condition: &ConditionOf<T>) -> bool {
+    return condition.hash_type.is_some()
+        || condition.boolean_app_num.is_some()
+        || condition.boolean_session_id.is_some()
+        || condition.deployed_contract_address.is_some()
+        || condition.virtual_contract_address.is_some()
+        || condition.gas_limit.is_some()
+        || condition.oracle.is_some()
+        || condition.question_hash.is_some()
+        || condition.block_number.is_some()
+        || condition.timestamp.is_some()
+        || condition.dependent_pay_id.is_some()
+        || condition.boolean_app_id.is_some()
+        || condition.numeric_app_id.is_some();
+}
+
+
+// Encode the message signed by the oracle, which is question_hash followed by result
+pub fn encode_oracle_outcome<T: Trait>(oracle_outcome: &OracleOutcomeOf<T>) -> Vec<u8> {
+    let mut encoded = oracle_outcome.question_hash.encode();
+    encoded.extend(oracle_outcome.result.encode());
+
+    return encoded;
+}
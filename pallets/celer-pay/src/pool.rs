@@ -1,20 +1,71 @@
 use super::{
-    Module as CelerPayModule, Allowed, BalanceOf, 
-    PoolBalances, Error, RawEvent
+    Module as CelerPayModule, Allowed, AllowanceExpiries, AssetAllowed, AssetPoolBalances,
+    BalanceOf, ExpiringAllowances, PermitNonces, PoolBalances, Subscriptions, Error, RawEvent
 };
-use crate::traits::Trait;
+use crate::traits::{Trait, Fungibles};
 use crate::celer_wallet::CelerWallet;
-use frame_support::traits::{Currency, ExistenceRequirement};
+use crate::custody::Custody;
+use crate::pay_resolver::{AssetId, TokenType};
+use codec::{Decode, Encode};
+use frame_support::traits::{Currency, Get};
 use frame_support::{
     ensure,
     storage::{StorageDoubleMap, StorageMap},
+    weights::Weight,
 };
-use frame_system::ensure_signed;
-use sp_runtime::traits::{CheckedAdd, CheckedSub};
-use sp_runtime::{ModuleId, DispatchError};
+use frame_system::{self as system, ensure_signed};
+use sp_runtime::traits::{CheckedAdd, CheckedSub, Zero};
+use sp_runtime::{ModuleId, RuntimeDebug, DispatchError};
+use sp_std::vec::Vec;
 
 pub const POOL_ID: ModuleId = ModuleId(*b"_pool_id");
 
+// Mandate of an owner allowing a spender to pull up to amount of native token
+// from Pool in every period blocks until expiry
+#[derive(Clone, Eq, PartialEq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
+pub struct Subscription<BlockNumber, Balance> {
+    pub amount: Balance,
+    pub period: BlockNumber,
+    pub start_block: BlockNumber,
+    pub expiry: BlockNumber,
+    pub pulled_period: BlockNumber, // index of the period of the last pull
+    pub pulled_amount: Balance, // amount pulled in pulled_period
+}
+
+pub type SubscriptionOf<T> = Subscription<
+    <T as system::Trait>::BlockNumber,
+    BalanceOf<T>,
+>;
+
+#[derive(Clone, Eq, PartialEq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
+pub struct PermitInfo<AccountId, BlockNumber, Balance> {
+    pub owner: AccountId,
+    pub spender: AccountId,
+    pub token_type: TokenType,
+    pub value: Balance,
+    pub nonce: u64,
+    pub deadline: BlockNumber,
+}
+
+pub type PermitInfoOf<T> = PermitInfo<
+    <T as system::Trait>::AccountId,
+    <T as system::Trait>::BlockNumber,
+    BalanceOf<T>,
+>;
+
+#[derive(Clone, Eq, PartialEq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
+pub struct PermitRequest<AccountId, BlockNumber, Balance, Signature> {
+    pub permit_info: PermitInfo<AccountId, BlockNumber, Balance>,
+    pub sig: Signature, // signature of owner
+}
+
+pub type PermitRequestOf<T> = PermitRequest<
+    <T as system::Trait>::AccountId,
+    <T as system::Trait>::BlockNumber,
+    BalanceOf<T>,
+    <T as Trait>::Signature,
+>;
+
 pub struct Pool<T>(sp_std::marker::PhantomData<T>);
 
 impl<T: Trait> Pool<T> {
@@ -42,12 +93,7 @@ impl<T: Trait> Pool<T> {
             None => PoolBalances::<T>::insert(&receiver, &msg_value),
         }
 
-        <T as Trait>::Currency::transfer(
-            &caller,
-            &pool_account,
-            msg_value,
-            ExistenceRequirement::AllowDeath,
-        )?;
+        Custody::<T>::deposit_to_pool(&TokenType::Celer, &caller, &receiver, msg_value)?;
 
         // Emit DepositToPool event
         CelerPayModule::<T>::deposit_event(RawEvent::DepositToPool(
@@ -72,16 +118,9 @@ impl<T: Trait> Pool<T> {
         ensure!(balances >= value, "caller does not have enough balances");
 
         let new_balances = balances.checked_sub(&value).ok_or(Error::<T>::UnderFlow)?;
+        Custody::<T>::withdraw_from_pool(&TokenType::Celer, &caller, &caller, value)?;
         PoolBalances::<T>::mutate(&caller, |balance| *balance = Some(new_balances));
 
-        let pool_account = CelerPayModule::<T>::get_pool_id();
-        <T as Trait>::Currency::transfer(
-            &pool_account,
-            &caller,
-            value,
-            ExistenceRequirement::AllowDeath,
-        )?;
-
         // Emit WithdrawFromPool event
         CelerPayModule::<T>::deposit_event(RawEvent::WithdrawFromPool(
             caller.clone(),
@@ -99,6 +138,7 @@ impl<T: Trait> Pool<T> {
         let caller = ensure_signed(origin)?;
 
         Allowed::<T>::insert(&caller, &spender, &value);
+        remove_allowance_expiry::<T>(&caller, &spender, &TokenType::Celer);
 
         // Emit Approval event
         CelerPayModule::<T>::deposit_event(RawEvent::Approval(
@@ -122,6 +162,7 @@ impl<T: Trait> Pool<T> {
             Some(_allowed_balances) => _allowed_balances,
             None => Err(Error::<T>::AllowedNotExist)?
         };
+        ensure_allowance_not_expired::<T>(&from, &caller, &TokenType::Celer)?;
 
         ensure!(
             allowed_balances >= value,
@@ -155,31 +196,35 @@ impl<T: Trait> Pool<T> {
         return Ok((from, to, value));
     }
 
-    // Transfer native token from one address to a wallet in CelerWallet Module.
+    // Transfer funds of given token from one address to a wallet in CelerWallet Module.
     // This function called by Celer Ledger.
     pub fn transfer_to_celer_wallet_by_ledger(
         origin: T::Origin,
         from: T::AccountId,
         wallet_id: T::Hash,
+        token_type: TokenType,
         amount: BalanceOf<T>,
     ) -> Result<(T::Hash, T::AccountId, BalanceOf<T>), DispatchError> {
         let caller = ensure_signed(origin.clone())?;
         let celer_ledger_account = CelerPayModule::<T>::get_celer_ledger_id();
         ensure!(caller == celer_ledger_account, "Caler is not Celer Ledger module",);
 
-        let pool_balances = match PoolBalances::<T>::get(&from) {
-            Some(_balance) => _balance,
-            None => Err(Error::<T>::PoolBalancesNotExist)?,
-        };
+        let pool_balances = match token_type {
+            TokenType::Celer => PoolBalances::<T>::get(&from),
+            TokenType::Asset(asset_id) => AssetPoolBalances::<T>::get(&from, asset_id),
+            TokenType::Invalid => Err(Error::<T>::InvalidTokenType)?,
+        }.ok_or(Error::<T>::PoolBalancesNotExist)?;
         ensure!(
             pool_balances >= amount,
             "Wallet owner does not deposit to pool enough value"
         );
 
-        let allowed_balances = match Allowed::<T>::get(&from, &celer_ledger_account) {
-            Some(_allowed_balances) => _allowed_balances,
-            None => Err(Error::<T>::AllowedNotExist)?
-        };
+        let allowed_balances = match token_type {
+            TokenType::Celer => Allowed::<T>::get(&from, &celer_ledger_account),
+            TokenType::Asset(asset_id) => AssetAllowed::<T>::get((&from, &celer_ledger_account), asset_id),
+            TokenType::Invalid => Err(Error::<T>::InvalidTokenType)?,
+        }.ok_or(Error::<T>::AllowedNotExist)?;
+        ensure_allowance_not_expired::<T>(&from, &celer_ledger_account, &token_type)?;
 
         ensure!(
             allowed_balances >= amount,
@@ -187,39 +232,50 @@ impl<T: Trait> Pool<T> {
         );
         let new_allowed_balances = allowed_balances
                 .checked_sub(&amount).ok_or(Error::<T>::UnderFlow)?;
-        Allowed::<T>::mutate(&from, &celer_ledger_account, |balance| {
-            *balance = Some(new_allowed_balances)
-        });
+        let new_pool_balances = pool_balances
+                .checked_sub(&amount).ok_or(Error::<T>::UnderFlow)?;
+        match token_type {
+            TokenType::Asset(asset_id) => {
+                AssetAllowed::<T>::insert((&from, &celer_ledger_account), asset_id, new_allowed_balances);
 
-        // Emit Approval event
-        CelerPayModule::<T>::deposit_event(RawEvent::Approval(
-            from.clone(),
-            celer_ledger_account.clone(),
-            new_allowed_balances
-        ));
+                // Emit AssetApproval event
+                CelerPayModule::<T>::deposit_event(RawEvent::AssetApproval(
+                    from.clone(),
+                    celer_ledger_account.clone(),
+                    asset_id,
+                    new_allowed_balances
+                ));
+            },
+            _ => {
+                Allowed::<T>::mutate(&from, &celer_ledger_account, |balance| {
+                    *balance = Some(new_allowed_balances)
+                });
 
-        // Deposit native token to CelerWallet
-        CelerWallet::<T>::deposit_native_token(
-            from.clone(),
-            wallet_id,
-            amount
-        )?;
+                // Emit Approval event
+                CelerPayModule::<T>::deposit_event(RawEvent::Approval(
+                    from.clone(),
+                    celer_ledger_account.clone(),
+                    new_allowed_balances
+                ));
+            }
+        }
 
         // Decrease Pool Balances
-        let new_pool_balances = pool_balances
-                .checked_sub(&amount).ok_or(Error::<T>::UnderFlow)?;
-        PoolBalances::<T>::mutate(&from, |balances| *balances = Some(new_pool_balances));
+        match token_type {
+            TokenType::Asset(asset_id) => AssetPoolBalances::<T>::insert(&from, asset_id, new_pool_balances),
+            _ => PoolBalances::<T>::mutate(&from, |balances| *balances = Some(new_pool_balances)),
+        }
 
-        let pool_account = CelerPayModule::<T>::get_pool_id();
-        let celer_wallet_account = CelerPayModule::<T>::get_celer_wallet_id();
-        <T as Trait>::Currency::transfer(
-            &pool_account,
-            &celer_wallet_account,
-            amount,
-            ExistenceRequirement::AllowDeath,
+        // Deposit token from Pool to CelerWallet
+        CelerWallet::<T>::deposit_from_pool(
+            from.clone(),
+            wallet_id,
+            token_type.clone(),
+            amount
         )?;
 
-        return Ok((wallet_id, celer_wallet_account, amount));
+        let wallet_account = CelerPayModule::<T>::get_wallet_account(&wallet_id);
+        return Ok((wallet_id, wallet_account, amount));
     }
 
     // Increase the amount of native token that an owner allowed to a spender.
@@ -276,6 +332,378 @@ impl<T: Trait> Pool<T> {
         ));
         return Ok((caller, spender, new_balances));
     }
+    // Deposit fungible asset to Pool
+    pub fn deposit_asset_pool(
+        origin: T::Origin,
+        receiver: T::AccountId,
+        asset_id: AssetId,
+        msg_value: BalanceOf<T>,
+    ) -> Result<(T::AccountId, BalanceOf<T>), DispatchError> {
+        let caller = ensure_signed(origin)?;
+        CelerPayModule::<T>::ensure_token_supported(&TokenType::Asset(asset_id))?;
+
+        ensure!(
+            <T as Trait>::Assets::balance(asset_id, &caller) >= msg_value,
+            "caller does not have enough balances"
+        );
+
+        let pool_account = CelerPayModule::<T>::get_pool_id();
+        ensure!(receiver != pool_account, "receiver address is pool account");
+
+        let new_balances = AssetPoolBalances::<T>::get(&receiver, asset_id)
+                .unwrap_or(Zero::zero())
+                .checked_add(&msg_value).ok_or(Error::<T>::OverFlow)?;
+        AssetPoolBalances::<T>::insert(&receiver, asset_id, new_balances);
+
+        <T as Trait>::Assets::transfer(asset_id, &caller, &pool_account, msg_value)?;
+
+        // Emit DepositAssetToPool event
+        CelerPayModule::<T>::deposit_event(RawEvent::DepositAssetToPool(
+            receiver.clone(),
+            asset_id,
+            msg_value
+        ));
+        return Ok((receiver, msg_value));
+    }
+
+    // Withdraw fungible asset from Pool
+    pub fn withdraw_asset(
+        origin: T::Origin,
+        asset_id: AssetId,
+        value: BalanceOf<T>,
+    ) -> Result<(T::AccountId, BalanceOf<T>), DispatchError> {
+        let caller = ensure_signed(origin)?;
+
+        let balances = match AssetPoolBalances::<T>::get(&caller, asset_id) {
+            Some(_balances) => _balances,
+            None => Err(Error::<T>::PoolBalancesNotExist)?
+        };
+
+        ensure!(balances >= value, "caller does not have enough balances");
+
+        let new_balances = balances.checked_sub(&value).ok_or(Error::<T>::UnderFlow)?;
+        AssetPoolBalances::<T>::insert(&caller, asset_id, new_balances);
+
+        let pool_account = CelerPayModule::<T>::get_pool_id();
+        <T as Trait>::Assets::transfer(asset_id, &pool_account, &caller, value)?;
+
+        // Emit WithdrawAssetFromPool event
+        CelerPayModule::<T>::deposit_event(RawEvent::WithdrawAssetFromPool(
+            caller.clone(),
+            asset_id,
+            value
+        ));
+        return Ok((caller, value));
+    }
+
+    // Approve the passed address the spend the specified amount of fungible asset on behalf of caller.
+    pub fn approve_asset(
+        origin: T::Origin,
+        spender: T::AccountId,
+        asset_id: AssetId,
+        value: BalanceOf<T>,
+    ) -> Result<(T::AccountId, T::AccountId, BalanceOf<T>), DispatchError> {
+        let caller = ensure_signed(origin)?;
+        CelerPayModule::<T>::ensure_token_supported(&TokenType::Asset(asset_id))?;
+
+        AssetAllowed::<T>::insert((&caller, &spender), asset_id, &value);
+        remove_allowance_expiry::<T>(&caller, &spender, &TokenType::Asset(asset_id));
+
+        // Emit AssetApproval event
+        CelerPayModule::<T>::deposit_event(RawEvent::AssetApproval(
+            caller.clone(),
+            spender.clone(),
+            asset_id,
+            value
+        ));
+        return Ok((caller, spender, value));
+    }
+
+    // Approve the spender to spend the specified amount of token on behalf of owner by
+    // an owner-signed permit, so that anyone can submit the approval for the owner.
+    pub fn permit(
+        origin: T::Origin,
+        permit_request: PermitRequestOf<T>,
+    ) -> Result<(T::AccountId, T::AccountId, BalanceOf<T>), DispatchError> {
+        ensure_signed(origin)?;
+
+        let permit_info = permit_request.permit_info;
+        ensure!(
+            frame_system::Module::<T>::block_number() <= permit_info.deadline,
+            "Permit deadline passed"
+        );
+        ensure!(
+            permit_info.nonce == PermitNonces::<T>::get(&permit_info.owner),
+            "Permit nonce error"
+        );
+
+        // Check signature
+        let encoded = encode_permit_info::<T>(permit_info.clone());
+        CelerPayModule::<T>::check_single_signature(
+            permit_request.sig,
+            &encoded,
+            permit_info.owner.clone()
+        )?;
+
+        let new_nonce = permit_info.nonce.checked_add(1).ok_or(Error::<T>::OverFlow)?;
+        PermitNonces::<T>::insert(&permit_info.owner, new_nonce);
+
+        let owner = permit_info.owner;
+        let spender = permit_info.spender;
+        let value = permit_info.value;
+        set_allowance::<T>(&owner, &spender, &permit_info.token_type, value)?;
+        return Ok((owner, spender, value));
+    }
+
+    // Approve the passed address to spend the specified amount of token on behalf of caller
+    // until the expiry block. The allowance is removed at the expiry block.
+    pub fn approve_with_expiry(
+        origin: T::Origin,
+        spender: T::AccountId,
+        token_type: TokenType,
+        value: BalanceOf<T>,
+        expiry: T::BlockNumber,
+    ) -> Result<(T::AccountId, T::AccountId, BalanceOf<T>), DispatchError> {
+        let caller = ensure_signed(origin)?;
+        ensure!(
+            frame_system::Module::<T>::block_number() < expiry,
+            "Allowance expiry passed"
+        );
+        // Bound the number of allowances removed in on_initialize of the expiry block.
+        // An allowance re-approved with the same expiry keeps its place in the queue.
+        let is_queued = AllowanceExpiries::<T>::get((&caller, &spender), &token_type) == Some(expiry);
+        let queued_num = ExpiringAllowances::<T>::decode_len(expiry).unwrap_or(0) as u32;
+        ensure!(
+            is_queued || queued_num < T::MaxExpiringAllowances::get(),
+            "Too many allowances expiring at the block"
+        );
+
+        // set_allowance removes the previous expiry of the allowance from the queue
+        set_allowance::<T>(&caller, &spender, &token_type, value)?;
+        AllowanceExpiries::<T>::insert((&caller, &spender), &token_type, expiry);
+        ExpiringAllowances::<T>::append(expiry, ((caller.clone(), spender.clone()), token_type.clone()));
+
+        // Emit ApprovalExpiry event
+        CelerPayModule::<T>::deposit_event(RawEvent::ApprovalExpiry(
+            caller.clone(),
+            spender.clone(),
+            token_type,
+            expiry
+        ));
+        return Ok((caller, spender, value));
+    }
+
+    // Remove allowances which are expired at the block. This function is called in on_initialize.
+    // At most MaxExpiringAllowances allowances are queued at a block.
+    pub fn remove_expired_allowances(block_number: T::BlockNumber) -> Weight {
+        let expiring_allowances = ExpiringAllowances::<T>::take(block_number);
+        let mut removed_num: u64 = 0;
+        for ((owner, spender), token_type) in expiring_allowances.iter() {
+            // Skip allowances which have been approved again after being queued
+            if AllowanceExpiries::<T>::get((owner, spender), token_type) != Some(block_number) {
+                continue;
+            }
+            AllowanceExpiries::<T>::remove((owner, spender), token_type);
+            match token_type {
+                TokenType::Asset(asset_id) => AssetAllowed::<T>::remove((owner, spender), asset_id),
+                _ => Allowed::<T>::remove(owner, spender),
+            }
+            removed_num += 1;
+        }
+
+        return T::DbWeight::get().reads_writes(
+            1 + expiring_allowances.len() as u64,
+            1 + 2 * removed_num
+        );
+    }
+
+    // Authorize the spender to pull up to amount of native token in every period blocks until expiry.
+    // A previous subscription of the spender is replaced.
+    pub fn authorize_subscription(
+        origin: T::Origin,
+        spender: T::AccountId,
+        amount: BalanceOf<T>,
+        period: T::BlockNumber,
+        expiry: T::BlockNumber,
+    ) -> Result<(T::AccountId, T::AccountId, BalanceOf<T>), DispatchError> {
+        let caller = ensure_signed(origin)?;
+        ensure!(period > Zero::zero(), "Subscription period is zero");
+
+        let block_number = frame_system::Module::<T>::block_number();
+        ensure!(block_number < expiry, "Subscription expiry passed");
+
+        let subscription = SubscriptionOf::<T> {
+            amount: amount,
+            period: period,
+            start_block: block_number,
+            expiry: expiry,
+            pulled_period: Zero::zero(),
+            pulled_amount: Zero::zero(),
+        };
+        Subscriptions::<T>::insert(&caller, &spender, subscription);
+
+        // Emit AuthorizeSubscription event
+        CelerPayModule::<T>::deposit_event(RawEvent::AuthorizeSubscription(
+            caller.clone(),
+            spender.clone(),
+            amount,
+            period,
+            expiry
+        ));
+        return Ok((caller, spender, amount));
+    }
+
+    // Pull native token of the current period of a subscription from Pool of the owner
+    pub fn pull_subscription(
+        origin: T::Origin,
+        owner: T::AccountId,
+        value: BalanceOf<T>,
+    ) -> Result<(T::AccountId, T::AccountId, BalanceOf<T>), DispatchError> {
+        let caller = ensure_signed(origin)?;
+
+        let mut subscription = match Subscriptions::<T>::get(&owner, &caller) {
+            Some(_subscription) => _subscription,
+            None => Err(Error::<T>::SubscriptionNotExist)?,
+        };
+        let block_number = frame_system::Module::<T>::block_number();
+        ensure!(block_number < subscription.expiry, "Subscription expired");
+
+        // Reset pulled amount when a new period begins
+        let current_period = (block_number - subscription.start_block) / subscription.period;
+        if current_period != subscription.pulled_period {
+            subscription.pulled_period = current_period;
+            subscription.pulled_amount = Zero::zero();
+        }
+        let new_pulled_amount = subscription.pulled_amount.checked_add(&value)
+                .ok_or(Error::<T>::OverFlow)?;
+        ensure!(
+            new_pulled_amount <= subscription.amount,
+            "Exceed subscription amount of the period"
+        );
+
+        let balances = match PoolBalances::<T>::get(&owner) {
+            Some(_balances) => _balances,
+            None => Err(Error::<T>::PoolBalancesNotExist)?
+        };
+        ensure!(
+            balances >= value,
+            "owner address does not have enough balances"
+        );
+
+        subscription.pulled_amount = new_pulled_amount;
+        Subscriptions::<T>::insert(&owner, &caller, subscription);
+
+        _transfer::<T>(owner.clone(), caller.clone(), value)?;
+
+        // Emit PullSubscription event
+        CelerPayModule::<T>::deposit_event(RawEvent::PullSubscription(
+            owner.clone(),
+            caller.clone(),
+            value
+        ));
+        return Ok((owner, caller, value));
+    }
+
+    // Cancel the subscription of the spender
+    pub fn cancel_subscription(
+        origin: T::Origin,
+        spender: T::AccountId,
+    ) -> Result<(T::AccountId, T::AccountId), DispatchError> {
+        let caller = ensure_signed(origin)?;
+        ensure!(
+            Subscriptions::<T>::contains_key(&caller, &spender),
+            Error::<T>::SubscriptionNotExist
+        );
+        Subscriptions::<T>::remove(&caller, &spender);
+
+        // Emit CancelSubscription event
+        CelerPayModule::<T>::deposit_event(RawEvent::CancelSubscription(
+            caller.clone(),
+            spender.clone()
+        ));
+        return Ok((caller, spender));
+    }
+}
+
+// Encode the message signed by owner of the permit.
+// The genesis hash and the Pool account are encoded first to separate permits
+// from signed messages of other chains and other modules.
+pub fn encode_permit_info<T: Trait>(permit_info: PermitInfoOf<T>) -> Vec<u8> {
+    let mut encoded = frame_system::Module::<T>::block_hash(T::BlockNumber::zero()).encode();
+    encoded.extend(CelerPayModule::<T>::get_pool_id().encode());
+    encoded.extend(permit_info.owner.encode());
+    encoded.extend(permit_info.spender.encode());
+    encoded.extend(permit_info.token_type.encode());
+    encoded.extend(permit_info.value.encode());
+    encoded.extend(permit_info.nonce.encode());
+    encoded.extend(permit_info.deadline.encode());
+
+    return encoded;
+}
+
+// Set the allowance of native token or fungible asset without expiry
+fn set_allowance<T: Trait>(
+    owner: &T::AccountId,
+    spender: &T::AccountId,
+    token_type: &TokenType,
+    value: BalanceOf<T>,
+) -> Result<(), DispatchError> {
+    CelerPayModule::<T>::ensure_token_supported(token_type)?;
+    match token_type {
+        TokenType::Celer => {
+            Allowed::<T>::insert(owner, spender, &value);
+
+            // Emit Approval event
+            CelerPayModule::<T>::deposit_event(RawEvent::Approval(
+                owner.clone(),
+                spender.clone(),
+                value
+            ));
+        },
+        TokenType::Asset(asset_id) => {
+            AssetAllowed::<T>::insert((owner, spender), asset_id, &value);
+
+            // Emit AssetApproval event
+            CelerPayModule::<T>::deposit_event(RawEvent::AssetApproval(
+                owner.clone(),
+                spender.clone(),
+                *asset_id,
+                value
+            ));
+        },
+        TokenType::Invalid => Err(Error::<T>::InvalidTokenType)?,
+    }
+    remove_allowance_expiry::<T>(owner, spender, token_type);
+
+    Ok(())
+}
+
+// Remove the expiry of an allowance together with its entry queued at the expiry block
+fn remove_allowance_expiry<T: Trait>(
+    owner: &T::AccountId,
+    spender: &T::AccountId,
+    token_type: &TokenType,
+) {
+    if let Some(expiry) = AllowanceExpiries::<T>::take((owner, spender), token_type) {
+        let key = ((owner.clone(), spender.clone()), token_type.clone());
+        ExpiringAllowances::<T>::mutate(expiry, |allowances| allowances.retain(|allowance| *allowance != key));
+    }
+}
+
+// Fail if the allowance of the spender has passed its expiry
+fn ensure_allowance_not_expired<T: Trait>(
+    owner: &T::AccountId,
+    spender: &T::AccountId,
+    token_type: &TokenType,
+) -> Result<(), DispatchError> {
+    if let Some(expiry) = AllowanceExpiries::<T>::get((owner, spender), token_type) {
+        ensure!(
+            frame_system::Module::<T>::block_number() < expiry,
+            "Allowance expired"
+        );
+    }
+
+    Ok(())
 }
 
 // Transfer Celer for a specified addresses.
@@ -289,8 +717,7 @@ fn _transfer<T: Trait>(
     let new_balances = balances.checked_sub(&value).ok_or(Error::<T>::OverFlow)?;
     PoolBalances::<T>::mutate(&from, |balance| *balance = Some(new_balances));
 
-    let pool_account = CelerPayModule::<T>::get_pool_id();
-    <T as Trait>::Currency::transfer(&pool_account, &to, value, ExistenceRequirement::AllowDeath)?;
+    Custody::<T>::withdraw_from_pool(&TokenType::Celer, &from, &to, value)?;
 
     Ok(())
 }
@@ -299,6 +726,7 @@ fn _transfer<T: Trait>(
 pub mod tests {
     use super::*;
     use crate::mock::*;
+    use sp_core::{sr25519, Pair, H256};
     use sp_runtime::DispatchError;
 
     #[test]
@@ -421,6 +849,287 @@ pub mod tests {
         })
     }
 
+    #[test]
+    fn test_pass_deposit_and_withdraw_asset_pool() {
+        ExtBuilder::build().execute_with(|| {   
+            let bob = account_key("Bob");
+            let (receiver, value) = Pool::<TestRuntime>::deposit_asset_pool(
+                Origin::signed(bob), bob, TEST_ASSET_ID, 100
+            ).unwrap();
+            assert_eq!(receiver, bob);
+            assert_eq!(value, 100);
+            assert_eq!(CelerPayModule::get_asset_pool_balance(bob, TEST_ASSET_ID).amount, 100);
+            assert_eq!(CelerPayModule::get_pool_balance(bob).amount, 0);
+            assert_eq!(MockAssets::balance(TEST_ASSET_ID, &bob), 900);
+
+            Pool::<TestRuntime>::withdraw_asset(Origin::signed(bob), TEST_ASSET_ID, 40).unwrap();
+            assert_eq!(CelerPayModule::get_asset_pool_balance(bob, TEST_ASSET_ID).amount, 60);
+            assert_eq!(MockAssets::balance(TEST_ASSET_ID, &bob), 940);
+        })
+    }
+
+    #[test]
+    fn test_fail_deposit_asset_pool_because_of_owner_does_not_enough_balance() {
+        ExtBuilder::build().execute_with(|| {   
+            let bob = account_key("Bob");
+            let err = Pool::<TestRuntime>::deposit_asset_pool(
+                Origin::signed(bob), bob, TEST_ASSET_ID + 1, 100
+            ).unwrap_err();
+            assert_eq!(
+                err,
+                DispatchError::Other("caller does not have enough balances")
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_approve_asset() {
+        ExtBuilder::build().execute_with(|| {   
+            let bob = account_key("Bob"); // owner address
+            let risa = account_key("Risa"); // spender address
+            Pool::<TestRuntime>::approve_asset(Origin::signed(bob), risa, TEST_ASSET_ID, 200).unwrap();
+            assert_eq!(CelerPayModule::get_asset_allowance(bob, risa, TEST_ASSET_ID).0.amount, 200);
+            assert_eq!(CelerPayModule::get_allowance(bob, risa).0.amount, 0);
+        })
+    }
+
+    #[test]
+    fn test_pass_pull_subscription() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let bob = account_key("Bob"); // owner address
+            let risa = account_key("Risa"); // spender address
+            deposit_pool(bob, 100);
+            Pool::<TestRuntime>::authorize_subscription(Origin::signed(bob), risa, 30, 10, 25).unwrap();
+
+            let risa_balance = Balances::free_balance(risa);
+            Pool::<TestRuntime>::pull_subscription(Origin::signed(risa), bob, 20).unwrap();
+            Pool::<TestRuntime>::pull_subscription(Origin::signed(risa), bob, 10).unwrap();
+            let err = Pool::<TestRuntime>::pull_subscription(Origin::signed(risa), bob, 1).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Exceed subscription amount of the period"));
+
+            // the amount is refilled in the next period
+            System::set_block_number(11);
+            Pool::<TestRuntime>::pull_subscription(Origin::signed(risa), bob, 30).unwrap();
+            assert_eq!(Balances::free_balance(risa), risa_balance + 60);
+            assert_eq!(CelerPayModule::get_pool_balance(bob).amount, 40);
+
+            System::set_block_number(25);
+            let err = Pool::<TestRuntime>::pull_subscription(Origin::signed(risa), bob, 10).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Subscription expired"));
+        })
+    }
+
+    #[test]
+    fn test_fail_pull_subscription_after_cancellation() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let bob = account_key("Bob"); // owner address
+            let risa = account_key("Risa"); // spender address
+            deposit_pool(bob, 100);
+            Pool::<TestRuntime>::authorize_subscription(Origin::signed(bob), risa, 30, 10, 25).unwrap();
+            let err = Pool::<TestRuntime>::cancel_subscription(Origin::signed(risa), bob).unwrap_err();
+            assert_eq!(err, DispatchError::from(Error::<TestRuntime>::SubscriptionNotExist));
+
+            Pool::<TestRuntime>::cancel_subscription(Origin::signed(bob), risa).unwrap();
+            let err = Pool::<TestRuntime>::pull_subscription(Origin::signed(risa), bob, 10).unwrap_err();
+            assert_eq!(err, DispatchError::from(Error::<TestRuntime>::SubscriptionNotExist));
+        })
+    }
+
+    #[test]
+    fn test_pass_permit() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let bob_pair = account_pair("Bob"); // owner
+            let bob = bob_pair.public();
+            let risa = account_key("Risa"); // spender
+            let carl = account_key("Carl"); // relayer
+
+            let permit_request = get_permit_request(bob_pair.clone(), risa, TokenType::Celer, 200, 0, 10);
+            Pool::<TestRuntime>::permit(Origin::signed(carl), permit_request.clone()).unwrap();
+            assert_eq!(CelerPayModule::get_allowance(bob, risa).0.amount, 200);
+            assert_eq!(CelerPayModule::permit_nonces(bob), 1);
+
+            // a permit can not be replayed
+            let err = Pool::<TestRuntime>::permit(Origin::signed(carl), permit_request).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Permit nonce error"));
+
+            let permit_request = get_permit_request(bob_pair, risa, TokenType::Asset(TEST_ASSET_ID), 50, 1, 10);
+            Pool::<TestRuntime>::permit(Origin::signed(carl), permit_request).unwrap();
+            assert_eq!(CelerPayModule::get_asset_allowance(bob, risa, TEST_ASSET_ID).0.amount, 50);
+        })
+    }
+
+    #[test]
+    fn test_fail_permit_with_invalid_signature_or_passed_deadline() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(11);
+            let bob_pair = account_pair("Bob"); // owner
+            let risa_pair = account_pair("Risa"); // spender
+            let risa = risa_pair.public();
+            let carl = account_key("Carl"); // relayer
+
+            // permit of bob signed by risa
+            let mut permit_request = get_permit_request(risa_pair, risa, TokenType::Celer, 200, 0, 20);
+            permit_request.permit_info.owner = bob_pair.public();
+            let err = Pool::<TestRuntime>::permit(Origin::signed(carl), permit_request).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Check sig failed"));
+
+            let permit_request = get_permit_request(bob_pair.clone(), risa, TokenType::Celer, 200, 0, 10);
+            let err = Pool::<TestRuntime>::permit(Origin::signed(carl), permit_request).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Permit deadline passed"));
+
+            // permit signed for the chain with another genesis hash
+            let permit_request = get_permit_request(bob_pair, risa, TokenType::Celer, 200, 0, 20);
+            frame_system::BlockHash::<TestRuntime>::insert(0, H256::from_low_u64_be(1));
+            let err = Pool::<TestRuntime>::permit(Origin::signed(carl), permit_request).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Check sig failed"));
+        })
+    }
+
+    #[test]
+    fn test_pass_transfer_from_with_expiring_allowance() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let alice = account_key("Alice"); // to address
+            let bob = account_key("Bob"); // owner address
+            let risa = account_key("Risa"); // spender address
+            deposit_pool(bob, 200);
+
+            let err = Pool::<TestRuntime>::approve_with_expiry(
+                Origin::signed(bob), risa, TokenType::Celer, 150, 1
+            ).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Allowance expiry passed"));
+
+            Pool::<TestRuntime>::approve_with_expiry(Origin::signed(bob), risa, TokenType::Celer, 150, 10).unwrap();
+            let (allowance, expiry) = CelerPayModule::get_allowance(bob, risa);
+            assert_eq!((allowance.amount, expiry), (150, 10));
+
+            Pool::<TestRuntime>::transfer_from(Origin::signed(risa), bob, alice, 50).unwrap();
+            assert_eq!(CelerPayModule::get_allowance(bob, risa).0.amount, 100);
+
+            System::set_block_number(10);
+            let err = Pool::<TestRuntime>::transfer_from(Origin::signed(risa), bob, alice, 50).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Allowance expired"));
+
+            // the expired allowance is removed at the expiry block
+            Pool::<TestRuntime>::remove_expired_allowances(10);
+            assert_eq!(CelerPayModule::allowed(bob, risa), None);
+            assert_eq!(CelerPayModule::allowance_expiries((bob, risa), TokenType::Celer), None);
+            assert_eq!(CelerPayModule::expiring_allowances(10), vec![]);
+        })
+    }
+
+    #[test]
+    fn test_pass_keep_allowance_approved_again_after_expiring_approval() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let bob = account_key("Bob"); // owner address
+            let risa = account_key("Risa"); // spender address
+            let token_type = TokenType::Asset(TEST_ASSET_ID);
+
+            Pool::<TestRuntime>::approve_with_expiry(Origin::signed(bob), risa, token_type.clone(), 150, 10).unwrap();
+            Pool::<TestRuntime>::approve_with_expiry(Origin::signed(bob), risa, token_type.clone(), 100, 20).unwrap();
+            Pool::<TestRuntime>::approve_with_expiry(Origin::signed(bob), risa, TokenType::Celer, 50, 10).unwrap();
+
+            // the asset allowance re-approved with a later expiry is kept
+            Pool::<TestRuntime>::remove_expired_allowances(10);
+            let (allowance, expiry) = CelerPayModule::get_asset_allowance(bob, risa, TEST_ASSET_ID);
+            assert_eq!((allowance.amount, expiry), (100, 20));
+            assert_eq!(CelerPayModule::allowed(bob, risa), None);
+
+            // the asset allowance approved without expiry is kept
+            Pool::<TestRuntime>::approve_asset(Origin::signed(bob), risa, TEST_ASSET_ID, 70).unwrap();
+            assert_eq!(CelerPayModule::expiring_allowances(20), vec![]);
+            Pool::<TestRuntime>::remove_expired_allowances(20);
+            let (allowance, expiry) = CelerPayModule::get_asset_allowance(bob, risa, TEST_ASSET_ID);
+            assert_eq!((allowance.amount, expiry), (70, 0));
+        })
+    }
+
+    #[test]
+    fn test_pass_queue_allowance_once_when_approved_again_with_expiry() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let bob = account_key("Bob"); // owner address
+            let risa = account_key("Risa"); // spender address
+
+            Pool::<TestRuntime>::approve_with_expiry(Origin::signed(bob), risa, TokenType::Celer, 150, 10).unwrap();
+            Pool::<TestRuntime>::approve_with_expiry(Origin::signed(bob), risa, TokenType::Celer, 100, 10).unwrap();
+            assert_eq!(
+                CelerPayModule::expiring_allowances(10),
+                vec![((bob, risa), TokenType::Celer)]
+            );
+
+            // the allowance moves to the queue of the new expiry
+            Pool::<TestRuntime>::approve_with_expiry(Origin::signed(bob), risa, TokenType::Celer, 100, 20).unwrap();
+            assert_eq!(CelerPayModule::expiring_allowances(10), vec![]);
+            assert_eq!(
+                CelerPayModule::expiring_allowances(20),
+                vec![((bob, risa), TokenType::Celer)]
+            );
+        })
+    }
+
+    #[test]
+    fn test_fail_approve_with_expiry_exceeding_max_expiring_allowances() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let bob = account_key("Bob"); // owner address
+            let risa = account_key("Risa"); // spender address
+            let carl = account_key("Carl"); // spender address
+            let token_type = TokenType::Asset(TEST_ASSET_ID);
+
+            // MaxExpiringAllowances is 2 in mock
+            Pool::<TestRuntime>::approve_with_expiry(Origin::signed(bob), risa, TokenType::Celer, 150, 10).unwrap();
+            Pool::<TestRuntime>::approve_with_expiry(Origin::signed(bob), carl, TokenType::Celer, 150, 10).unwrap();
+            let err = Pool::<TestRuntime>::approve_with_expiry(
+                Origin::signed(bob), risa, token_type.clone(), 150, 10
+            ).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Too many allowances expiring at the block"));
+
+            // a queued allowance can be approved again with the same expiry
+            Pool::<TestRuntime>::approve_with_expiry(Origin::signed(bob), risa, TokenType::Celer, 100, 10).unwrap();
+
+            // the queue has room after an allowance is approved without expiry
+            Pool::<TestRuntime>::approve(Origin::signed(bob), carl, 150).unwrap();
+            Pool::<TestRuntime>::approve_with_expiry(Origin::signed(bob), risa, token_type, 150, 10).unwrap();
+            assert_eq!(CelerPayModule::expiring_allowances(10).len(), 2);
+        })
+    }
+
+    pub fn get_permit_request(
+        owner_pair: sr25519::Pair,
+        spender: AccountId,
+        token_type: TokenType,
+        value: Balance,
+        nonce: u64,
+        deadline: BlockNumber,
+    ) -> PermitRequest<AccountId, BlockNumber, Balance, Signature> {
+        let permit_info = PermitInfo {
+            owner: owner_pair.public(),
+            spender: spender,
+            token_type: token_type,
+            value: value,
+            nonce: nonce,
+            deadline: deadline,
+        };
+        let mut encoded = System::block_hash(0).encode();
+        encoded.extend(CelerPayModule::get_pool_id().encode());
+        encoded.extend(permit_info.owner.encode());
+        encoded.extend(permit_info.spender.encode());
+        encoded.extend(permit_info.token_type.encode());
+        encoded.extend(permit_info.value.encode());
+        encoded.extend(permit_info.nonce.encode());
+        encoded.extend(permit_info.deadline.encode());
+
+        return PermitRequest {
+            permit_info: permit_info,
+            sig: owner_pair.sign(&encoded),
+        };
+    }
+
     pub fn deposit_pool(receiver: AccountId, value: Balance) {
         let (_receiver, _value) =
             Pool::<TestRuntime>::deposit_pool(Origin::signed(receiver), receiver, value).unwrap();
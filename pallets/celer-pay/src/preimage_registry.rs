@@ -0,0 +1,111 @@
+use super::{BalanceOf, Error, Module as CelerPayModule, PreimageMap, RawEvent};
+use crate::traits::Trait;
+use crate::storage_deposit::StorageDeposit;
+use crate::pay_resolver::HashType;
+use codec::{Decode, Encode};
+use frame_support::{ensure, storage::StorageDoubleMap, traits::Get};
+use frame_system::{self as system, ensure_signed};
+use sp_io::hashing::{blake2_256, keccak_256, sha2_256};
+use sp_runtime::traits::CheckedAdd;
+use sp_runtime::{RuntimeDebug, DispatchError};
+
+#[derive(Clone, Eq, PartialEq, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
+pub struct PreimageInfo<Hash, BlockNumber> {
+    pub preimage: Hash,
+    pub reveal_block: BlockNumber,
+}
+
+pub type PreimageInfoOf<T> = PreimageInfo<
+    <T as system::Trait>::Hash,
+    <T as system::Trait>::BlockNumber,
+>;
+
+pub struct PreimageRegistry<T>(sp_std::marker::PhantomData<T>);
+
+impl<T: Trait> PreimageRegistry<T> {
+    // Reveal a preimage on chain so that hash lock conditions locked by it
+    // can be resolved without carrying the preimage, and reserve the storage deposit of the preimage
+    pub fn reveal_preimage(
+        origin: T::Origin,
+        preimage: T::Hash,
+        hash_type: HashType,
+    ) -> Result<T::Hash, DispatchError> {
+        let caller = ensure_signed(origin)?;
+
+        let hash_lock = Self::calculate_hash_lock(&preimage, hash_type)?;
+        ensure!(
+            PreimageMap::<T>::contains_key(hash_type, &hash_lock) == false,
+            "Preimage already revealed"
+        );
+
+        StorageDeposit::<T>::reserve_preimage_deposit(&caller, hash_type, &hash_lock)?;
+        let reveal_block = frame_system::Module::<T>::block_number();
+        let preimage_info = PreimageInfoOf::<T> {
+            preimage: preimage,
+            reveal_block: reveal_block,
+        };
+        PreimageMap::<T>::insert(hash_type, &hash_lock, preimage_info);
+
+        // Emit PreimageRevealed event
+        CelerPayModule::<T>::deposit_event(RawEvent::PreimageRevealed(
+            hash_type,
+            hash_lock,
+            preimage,
+            reveal_block
+        ));
+        return Ok(hash_lock);
+    }
+
+    // Remove a revealed preimage after `PreimagePruneDelay` from its reveal block,
+    // and refund the storage deposit of the preimage
+    pub fn prune_preimage(
+        origin: T::Origin,
+        hash_lock: T::Hash,
+        hash_type: HashType,
+    ) -> Result<BalanceOf<T>, DispatchError> {
+        let caller = ensure_signed(origin)?;
+        let preimage_info = match PreimageMap::<T>::get(hash_type, &hash_lock) {
+            Some(_preimage_info) => _preimage_info,
+            None => Err(DispatchError::Other("Preimage not revealed"))?,
+        };
+        let prune_block = preimage_info.reveal_block.checked_add(&T::PreimagePruneDelay::get())
+            .ok_or(Error::<T>::OverFlow)?;
+        ensure!(
+            frame_system::Module::<T>::block_number() > prune_block,
+            "Preimage prune delay not passed"
+        );
+
+        PreimageMap::<T>::remove(hash_type, &hash_lock);
+        let reward = StorageDeposit::<T>::refund_preimage_deposit(hash_type, &hash_lock, &caller);
+
+        // Emit PrunePreimage event
+        CelerPayModule::<T>::deposit_event(RawEvent::PrunePreimage(
+            hash_type,
+            hash_lock,
+            caller,
+            reward
+        ));
+        return Ok(reward);
+    }
+
+    // Calculate hash lock of preimage with the given hash function
+    pub fn calculate_hash_lock(
+        preimage: &T::Hash,
+        hash_type: HashType,
+    ) -> Result<T::Hash, DispatchError> {
+        let hash = match hash_type {
+            HashType::Blake2_256 => blake2_256(preimage.as_ref()),
+            HashType::Sha256 => sha2_256(preimage.as_ref()),
+            HashType::Keccak256 => keccak_256(preimage.as_ref()),
+        };
+        match T::Hash::decode(&mut &hash[..]) {
+            Ok(hash_lock) => Ok(hash_lock),
+            Err(_) => Err(Error::<T>::Error)?,
+        }
+    }
+
+    // Return true if preimage of the hash lock has been revealed
+    pub fn is_revealed(hash_lock: &T::Hash, hash_type: HashType) -> bool {
+        return PreimageMap::<T>::contains_key(hash_type, hash_lock);
+    }
+}
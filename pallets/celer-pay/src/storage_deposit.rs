@@ -0,0 +1,265 @@
+use super::{BalanceOf, ChannelDeposits, PayInfoDeposits, PayInfoMap, PreimageDeposits};
+use crate::traits::Trait;
+use crate::custody::Custody;
+use crate::pay_resolver::{calculate_pay_id, encode_conditional_pay, ConditionalPayOf, HashType};
+use frame_support::{
+    ensure,
+    storage::{StorageDoubleMap, StorageMap},
+    traits::{BalanceStatus, Get, ReservableCurrency},
+};
+use sp_runtime::traits::{Hash, Saturating, Zero};
+use sp_runtime::DispatchError;
+
+// Storage deposits reserved from the creator of ChannelMap, Wallets, PayInfoMap and PreimageMap entries,
+// which are refunded when the entries are pruned
+pub struct StorageDeposit<T>(sp_std::marker::PhantomData<T>);
+
+impl<T: Trait> StorageDeposit<T> {
+    // Reserve the storage deposit of a new channel and its wallet from the depositor.
+    // The deposit is at least `endowment`, which is left free to endow the sub-account of the wallet.
+    // This function is called before the channel is created.
+    pub fn reserve_channel_deposit(
+        depositor: &T::AccountId,
+        endowment: BalanceOf<T>,
+    ) -> Result<BalanceOf<T>, DispatchError> {
+        let deposit = T::ChannelDeposit::get().max(endowment);
+        ensure!(
+            <T as Trait>::Currency::can_reserve(depositor, deposit),
+            "caller can not reserve storage deposit"
+        );
+        reserve::<T>(depositor, deposit.saturating_sub(endowment))?;
+        Ok(deposit)
+    }
+
+    // Record the depositor of the storage deposit of a created channel
+    pub fn set_channel_deposit(
+        channel_id: T::Hash,
+        depositor: T::AccountId,
+        deposit: BalanceOf<T>,
+    ) {
+        if !deposit.is_zero() {
+            ChannelDeposits::<T>::insert(channel_id, (depositor, deposit));
+        }
+    }
+
+    // Return pay id of the conditional pay if resolving it creates a new pay info,
+    // after checking that the depositor can reserve the storage deposit of the pay info.
+    // This function is called before the pay is resolved.
+    pub fn check_pay_info_deposit(
+        depositor: &T::AccountId,
+        cond_pay: &ConditionalPayOf<T>,
+    ) -> Result<Option<T::Hash>, DispatchError> {
+        let encoded = encode_conditional_pay::<T>(cond_pay.clone());
+        let pay_id = calculate_pay_id::<T>(T::Hashing::hash(&encoded));
+        if PayInfoMap::<T>::contains_key(&pay_id) {
+            return Ok(None);
+        }
+
+        ensure!(
+            <T as Trait>::Currency::can_reserve(depositor, T::PayInfoDeposit::get()),
+            "caller can not reserve storage deposit"
+        );
+        Ok(Some(pay_id))
+    }
+
+    // Reserve the storage deposit of a pay info created by the depositor
+    pub fn reserve_pay_info_deposit(
+        depositor: &T::AccountId,
+        new_pay_id: Option<T::Hash>,
+    ) -> Result<(), DispatchError> {
+        let pay_id = match new_pay_id {
+            Some(_pay_id) => _pay_id,
+            None => return Ok(()),
+        };
+
+        let deposit = T::PayInfoDeposit::get();
+        reserve::<T>(depositor, deposit)?;
+        if !deposit.is_zero() {
+            PayInfoDeposits::<T>::insert(pay_id, (depositor.clone(), deposit));
+        }
+        Ok(())
+    }
+
+    // Reserve the storage deposit of a preimage revealed by the depositor.
+    // This function is called before the preimage is recorded.
+    pub fn reserve_preimage_deposit(
+        depositor: &T::AccountId,
+        hash_type: HashType,
+        hash_lock: &T::Hash,
+    ) -> Result<(), DispatchError> {
+        let deposit = T::PreimageDeposit::get();
+        reserve::<T>(depositor, deposit)?;
+        if !deposit.is_zero() {
+            PreimageDeposits::<T>::insert(hash_type, hash_lock, (depositor.clone(), deposit));
+        }
+        Ok(())
+    }
+
+    // Refund the storage deposit of a pruned channel to its depositor,
+    // and return the reward paid to the pruner from the reserved part of the deposit.
+    // The endowment of the wallet is swept back from its sub-account.
+    pub fn refund_channel_deposit(channel_id: &T::Hash, pruner: &T::AccountId) -> BalanceOf<T> {
+        match ChannelDeposits::<T>::take(channel_id) {
+            Some((depositor, deposit)) => {
+                let endowment = Custody::<T>::sweep_wallet(*channel_id, &depositor);
+                refund::<T>(&depositor, deposit.saturating_sub(endowment), pruner)
+            }
+            None => Zero::zero(),
+        }
+    }
+
+    // Refund the storage deposit of a pruned pay info to its depositor,
+    // and return the reward paid to the pruner from the deposit
+    pub fn refund_pay_info_deposit(pay_id: &T::Hash, pruner: &T::AccountId) -> BalanceOf<T> {
+        match PayInfoDeposits::<T>::take(pay_id) {
+            Some((depositor, deposit)) => refund::<T>(&depositor, deposit, pruner),
+            None => Zero::zero(),
+        }
+    }
+
+    // Refund the storage deposit of a pruned preimage to its depositor,
+    // and return the reward paid to the pruner from the deposit
+    pub fn refund_preimage_deposit(
+        hash_type: HashType,
+        hash_lock: &T::Hash,
+        pruner: &T::AccountId,
+    ) -> BalanceOf<T> {
+        match PreimageDeposits::<T>::take(hash_type, hash_lock) {
+            Some((depositor, deposit)) => refund::<T>(&depositor, deposit, pruner),
+            None => Zero::zero(),
+        }
+    }
+}
+
+fn reserve<T: Trait>(
+    depositor: &T::AccountId,
+    deposit: BalanceOf<T>,
+) -> Result<(), DispatchError> {
+    ensure!(
+        <T as Trait>::Currency::can_reserve(depositor, deposit),
+        "caller can not reserve storage deposit"
+    );
+    <T as Trait>::Currency::reserve(depositor, deposit)
+}
+
+// Pay `PruneReward` of the deposit to the pruner and unreserve the rest
+fn refund<T: Trait>(
+    depositor: &T::AccountId,
+    deposit: BalanceOf<T>,
+    pruner: &T::AccountId,
+) -> BalanceOf<T> {
+    if depositor == pruner {
+        <T as Trait>::Currency::unreserve(depositor, deposit);
+        return Zero::zero();
+    }
+
+    let reward = T::PruneReward::get() * deposit;
+    let unpaid = <T as Trait>::Currency::repatriate_reserved(depositor, pruner, reward, BalanceStatus::Free)
+        .unwrap_or(reward);
+    <T as Trait>::Currency::unreserve(depositor, deposit.saturating_sub(reward).saturating_add(unpaid));
+    reward.saturating_sub(unpaid)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::mock::*;
+    use crate::tests::ledger_operation::test_ledger_operation::*;
+    use crate::tests::pay_resolver::test_pay_resolver::{get_condition, get_transfer_func};
+    use crate::ledger_operation::LedgerOperation;
+    use crate::pay_resolver::{ConditionalPay, ResolvePaymentConditionsRequest};
+    use frame_support::{assert_noop, assert_ok};
+    use sp_core::H256;
+
+    #[test]
+    fn test_pass_reserve_and_refund_channel_deposit() {
+        ExtBuilder::build().execute_with(|| {
+            MockStorageDeposits::set(10, 1);
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+
+            let open_channel_request = get_open_channel_request(false, 0, 500001, 10, true, channel_peers.clone(), 1, peers_pair);
+            let caller_balance = Balances::free_balance(&channel_peers[1]);
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[1]),
+                open_channel_request.clone(),
+                0
+            ).unwrap();
+            // the existential deposit of the wallet sub-account is paid from the deposit
+            assert_eq!(Balances::free_balance(&channel_peers[1]), caller_balance - 10);
+            assert_eq!(Balances::reserved_balance(&channel_peers[1]), 9);
+            assert_eq!(Balances::free_balance(CelerPayModule::get_wallet_account(&channel_id)), 1);
+            assert_eq!(CelerPayModule::channel_deposits(channel_id), Some((channel_peers[1], 10)));
+
+            let risa = account_key("Risa");
+            let reward = StorageDeposit::<TestRuntime>::refund_channel_deposit(&channel_id, &risa);
+            assert_eq!(reward, 1);
+            assert_eq!(Balances::free_balance(&risa), 1001);
+            assert_eq!(Balances::free_balance(&channel_peers[1]), caller_balance - 1);
+            assert_eq!(Balances::reserved_balance(&channel_peers[1]), 0);
+            assert_eq!(Balances::free_balance(CelerPayModule::get_wallet_account(&channel_id)), 0);
+            assert_eq!(CelerPayModule::channel_deposits(channel_id), None);
+        })
+    }
+
+    #[test]
+    fn test_pass_reserve_pay_info_deposit_only_for_new_pay_info() {
+        ExtBuilder::build().execute_with(|| {
+            MockStorageDeposits::set(10, 1);
+            System::set_block_number(1);
+            let alice = account_key("Alice");
+            let bob = account_key("Bob");
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_condition(0)],
+                transfer_func: get_transfer_func(alice, 10, 0),
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            // an account which can not reserve the deposit can not create a pay info
+            assert_noop!(
+                CelerPayModule::resolve_payment_by_conditions(
+                    Origin::signed(account_key("src")),
+                    pay_request.clone()
+                ),
+                "caller can not reserve storage deposit"
+            );
+
+            assert_ok!(CelerPayModule::resolve_payment_by_conditions(
+                Origin::signed(alice),
+                pay_request.clone()
+            ));
+            assert_eq!(Balances::reserved_balance(&alice), 1);
+
+            let encoded = encode_conditional_pay::<TestRuntime>(cond_pay);
+            let pay_id = calculate_pay_id::<TestRuntime>(<TestRuntime as frame_system::Trait>::Hashing::hash(&encoded));
+            assert_eq!(CelerPayModule::pay_info_deposits(pay_id), Some((alice, 1)));
+
+            // resolving the existing pay info does not reserve a deposit
+            assert_noop!(
+                CelerPayModule::resolve_payment_by_conditions(
+                    Origin::signed(bob),
+                    pay_request
+                ),
+                "New amount is not larger"
+            );
+            assert_eq!(Balances::reserved_balance(&bob), 0);
+
+            // the depositor pruning its own pay info is not rewarded
+            assert_eq!(StorageDeposit::<TestRuntime>::refund_pay_info_deposit(&pay_id, &alice), 0);
+            assert_eq!(Balances::reserved_balance(&alice), 0);
+        })
+    }
+}
@@ -1,6 +1,6 @@
 //! The crate's tests.
 
 pub mod ledger_operation;
-mod pay_resolver;
+pub mod pay_resolver;
 mod celer_pay;
 
@@ -567,6 +567,8 @@ pub mod tests_celer {
                         let pay_request = ResolvePaymentConditionsRequest {
                             cond_pay: cond_pays[peer_index as usize][list_index as usize][pay_index as usize].clone(),
                             hash_preimages: vec![],
+                            oracle_outcomes: vec![],
+                            lottery_secret: None,
                         };
                         assert_ok!(PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request));
                     }
@@ -735,10 +737,14 @@ pub mod tests_celer {
                 transfer_func: transfer_func,
                 resolve_deadline: 99999,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: None,
             };
             let pay_request = ResolvePaymentConditionsRequest {
                 cond_pay: cond_pay,
                 hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
             };
 
             assert_ok!(CelerPayModule::resolve_payment_by_conditions(
@@ -760,6 +766,8 @@ pub mod tests_celer {
                 transfer_func: transfer_func,
                 resolve_deadline: 99999,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: None,
             };
 
             let encoded_cond_pay = encode_conditional_pay(shared_pay.clone());
@@ -8,6 +8,7 @@ pub mod test_ledger_operation {
         TransferFunction, TransferFunctionType, TokenType, AccountAmtPair, TokenInfo,
     };
     use crate::pool::Pool;
+    use crate::traits::Fungibles;
     use crate::pay_registry::PayRegistry;
     use crate::tests::pay_resolver::test_pay_resolver::*;
     use crate::ledger_operation::*;
@@ -1136,6 +1137,8 @@ pub mod test_ledger_operation {
             let pay_request = ResolvePaymentConditionsRequest {
                 cond_pay: cond_pays[0][0][0].clone(),
                 hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
             };
 
             let (pay_id, _amount_1, _) =
@@ -1208,6 +1211,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[0][0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -1217,6 +1222,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[1][0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -1314,6 +1321,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[0][0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -1322,6 +1331,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[1][0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -1405,6 +1416,8 @@ pub mod test_ledger_operation {
                         let pay_request = ResolvePaymentConditionsRequest {
                             cond_pay: cond_pays[peer_index as usize][list_index as usize][pay_index as usize].clone(),
                             hash_preimages: vec![],
+                            oracle_outcomes: vec![],
+                            lottery_secret: None,
                         };
                         let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
                     }
@@ -1505,6 +1518,8 @@ pub mod test_ledger_operation {
                         let pay_request = ResolvePaymentConditionsRequest {
                             cond_pay: cond_pays[peer_index as usize][list_index as usize][pay_index as usize].clone(),
                             hash_preimages: vec![],
+                            oracle_outcomes: vec![],
+                            lottery_secret: None,
                         };
                         let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
                     }
@@ -1579,6 +1594,8 @@ pub mod test_ledger_operation {
                         let pay_request = ResolvePaymentConditionsRequest {
                             cond_pay: cond_pays[peer_index as usize][list_index as usize][pay_index as usize].clone(),
                             hash_preimages: vec![],
+                            oracle_outcomes: vec![],
+                            lottery_secret: None,
                         };
                         let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
                     }
@@ -1676,6 +1693,8 @@ pub mod test_ledger_operation {
                         let pay_request = ResolvePaymentConditionsRequest {
                             cond_pay: cond_pays[peer_index as usize][list_index as usize][pay_index as usize].clone(),
                             hash_preimages: vec![],
+                            oracle_outcomes: vec![],
+                            lottery_secret: None,
                         };
                         let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
                     }
@@ -1780,6 +1799,8 @@ pub mod test_ledger_operation {
                         let pay_request = ResolvePaymentConditionsRequest {
                             cond_pay: cond_pays[peer_index as usize][list_index as usize][pay_index as usize].clone(),
                             hash_preimages: vec![],
+                            oracle_outcomes: vec![],
+                            lottery_secret: None,
                         };
                         let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
                     }
@@ -1875,6 +1896,8 @@ pub mod test_ledger_operation {
                         let pay_request = ResolvePaymentConditionsRequest {
                             cond_pay: cond_pays[peer_index as usize][list_index as usize][pay_index as usize].clone(),
                             hash_preimages: vec![],
+                            oracle_outcomes: vec![],
+                            lottery_secret: None,
                         };
                         let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
                     }
@@ -1928,6 +1951,297 @@ pub mod test_ledger_operation {
         })
     }
 
+    #[test]
+    fn test_pass_confirm_settle_with_merkle_root_pending_pays() {
+        ExtBuilder::build().execute_with(|| { 
+            System::set_block_number(1);  
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+            let open_channel_request = get_open_channel_request(true, 2000, 500001, 10, true, channel_peers.clone(), 1, peers_pair.clone());
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap();
+            assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                Origin::signed(channel_peers[0]),
+                channel_id,
+                channel_peers[0],
+                500,
+                0
+            ));
+            assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                Origin::signed(channel_peers[1]),
+                channel_id,
+                channel_peers[1],
+                500,
+                0
+            ));
+
+            // the meaning of the index: [peer index][pay hash list index][pay index]
+            let peers_pay_hash_lists_amts: Vec<Vec<Vec<Balance>>> =
+                vec![vec![vec![1, 2], vec![3, 4]], vec![vec![5, 6], vec![7, 8]]];
+
+            let global_result: (
+                SignedSimplexStateArray<H256, AccountId, BlockNumber, Balance, Signature>,
+                Vec<BlockNumber>,
+                Vec<Vec<Vec<ConditionalPay<Moment, BlockNumber, AccountId, H256, MockCall, Balance>>>>,
+                Vec<Vec<H256>>,
+                Vec<Vec<PayIdList<H256>>>,
+            ) = get_cosigned_intend_settle(
+                vec![channel_id, channel_id],
+                peers_pay_hash_lists_amts,
+                vec![1, 1],         // seq_nums
+                vec![10, 20],       // transfer amounts
+                vec![99999, 99999], // last_pay_resolve_deadlines
+                vec![channel_peers[0], channel_peers[1]],
+                channel_peers[0],
+                vec![peers_pair[0].clone(), peers_pair[1].clone()],
+                1,
+            );
+
+            let cond_pays = global_result.2;
+            for list_index in 0..cond_pays[0].len() {
+                for pay_index in 0..cond_pays[0][list_index as usize].len() {
+                    let pay_request = ResolvePaymentConditionsRequest {
+                        cond_pay: cond_pays[0][list_index as usize][pay_index as usize].clone(),
+                        hash_preimages: vec![],
+                        oracle_outcomes: vec![],
+                        lottery_secret: None,
+                    };
+                    let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+                }
+            }
+
+            // pass onchain  resolve deadline of all onchain resolved pays
+            System::set_block_number(System::block_number() + 6);
+
+            // commit all pending pays of peer 0 by a single Merkle root
+            let pay_id_list_array = global_result.4;
+            let mut pay_ids: Vec<H256> = vec![];
+            for list_index in 0..pay_id_list_array[0].len() {
+                pay_ids.extend(pay_id_list_array[0][list_index as usize].pay_ids.clone());
+            }
+            let (pending_pay_root, proofs) = get_pay_id_merkle_proofs(pay_ids.clone());
+            let signed_simplex_state_array = SignedSimplexStateArray {
+                signed_simplex_states: vec![get_merkle_root_co_signed_simplex_state(
+                    channel_id,
+                    channel_peers[0],
+                    1,
+                    10,
+                    pending_pay_root,
+                    20,
+                    10,
+                    peers_pair.clone(),
+                )],
+            };
+            assert_ok!(LedgerOperation::<TestRuntime>::intend_settle(
+                Origin::signed(channel_peers[0]),
+                signed_simplex_state_array,
+            ));
+
+            // fail to clear a pay by the proof of another pay
+            assert_noop!(
+                LedgerOperation::<TestRuntime>::clear_pays_by_proofs(
+                    channel_id,
+                    channel_peers[0],
+                    vec![PayIdProof { pay_id: pay_ids[2], receiver: None, proof: proofs[0].clone() }],
+                ),
+                DispatchError::Other("Invalid pay id proof")
+            );
+
+            // clear pays in batches of any order, pays already cleared are skipped
+            assert_ok!(CelerPayModule::clear_pays_by_proofs(
+                Origin::signed(channel_peers[1]),
+                channel_id,
+                channel_peers[0],
+                vec![
+                    PayIdProof { pay_id: pay_ids[3], receiver: None, proof: proofs[3].clone() },
+                    PayIdProof { pay_id: pay_ids[0], receiver: None, proof: proofs[0].clone() },
+                ],
+            ));
+            let (_, transfer_out) = CelerPayModule::get_transfer_out_map(channel_id);
+            assert_eq!(transfer_out[0].amount, 15);
+            assert_ok!(CelerPayModule::clear_pays_by_proofs(
+                Origin::signed(channel_peers[1]),
+                channel_id,
+                channel_peers[0],
+                vec![
+                    PayIdProof { pay_id: pay_ids[0], receiver: None, proof: proofs[0].clone() },
+                    PayIdProof { pay_id: pay_ids[1], receiver: None, proof: proofs[1].clone() },
+                    PayIdProof { pay_id: pay_ids[2], receiver: None, proof: proofs[2].clone() },
+                    PayIdProof { pay_id: pay_ids[2], receiver: None, proof: proofs[2].clone() },
+                ],
+            ));
+            let (_, transfer_out) = CelerPayModule::get_transfer_out_map(channel_id);
+            assert_eq!(vec![transfer_out[0].amount, transfer_out[1].amount], [20, 0]);
+
+            let expected_event = TestEvent::celer(RawEvent::ClearOnePay(
+                channel_id,
+                pay_ids[2],
+                channel_peers[0],
+                3,
+            ));
+            assert_eq!(System::events().iter().filter(|a| a.event == expected_event).count(), 1);
+
+            // Merkle-root pays are finalized only after last_pay_resolve_deadline
+            let settle_finalized_time = CelerPayModule::get_settle_finalized_time(channel_id);
+            System::set_block_number(settle_finalized_time);
+            let err = LedgerOperation::<TestRuntime>::confirm_settle(channel_id).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Payments are not finalized"));
+
+            System::set_block_number(21);
+            let (_, settle_balance) =
+                LedgerOperation::<TestRuntime>::confirm_settle(channel_id).unwrap();
+            assert_eq!(settle_balance, [480, 520]);
+
+            let status = CelerPayModule::get_channel_status(channel_id);
+            assert_eq!(status, 3);
+        })
+    }
+
+    #[test]
+    fn test_fail_clear_pays_by_proofs_with_invalid_proof() {
+        ExtBuilder::build().execute_with(|| { 
+            let (channel_peers, peers_pair) = get_sorted_peer(account_pair("Alice"), account_pair("Bob"));
+            let (channel_id, pay_ids, proofs) =
+                intend_settle_with_merkle_root_pending_pays(channel_peers.clone(), peers_pair.clone(), 1);
+
+            // a proof of another pay, a tampered proof and an empty proof are rejected
+            let mut tampered_proof = proofs[1].clone();
+            tampered_proof[0] = H256::from_low_u64_be(1);
+            for invalid_proof in vec![
+                PayIdProof { pay_id: pay_ids[2], receiver: None, proof: proofs[0].clone() },
+                PayIdProof { pay_id: pay_ids[1], receiver: None, proof: tampered_proof },
+                PayIdProof { pay_id: pay_ids[1], receiver: None, proof: vec![] },
+                PayIdProof { pay_id: pay_ids[1], receiver: Some(channel_peers[1]), proof: proofs[1].clone() },
+            ] {
+                // the whole batch fails, so the valid proof in it does not clear its pay
+                assert_noop!(
+                    LedgerOperation::<TestRuntime>::clear_pays_by_proofs(
+                        channel_id,
+                        channel_peers[0],
+                        vec![
+                            PayIdProof { pay_id: pay_ids[0], receiver: None, proof: proofs[0].clone() },
+                            invalid_proof,
+                        ],
+                    ),
+                    DispatchError::Other("Invalid pay id proof")
+                );
+            }
+            let (_, transfer_out) = CelerPayModule::get_transfer_out_map(channel_id);
+            assert_eq!(transfer_out[0].amount, 10);
+        })
+    }
+
+    #[test]
+    fn test_pass_clear_pays_by_proofs_with_duplicate_pay_ids_across_batches() {
+        ExtBuilder::build().execute_with(|| { 
+            let (channel_peers, peers_pair) = get_sorted_peer(account_pair("Alice"), account_pair("Bob"));
+            let (channel_id, pay_ids, proofs) =
+                intend_settle_with_merkle_root_pending_pays(channel_peers.clone(), peers_pair.clone(), 1);
+
+            assert_ok!(LedgerOperation::<TestRuntime>::clear_pays_by_proofs(
+                channel_id,
+                channel_peers[0],
+                vec![
+                    PayIdProof { pay_id: pay_ids[0], receiver: None, proof: proofs[0].clone() },
+                    PayIdProof { pay_id: pay_ids[1], receiver: None, proof: proofs[1].clone() },
+                ],
+            ));
+            // pay 1 is skipped, since it has been cleared by the former batch
+            assert_ok!(LedgerOperation::<TestRuntime>::clear_pays_by_proofs(
+                channel_id,
+                channel_peers[0],
+                vec![
+                    PayIdProof { pay_id: pay_ids[1], receiver: None, proof: proofs[1].clone() },
+                    PayIdProof { pay_id: pay_ids[2], receiver: None, proof: proofs[2].clone() },
+                ],
+            ));
+            let (_, transfer_out) = CelerPayModule::get_transfer_out_map(channel_id);
+            assert_eq!(transfer_out[0].amount, 16);
+            let expected_event = TestEvent::celer(RawEvent::ClearOnePay(
+                channel_id,
+                pay_ids[1],
+                channel_peers[0],
+                2,
+            ));
+            assert_eq!(System::events().iter().filter(|a| a.event == expected_event).count(), 1);
+
+            // pays cleared for the former state are cleared again for a newer state with the same pays
+            let (pending_pay_root, _) = get_pay_id_merkle_proofs(pay_ids.clone());
+            assert_ok!(LedgerOperation::<TestRuntime>::intend_settle(
+                Origin::signed(channel_peers[0]),
+                SignedSimplexStateArray {
+                    signed_simplex_states: vec![get_merkle_root_co_signed_simplex_state(
+                        channel_id,
+                        channel_peers[0],
+                        2,
+                        10,
+                        pending_pay_root,
+                        20,
+                        10,
+                        peers_pair.clone(),
+                    )],
+                }
+            ));
+            assert_ok!(LedgerOperation::<TestRuntime>::clear_pays_by_proofs(
+                channel_id,
+                channel_peers[0],
+                vec![PayIdProof { pay_id: pay_ids[1], receiver: None, proof: proofs[1].clone() }],
+            ));
+            let (_, transfer_out) = CelerPayModule::get_transfer_out_map(channel_id);
+            assert_eq!(transfer_out[0].amount, 12);
+        })
+    }
+
+    #[test]
+    fn test_pass_clear_pays_by_proofs_in_parallel_batches_from_different_submitters() {
+        ExtBuilder::build().execute_with(|| { 
+            let (channel_peers, peers_pair) = get_sorted_peer(account_pair("Alice"), account_pair("Bob"));
+            let (channel_id, pay_ids, proofs) =
+                intend_settle_with_merkle_root_pending_pays(channel_peers.clone(), peers_pair.clone(), 1);
+
+            // batches of both peers and a third party, all against the same intended state
+            let batches = vec![
+                (channel_peers[0], vec![0, 1]),
+                (channel_peers[1], vec![2, 3]),
+                (account_key("Risa"), vec![3, 1, 0]),
+            ];
+            for (submitter, pay_indexes) in batches {
+                assert_ok!(CelerPayModule::clear_pays_by_proofs(
+                    Origin::signed(submitter),
+                    channel_id,
+                    channel_peers[0],
+                    pay_indexes.iter().map(|i| PayIdProof {
+                        pay_id: pay_ids[*i],
+                        receiver: None,
+                        proof: proofs[*i].clone(),
+                    }).collect(),
+                ));
+            }
+
+            // every pay is added to the transfer out once
+            let (_, transfer_out) = CelerPayModule::get_transfer_out_map(channel_id);
+            assert_eq!(vec![transfer_out[0].amount, transfer_out[1].amount], [20, 0]);
+            for i in 0..pay_ids.len() {
+                let expected_event = TestEvent::celer(RawEvent::ClearOnePay(
+                    channel_id,
+                    pay_ids[i],
+                    channel_peers[0],
+                    (i + 1) as Balance,
+                ));
+                assert_eq!(System::events().iter().filter(|a| a.event == expected_event).count(), 1);
+            }
+
+            System::set_block_number(21);
+            let (_, settle_balance) =
+                LedgerOperation::<TestRuntime>::confirm_settle(channel_id).unwrap();
+            assert_eq!(settle_balance, [480, 520]);
+        })
+    }
+
     #[test]
     fn test_pass_open_channel_when_total_deposit_is_larger_than_zero() {
         ExtBuilder::build().execute_with(|| {   
@@ -1984,83 +2298,963 @@ pub mod test_ledger_operation {
     }
 
     #[test]
-    fn test_fail_cooperative_settle_when_submitted_sum_is_not_equal_to_deposit_sum() {
+    fn test_pass_open_channel_and_deposit_with_fungible_asset() {
         ExtBuilder::build().execute_with(|| {   
+            let celer_ledger_account = CelerPayModule::get_celer_ledger_id();
             let alice_pair = account_pair("Alice");
             let bob_pair = account_pair("Bob");
             let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
-            let open_channel_request = get_open_channel_request(true, 800, 500001, 10, true, channel_peers.clone(), 1, peers_pair.clone());
-            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+
+            assert_ok!(Pool::<TestRuntime>::deposit_asset_pool(
+                Origin::signed(channel_peers[0]),
+                channel_peers[0],
+                TEST_ASSET_ID,
+                100,
+            ));
+            assert_ok!(Pool::<TestRuntime>::approve_asset(
                 Origin::signed(channel_peers[0]),
+                celer_ledger_account,
+                TEST_ASSET_ID,
+                100,
+            ));
+
+            let mut channel_initializer = get_payment_channel_initializer(false, 1000, 500001, 10, false, channel_peers.clone(), 1);
+            channel_initializer.init_distribution.token = TokenInfo { token_type: TokenType::Asset(TEST_ASSET_ID) };
+            let open_channel_request = get_signed_open_channel_request(channel_initializer, peers_pair.clone());
+            let native_balance = Balances::free_balance(&channel_peers[1]);
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[1]),
                 open_channel_request,
-                0,
+                200,
             ).unwrap();
+
+            let (_, deposits, _) = CelerPayModule::get_balance_map(channel_id);
+            assert_eq!(vec![deposits[0].amount, deposits[1].amount], [100, 200]);
+            assert_eq!(Balances::free_balance(&channel_peers[1]), native_balance);
+            assert_eq!(MockAssets::balance(TEST_ASSET_ID, &channel_peers[1]), 800);
+            assert_eq!(CelerPayModule::get_asset_pool_balance(channel_peers[0], TEST_ASSET_ID).amount, 0);
+
             assert_ok!(LedgerOperation::<TestRuntime>::deposit(
-                Origin::signed(channel_peers[0]),
+                Origin::signed(channel_peers[1]),
                 channel_id,
-                channel_peers[0],
-                5,
+                channel_peers[1],
+                50,
                 0
             ));
+            let (_, deposits, _) = CelerPayModule::get_balance_map(channel_id);
+            assert_eq!(vec![deposits[0].amount, deposits[1].amount], [100, 250]);
+            assert_eq!(MockAssets::balance(TEST_ASSET_ID, &CelerPayModule::get_wallet_account(&channel_id)), 350);
+            assert_eq!(MockAssets::balance(TEST_ASSET_ID, &CelerPayModule::get_celer_wallet_id()), 0);
+        })
+    }
+
+    #[test]
+    fn test_pass_cooperative_settle_fungible_asset_channel() {
+        ExtBuilder::build().execute_with(|| {   
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+            let channel_id = open_asset_channel(channel_peers.clone(), peers_pair.clone());
+            let native_balances = [Balances::free_balance(&channel_peers[0]), Balances::free_balance(&channel_peers[1])];
 
             let cooperative_settle_request = get_cooperative_settle_request(
                 channel_id,
                 2,
-                channel_peers,
-                vec![200, 200],
+                channel_peers.clone(),
+                vec![250, 50],
                 500000,
                 peers_pair,
             );
-
-            let err = LedgerOperation::<TestRuntime>::cooperative_settle(cooperative_settle_request).unwrap_err();
-            assert_eq!(err, DispatchError::Other("Balance sum mismatch"));
+            let (_, settle_balance) =
+                LedgerOperation::<TestRuntime>::cooperative_settle(cooperative_settle_request).unwrap();
+            assert_eq!(settle_balance, [250, 50]);
+            assert_eq!(CelerPayModule::get_channel_status(channel_id), 3);
+
+            assert_eq!(MockAssets::balance(TEST_ASSET_ID, &channel_peers[0]), 1150);
+            assert_eq!(MockAssets::balance(TEST_ASSET_ID, &channel_peers[1]), 850);
+            assert_eq!(MockAssets::balance(TEST_ASSET_ID, &CelerPayModule::get_wallet_account(&channel_id)), 0);
+            assert_eq!(Balances::free_balance(&channel_peers[0]), native_balances[0]);
+            assert_eq!(Balances::free_balance(&channel_peers[1]), native_balances[1]);
         })
     }
 
     #[test]
-    fn test_pass_cooperative_settle() {
+    fn test_pass_intend_settle_and_confirm_settle_fungible_asset_channel() {
         ExtBuilder::build().execute_with(|| {   
+            System::set_block_number(1);
             let alice_pair = account_pair("Alice");
             let bob_pair = account_pair("Bob");
             let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
-            let open_channel_request = get_open_channel_request(true, 800, 500001, 10, true, channel_peers.clone(), 1, peers_pair.clone());
-            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
-                Origin::signed(channel_peers[0]),
-                open_channel_request,
-                0,
-            ).unwrap();
-            assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+            let channel_id = open_asset_channel(channel_peers.clone(), peers_pair.clone());
+
+            // peer 0 transfers 30 units of the asset to peer 1, peer 1 transfers 10 back
+            let signed_simplex_state_array = SignedSimplexStateArray {
+                signed_simplex_states: vec![
+                    get_asset_co_signed_simplex_state(channel_id, channel_peers[0], 1, 30, peers_pair.clone()),
+                    get_asset_co_signed_simplex_state(channel_id, channel_peers[1], 1, 10, peers_pair.clone()),
+                ],
+            };
+            assert_ok!(LedgerOperation::<TestRuntime>::intend_settle(
                 Origin::signed(channel_peers[0]),
-                channel_id,
-                channel_peers[0],
-                200,
-                0
+                signed_simplex_state_array,
             ));
+            assert_eq!(CelerPayModule::get_channel_status(channel_id), 2);
 
-            let cooperative_settle_request = get_cooperative_settle_request(
-                channel_id,
-                2,
-                channel_peers,
-                vec![150, 50],
-                500000,
-                peers_pair,
-            );
-
-            let total_balance = CelerPayModule::get_total_balance(channel_id);
-            assert_eq!(total_balance.amount, 200);
-
-            let (channel_id, settle_balance): (H256, Vec<Balance>) =
-                LedgerOperation::<TestRuntime>::cooperative_settle(cooperative_settle_request).unwrap();
-            assert_eq!(settle_balance, [150, 50]);
+            let settle_finalized_time = CelerPayModule::get_settle_finalized_time(channel_id);
+            System::set_block_number(settle_finalized_time);
+            let (_, settle_balance) = LedgerOperation::<TestRuntime>::confirm_settle(channel_id).unwrap();
+            assert_eq!(settle_balance, [80, 220]);
+            assert_eq!(CelerPayModule::get_channel_status(channel_id), 3);
 
-            let status = CelerPayModule::get_channel_status(channel_id);
-            assert_eq!(status, 3);
+            assert_eq!(MockAssets::balance(TEST_ASSET_ID, &channel_peers[0]), 980);
+            assert_eq!(MockAssets::balance(TEST_ASSET_ID, &channel_peers[1]), 1020);
+            assert_eq!(MockAssets::balance(TEST_ASSET_ID, &CelerPayModule::get_wallet_account(&channel_id)), 0);
         })
     }
 
     #[test]
-    fn test_pass_intend_settle_when_time_is_after_last_pay_resolve_deadline() {
+    fn test_fail_intend_settle_fungible_asset_channel_with_native_token_transfer() {
         ExtBuilder::build().execute_with(|| {   
+            System::set_block_number(1);
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+            let channel_id = open_asset_channel(channel_peers.clone(), peers_pair.clone());
+
+            let pay_id_list = PayIdList { pay_ids: vec![], next_list_hash: None };
+            let signed_simplex_state_array = SignedSimplexStateArray {
+                signed_simplex_states: vec![get_co_signed_simplex_state(
+                    channel_id, channel_peers[0], 1, 30, pay_id_list, 1, 0, peers_pair.clone(),
+                )],
+            };
+            assert_noop!(
+                LedgerOperation::<TestRuntime>::intend_settle(
+                    Origin::signed(channel_peers[0]),
+                    signed_simplex_state_array,
+                ),
+                DispatchError::Other("Token mismatch of channel")
+            );
+        })
+    }
+
+    #[test]
+    fn test_fail_open_channel_with_invalid_token_type() {
+        ExtBuilder::build().execute_with(|| {   
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+
+            let mut channel_initializer = get_payment_channel_initializer(false, 1000, 500001, 10, true, channel_peers.clone(), 1);
+            channel_initializer.init_distribution.token = TokenInfo { token_type: TokenType::Invalid };
+            let open_channel_request = get_signed_open_channel_request(channel_initializer, peers_pair.clone());
+            assert_noop!(
+                LedgerOperation::<TestRuntime>::open_channel(
+                    Origin::signed(channel_peers[1]),
+                    open_channel_request,
+                    0,
+                ),
+                Error::<TestRuntime>::InvalidTokenType
+            );
+        })
+    }
+
+    #[test]
+    fn test_fail_cooperative_settle_when_submitted_sum_is_not_equal_to_deposit_sum() {
+        ExtBuilder::build().execute_with(|| {   
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+            let open_channel_request = get_open_channel_request(true, 800, 500001, 10, true, channel_peers.clone(), 1, peers_pair.clone());
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap();
+            assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                Origin::signed(channel_peers[0]),
+                channel_id,
+                channel_peers[0],
+                5,
+                0
+            ));
+
+            let cooperative_settle_request = get_cooperative_settle_request(
+                channel_id,
+                2,
+                channel_peers,
+                vec![200, 200],
+                500000,
+                peers_pair,
+            );
+
+            let err = LedgerOperation::<TestRuntime>::cooperative_settle(cooperative_settle_request).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Balance sum mismatch"));
+        })
+    }
+
+    #[test]
+    fn test_pass_cooperative_settle() {
+        ExtBuilder::build().execute_with(|| {   
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+            let open_channel_request = get_open_channel_request(true, 800, 500001, 10, true, channel_peers.clone(), 1, peers_pair.clone());
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap();
+            assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                Origin::signed(channel_peers[0]),
+                channel_id,
+                channel_peers[0],
+                200,
+                0
+            ));
+
+            let cooperative_settle_request = get_cooperative_settle_request(
+                channel_id,
+                2,
+                channel_peers,
+                vec![150, 50],
+                500000,
+                peers_pair,
+            );
+
+            let total_balance = CelerPayModule::get_total_balance(channel_id);
+            assert_eq!(total_balance.amount, 200);
+
+            let (channel_id, settle_balance): (H256, Vec<Balance>) =
+                LedgerOperation::<TestRuntime>::cooperative_settle(cooperative_settle_request).unwrap();
+            assert_eq!(settle_balance, [150, 50]);
+
+            let status = CelerPayModule::get_channel_status(channel_id);
+            assert_eq!(status, 3);
+        })
+    }
+
+    #[test]
+    fn test_pass_prune_channel_after_prune_delay() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            MockStorageDeposits::set(10, 0);
+            let risa = account_key("Risa");
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+            let open_channel_request = get_open_channel_request(true, 800, 500001, 10, true, channel_peers.clone(), 1, peers_pair.clone());
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request.clone(),
+                0,
+            ).unwrap();
+            assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                Origin::signed(channel_peers[0]),
+                channel_id,
+                channel_peers[0],
+                200,
+                0
+            ));
+            assert_noop!(
+                LedgerOperation::<TestRuntime>::prune_channel(Origin::signed(risa), channel_id),
+                DispatchError::Other("Channel status error")
+            );
+
+            let cooperative_settle_request = get_cooperative_settle_request(
+                channel_id,
+                2,
+                channel_peers.clone(),
+                vec![150, 50],
+                500000,
+                peers_pair,
+            );
+            LedgerOperation::<TestRuntime>::cooperative_settle(cooperative_settle_request).unwrap();
+            assert_eq!(CelerPayModule::channel_closed_block(channel_id), Some(1));
+            assert_eq!(CelerPayModule::channel_status_nums(ChannelStatus::Closed as u8), Some(1));
+
+            // channel can be pruned after ChannelPruneDelay from its closing
+            System::set_block_number(10);
+            assert_noop!(
+                LedgerOperation::<TestRuntime>::prune_channel(Origin::signed(risa), channel_id),
+                DispatchError::Other("Channel prune delay not passed")
+            );
+
+            System::set_block_number(11);
+            let reward = LedgerOperation::<TestRuntime>::prune_channel(Origin::signed(risa), channel_id).unwrap();
+            assert_eq!(reward, 1);
+            assert_eq!(CelerPayModule::channel_map(channel_id), None);
+            assert_eq!(CelerPayModule::wallet(channel_id), None);
+            assert_eq!(CelerPayModule::channel_closed_block(channel_id), None);
+            assert_eq!(CelerPayModule::channel_deposits(channel_id), None);
+            assert_eq!(CelerPayModule::channel_status_nums(ChannelStatus::Closed as u8), Some(0));
+            assert_eq!(Balances::reserved_balance(&channel_peers[0]), 0);
+            assert_eq!(Balances::free_balance(&risa), 1001);
+            assert_eq!(CelerPayModule::pruned_channels(channel_id), true);
+
+            assert_noop!(
+                LedgerOperation::<TestRuntime>::prune_channel(Origin::signed(risa), channel_id),
+                Error::<TestRuntime>::ChannelNotExist
+            );
+
+            // pruned channel can not be opened again by replaying its open channel request
+            assert_noop!(
+                LedgerOperation::<TestRuntime>::open_channel(
+                    Origin::signed(channel_peers[0]),
+                    open_channel_request,
+                    0,
+                ),
+                DispatchError::Other("Channel is pruned")
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_settle_and_prune_channel_leaving_wallet_below_existential_deposit() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            MockExistentialDeposit::set(500);
+            let carl = account_key("Carl");
+            let risa = account_key("Risa");
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+            let open_channel_request = get_open_channel_request(false, 0, 500001, 10, true, channel_peers.clone(), 1, peers_pair.clone());
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(carl),
+                open_channel_request,
+                0,
+            ).unwrap();
+            let wallet_account = CelerPayModule::get_wallet_account(&channel_id);
+            assert_eq!(Balances::free_balance(&wallet_account), 500);
+            assert_eq!(Balances::free_balance(&carl), 99500);
+
+            for peer in channel_peers.iter() {
+                assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                    Origin::signed(*peer),
+                    channel_id,
+                    *peer,
+                    500,
+                    0
+                ));
+            }
+
+            // the first transfer leaves less than the existential deposit in the wallet
+            let cooperative_settle_request = get_cooperative_settle_request(
+                channel_id,
+                2,
+                channel_peers.clone(),
+                vec![600, 400],
+                500000,
+                peers_pair,
+            );
+            LedgerOperation::<TestRuntime>::cooperative_settle(cooperative_settle_request).unwrap();
+            assert_eq!(CelerPayModule::get_channel_status(channel_id), 3);
+            assert_eq!(CelerPayModule::get_wallet_balance(channel_id).amount, 0);
+            assert_eq!(Balances::free_balance(&channel_peers[0]), 1100);
+            assert_eq!(Balances::free_balance(&channel_peers[1]), 900);
+            assert_eq!(Balances::free_balance(&wallet_account), 500);
+
+            // the endowment of the wallet sub-account is refunded when the channel is pruned
+            System::set_block_number(11);
+            let reward = LedgerOperation::<TestRuntime>::prune_channel(Origin::signed(risa), channel_id).unwrap();
+            assert_eq!(reward, 0);
+            assert_eq!(CelerPayModule::wallet_endowments(channel_id), None);
+            assert_eq!(Balances::free_balance(&wallet_account), 0);
+            assert_eq!(Balances::free_balance(&carl), 100000);
+            assert_eq!(Balances::free_balance(&risa), 1000);
+        })
+    }
+
+    #[test]
+    fn test_pass_open_multi_peer_channel() {
+        ExtBuilder::build().execute_with(|| {   
+            let celer_ledger_account = CelerPayModule::get_celer_ledger_id();
+            let (channel_peers, peers_pair) = get_sorted_peers(
+                vec![account_pair("Alice"), account_pair("Bob"), account_pair("Risa")]
+            );
+            for i in 1..3 {
+                assert_ok!(Pool::<TestRuntime>::deposit_pool(
+                    Origin::signed(channel_peers[i]),
+                    channel_peers[i],
+                    100,
+                ));
+                approve(channel_peers[i], celer_ledger_account, 100);
+            }
+
+            let open_channel_request = get_multi_peer_open_channel_request(
+                channel_peers.clone(), vec![300, 100, 50], 0, peers_pair.clone()
+            );
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                300,
+            ).unwrap();
+
+            let (peers, deposits, withdrawals) = CelerPayModule::get_balance_map(channel_id);
+            assert_eq!(peers, channel_peers);
+            assert_eq!(deposits.iter().map(|d| d.amount).collect::<Vec<Balance>>(), [300, 100, 50]);
+            assert_eq!(withdrawals.iter().map(|w| w.amount).collect::<Vec<Balance>>(), [0, 0, 0]);
+            assert_eq!(CelerPayModule::get_total_balance(channel_id).amount, 450);
+        })
+    }
+
+    #[test]
+    fn test_fail_open_multi_peer_channel_without_all_co_signatures() {
+        ExtBuilder::build().execute_with(|| {   
+            let (channel_peers, peers_pair) = get_sorted_peers(
+                vec![account_pair("Alice"), account_pair("Bob"), account_pair("Risa")]
+            );
+            let mut open_channel_request = get_multi_peer_open_channel_request(
+                channel_peers.clone(), vec![0, 0, 0], 0, peers_pair.clone()
+            );
+            open_channel_request.sigs.pop();
+
+            let err = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Check co-sigs failed"));
+        })
+    }
+
+    #[test]
+    fn test_fail_open_channel_when_peers_exceed_max_channel_peers() {
+        ExtBuilder::build().execute_with(|| {   
+            let (channel_peers, peers_pair) = get_sorted_peers(vec![
+                account_pair("Alice"), account_pair("Bob"), account_pair("Risa"),
+                account_pair("Carl"), account_pair("Dave"),
+            ]);
+            let open_channel_request = get_multi_peer_open_channel_request(
+                channel_peers.clone(), vec![0, 0, 0, 0, 0], 0, peers_pair.clone()
+            );
+
+            let err = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Wrong length"));
+        })
+    }
+
+    #[test]
+    fn test_pass_cooperative_settle_multi_peer_channel() {
+        ExtBuilder::build().execute_with(|| {   
+            let (channel_peers, peers_pair) = get_sorted_peers(
+                vec![account_pair("Alice"), account_pair("Bob"), account_pair("Risa")]
+            );
+            let open_channel_request = get_multi_peer_open_channel_request(
+                channel_peers.clone(), vec![0, 0, 0], 0, peers_pair.clone()
+            );
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap();
+            assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                Origin::signed(channel_peers[0]),
+                channel_id,
+                channel_peers[0],
+                300,
+                0
+            ));
+
+            let cooperative_settle_request = get_multi_peer_cooperative_settle_request(
+                channel_id,
+                1,
+                channel_peers.clone(),
+                vec![100, 150, 50],
+                500000,
+                peers_pair,
+            );
+            let (_, settle_balance) =
+                LedgerOperation::<TestRuntime>::cooperative_settle(cooperative_settle_request).unwrap();
+            assert_eq!(settle_balance, [100, 150, 50]);
+            assert_eq!(CelerPayModule::get_channel_status(channel_id), 3);
+        })
+    }
+
+    #[test]
+    fn test_fail_cooperative_settle_multi_peer_channel_with_misaligned_accounts_or_signatures() {
+        ExtBuilder::build().execute_with(|| {   
+            let (channel_peers, peers_pair) = get_sorted_peers(
+                vec![account_pair("Alice"), account_pair("Bob"), account_pair("Risa")]
+            );
+            let open_channel_request = get_multi_peer_open_channel_request(
+                channel_peers.clone(), vec![0, 0, 0], 0, peers_pair.clone()
+            );
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap();
+
+            // settle accounts are checked before any signature, so unsigned requests are rejected early
+            let mut cooperative_settle_request = get_multi_peer_cooperative_settle_request(
+                channel_id,
+                1,
+                channel_peers.clone(),
+                vec![0, 0, 0],
+                500000,
+                peers_pair.clone(),
+            );
+            cooperative_settle_request.settle_info.settle_balance.pop();
+            cooperative_settle_request.sigs = vec![];
+            assert_noop!(
+                LedgerOperation::<TestRuntime>::cooperative_settle(cooperative_settle_request),
+                DispatchError::Other("Settle accounts mismatch")
+            );
+
+            // the i-th signature must be made by the i-th peer
+            let mut cooperative_settle_request = get_multi_peer_cooperative_settle_request(
+                channel_id,
+                1,
+                channel_peers.clone(),
+                vec![0, 0, 0],
+                500000,
+                peers_pair,
+            );
+            cooperative_settle_request.sigs.swap(0, 2);
+            assert_noop!(
+                LedgerOperation::<TestRuntime>::cooperative_settle(cooperative_settle_request),
+                DispatchError::Other("Check co-sigs failed")
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_confirm_settle_multi_peer_channel() {
+        ExtBuilder::build().execute_with(|| {   
+            let (channel_peers, peers_pair) = get_sorted_peers(
+                vec![account_pair("Alice"), account_pair("Bob"), account_pair("Risa")]
+            );
+            let open_channel_request = get_multi_peer_open_channel_request(
+                channel_peers.clone(), vec![0, 0, 0], 0, peers_pair.clone()
+            );
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap();
+            for i in 0..2 {
+                assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                    Origin::signed(channel_peers[i]),
+                    channel_id,
+                    channel_peers[i],
+                    100,
+                    0
+                ));
+            }
+
+            // peer 0 pays 30 to peer 2, peer 1 pays 20 to peer 0
+            let signed_simplex_state_array = SignedSimplexStateArray {
+                signed_simplex_states: vec![
+                    get_multi_peer_co_signed_simplex_state(
+                        channel_id, channel_peers[0], 1, Some(vec![(channel_peers[2], 30)]), peers_pair.clone()
+                    ),
+                    get_multi_peer_co_signed_simplex_state(
+                        channel_id, channel_peers[1], 1, Some(vec![(channel_peers[0], 20)]), peers_pair.clone()
+                    ),
+                ],
+            };
+            assert_ok!(LedgerOperation::<TestRuntime>::intend_settle(
+                Origin::signed(channel_peers[0]),
+                signed_simplex_state_array
+            ));
+
+            System::set_block_number(System::block_number() + 11);
+            let (_, settle_balance) = LedgerOperation::<TestRuntime>::confirm_settle(channel_id).unwrap();
+            assert_eq!(settle_balance, [90, 80, 30]);
+            assert_eq!(CelerPayModule::get_channel_status(channel_id), 3);
+        })
+    }
+
+    #[test]
+    fn test_pass_confirm_settle_multi_peer_channel_with_transfers_to_different_receivers() {
+        ExtBuilder::build().execute_with(|| {   
+            let (channel_peers, peers_pair) = get_sorted_peers(
+                vec![account_pair("Alice"), account_pair("Bob"), account_pair("Risa")]
+            );
+            let open_channel_request = get_multi_peer_open_channel_request(
+                channel_peers.clone(), vec![0, 0, 0], 0, peers_pair.clone()
+            );
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap();
+            for i in 0..2 {
+                assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                    Origin::signed(channel_peers[i]),
+                    channel_id,
+                    channel_peers[i],
+                    100,
+                    0
+                ));
+            }
+
+            // peer 0 pays 30 to peer 2, and then 20 to peer 1.
+            // The newer state keeps the amount transferred to peer 2, so no older state is needed.
+            let older_state = get_multi_peer_co_signed_simplex_state(
+                channel_id, channel_peers[0], 1, Some(vec![(channel_peers[2], 30)]), peers_pair.clone()
+            );
+            let newer_state = get_multi_peer_co_signed_simplex_state(
+                channel_id, channel_peers[0], 2, Some(vec![(channel_peers[2], 30), (channel_peers[1], 20)]), peers_pair.clone()
+            );
+            assert_ok!(LedgerOperation::<TestRuntime>::intend_settle(
+                Origin::signed(channel_peers[1]),
+                SignedSimplexStateArray { signed_simplex_states: vec![newer_state] }
+            ));
+            let (_, transfer_outs) = CelerPayModule::get_transfer_out_map(channel_id);
+            assert_eq!(transfer_outs[0].amount, 50);
+
+            // the older state can not override the newer one
+            assert_noop!(
+                LedgerOperation::<TestRuntime>::intend_settle(
+                    Origin::signed(channel_peers[2]),
+                    SignedSimplexStateArray { signed_simplex_states: vec![older_state] }
+                ),
+                DispatchError::Other("seqNum error")
+            );
+
+            System::set_block_number(System::block_number() + 11);
+            let (_, settle_balance) = LedgerOperation::<TestRuntime>::confirm_settle(channel_id).unwrap();
+            assert_eq!(settle_balance, [50, 120, 30]);
+        })
+    }
+
+    #[test]
+    fn test_fail_intend_settle_multi_peer_channel_with_mismatched_transfer_sum() {
+        ExtBuilder::build().execute_with(|| {   
+            let (channel_peers, peers_pair) = get_sorted_peers(
+                vec![account_pair("Alice"), account_pair("Bob"), account_pair("Risa")]
+            );
+            let open_channel_request = get_multi_peer_open_channel_request(
+                channel_peers.clone(), vec![0, 0, 0], 0, peers_pair.clone()
+            );
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap();
+
+            let mut simplex_state = get_multi_peer_co_signed_simplex_state(
+                channel_id, channel_peers[0], 1, Some(vec![(channel_peers[2], 30), (channel_peers[1], 20)]), peers_pair.clone()
+            ).simplex_state;
+            simplex_state.transfer_to_peer.as_mut().unwrap().receiver.amt = 30;
+            let signed_simplex_state = sign_multi_peer_simplex_state(simplex_state.clone(), peers_pair.clone());
+            assert_noop!(
+                LedgerOperation::<TestRuntime>::intend_settle(
+                    Origin::signed(channel_peers[0]),
+                    SignedSimplexStateArray { signed_simplex_states: vec![signed_simplex_state] }
+                ),
+                DispatchError::Other("Transfer sum mismatch")
+            );
+
+            // every receiver is listed once
+            simplex_state.transfer_to_peer.as_mut().unwrap().receiver.amt = 60;
+            simplex_state.transfer_to_peers = Some(vec![
+                AccountAmtPair { account: Some(channel_peers[2]), amt: 30 },
+                AccountAmtPair { account: Some(channel_peers[2]), amt: 30 },
+            ]);
+            let signed_simplex_state = sign_multi_peer_simplex_state(simplex_state, peers_pair.clone());
+            assert_noop!(
+                LedgerOperation::<TestRuntime>::intend_settle(
+                    Origin::signed(channel_peers[0]),
+                    SignedSimplexStateArray { signed_simplex_states: vec![signed_simplex_state] }
+                ),
+                DispatchError::Other("Duplicate transfer receiver")
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_confirm_settle_multi_peer_channel_with_pending_pays_to_different_receivers() {
+        ExtBuilder::build().execute_with(|| {   
+            System::set_block_number(1);
+            let (channel_peers, peers_pair) = get_sorted_peers(
+                vec![account_pair("Alice"), account_pair("Bob"), account_pair("Risa")]
+            );
+            let open_channel_request = get_multi_peer_open_channel_request(
+                channel_peers.clone(), vec![0, 0, 0], 0, peers_pair.clone()
+            );
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap();
+            for i in 0..2 {
+                assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                    Origin::signed(channel_peers[i]),
+                    channel_id,
+                    channel_peers[i],
+                    100,
+                    0
+                ));
+            }
+
+            // pays of 1 and 2 are resolved on-chain
+            let pay_id_list_info = get_pay_id_list_info(vec![vec![1, 2]], 1);
+            for cond_pay in pay_id_list_info.2[0].iter() {
+                let pay_request = ResolvePaymentConditionsRequest {
+                    cond_pay: cond_pay.clone(),
+                    hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
+                };
+                assert_ok!(PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request));
+            }
+            System::set_block_number(System::block_number() + 6);
+
+            // peer 0 transfers 30 to peer 2, and has pending pays to peer 1 and peer 2
+            let pay_ids = pay_id_list_info.0[0].pay_ids.clone();
+            let pays = vec![(pay_ids[0], channel_peers[1]), (pay_ids[1], channel_peers[2])];
+            let (pending_pay_root, proofs) = get_multi_peer_pay_id_merkle_proofs(pays.clone());
+            assert_ok!(LedgerOperation::<TestRuntime>::intend_settle(
+                Origin::signed(channel_peers[0]),
+                SignedSimplexStateArray {
+                    signed_simplex_states: vec![get_multi_peer_merkle_root_co_signed_simplex_state(
+                        channel_id,
+                        channel_peers[0],
+                        1,
+                        vec![(channel_peers[2], 30)],
+                        pending_pay_root,
+                        20,
+                        3,
+                        peers_pair.clone(),
+                    )],
+                }
+            ));
+
+            // the proof commits to the receiver of the pay
+            assert_noop!(
+                LedgerOperation::<TestRuntime>::clear_pays_by_proofs(
+                    channel_id,
+                    channel_peers[0],
+                    vec![PayIdProof { pay_id: pay_ids[0], receiver: Some(channel_peers[2]), proof: proofs[0].clone() }],
+                ),
+                DispatchError::Other("Invalid pay id proof")
+            );
+            assert_ok!(LedgerOperation::<TestRuntime>::clear_pays_by_proofs(
+                channel_id,
+                channel_peers[0],
+                vec![
+                    PayIdProof { pay_id: pay_ids[0], receiver: Some(channel_peers[1]), proof: proofs[0].clone() },
+                    PayIdProof { pay_id: pay_ids[1], receiver: Some(channel_peers[2]), proof: proofs[1].clone() },
+                ],
+            ));
+            let (_, transfer_outs) = CelerPayModule::get_transfer_out_map(channel_id);
+            assert_eq!(transfer_outs[0].amount, 33);
+
+            System::set_block_number(21);
+            let (_, settle_balance) = LedgerOperation::<TestRuntime>::confirm_settle(channel_id).unwrap();
+            assert_eq!(settle_balance, [67, 101, 32]);
+        })
+    }
+
+    #[test]
+    fn test_fail_intend_settle_multi_peer_channel_without_receiver() {
+        ExtBuilder::build().execute_with(|| {   
+            let (channel_peers, peers_pair) = get_sorted_peers(
+                vec![account_pair("Alice"), account_pair("Bob"), account_pair("Risa")]
+            );
+            let open_channel_request = get_multi_peer_open_channel_request(
+                channel_peers.clone(), vec![0, 0, 0], 0, peers_pair.clone()
+            );
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap();
+
+            let signed_simplex_state_array = SignedSimplexStateArray {
+                signed_simplex_states: vec![
+                    get_multi_peer_co_signed_simplex_state(
+                        channel_id, channel_peers[0], 1, None, peers_pair.clone()
+                    ),
+                ],
+            };
+            let err = LedgerOperation::<TestRuntime>::intend_settle(
+                Origin::signed(channel_peers[0]),
+                signed_simplex_state_array
+            ).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Transfer receiver is not specified"));
+        })
+    }
+
+    #[test]
+    fn test_pass_intend_settle_when_time_is_after_last_pay_resolve_deadline() {
+        ExtBuilder::build().execute_with(|| {   
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+            let open_channel_request = get_open_channel_request(true, 2000, 500001, 10, true, channel_peers.clone(), 1, peers_pair.clone());
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap();
+            assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                Origin::signed(channel_peers[0]),
+                channel_id,
+                channel_peers[0],
+                500,
+                0
+            ));
+            assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                Origin::signed(channel_peers[1]),
+                channel_id,
+                channel_peers[1],
+                500,
+                0
+            ));
+
+            // the meaning of the index: [peer index][pay hash list index][pay index]
+            let peers_pay_hash_lists_amts: Vec<Vec<Vec<Balance>>> =
+                vec![vec![vec![1, 2], vec![3, 4]], vec![vec![5, 6], vec![7, 8]]];
+
+            let global_result: (
+                SignedSimplexStateArray<H256, AccountId, BlockNumber, Balance, Signature>,
+                Vec<BlockNumber>,
+                Vec<Vec<Vec<ConditionalPay<Moment, BlockNumber, AccountId, H256, MockCall, Balance>>>>,
+                Vec<Vec<H256>>,
+                Vec<Vec<PayIdList<H256>>>,
+            ) = get_cosigned_intend_settle(
+                vec![channel_id, channel_id],
+                peers_pay_hash_lists_amts,
+                vec![1, 1],   // seq_nums
+                vec![10, 20], // transfer amounts
+                vec![2, 2],   // last_pay_resolve_deadlines
+                vec![channel_peers[0], channel_peers[1]],
+                channel_peers[0],
+                vec![peers_pair[0].clone(), peers_pair[1].clone()],
+                1,
+            );
+
+            let signed_simplex_state_array = global_result.0;
+            let cond_pays = global_result.2;
+
+            // ensure it passes the lat pay resolve deadline
+            System::set_block_number(System::block_number() + 3);
+            assert_eq!(System::block_number(), 3);
+
+            // intend settle
+            let _ = CelerPayModule::intend_settle(
+                Origin::signed(channel_peers[0]),
+                signed_simplex_state_array,
+            ).unwrap();
+
+            let settle_finalized_time = CelerPayModule::get_settle_finalized_time(channel_id);
+            //System::set_block_number(System::block_number() + settle_finalized_time);
+            let expected_settle_finalized_time = System::block_number() + 10;
+            assert_eq!(settle_finalized_time, expected_settle_finalized_time);
+
+            let status = CelerPayModule::get_channel_status(channel_id);
+            assert_eq!(status, 2);
+
+            for i in 0..2 {
+                // for each simplex state
+                for j in 0..2 {
+                    // for each pays in head PayIdList
+                    let encoded = encode_conditional_pay(cond_pays[i][0][j].clone());
+                    let pay_hash = hashing::blake2_256(&encoded).into();
+                    let pay_id = PayRegistry::<TestRuntime>::calculate_pay_id(pay_hash);
+                    let expected_event = TestEvent::celer(RawEvent::ClearOnePay(
+                        channel_id,
+                        pay_id,
+                        channel_peers[i as usize],
+                        0,
+                    ));
+                    assert!(System::events().iter().any(|a| a.event == expected_event));
+                }
+            }
+
+            let (_, _, _, _, transfer_out, pending_pay_out) = CelerPayModule::get_peers_migration_info(channel_id);
+            // updated transfer_out map with cleared pays in the head PayIdList
+            assert_eq!(vec![transfer_out[0].amount, transfer_out[1].amount], [10, 20]);
+            assert_eq!(vec![pending_pay_out[0].amount, pending_pay_out[1].amount], [10, 26]);
+        })
+    }
+
+    #[test]
+    fn test_confirm_settle_when_pay_proof_type_is_hash_array_and_time_is_after_last_pay_resolve_deadline(
+    ) {
+        ExtBuilder::build().execute_with(|| {   
+            let alice_pair = account_pair("Alice");
+            let bob_pair = account_pair("Bob");
+            let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
+            let open_channel_request = get_open_channel_request(true, 2000, 500001, 10, true, channel_peers.clone(), 1, peers_pair.clone());
+            let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+                Origin::signed(channel_peers[0]),
+                open_channel_request,
+                0,
+            ).unwrap();
+            assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                Origin::signed(channel_peers[0]),
+                channel_id,
+                channel_peers[0],
+                100,
+                0
+            ));
+            assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                Origin::signed(channel_peers[1]),
+                channel_id,
+                channel_peers[1],
+                200,
+                0
+            ));
+
+            // the meaning of the index: [peer index][pay hash list index][pay index]
+            let peers_pay_hash_lists_amts: Vec<Vec<Vec<Balance>>> =
+                vec![vec![vec![1, 2], vec![3, 4]], vec![vec![5, 6], vec![7, 8]]];
+
+            let global_result: (
+                SignedSimplexStateArray<H256, AccountId, BlockNumber, Balance, Signature>,
+                Vec<BlockNumber>,
+                Vec<Vec<Vec<ConditionalPay<Moment, BlockNumber, AccountId, H256, MockCall, Balance>>>>,
+                Vec<Vec<H256>>,
+                Vec<Vec<PayIdList<H256>>>,
+            ) = get_cosigned_intend_settle(
+                vec![channel_id, channel_id],
+                peers_pay_hash_lists_amts,
+                vec![1, 1],   // seq_nums
+                vec![10, 20], // transfer amounts
+                vec![2, 2],   // last_pay_resolve_deadlines
+                vec![channel_peers[0], channel_peers[1]],
+                channel_peers[0],
+                vec![peers_pair[0].clone(), peers_pair[1].clone()],
+                1,
+            );
+
+            let signed_simplex_state_array = global_result.0;
+
+            // ensure it passes the lat pay resolve deadline
+            System::set_block_number(System::block_number() + 3);
+            assert_eq!(System::block_number(), 3);
+
+            // intend settle
+            let _ = LedgerOperation::<TestRuntime>::intend_settle(
+                Origin::signed(channel_peers[0]),
+                signed_simplex_state_array,
+            ).unwrap();
+
+            let settle_finalized_time = CelerPayModule::get_settle_finalized_time(channel_id);
+            System::set_block_number(System::block_number() + settle_finalized_time);
+
+            let (_, settle_balance) =
+                LedgerOperation::<TestRuntime>::confirm_settle(channel_id).unwrap();
+            assert_eq!(settle_balance, [110, 190]);
+
+            let status = CelerPayModule::get_channel_status(channel_id);
+            assert_eq!(status, 3);
+        })
+    }
+
+    #[test]
+    fn test_fail_confirm_settle_with_multi_list_pending_pays_within_clear_safe_margin() {
+        ExtBuilder::build().execute_with(|| {
+            MockClearPaysSafeMargin::set(20);
             let alice_pair = account_pair("Alice");
             let bob_pair = account_pair("Bob");
             let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
@@ -2074,16 +3268,17 @@ pub mod test_ledger_operation {
                 Origin::signed(channel_peers[0]),
                 channel_id,
                 channel_peers[0],
-                500,
+                100,
                 0
             ));
             assert_ok!(LedgerOperation::<TestRuntime>::deposit(
                 Origin::signed(channel_peers[1]),
                 channel_id,
                 channel_peers[1],
-                500,
+                200,
                 0
             ));
+            assert_eq!(CelerPayModule::get_clear_safe_margin(channel_id), 20);
 
             // the meaning of the index: [peer index][pay hash list index][pay index]
             let peers_pay_hash_lists_amts: Vec<Vec<Vec<Balance>>> =
@@ -2108,58 +3303,45 @@ pub mod test_ledger_operation {
             );
 
             let signed_simplex_state_array = global_result.0;
-            let cond_pays = global_result.2;
 
-            // ensure it passes the lat pay resolve deadline
-            System::set_block_number(System::block_number() + 3);
-            assert_eq!(System::block_number(), 3);
+            // pass the last pay resolve deadline
+            System::set_block_number(3);
 
-            // intend settle
-            let _ = CelerPayModule::intend_settle(
+            // intend settle leaves the second pay id list of each peer pending
+            let _ = LedgerOperation::<TestRuntime>::intend_settle(
                 Origin::signed(channel_peers[0]),
                 signed_simplex_state_array,
             ).unwrap();
 
+            // pending pays are not expired until last_pay_resolve_deadline + clear_safe_margin
             let settle_finalized_time = CelerPayModule::get_settle_finalized_time(channel_id);
-            //System::set_block_number(System::block_number() + settle_finalized_time);
-            let expected_settle_finalized_time = System::block_number() + 10;
-            assert_eq!(settle_finalized_time, expected_settle_finalized_time);
-
-            let status = CelerPayModule::get_channel_status(channel_id);
-            assert_eq!(status, 2);
+            assert_eq!(settle_finalized_time, 13);
+            System::set_block_number(settle_finalized_time);
+            let err = LedgerOperation::<TestRuntime>::confirm_settle(channel_id).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Payments are not finalized"));
 
-            for i in 0..2 {
-                // for each simplex state
-                for j in 0..2 {
-                    // for each pays in head PayIdList
-                    let encoded = encode_conditional_pay(cond_pays[i][0][j].clone());
-                    let pay_hash = hashing::blake2_256(&encoded).into();
-                    let pay_id = PayRegistry::<TestRuntime>::calculate_pay_id(pay_hash);
-                    let expected_event = TestEvent::celer(RawEvent::ClearOnePay(
-                        channel_id,
-                        pay_id,
-                        channel_peers[i as usize],
-                        0,
-                    ));
-                    assert!(System::events().iter().any(|a| a.event == expected_event));
-                }
-            }
+            System::set_block_number(22);
+            let err = LedgerOperation::<TestRuntime>::confirm_settle(channel_id).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Payments are not finalized"));
 
-            let (_, _, _, _, transfer_out, pending_pay_out) = CelerPayModule::get_peers_migration_info(channel_id);
-            // updated transfer_out map with cleared pays in the head PayIdList
-            assert_eq!(vec![transfer_out[0].amount, transfer_out[1].amount], [10, 20]);
-            assert_eq!(vec![pending_pay_out[0].amount, pending_pay_out[1].amount], [10, 26]);
+            System::set_block_number(23);
+            let (_, settle_balance) =
+                LedgerOperation::<TestRuntime>::confirm_settle(channel_id).unwrap();
+            assert_eq!(settle_balance, [110, 190]);
+            assert_eq!(CelerPayModule::get_channel_status(channel_id), 3);
         })
     }
 
     #[test]
-    fn test_confirm_settle_when_pay_proof_type_is_hash_array_and_time_is_after_last_pay_resolve_deadline(
-    ) {
-        ExtBuilder::build().execute_with(|| {   
+    fn test_pass_confirm_settle_with_multi_list_pending_pays_after_channel_clear_safe_margin() {
+        ExtBuilder::build().execute_with(|| {
+            MockClearPaysSafeMargin::set(20);
             let alice_pair = account_pair("Alice");
             let bob_pair = account_pair("Bob");
             let (channel_peers, peers_pair) = get_sorted_peer(alice_pair.clone(), bob_pair.clone());
-            let open_channel_request = get_open_channel_request(true, 2000, 500001, 10, true, channel_peers.clone(), 1, peers_pair.clone());
+            let mut channel_initializer = get_payment_channel_initializer(true, 2000, 500001, 10, true, channel_peers.clone(), 1);
+            channel_initializer.clear_safe_margin = Some(5);
+            let open_channel_request = get_signed_open_channel_request(channel_initializer, peers_pair.clone());
             let channel_id = LedgerOperation::<TestRuntime>::open_channel(
                 Origin::signed(channel_peers[0]),
                 open_channel_request,
@@ -2179,6 +3361,7 @@ pub mod test_ledger_operation {
                 200,
                 0
             ));
+            assert_eq!(CelerPayModule::get_clear_safe_margin(channel_id), 5);
 
             // the meaning of the index: [peer index][pay hash list index][pay index]
             let peers_pay_hash_lists_amts: Vec<Vec<Vec<Balance>>> =
@@ -2204,25 +3387,22 @@ pub mod test_ledger_operation {
 
             let signed_simplex_state_array = global_result.0;
 
-            // ensure it passes the lat pay resolve deadline
-            System::set_block_number(System::block_number() + 3);
-            assert_eq!(System::block_number(), 3);
+            // pass the last pay resolve deadline
+            System::set_block_number(3);
 
-            // intend settle
+            // intend settle leaves the second pay id list of each peer pending
             let _ = LedgerOperation::<TestRuntime>::intend_settle(
                 Origin::signed(channel_peers[0]),
                 signed_simplex_state_array,
             ).unwrap();
 
+            // the clear safe margin of the channel overrides ClearPaysSafeMargin of the runtime
             let settle_finalized_time = CelerPayModule::get_settle_finalized_time(channel_id);
-            System::set_block_number(System::block_number() + settle_finalized_time);
-
+            System::set_block_number(settle_finalized_time);
             let (_, settle_balance) =
                 LedgerOperation::<TestRuntime>::confirm_settle(channel_id).unwrap();
             assert_eq!(settle_balance, [110, 190]);
-
-            let status = CelerPayModule::get_channel_status(channel_id);
-            assert_eq!(status, 3);
+            assert_eq!(CelerPayModule::get_channel_status(channel_id), 3);
         })
     }
 
@@ -2420,6 +3600,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -2519,6 +3701,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -2657,6 +3841,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -2665,6 +3851,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -2829,6 +4017,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -2837,6 +4027,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -3211,6 +4403,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -3304,6 +4498,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[0][0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -3313,6 +4509,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[1][0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -3391,6 +4589,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[0][0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -3400,6 +4600,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[1][0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -3787,6 +4989,8 @@ pub mod test_ledger_operation {
                 let pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pays[0][i].clone(),
                     hash_preimages: vec![],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
                 let _ = PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
             }
@@ -3826,6 +5030,42 @@ pub mod test_ledger_operation {
         })
     }
 
+    #[test]
+    fn test_pass_encode_simplex_state_without_pending_pay_root_as_initial_release() {
+        ExtBuilder::build().execute_with(|| {
+            let mut simplex_payment_channel = SimplexPaymentChannel {
+                channel_id: H256::from_low_u64_be(1),
+                peer_from: None,
+                seq_num: 0,
+                transfer_to_peer: None,
+                pending_pay_ids: None,
+                last_pay_resolve_deadline: None,
+                total_pending_amount: None,
+                pending_pay_root: None,
+                transfer_to_peers: None,
+            };
+            let mut encoded = simplex_payment_channel.channel_id.encode();
+            encoded.extend(simplex_payment_channel.peer_from.encode());
+            encoded.extend(simplex_payment_channel.seq_num.encode());
+            encoded.extend(simplex_payment_channel.transfer_to_peer.encode());
+            encoded.extend(simplex_payment_channel.pending_pay_ids.encode());
+            encoded.extend(simplex_payment_channel.last_pay_resolve_deadline.encode());
+            encoded.extend(simplex_payment_channel.total_pending_amount.encode());
+            assert_eq!(
+                encode_signed_simplex_null_state::<TestRuntime>(simplex_payment_channel.clone()),
+                encoded
+            );
+
+            // a pending pay root is appended to the signed message
+            simplex_payment_channel.pending_pay_root = Some(H256::from_low_u64_be(2));
+            encoded.extend(H256::from_low_u64_be(2).encode());
+            assert_eq!(
+                encode_signed_simplex_null_state::<TestRuntime>(simplex_payment_channel),
+                encoded
+            );
+        })
+    }
+
     #[test]
     fn test_fail_intend_settle_operable_channel_for_a_non_peer() {
         ExtBuilder::build().execute_with(|| {   
@@ -4057,6 +5297,162 @@ pub mod test_ledger_operation {
         }
     }
 
+    pub fn get_sorted_peers(
+        mut peers_pair: Vec<sr25519::Pair>,
+    ) -> (Vec<AccountId>, Vec<sr25519::Pair>) {
+        peers_pair.sort_by(|a, b| a.public().cmp(&b.public()));
+        let channel_peers: Vec<AccountId> = peers_pair.iter().map(|pair| pair.public()).collect();
+        return (channel_peers, peers_pair);
+    }
+
+    pub fn get_multi_peer_open_channel_request(
+        channel_peers: Vec<AccountId>,
+        amounts: Vec<Balance>,
+        msg_value_receiver: u8,
+        peers_pair: Vec<sr25519::Pair>,
+    ) -> OpenChannelRequest<AccountId, BlockNumber, Balance, Signature> {
+        let distribution: Vec<AccountAmtPair<AccountId, Balance>> = channel_peers
+            .iter()
+            .zip(amounts.iter())
+            .map(|(peer, amount)| AccountAmtPair { account: Some(*peer), amt: *amount })
+            .collect();
+        let channel_initializer = PaymentChannelInitializer {
+            balance_limits_enabled: false,
+            balance_limits: None,
+            init_distribution: TokenDistribution {
+                token: TokenInfo { token_type: TokenType::Celer },
+                distribution: distribution,
+            },
+            open_deadline: 500001,
+            dispute_timeout: 10,
+            msg_value_receiver: msg_value_receiver,
+            clear_safe_margin: None,
+        };
+
+        return get_signed_open_channel_request(channel_initializer, peers_pair);
+    }
+
+    pub fn get_multi_peer_co_signed_simplex_state(
+        channel_id: H256,
+        peer_from: AccountId,
+        seq_num: u128,
+        transfers: Option<Vec<(AccountId, Balance)>>,
+        peers_pair: Vec<sr25519::Pair>,
+    ) -> SignedSimplexState<H256, AccountId, BlockNumber, Balance, Signature> {
+        let transfer_amount: Balance = transfers.clone().unwrap_or_default().iter().map(|(_, amt)| amt).sum();
+        let simplex_payment_channel = SimplexPaymentChannel {
+            channel_id: channel_id,
+            peer_from: Some(peer_from),
+            seq_num: seq_num,
+            transfer_to_peer: Some(TokenTransfer {
+                token: TokenInfo { token_type: TokenType::Celer },
+                receiver: AccountAmtPair { account: None, amt: transfer_amount },
+            }),
+            pending_pay_ids: Some(PayIdList { pay_ids: vec![], next_list_hash: None }),
+            last_pay_resolve_deadline: Some(0),
+            total_pending_amount: Some(0),
+            pending_pay_root: None,
+            transfer_to_peers: transfers.map(|transfers| transfers.into_iter().map(|(account, amt)| {
+                AccountAmtPair { account: Some(account), amt: amt }
+            }).collect()),
+        };
+
+        return sign_multi_peer_simplex_state(simplex_payment_channel, peers_pair);
+    }
+
+    pub fn get_multi_peer_merkle_root_co_signed_simplex_state(
+        channel_id: H256,
+        peer_from: AccountId,
+        seq_num: u128,
+        transfers: Vec<(AccountId, Balance)>,
+        pending_pay_root: H256,
+        last_pay_resolve_deadline: BlockNumber,
+        total_pending_amount: Balance,
+        peers_pair: Vec<sr25519::Pair>,
+    ) -> SignedSimplexState<H256, AccountId, BlockNumber, Balance, Signature> {
+        let transfer_amount: Balance = transfers.iter().map(|(_, amt)| amt).sum();
+        let simplex_payment_channel = SimplexPaymentChannel {
+            channel_id: channel_id,
+            peer_from: Some(peer_from),
+            seq_num: seq_num,
+            transfer_to_peer: Some(TokenTransfer {
+                token: TokenInfo { token_type: TokenType::Celer },
+                receiver: AccountAmtPair { account: None, amt: transfer_amount },
+            }),
+            pending_pay_ids: None,
+            last_pay_resolve_deadline: Some(last_pay_resolve_deadline),
+            total_pending_amount: Some(total_pending_amount),
+            pending_pay_root: Some(pending_pay_root),
+            transfer_to_peers: Some(transfers.into_iter().map(|(account, amt)| {
+                AccountAmtPair { account: Some(account), amt: amt }
+            }).collect()),
+        };
+
+        return sign_multi_peer_simplex_state(simplex_payment_channel, peers_pair);
+    }
+
+    pub fn sign_multi_peer_simplex_state(
+        simplex_payment_channel: SimplexPaymentChannel<H256, AccountId, BlockNumber, Balance>,
+        peers_pair: Vec<sr25519::Pair>,
+    ) -> SignedSimplexState<H256, AccountId, BlockNumber, Balance, Signature> {
+        let mut encoded = simplex_payment_channel.channel_id.encode();
+        encoded.extend(simplex_payment_channel.peer_from.encode());
+        encoded.extend(simplex_payment_channel.seq_num.encode());
+        encoded.extend(simplex_payment_channel.clone().transfer_to_peer.unwrap().token.token_type.encode());
+        encoded.extend(simplex_payment_channel.clone().transfer_to_peer.unwrap().receiver.account.encode());
+        encoded.extend(simplex_payment_channel.clone().transfer_to_peer.unwrap().receiver.amt.encode());
+        if let Some(pending_pay_ids) = simplex_payment_channel.clone().pending_pay_ids {
+            pending_pay_ids.pay_ids.iter().for_each(|pay_id| encoded.extend(pay_id.encode()));
+            encoded.extend(pending_pay_ids.next_list_hash.encode());
+        }
+        encoded.extend(simplex_payment_channel.last_pay_resolve_deadline.encode());
+        encoded.extend(simplex_payment_channel.total_pending_amount.encode());
+        if let Some(pending_pay_root) = simplex_payment_channel.pending_pay_root {
+            encoded.extend(pending_pay_root.encode());
+        }
+        if let Some(transfer_to_peers) = simplex_payment_channel.clone().transfer_to_peers {
+            encoded.extend(transfer_to_peers.encode());
+        }
+
+        return SignedSimplexState {
+            simplex_state: simplex_payment_channel,
+            sigs: peers_pair.iter().map(|pair| pair.sign(&encoded)).collect(),
+        };
+    }
+
+    pub fn get_multi_peer_cooperative_settle_request(
+        channel_id: H256,
+        seq_num: u128,
+        channel_peers: Vec<AccountId>,
+        settle_amounts: Vec<Balance>,
+        settle_deadline: BlockNumber,
+        peers_pair: Vec<sr25519::Pair>,
+    ) -> CooperativeSettleRequest<H256, BlockNumber, AccountId, Balance, Signature> {
+        let settle_info = CooperativeSettleInfo {
+            channel_id: channel_id,
+            seq_num: seq_num,
+            settle_balance: channel_peers
+                .iter()
+                .zip(settle_amounts.iter())
+                .map(|(peer, amount)| AccountAmtPair { account: Some(*peer), amt: *amount })
+                .collect(),
+            settle_deadline: settle_deadline,
+        };
+
+        let mut encoded = settle_info.channel_id.encode();
+        encoded.extend(settle_info.seq_num.encode());
+        for pair in settle_info.settle_balance.iter() {
+            encoded.extend(pair.account.encode());
+            encoded.extend(pair.amt.encode());
+        }
+        encoded.extend(settle_info.settle_deadline.encode());
+
+        return CooperativeSettleRequest {
+            settle_info: settle_info,
+            sigs: peers_pair.iter().map(|pair| pair.sign(&encoded)).collect(),
+        };
+    }
+
     pub fn get_open_channel_request(
         balance_limits_enabled: bool,
         balance_limits: Balance,
@@ -4077,23 +5473,91 @@ pub mod test_ledger_operation {
             msg_value_receiver,
         );
 
+        return get_signed_open_channel_request(channel_initializer, peers_sr25519_pairs);
+    }
+
+    // Open a channel of `TEST_ASSET_ID` funded with 100 and 200 units by the pools of the peers
+    pub fn open_asset_channel(
+        channel_peers: Vec<AccountId>,
+        peers_pair: Vec<sr25519::Pair>,
+    ) -> H256 {
+        let celer_ledger_account = CelerPayModule::get_celer_ledger_id();
+        assert_ok!(Pool::<TestRuntime>::deposit_asset_pool(
+            Origin::signed(channel_peers[0]),
+            channel_peers[0],
+            TEST_ASSET_ID,
+            100,
+        ));
+        assert_ok!(Pool::<TestRuntime>::approve_asset(
+            Origin::signed(channel_peers[0]),
+            celer_ledger_account,
+            TEST_ASSET_ID,
+            100,
+        ));
+
+        let mut channel_initializer = get_payment_channel_initializer(false, 1000, 500001, 10, false, channel_peers.clone(), 1);
+        channel_initializer.init_distribution.token = TokenInfo { token_type: TokenType::Asset(TEST_ASSET_ID) };
+        let open_channel_request = get_signed_open_channel_request(channel_initializer, peers_pair);
+        return LedgerOperation::<TestRuntime>::open_channel(
+            Origin::signed(channel_peers[1]),
+            open_channel_request,
+            200,
+        ).unwrap();
+    }
+
+    // Co-signed state of an asset channel transferring `transfer_amount` to the peer without pending pays
+    pub fn get_asset_co_signed_simplex_state(
+        channel_id: H256,
+        peer_from: AccountId,
+        seq_num: u128,
+        transfer_amount: Balance,
+        peers_pair: Vec<sr25519::Pair>,
+    ) -> SignedSimplexState<H256, AccountId, BlockNumber, Balance, Signature> {
+        let simplex_payment_channel = SimplexPaymentChannel {
+            channel_id: channel_id,
+            peer_from: Some(peer_from),
+            seq_num: seq_num,
+            transfer_to_peer: Some(TokenTransfer {
+                token: TokenInfo { token_type: TokenType::Asset(TEST_ASSET_ID) },
+                receiver: AccountAmtPair { account: None, amt: transfer_amount },
+            }),
+            pending_pay_ids: Some(PayIdList { pay_ids: vec![], next_list_hash: None }),
+            last_pay_resolve_deadline: Some(1),
+            total_pending_amount: Some(0),
+            pending_pay_root: None,
+            transfer_to_peers: None,
+        };
+        let encoded = encode_signed_simplex_state_array::<TestRuntime>(simplex_payment_channel.clone());
+
+        return SignedSimplexState {
+            simplex_state: simplex_payment_channel,
+            sigs: peers_pair.iter().map(|pair| pair.sign(&encoded)).collect(),
+        };
+    }
+
+    pub fn get_signed_open_channel_request(
+        channel_initializer: PaymentChannelInitializer<AccountId, BlockNumber, Balance>,
+        peers_sr25519_pairs: Vec<sr25519::Pair>,
+    ) -> OpenChannelRequest<AccountId, BlockNumber, Balance, Signature> {
         let mut encoded = channel_initializer.balance_limits_enabled.encode();
         encoded.extend(channel_initializer.balance_limits.encode());
         encoded.extend(channel_initializer.init_distribution.token.token_type.encode());
-        encoded.extend(channel_initializer.init_distribution.distribution[0].account.encode());
-        encoded.extend(channel_initializer.init_distribution.distribution[0].amt.encode());
-        encoded.extend(channel_initializer.init_distribution.distribution[1].account.encode());
-        encoded.extend(channel_initializer.init_distribution.distribution[1].amt.encode());
+        for pair in channel_initializer.init_distribution.distribution.iter() {
+            encoded.extend(pair.account.encode());
+            encoded.extend(pair.amt.encode());
+        }
         encoded.extend(channel_initializer.open_deadline.encode());
         encoded.extend(channel_initializer.dispute_timeout.encode());
         encoded.extend(channel_initializer.msg_value_receiver.encode());
+        if let Some(clear_safe_margin) = channel_initializer.clear_safe_margin {
+            encoded.extend(clear_safe_margin.encode());
+        }
         
-        let sigs_1 = peers_sr25519_pairs[0].sign(&encoded);
-        let sigs_2 = peers_sr25519_pairs[1].sign(&encoded);
+        let sigs: Vec<Signature> = peers_sr25519_pairs.iter().map(|pair| pair.sign(&encoded)).collect();
 
         let open_channel_request = OpenChannelRequest {
             channel_initializer: channel_initializer,
-            sigs: vec![sigs_1, sigs_2],
+            sigs: sigs,
         };
 
         return open_channel_request;
@@ -4155,6 +5619,7 @@ pub mod test_ledger_operation {
                 open_deadline: open_deadline,
                 dispute_timeout: dispute_timeout,
                 msg_value_receiver: msg_value_receiver,
+                clear_safe_margin: None,
             };
         } else {
             initializer = PaymentChannelInitializer {
@@ -4164,6 +5629,7 @@ pub mod test_ledger_operation {
                 open_deadline: open_deadline,
                 dispute_timeout: dispute_timeout,
                 msg_value_receiver: msg_value_receiver,
+                clear_safe_margin: None,
             };
         }
 
@@ -4277,6 +5743,8 @@ pub mod test_ledger_operation {
             transfer_func: init_transfer_func,
             resolve_deadline: 0,
             resolve_timeout: 0,
+            lottery: None,
+            stream: None,
         };
         let mut cond_pays: Vec<
             Vec<Vec<ConditionalPay<Moment, BlockNumber,  AccountId, H256, MockCall, Balance>>>,
@@ -4418,6 +5886,8 @@ pub mod test_ledger_operation {
             transfer_func: init_transfer_func,
             resolve_deadline: 0,
             resolve_timeout: 0,
+            lottery: None,
+            stream: None,
         };
         // 2-d array list of PayIdList of a simplex channel,
         // for resolving pays with PayRegistry
@@ -4439,7 +5909,7 @@ pub mod test_ledger_operation {
             let mut pay_ids: Vec<H256> = vec![H256::from_low_u64_be(0), H256::from_low_u64_be(0)];
             for j in 0..pay_amounts_len_2 {
                 total_pending_amount += pay_amounts[i][j];
-                let conditions: Condition<H256, MockCall>;
+                let conditions: Condition<Moment, BlockNumber, AccountId, H256, MockCall>;
                 if pay_conditions == 1 {
                     conditions = get_condition(1);
                 } else {
@@ -4455,6 +5925,8 @@ pub mod test_ledger_operation {
                     transfer_func: transfer_func,
                     resolve_deadline: 999999,
                     resolve_timeout: 5,
+                    lottery: None,
+                    stream: None,
                 };
                 let encoded_cond_pay = encode_conditional_pay(cond_pay_array[i][j].clone());
                 let pay_hash = hashing::blake2_256(&encoded_cond_pay).into();
@@ -4562,6 +6034,8 @@ pub mod test_ledger_operation {
             pending_pay_ids: None,
             last_pay_resolve_deadline: None,
             total_pending_amount: None,
+            pending_pay_root: None,
+            transfer_to_peers: None,
         };
 
         let mut encoded = simplex_payment_channel.channel_id.encode();
@@ -4571,6 +6045,9 @@ pub mod test_ledger_operation {
         encoded.extend(simplex_payment_channel.pending_pay_ids.encode());
         encoded.extend(simplex_payment_channel.last_pay_resolve_deadline.encode());
         encoded.extend(simplex_payment_channel.total_pending_amount.encode());
+        if let Some(pending_pay_root) = simplex_payment_channel.pending_pay_root {
+            encoded.extend(pending_pay_root.encode());
+        }
 
         if signer == peers_pair[0].public() {
             let sig = peers_pair[0].sign(&encoded);
@@ -4621,6 +6098,8 @@ pub mod test_ledger_operation {
             pending_pay_ids: Some(pending_pay_ids),
             last_pay_resolve_deadline: Some(last_pay_resolve_deadline),
             total_pending_amount: Some(total_pending_amount),
+            pending_pay_root: None,
+            transfer_to_peers: None,
         };
         let mut encoded = simplex_payment_channel.channel_id.encode();
         encoded.extend(simplex_payment_channel.peer_from.encode());
@@ -4634,6 +6113,9 @@ pub mod test_ledger_operation {
         encoded.extend(simplex_payment_channel.clone().pending_pay_ids.unwrap().next_list_hash.encode());
         encoded.extend(simplex_payment_channel.last_pay_resolve_deadline.encode());
         encoded.extend(simplex_payment_channel.total_pending_amount.encode());
+        if let Some(pending_pay_root) = simplex_payment_channel.pending_pay_root {
+            encoded.extend(pending_pay_root.encode());
+        }
     
         let sig_1 = peers_pair[0].sign(&encoded);
         let sig_2 = peers_pair[1].sign(&encoded);
@@ -4645,6 +6127,140 @@ pub mod test_ledger_operation {
         return signed_simplex_state;
     }
 
+    pub fn get_merkle_root_co_signed_simplex_state(
+        channel_id: H256,
+        peer_from: AccountId,
+        seq_num: u128,
+        transfer_amount: Balance,
+        pending_pay_root: H256,
+        last_pay_resolve_deadline: BlockNumber,
+        total_pending_amount: Balance,
+        peers_pair: Vec<sr25519::Pair>,
+    ) -> SignedSimplexState<H256, AccountId, BlockNumber, Balance, Signature> {
+        let simplex_payment_channel = SimplexPaymentChannel {
+            channel_id: channel_id,
+            peer_from: Some(peer_from),
+            seq_num: seq_num,
+            transfer_to_peer: Some(TokenTransfer {
+                token: TokenInfo { token_type: TokenType::Celer },
+                receiver: AccountAmtPair { account: None, amt: transfer_amount },
+            }),
+            pending_pay_ids: None,
+            last_pay_resolve_deadline: Some(last_pay_resolve_deadline),
+            total_pending_amount: Some(total_pending_amount),
+            pending_pay_root: Some(pending_pay_root),
+            transfer_to_peers: None,
+        };
+        let mut encoded = simplex_payment_channel.channel_id.encode();
+        encoded.extend(simplex_payment_channel.peer_from.encode());
+        encoded.extend(simplex_payment_channel.seq_num.encode());
+        encoded.extend(simplex_payment_channel.clone().transfer_to_peer.unwrap().token.token_type.encode());
+        encoded.extend(simplex_payment_channel.clone().transfer_to_peer.unwrap().receiver.account.encode());
+        encoded.extend(simplex_payment_channel.clone().transfer_to_peer.unwrap().receiver.amt.encode());
+        encoded.extend(simplex_payment_channel.last_pay_resolve_deadline.encode());
+        encoded.extend(simplex_payment_channel.total_pending_amount.encode());
+        if let Some(pending_pay_root) = simplex_payment_channel.pending_pay_root {
+            encoded.extend(pending_pay_root.encode());
+        }
+
+        return SignedSimplexState {
+            simplex_state: simplex_payment_channel,
+            sigs: vec![peers_pair[0].sign(&encoded), peers_pair[1].sign(&encoded)],
+        };
+    }
+
+    // Build a Merkle tree of pay ids with sorted pair hashing, return the root and a proof for each pay id
+    // Open a channel with deposits of 500 from both peers, resolve pays of 1, 2, 3 and 4 on-chain, and
+    // intend settle the state of peer 0 transferring 10 and committing the pays by a Merkle root.
+    // Return the channel id, the pay ids and their Merkle proofs.
+    pub fn intend_settle_with_merkle_root_pending_pays(
+        channel_peers: Vec<AccountId>,
+        peers_pair: Vec<sr25519::Pair>,
+        seq_num: u128,
+    ) -> (H256, Vec<H256>, Vec<Vec<H256>>) {
+        System::set_block_number(1);
+        let open_channel_request = get_open_channel_request(true, 2000, 500001, 10, true, channel_peers.clone(), 1, peers_pair.clone());
+        let channel_id = LedgerOperation::<TestRuntime>::open_channel(
+            Origin::signed(channel_peers[0]),
+            open_channel_request,
+            0,
+        ).unwrap();
+        for i in 0..2 {
+            assert_ok!(LedgerOperation::<TestRuntime>::deposit(
+                Origin::signed(channel_peers[i]),
+                channel_id,
+                channel_peers[i],
+                500,
+                0
+            ));
+        }
+
+        let pay_id_list_info = get_pay_id_list_info(vec![vec![1, 2, 3, 4]], 1);
+        for cond_pay in pay_id_list_info.2[0].iter() {
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_ok!(PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request));
+        }
+        System::set_block_number(System::block_number() + 6);
+
+        let pay_ids = pay_id_list_info.0[0].pay_ids.clone();
+        let (pending_pay_root, proofs) = get_pay_id_merkle_proofs(pay_ids.clone());
+        assert_ok!(LedgerOperation::<TestRuntime>::intend_settle(
+            Origin::signed(channel_peers[0]),
+            SignedSimplexStateArray {
+                signed_simplex_states: vec![get_merkle_root_co_signed_simplex_state(
+                    channel_id,
+                    channel_peers[0],
+                    seq_num,
+                    10,
+                    pending_pay_root,
+                    20,
+                    10,
+                    peers_pair.clone(),
+                )],
+            }
+        ));
+        return (channel_id, pay_ids, proofs);
+    }
+
+    pub fn get_pay_id_merkle_proofs(pay_ids: Vec<H256>) -> (H256, Vec<Vec<H256>>) {
+        return get_merkle_proofs(pay_ids.iter().map(|pay_id| hashing::blake2_256(&pay_id.encode()).into()).collect());
+    }
+
+    // Build a Merkle tree of pays of a multi-peer channel, whose leaves commit to the receivers
+    pub fn get_multi_peer_pay_id_merkle_proofs(pays: Vec<(H256, AccountId)>) -> (H256, Vec<Vec<H256>>) {
+        return get_merkle_proofs(pays.iter().map(|pay| hashing::blake2_256(&pay.encode()).into()).collect());
+    }
+
+    pub fn get_merkle_proofs(leaves: Vec<H256>) -> (H256, Vec<Vec<H256>>) {
+        let hash_pair = |a: &H256, b: &H256| -> H256 {
+            let (first, second) = if a <= b { (a, b) } else { (b, a) };
+            let mut encoded = first.encode();
+            encoded.extend(second.encode());
+            hashing::blake2_256(&encoded).into()
+        };
+        let mut level: Vec<H256> = leaves.clone();
+        let mut indexes: Vec<usize> = (0..leaves.len()).collect();
+        let mut proofs: Vec<Vec<H256>> = vec![vec![]; leaves.len()];
+        while level.len() > 1 {
+            for i in 0..leaves.len() {
+                let sibling = indexes[i] ^ 1;
+                if sibling < level.len() {
+                    proofs[i].push(level[sibling]);
+                }
+                indexes[i] = indexes[i] / 2;
+            }
+            level = level.chunks(2).map(|pair| {
+                if pair.len() == 2 { hash_pair(&pair[0], &pair[1]) } else { pair[0] }
+            }).collect();
+        }
+        return (level[0], proofs);
+    }
+
     pub fn get_cooperative_settle_request(
         channel_id: H256,
         seq_num: u128,
@@ -4725,6 +6341,7 @@ pub mod test_ledger_operation {
         let transfer_func = TransferFunction {
             logic_type: TransferFunctionType::BooleanAnd,
             max_transfer: token_transfer,
+            boolean_circuit: None,
         };
 
         return transfer_func;
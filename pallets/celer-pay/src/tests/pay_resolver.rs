@@ -3,9 +3,14 @@ pub mod test_pay_resolver {
     use crate::mock::*;
     use crate::mock::Call as MockCall;
     use sp_core::{hashing, Pair, H256};
-    use sp_runtime::DispatchError;
+    use sp_runtime::{DispatchError, Perbill};
+    use frame_support::{assert_noop, assert_ok, storage::StorageMap, traits::Get};
+    use crate::Error;
+    use crate::traits::{boolean_app_ids, has_duplicate_app_ids, numeric_app_ids};
+    use frame_support::traits::IntegrityTest;
     use crate::pay_resolver::*;
-    use codec::{Encode};
+    use crate::preimage_registry::PreimageRegistry;
+    use codec::{Compact, Encode};
     use mock_boolean_condition::Call as MockBooleanCall;
 
     #[test]
@@ -20,12 +25,16 @@ pub mod test_pay_resolver {
                 transfer_func: transfer_func,
                 resolve_deadline: 99999,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: None,
             };
             let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
             let pay_hash: H256 = hashing::blake2_256(&encoded_cond_pay).into();
             let pay_request = ResolvePaymentConditionsRequest {
                 cond_pay: cond_pay,
                 hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
             };
 
             let (pay_id, amount, resolve_deadline) =
@@ -48,12 +57,16 @@ pub mod test_pay_resolver {
                 transfer_func: transfer_func,
                 resolve_deadline: 99999,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: None,
             };
             let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
             let pay_hash: H256 = hashing::blake2_256(&encoded_cond_pay).into();
             let pay_request = ResolvePaymentConditionsRequest {
                 cond_pay: cond_pay,
                 hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
             };
 
             let (pay_id, amount, resolve_deadline) =
@@ -76,6 +89,8 @@ pub mod test_pay_resolver {
                 transfer_func: transfer_func,
                 resolve_deadline: 99999,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: None,
             };
 
             let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
@@ -83,6 +98,8 @@ pub mod test_pay_resolver {
             let pay_request = ResolvePaymentConditionsRequest {
                 cond_pay: cond_pay,
                 hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
             };
 
             let (pay_id, amount, resolve_deadline) =
@@ -106,6 +123,8 @@ pub mod test_pay_resolver {
                 transfer_func: transfer_func,
                 resolve_deadline: 99999,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: None,
             };
 
             let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
@@ -113,6 +132,8 @@ pub mod test_pay_resolver {
             let pay_request = ResolvePaymentConditionsRequest {
                 cond_pay: cond_pay,
                 hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
             };
 
             let (pay_id, amount, resolve_deadline) =
@@ -165,6 +186,8 @@ pub mod test_pay_resolver {
                 transfer_func: transfer_func,
                 resolve_deadline: 99999,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: None,
             };
 
             let encoded_cond_pay = encode_conditional_pay(shared_pay.clone());
@@ -198,6 +221,8 @@ pub mod test_pay_resolver {
                 transfer_func: transfer_func,
                 resolve_deadline: 99999,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: None,
             };
 
             let encoded_cond_pay = encode_conditional_pay(shared_pay.clone());
@@ -232,10 +257,14 @@ pub mod test_pay_resolver {
                 transfer_func: transfer_func,
                 resolve_deadline: 1,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: None,
             };
             let pay_request = ResolvePaymentConditionsRequest {
                 cond_pay: cond_pay,
                 hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
             };
 
             System::set_block_number(3);
@@ -260,6 +289,8 @@ pub mod test_pay_resolver {
                 transfer_func: transfer_func,
                 resolve_deadline: 1,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: None,
             };
 
             let encoded_cond_pay = encode_conditional_pay(shared_pay.clone());
@@ -301,6 +332,8 @@ pub mod test_pay_resolver {
                 transfer_func: transfer_func,
                 resolve_deadline: 99999,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: None,
             };
             let encoded_cond_pay = encode_conditional_pay(shared_pay.clone());
             let sig_of_src = account_pair("src").sign(&encoded_cond_pay);
@@ -337,6 +370,8 @@ pub mod test_pay_resolver {
                 transfer_func: transfer_func,
                 resolve_deadline: 99999,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: None,
             };
             let encoded_cond_pay = encode_conditional_pay(shared_pay.clone());
             let sig_of_src = account_pair("src").sign(&encoded_cond_pay);
@@ -353,6 +388,8 @@ pub mod test_pay_resolver {
             let pay_request = ResolvePaymentConditionsRequest {
                 cond_pay: shared_pay,
                 hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
             };
 
             let _ =
@@ -381,10 +418,14 @@ pub mod test_pay_resolver {
                 transfer_func: transfer_func,
                 resolve_deadline: 99999,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: None,
             };
             let pay_request = ResolvePaymentConditionsRequest {
                 cond_pay: cond_pay,
                 hash_preimages: vec![H256::from_low_u64_be(1), H256::from_low_u64_be(0)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
             };
 
             let err =
@@ -393,6 +434,919 @@ pub mod test_pay_resolver {
         })
     }
 
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_sha256_and_keccak256_hash_locks() {
+        ExtBuilder::build().execute_with(|| {   
+            let transfer_func = get_transfer_func(account_key("Alice"), 200, 0);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![
+                    get_hash_lock_condition(H256::from_low_u64_be(2), HashType::Sha256),
+                    get_hash_lock_condition(H256::from_low_u64_be(3), HashType::Keccak256),
+                    get_hash_lock_condition(H256::from_low_u64_be(4), HashType::Blake2_256),
+                ],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
+            let pay_hash: H256 = hashing::blake2_256(&encoded_cond_pay).into();
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![
+                    H256::from_low_u64_be(2),
+                    H256::from_low_u64_be(3),
+                    H256::from_low_u64_be(4),
+                ],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let (pay_id, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(pay_id, calculate_pay_id::<TestRuntime>(pay_hash));
+            assert_eq!(amount, 200);
+        })
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_when_revealing_hash_lock_instead_of_preimage() {
+        ExtBuilder::build().execute_with(|| {   
+            let transfer_func = get_transfer_func(account_key("Alice"), 200, 0);
+            let hash_lock_condition = get_hash_lock_condition(H256::from_low_u64_be(2), HashType::Sha256);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![hash_lock_condition.clone()],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![hash_lock_condition.hash_lock.unwrap()],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let err =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Wrong preimage"));
+        })
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_when_hash_type_mismatch() {
+        ExtBuilder::build().execute_with(|| {   
+            let transfer_func = get_transfer_func(account_key("Alice"), 200, 0);
+            let mut hash_lock_condition = get_hash_lock_condition(H256::from_low_u64_be(2), HashType::Sha256);
+            hash_lock_condition.hash_type = Some(HashType::Keccak256);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![hash_lock_condition],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(2)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let err =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Wrong preimage"));
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_revealed_preimages() {
+        ExtBuilder::build().execute_with(|| {   
+            let hash_lock = PreimageRegistry::<TestRuntime>::reveal_preimage(
+                Origin::signed(account_key("Alice")),
+                H256::from_low_u64_be(2),
+                HashType::Sha256,
+            ).unwrap();
+            let (is_revealed, preimage, reveal_block)
+                = CelerPayModule::get_preimage_reveal_info(hash_lock, HashType::Sha256);
+            assert_eq!(is_revealed, true);
+            assert_eq!(preimage, H256::from_low_u64_be(2));
+            assert_eq!(reveal_block, System::block_number());
+
+            let transfer_func = get_transfer_func(account_key("Alice"), 200, 0);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![
+                    get_hash_lock_condition(H256::from_low_u64_be(3), HashType::Keccak256),
+                    get_hash_lock_condition(H256::from_low_u64_be(2), HashType::Sha256),
+                ],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(3)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 200);
+        })
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_when_preimage_is_not_revealed() {
+        ExtBuilder::build().execute_with(|| {   
+            let transfer_func = get_transfer_func(account_key("Alice"), 200, 0);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_hash_lock_condition(H256::from_low_u64_be(2), HashType::Sha256)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let err =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Preimage not revealed"));
+
+            let hash_lock = get_hash_lock_condition(H256::from_low_u64_be(2), HashType::Sha256).hash_lock.unwrap();
+            let (is_revealed, _, _) = CelerPayModule::get_preimage_reveal_info(hash_lock, HashType::Sha256);
+            assert_eq!(is_revealed, false);
+        })
+    }
+
+    #[test]
+    fn test_fail_reveal_preimage_twice() {
+        ExtBuilder::build().execute_with(|| {   
+            assert_ok!(PreimageRegistry::<TestRuntime>::reveal_preimage(
+                Origin::signed(account_key("Alice")),
+                H256::from_low_u64_be(2),
+                HashType::Blake2_256,
+            ));
+            let err = PreimageRegistry::<TestRuntime>::reveal_preimage(
+                Origin::signed(account_key("Bob")),
+                H256::from_low_u64_be(2),
+                HashType::Blake2_256,
+            ).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Preimage already revealed"));
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_revealed_preimage_before_supplied_preimage() {
+        ExtBuilder::build().execute_with(|| {   
+            assert_ok!(PreimageRegistry::<TestRuntime>::reveal_preimage(
+                Origin::signed(account_key("Alice")),
+                H256::from_low_u64_be(2),
+                HashType::Sha256,
+            ));
+
+            let transfer_func = get_transfer_func(account_key("Alice"), 200, 0);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![
+                    get_hash_lock_condition(H256::from_low_u64_be(2), HashType::Sha256),
+                    get_hash_lock_condition(H256::from_low_u64_be(3), HashType::Keccak256),
+                ],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(3)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 200);
+        })
+    }
+
+    #[test]
+    fn test_pass_prune_preimage_after_prune_delay() {
+        ExtBuilder::build().execute_with(|| {   
+            System::set_block_number(1);
+            MockPreimageDeposit::set(10);
+            let alice = account_key("Alice");
+            let bob = account_key("Bob");
+            assert_ok!(CelerPayModule::reveal_preimage(
+                Origin::signed(alice),
+                H256::from_low_u64_be(2),
+                HashType::Sha256,
+            ));
+            let hash_lock = get_hash_lock_condition(H256::from_low_u64_be(2), HashType::Sha256).hash_lock.unwrap();
+            assert_eq!(Balances::reserved_balance(alice), 10);
+            assert_eq!(CelerPayModule::preimage_deposits(HashType::Sha256, hash_lock), Some((alice, 10)));
+
+            // preimage can be pruned after PreimagePruneDelay from its reveal block
+            System::set_block_number(11);
+            assert_noop!(
+                CelerPayModule::prune_preimage(Origin::signed(bob), hash_lock, HashType::Sha256),
+                DispatchError::Other("Preimage prune delay not passed")
+            );
+
+            System::set_block_number(12);
+            assert_ok!(CelerPayModule::prune_preimage(Origin::signed(bob), hash_lock, HashType::Sha256));
+            let (is_revealed, _, _) = CelerPayModule::get_preimage_reveal_info(hash_lock, HashType::Sha256);
+            assert_eq!(is_revealed, false);
+            assert_eq!(CelerPayModule::preimage_deposits(HashType::Sha256, hash_lock), None);
+            assert_eq!(Balances::reserved_balance(alice), 0);
+            assert_eq!(Balances::free_balance(alice), 999);
+            assert_eq!(Balances::free_balance(bob), 1001);
+
+            assert_noop!(
+                CelerPayModule::prune_preimage(Origin::signed(bob), hash_lock, HashType::Sha256),
+                DispatchError::Other("Preimage not revealed")
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_when_boolean_circuit_is_true() {
+        ExtBuilder::build().execute_with(|| {   
+            // hash_lock AND (true OR false)
+            let transfer_func = get_boolean_circuit_transfer_func(
+                account_key("Alice"),
+                200,
+                vec![BooleanGate::Or(1, 2), BooleanGate::And(0, 3)],
+            );
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_condition(0), get_condition(1), get_condition(2)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
+            let pay_hash: H256 = hashing::blake2_256(&encoded_cond_pay).into();
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let (pay_id, amount, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(pay_id, calculate_pay_id::<TestRuntime>(pay_hash));
+            assert_eq!(amount, 200);
+            assert_eq!(resolve_deadline, System::block_number());
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_when_boolean_circuit_is_false() {
+        ExtBuilder::build().execute_with(|| {   
+            // hash_lock AND (NOT true)
+            let transfer_func = get_boolean_circuit_transfer_func(
+                account_key("Alice"),
+                200,
+                vec![BooleanGate::Not(1), BooleanGate::And(0, 2)],
+            );
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_condition(0), get_condition(1)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let (_, amount, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 0);
+            assert_eq!(resolve_deadline, System::block_number() + 10);
+        })
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_when_boolean_circuit_negates_unrevealed_hash_lock() {
+        ExtBuilder::build().execute_with(|| {   
+            // NOT hash_lock
+            let transfer_func = get_boolean_circuit_transfer_func(
+                account_key("Alice"),
+                200,
+                vec![BooleanGate::Not(0)],
+            );
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_condition(0)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let err =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Preimage not revealed"));
+
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(2)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let err =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Wrong preimage"));
+        })
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_when_boolean_circuit_refers_to_unknown_wire() {
+        ExtBuilder::build().execute_with(|| {   
+            let transfer_func = get_boolean_circuit_transfer_func(
+                account_key("Alice"),
+                200,
+                vec![BooleanGate::And(0, 2)],
+            );
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_condition(0), get_condition(1)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let err =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Invalid boolean circuit"));
+        })
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_when_boolean_circuit_exceeds_max_gates() {
+        ExtBuilder::build().execute_with(|| {   
+            let transfer_func = get_boolean_circuit_transfer_func(
+                account_key("Alice"),
+                200,
+                vec![BooleanGate::Not(0); 17],
+            );
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_condition(1)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            assert_eq!(boolean_circuit_gates_len::<TestRuntime>(&cond_pay.transfer_func), 16);
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let err =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Exceed max boolean circuit gates"));
+
+            // the number of gates is checked before the gates are decoded
+            let mut oversized_cond_pay = cond_pay;
+            oversized_cond_pay.transfer_func.boolean_circuit = Some(Compact(u32::max_value()).encode());
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: oversized_cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let err =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Exceed max boolean circuit gates"));
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_registered_boolean_apps() {
+        ExtBuilder::build().execute_with(|| {   
+            let transfer_func = get_transfer_func(account_key("Alice"), 200, 0);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![
+                    get_condition(0),
+                    get_registered_boolean_condition(0, true, true),
+                    get_registered_boolean_condition(0, true, true),
+                ],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 200);
+
+            let mut or_cond_pay = cond_pay;
+            or_cond_pay.transfer_func = get_transfer_func(account_key("Alice"), 200, 1);
+            or_cond_pay.conditions[2] = get_registered_boolean_condition(0, true, false);
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: or_cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 200);
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_a_false_registered_boolean_app() {
+        ExtBuilder::build().execute_with(|| {   
+            let transfer_func = get_transfer_func(account_key("Alice"), 200, 0);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![
+                    get_condition(0),
+                    get_registered_boolean_condition(0, true, true),
+                    get_registered_boolean_condition(0, true, false),
+                ],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 0);
+        })
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_when_registered_boolean_app_is_not_finalized() {
+        ExtBuilder::build().execute_with(|| {   
+            let transfer_func = get_transfer_func(account_key("Alice"), 200, 0);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_registered_boolean_condition(0, false, true)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let err =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap_err();
+            assert_eq!(err, DispatchError::Other("Condition is not finalized"));
+        })
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_with_unregistered_app_numbers() {
+        ExtBuilder::build().execute_with(|| {   
+            let transfer_func = get_transfer_func(account_key("Alice"), 200, 0);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_registered_boolean_condition(1, true, true)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                Error::<TestRuntime>::BooleanAppNotExist
+            );
+
+            let mut numeric_condition = get_condition(3);
+            numeric_condition.numeric_app_num = Some(1);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![numeric_condition],
+                transfer_func: get_transfer_func(account_key("Alice"), 50, 3),
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                Error::<TestRuntime>::NumericAppNotExist
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_app_ids() {
+        ExtBuilder::build().execute_with(|| {
+            let mut boolean_condition = get_registered_boolean_condition(0, true, true);
+            boolean_condition.boolean_app_num = None;
+            boolean_condition.boolean_app_id = Some(*b"mockbool");
+            let mut numeric_condition = get_condition(3);
+            numeric_condition.numeric_app_num = None;
+            numeric_condition.numeric_app_id = Some(*b"mock_num");
+
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![boolean_condition.clone()],
+                transfer_func: get_transfer_func(account_key("Alice"), 200, 0),
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 200);
+
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![numeric_condition],
+                transfer_func: get_transfer_func(account_key("Alice"), 50, 3),
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 10);
+
+            // unknown app id
+            boolean_condition.boolean_app_id = Some(*b"unknown_");
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![boolean_condition],
+                transfer_func: get_transfer_func(account_key("Alice"), 200, 0),
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                Error::<TestRuntime>::BooleanAppNotExist
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_migrated_app_numbers() {
+        ExtBuilder::build().execute_with(|| {
+            crate::migration::on_runtime_upgrade::<TestRuntime>();
+            assert_eq!(CelerPayModule::boolean_app_ids(0), Some(*b"mockbool"));
+            assert_eq!(CelerPayModule::boolean_app_ids(1), None);
+            assert_eq!(CelerPayModule::numeric_app_ids(0), Some(*b"mock_num"));
+
+            // a legacy app number keeps resolving to the recorded app id
+            crate::BooleanAppIds::insert(5, *b"mockbool");
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_registered_boolean_condition(5, true, true)],
+                transfer_func: get_transfer_func(account_key("Alice"), 200, 0),
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 200);
+
+            // recorded app numbers are not renumbered by later runtime upgrades
+            crate::BooleanAppIds::remove(0);
+            crate::migration::on_runtime_upgrade::<TestRuntime>();
+            assert_eq!(CelerPayModule::boolean_app_ids(0), None);
+            assert_eq!(CelerPayModule::boolean_app_ids(6), None);
+
+            // legacy app numbers are not mapped by positions once app numbers are recorded
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_registered_boolean_condition(0, true, true)],
+                transfer_func: get_transfer_func(account_key("Alice"), 200, 0),
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                Error::<TestRuntime>::BooleanAppNotExist
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_only_condition_calls() {
+        ExtBuilder::build().execute_with(|| {   
+            // calls of boolean runtime modules are answered by the app recorded for them
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_legacy_call_condition(1)],
+                transfer_func: get_transfer_func(account_key("Alice"), 200, 0),
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 200);
+
+            let mut false_cond_pay = cond_pay;
+            false_cond_pay.conditions = vec![get_legacy_call_condition(0)];
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: false_cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                DispatchError::Other("Condition is not finalized")
+            );
+        })
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_with_a_disallowed_condition_call() {
+        ExtBuilder::build().execute_with(|| {   
+            let mut condition = get_legacy_call_condition(1);
+            condition.call_is_finalized = Some(Box::new(MockCall::System(
+                frame_system::Call::remark(vec![])
+            )));
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![condition],
+                transfer_func: get_transfer_func(account_key("Alice"), 200, 0),
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                Error::<TestRuntime>::ConditionCallNotAllowed
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_recorded_condition_calls() {
+        ExtBuilder::build().execute_with(|| {
+            crate::migration::on_runtime_upgrade::<TestRuntime>();
+            let is_finalized_index = get_call_index(MockBooleanCall::is_finalized(H256::zero(), 0));
+            let get_outcome_index = get_call_index(MockBooleanCall::get_outcome(H256::zero(), 0));
+            assert_eq!(CelerPayModule::legacy_call_app_ids(is_finalized_index), Some(*b"mockbool"));
+            assert_eq!(CelerPayModule::legacy_call_app_ids(get_outcome_index), Some(*b"mockbool"));
+
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_legacy_call_condition(1)],
+                transfer_func: get_transfer_func(account_key("Alice"), 200, 0),
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 200);
+
+            // recorded call indices keep their app in later runtime upgrades
+            crate::LegacyCallAppIds::insert(is_finalized_index, *b"otherapp");
+            crate::migration::on_runtime_upgrade::<TestRuntime>();
+            assert_eq!(CelerPayModule::legacy_call_app_ids(is_finalized_index), Some(*b"otherapp"));
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                Error::<TestRuntime>::BooleanAppNotExist
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_rolls_back_app_queries() {
+        ExtBuilder::build().execute_with(|| {   
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_condition(1), get_legacy_call_condition(1)],
+                transfer_func: get_transfer_func(account_key("Alice"), 200, 0),
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 200);
+
+            // MockBooleanApp writes storage in every query
+            assert_eq!(
+                frame_support::storage::unhashed::get::<bool>(MOCK_BOOLEAN_QUERIED_KEY),
+                None
+            );
+        })
+    }
+
+    #[test]
+    fn test_fail_integrity_test_with_duplicate_app_ids() {
+        ExtBuilder::build().execute_with(|| {   
+            <CelerPayModule as IntegrityTest>::integrity_test();
+
+            let app_ids = boolean_app_ids::<H256, (MockBooleanApp, MockBooleanApp)>();
+            assert_eq!(app_ids, vec![*b"mockbool", *b"mockbool"]);
+            assert!(has_duplicate_app_ids(&app_ids));
+            let app_ids = numeric_app_ids::<H256, Balance, (MockNumericApp,)>();
+            assert!(!has_duplicate_app_ids(&app_ids));
+        })
+    }
+
     #[test]
     fn test_pass_resolve_payment_by_conditions_when_numeric_add() {
         ExtBuilder::build().execute_with(|| {   
@@ -401,79 +1355,1082 @@ pub mod test_pay_resolver {
                 pay_timestamp: Timestamp::get(),
                 src: account_key("src"),
                 dest: account_key("dest"),
-                conditions: vec![get_condition(0), get_condition(3), get_condition(4)],
-                transfer_func: transfer_func,
-                resolve_deadline: 99999,
+                conditions: vec![get_condition(0), get_condition(3), get_condition(4)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
+            let pay_hash: H256 = hashing::blake2_256(&encoded_cond_pay).into();
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let (pay_id, amount, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(pay_id, calculate_pay_id::<TestRuntime>(pay_hash));
+            assert_eq!(amount, 35);
+            assert_eq!(resolve_deadline, System::block_number() + 10);
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_when_numeric_max() {
+        ExtBuilder::build().execute_with(|| {   
+            let transfer_func = get_transfer_func(account_key("Alice"), 50, 4);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_condition(0), get_condition(3), get_condition(4)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
+            let pay_hash: H256 = hashing::blake2_256(&encoded_cond_pay).into();
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let (pay_id, amount, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(pay_id, calculate_pay_id::<TestRuntime>(pay_hash));
+            assert_eq!(amount, 25);
+            assert_eq!(resolve_deadline, System::block_number() + 10);
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_when_numeric_min() {
+        ExtBuilder::build().execute_with(|| {   
+            let transfer_func = get_transfer_func(account_key("Alice"), 50, 5);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_condition(0), get_condition(3), get_condition(4)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
+            let pay_hash: H256 = hashing::blake2_256(&encoded_cond_pay).into();
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let (pay_id, amount, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(pay_id, calculate_pay_id::<TestRuntime>(pay_hash));
+            assert_eq!(amount, 10);
+            assert_eq!(resolve_deadline, System::block_number() + 10);
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_contract_conditions() {
+        ExtBuilder::build().execute_with(|| {
+            let deployed_address = account_key("deployed_contract");
+            let virtual_address = account_key("virtual_contract");
+            let virt_addr = H256::from_low_u64_be(7);
+            MockContracts::deploy(&deployed_address, true, true.encode());
+            MockContracts::deploy(&virtual_address, true, true.encode());
+            MockContracts::register_virtual(virt_addr, &virtual_address);
+
+            let transfer_func = get_transfer_func(account_key("Alice"), 10, 0);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![
+                    get_condition(0),
+                    get_deployed_contract_condition(deployed_address, Some(1000)),
+                    get_virtual_contract_condition(virt_addr, Some(1000)),
+                ],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            assert_eq!(contract_conditions_gas_limit::<TestRuntime>(&cond_pay), 4000);
+            let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
+            let pay_hash: H256 = hashing::blake2_256(&encoded_cond_pay).into();
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let (pay_id, amount, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(pay_id, calculate_pay_id::<TestRuntime>(pay_hash));
+            assert_eq!(amount, 10);
+            assert_eq!(resolve_deadline, System::block_number());
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_numeric_contract_condition() {
+        ExtBuilder::build().execute_with(|| {
+            let deployed_address = account_key("deployed_contract");
+            MockContracts::deploy(&deployed_address, true, (15 as Balance).encode());
+
+            let transfer_func = get_transfer_func(account_key("Alice"), 50, 3);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![
+                    get_condition(0),
+                    get_condition(3),
+                    get_deployed_contract_condition(deployed_address, Some(1000)),
+                ],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 25);
+        })
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_with_overflowing_numeric_add() {
+        ExtBuilder::build().execute_with(|| {
+            let deployed_address = account_key("deployed_contract");
+            MockContracts::deploy(&deployed_address, true, Balance::max_value().encode());
+
+            let transfer_func = get_transfer_func(account_key("Alice"), 50, 3);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![
+                    get_condition(0),
+                    get_condition(3),
+                    get_deployed_contract_condition(deployed_address, Some(1000)),
+                ],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                Error::<TestRuntime>::OverFlow
+            );
+        })
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_with_invalid_contract_condition() {
+        ExtBuilder::build().execute_with(|| {
+            let deployed_address = account_key("deployed_contract");
+            MockContracts::deploy(&deployed_address, false, true.encode());
+
+            let transfer_func = get_transfer_func(account_key("Alice"), 10, 0);
+            let mut cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_deployed_contract_condition(deployed_address, None)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                Error::<TestRuntime>::GasLimitNotExist
+            );
+
+            // virtual contract which is not deployed
+            cond_pay.conditions = vec![get_virtual_contract_condition(H256::from_low_u64_be(7), Some(1000))];
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                DispatchError::Other("Virtual contract is not deployed")
+            );
+
+            // contract condition which is not finalized
+            cond_pay.conditions = vec![get_deployed_contract_condition(deployed_address, Some(1000))];
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                DispatchError::Other("Condition is not finalized")
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_oracle_signed_conditions() {
+        ExtBuilder::build().execute_with(|| {
+            let question_1 = H256::from_low_u64_be(11);
+            let question_2 = H256::from_low_u64_be(12);
+
+            // BooleanAnd of a hash lock and two oracle signed conditions
+            let transfer_func = get_transfer_func(account_key("Alice"), 10, 0);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![
+                    get_condition(0),
+                    get_oracle_condition(account_key("oracle"), question_1),
+                    get_oracle_condition(account_key("oracle"), question_2),
+                ],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
+            let pay_hash: H256 = hashing::blake2_256(&encoded_cond_pay).into();
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![
+                    get_oracle_outcome("oracle", question_1, OracleResult::Boolean(true)),
+                    get_oracle_outcome("oracle", question_2, OracleResult::Boolean(true)),
+                ],
+                lottery_secret: None,
+            };
+            let (pay_id, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(pay_id, calculate_pay_id::<TestRuntime>(pay_hash));
+            assert_eq!(amount, 10);
+
+            // NumericAdd of a numeric runtime module condition and an oracle signed condition
+            let transfer_func = get_transfer_func(account_key("Alice"), 50, 3);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![
+                    get_condition(3),
+                    get_oracle_condition(account_key("oracle"), question_1),
+                ],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![
+                    get_oracle_outcome("oracle", question_1, OracleResult::Numeric(30)),
+                ],
+                lottery_secret: None,
+            };
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 40);
+        })
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_with_invalid_oracle_outcome() {
+        ExtBuilder::build().execute_with(|| {
+            let question = H256::from_low_u64_be(11);
+            let transfer_func = get_transfer_func(account_key("Alice"), 10, 0);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_oracle_condition(account_key("oracle"), question)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+
+            // outcome is not submitted
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                Error::<TestRuntime>::OracleOutcomeNotExist
+            );
+
+            // outcome is not signed by the oracle
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![
+                    get_oracle_outcome("Alice", question, OracleResult::Boolean(true)),
+                ],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                DispatchError::Other("Check sig failed")
+            );
+
+            // outcome answers another question
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![
+                    get_oracle_outcome("oracle", H256::from_low_u64_be(12), OracleResult::Boolean(true)),
+                ],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                DispatchError::Other("Wrong question hash")
+            );
+
+            // numeric outcome of boolean transfer function
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![
+                    get_oracle_outcome("oracle", question, OracleResult::Numeric(10)),
+                ],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                Error::<TestRuntime>::InvalidOracleResult
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_vouched_result_with_oracle_signed_condition() {
+        ExtBuilder::build().execute_with(|| {
+            let transfer_func = get_transfer_func(account_key("Alice"), 100, 3);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_oracle_condition(account_key("oracle"), H256::from_low_u64_be(11))],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
+            let pay_hash: H256 = hashing::blake2_256(&encoded_cond_pay).into();
+            let vouched_cond_pay_result = VouchedCondPayResult {
+                cond_pay_result: CondPayResult {
+                    cond_pay: cond_pay,
+                    amount: 30,
+                },
+                sig_of_src: account_pair("src").sign(&encoded_cond_pay),
+                sig_of_dest: account_pair("dest").sign(&encoded_cond_pay),
+            };
+
+            let (pay_id, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_vouched_result(vouched_cond_pay_result)
+                    .unwrap();
+            assert_eq!(pay_id, calculate_pay_id::<TestRuntime>(pay_hash));
+            assert_eq!(amount, 30);
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_block_conditions() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(5);
+            let transfer_func = get_transfer_func(account_key("Alice"), 10, 0);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![
+                    get_time_condition(ConditionType::BlockAfter, Some(10), None),
+                    get_time_condition(ConditionType::BlockBefore, Some(20), None),
+                ],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+
+            // BlockAfter condition is not finalized before block 10
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                DispatchError::Other("Condition is not finalized")
+            );
+
+            // from block 10 until block 20
+            System::set_block_number(10);
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (_, amount, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 10);
+            assert_eq!(resolve_deadline, 10);
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_time_conditions() {
+        ExtBuilder::build().execute_with(|| {
+            Timestamp::set_timestamp(100);
+
+            // expired TimeBefore condition makes the numeric payment zero
+            let transfer_func = get_transfer_func(account_key("Alice"), 50, 3);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![
+                    get_condition(3),
+                    get_time_condition(ConditionType::TimeBefore, None, Some(100)),
+                ],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 0);
+
+            // satisfied TimeAfter condition in BooleanOr payment
+            let transfer_func = get_transfer_func(account_key("Alice"), 20, 1);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![
+                    get_condition(2),
+                    get_time_condition(ConditionType::TimeAfter, None, Some(100)),
+                ],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 20);
+
+            // unreached TimeAfter condition is not finalized
+            let transfer_func = get_transfer_func(account_key("Alice"), 20, 1);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_time_condition(ConditionType::TimeAfter, None, Some(200))],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                DispatchError::Other("Condition is not finalized")
+            );
+
+            // timestamp is not set
+            let transfer_func = get_transfer_func(account_key("Alice"), 20, 0);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_time_condition(ConditionType::TimeAfter, None, None)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                Error::<TestRuntime>::TimestampNotExist
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_pay_result_conditions() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+
+            // resolve pay A to its max amount
+            let transfer_func = get_transfer_func(account_key("Alice"), 10, 0);
+            let cond_pay_a = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_condition(0)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay_a,
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (pay_id_a, _, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+
+            // pay B is paid only if pay A resolved to 10
+            let transfer_func = get_transfer_func(account_key("Alice"), 5, 0);
+            let cond_pay_b = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_pay_result_condition(pay_id_a, Some(10))],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay_b.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()),
+                DispatchError::Other("Dependent pay is not finalized")
+            );
+
+            System::set_block_number(2);
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 5);
+
+            // pay C pays the resolved amount of pay A
+            let transfer_func = get_transfer_func(account_key("Alice"), 20, 3);
+            let cond_pay_c = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_pay_result_condition(pay_id_a, None)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay_c,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 10);
+        })
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_with_unresolved_dependent_pay() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(2);
+            let transfer_func = get_transfer_func(account_key("Alice"), 5, 1);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_pay_result_condition(H256::from_low_u64_be(5), None)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                DispatchError::Other("Dependent pay is not finalized")
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_lottery_tickets() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let secret = H256::from_low_u64_be(7);
+
+            // a ticket with win probability 100% always pays max_transfer
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_condition(0)],
+                transfer_func: get_lottery_transfer_func(account_key("Alice"), 10),
+                resolve_deadline: 200,
                 resolve_timeout: 10,
+                lottery: Some(get_lottery_ticket(secret, Perbill::one(), 0)),
+                stream: None,
             };
-            let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
-            let pay_hash: H256 = hashing::blake2_256(&encoded_cond_pay).into();
             let pay_request = ResolvePaymentConditionsRequest {
                 cond_pay: cond_pay,
                 hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: Some(secret),
             };
+            let (_, amount, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 10);
+            assert_eq!(resolve_deadline, 1);
 
-            let (pay_id, amount, resolve_deadline) =
+            // a ticket with win probability 0% never pays
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![],
+                transfer_func: get_lottery_transfer_func(account_key("Alice"), 10),
+                resolve_deadline: 200,
+                resolve_timeout: 10,
+                lottery: Some(get_lottery_ticket(secret, Perbill::zero(), 0)),
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: Some(secret),
+            };
+            let (_, amount, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()).unwrap();
+            assert_eq!(amount, 0);
+            assert_eq!(resolve_deadline, 11);
+
+            // a losing ticket is drawn the same in later blocks
+            System::set_block_number(2);
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                DispatchError::Other("New amount is not larger")
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_lottery_ticket_drawn_independent_of_resolve_block() {
+        let secret = H256::from_low_u64_be(7);
+        let cond_pay = ConditionalPay {
+            pay_timestamp: 0,
+            src: account_key("src"),
+            dest: account_key("dest"),
+            conditions: vec![],
+            transfer_func: get_lottery_transfer_func(account_key("Alice"), 10),
+            resolve_deadline: 200,
+            resolve_timeout: 10,
+            lottery: Some(get_lottery_ticket(secret, Perbill::from_percent(50), 3)),
+            stream: None,
+        };
+        let pay_request = ResolvePaymentConditionsRequest {
+            cond_pay: cond_pay,
+            hash_preimages: vec![],
+            oracle_outcomes: vec![],
+            lottery_secret: Some(secret),
+        };
+
+        // the resolver cannot choose the draw by choosing the block in which the pay is resolved
+        let mut amounts: Vec<Balance> = vec![];
+        for resolve_block in vec![4, 5, 10, 100] {
+            ExtBuilder::build().execute_with(|| {
+                System::set_block_number(resolve_block);
+                frame_system::BlockHash::<TestRuntime>::insert(3, H256::from_low_u64_be(33));
+                for block in 4..resolve_block {
+                    frame_system::BlockHash::<TestRuntime>::insert(block, H256::from_low_u64_be(block));
+                }
+                let (_, amount, _) =
+                    PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()).unwrap();
+                amounts.push(amount);
+            });
+        }
+        assert!(amounts.iter().all(|amount| *amount == amounts[0]));
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_before_lottery_draw_block_hash_available() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(3);
+            let secret = H256::from_low_u64_be(7);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![],
+                transfer_func: get_lottery_transfer_func(account_key("Alice"), 10),
+                resolve_deadline: 200,
+                resolve_timeout: 10,
+                lottery: Some(get_lottery_ticket(secret, Perbill::one(), 3)),
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: Some(secret),
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()),
+                DispatchError::Other("Lottery draw block not reached")
+            );
+
+            // hash of the draw block is not kept
+            System::set_block_number(4);
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()),
+                DispatchError::Other("Lottery draw block hash not available")
+            );
+
+            frame_system::BlockHash::<TestRuntime>::insert(3, H256::from_low_u64_be(33));
+            let (_, amount, _) =
                 PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
-            assert_eq!(pay_id, calculate_pay_id::<TestRuntime>(pay_hash));
-            assert_eq!(amount, 35);
-            assert_eq!(resolve_deadline, System::block_number() + 10);
+            assert_eq!(amount, 10);
         })
     }
 
     #[test]
-    fn test_pass_resolve_payment_by_conditions_when_numeric_max() {
-        ExtBuilder::build().execute_with(|| {   
-            let transfer_func = get_transfer_func(account_key("Alice"), 50, 4);
+    fn test_fail_resolve_payment_by_conditions_with_lottery_resolve_deadline_beyond_block_hash_window() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(4);
+            frame_system::BlockHash::<TestRuntime>::insert(3, H256::from_low_u64_be(33));
+            let secret = H256::from_low_u64_be(7);
+            let mut cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![],
+                transfer_func: get_lottery_transfer_func(account_key("Alice"), 10),
+                resolve_deadline: 3 + BlockHashCount::get() + 1,
+                resolve_timeout: 10,
+                lottery: Some(get_lottery_ticket(secret, Perbill::one(), 3)),
+                stream: None,
+            };
+            let mut pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: Some(secret),
+            };
+            // the ticket is refused even while the hash of its draw block is kept
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()),
+                DispatchError::Other("Lottery resolve deadline beyond block hash window")
+            );
+
+            cond_pay.resolve_deadline = 3 + BlockHashCount::get();
+            pay_request.cond_pay = cond_pay;
+            let (_, amount, _) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 10);
+        })
+    }
+
+    #[test]
+    fn test_fail_resolve_payment_by_conditions_with_invalid_lottery_ticket() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            let secret = H256::from_low_u64_be(7);
+            let mut cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![],
+                transfer_func: get_lottery_transfer_func(account_key("Alice"), 10),
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let mut pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: Some(secret),
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()),
+                Error::<TestRuntime>::LotteryTicketNotExist
+            );
+
+            cond_pay.lottery = Some(get_lottery_ticket(secret, Perbill::one(), 0));
+            pay_request.cond_pay = cond_pay.clone();
+            pay_request.lottery_secret = None;
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()),
+                Error::<TestRuntime>::LotterySecretNotExist
+            );
+
+            pay_request.lottery_secret = Some(H256::from_low_u64_be(8));
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()),
+                DispatchError::Other("Wrong lottery secret")
+            );
+
+            // conditions of the ticket must be met before it is drawn
+            cond_pay.conditions = vec![get_condition(2)];
+            pay_request.cond_pay = cond_pay;
+            pay_request.lottery_secret = Some(secret);
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                DispatchError::Other("Lottery conditions are not met")
+            );
+        })
+    }
+
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_linear_stream() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(5);
+            let mut transfer_func = get_transfer_func(account_key("Alice"), 15, 0);
+            transfer_func.logic_type = TransferFunctionType::Linear;
             let cond_pay = ConditionalPay {
                 pay_timestamp: Timestamp::get(),
                 src: account_key("src"),
                 dest: account_key("dest"),
-                conditions: vec![get_condition(0), get_condition(3), get_condition(4)],
+                conditions: vec![get_condition(0)],
                 transfer_func: transfer_func,
                 resolve_deadline: 99999,
-                resolve_timeout: 10,
+                resolve_timeout: 100,
+                lottery: None,
+                stream: Some(LinearStream { rate_per_block: 2, start_block: 10, end_block: 20 }),
             };
-            let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
-            let pay_hash: H256 = hashing::blake2_256(&encoded_cond_pay).into();
             let pay_request = ResolvePaymentConditionsRequest {
                 cond_pay: cond_pay,
                 hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
             };
 
+            // nothing accrued before start_block
             let (pay_id, amount, resolve_deadline) =
-                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
-            assert_eq!(pay_id, calculate_pay_id::<TestRuntime>(pay_hash));
-            assert_eq!(amount, 25);
-            assert_eq!(resolve_deadline, System::block_number() + 10);
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()).unwrap();
+            assert_eq!(amount, 0);
+            assert_eq!(resolve_deadline, 105);
+
+            // re-resolve periodically without moving the resolve deadline
+            System::set_block_number(14);
+            let (_, amount, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()).unwrap();
+            assert_eq!(amount, 8);
+            assert_eq!(resolve_deadline, 105);
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()),
+                DispatchError::Other("New amount is not larger")
+            );
+
+            // accrued amount is capped at max_transfer, which finalizes the pay
+            System::set_block_number(18);
+            let (_, amount, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()).unwrap();
+            assert_eq!(amount, 15);
+            assert_eq!(resolve_deadline, 18);
+            let (pay_info, pay_deadline) = CelerPayModule::get_pay_info(pay_id);
+            assert_eq!((pay_info.amount, pay_deadline), (15, 18));
         })
     }
 
     #[test]
-    fn test_pass_resolve_payment_by_conditions_when_numeric_min() {
-        ExtBuilder::build().execute_with(|| {   
-            let transfer_func = get_transfer_func(account_key("Alice"), 50, 5);
-            let cond_pay = ConditionalPay {
+    fn test_pass_resolve_payment_by_conditions_with_linear_stream_longer_than_resolve_timeout() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(5);
+            let mut transfer_func = get_transfer_func(account_key("Alice"), 100, 0);
+            transfer_func.logic_type = TransferFunctionType::Linear;
+            let mut cond_pay = ConditionalPay {
                 pay_timestamp: Timestamp::get(),
                 src: account_key("src"),
                 dest: account_key("dest"),
-                conditions: vec![get_condition(0), get_condition(3), get_condition(4)],
+                conditions: vec![],
                 transfer_func: transfer_func,
                 resolve_deadline: 99999,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: Some(LinearStream { rate_per_block: 2, start_block: 10, end_block: 50 }),
             };
-            let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
-            let pay_hash: H256 = hashing::blake2_256(&encoded_cond_pay).into();
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+
+            // onchain resolve deadline is extended to end_block rather than block + resolve_timeout
+            let (_, amount, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()).unwrap();
+            assert_eq!(amount, 0);
+            assert_eq!(resolve_deadline, 50);
+
+            // the stream keeps accruing after block + resolve_timeout
+            System::set_block_number(30);
+            let (_, amount, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request.clone()).unwrap();
+            assert_eq!(amount, 40);
+            assert_eq!(resolve_deadline, 50);
+
+            System::set_block_number(50);
+            let (_, amount, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(amount, 80);
+            assert_eq!(resolve_deadline, 50);
+
+            // onchain resolve deadline is still capped at resolve_deadline of the pay
+            cond_pay.resolve_deadline = 40;
             let pay_request = ResolvePaymentConditionsRequest {
                 cond_pay: cond_pay,
-                hash_preimages: vec![H256::from_low_u64_be(1)],
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
             };
+            System::set_block_number(5);
+            let (_, _, resolve_deadline) =
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
+            assert_eq!(resolve_deadline, 40);
+        })
+    }
 
-            let (pay_id, amount, resolve_deadline) =
+    #[test]
+    fn test_pass_resolve_payment_by_conditions_with_linear_stream_and_false_condition() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(15);
+            let mut transfer_func = get_transfer_func(account_key("Alice"), 15, 0);
+            transfer_func.logic_type = TransferFunctionType::Linear;
+            let mut cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_condition(2)],
+                transfer_func: transfer_func,
+                resolve_deadline: 99999,
+                resolve_timeout: 100,
+                lottery: None,
+                stream: None,
+            };
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            let (_, amount, _) =
                 PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request).unwrap();
-            assert_eq!(pay_id, calculate_pay_id::<TestRuntime>(pay_hash));
-            assert_eq!(amount, 10);
-            assert_eq!(resolve_deadline, System::block_number() + 10);
+            assert_eq!(amount, 0);
+
+            cond_pay.conditions = vec![];
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay,
+                hash_preimages: vec![],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_noop!(
+                PayResolver::<TestRuntime>::resolve_payment_by_conditions(pay_request),
+                Error::<TestRuntime>::LinearStreamNotExist
+            );
         })
     }
 
@@ -492,6 +2449,7 @@ pub mod test_pay_resolver {
                 H256,
                 MockCall,
                 Balance,
+                Signature,
             >;
             let mut result: (H256, Balance, BlockNumber);
             for i in 0..6 {
@@ -507,12 +2465,16 @@ pub mod test_pay_resolver {
                     transfer_func: transfer_func,
                     resolve_deadline: 99999,
                     resolve_timeout: 10,
+                    lottery: None,
+                    stream: None,
                 };
                 encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
                 pay_hash = hashing::blake2_256(&encoded_cond_pay).into();
                 pay_request = ResolvePaymentConditionsRequest {
                     cond_pay: cond_pay,
                     hash_preimages: vec![H256::from_low_u64_be(1)],
+                    oracle_outcomes: vec![],
+                    lottery_secret: None,
                 };
 
                 result =
@@ -536,6 +2498,8 @@ pub mod test_pay_resolver {
                 transfer_func: transfer_func,
                 resolve_deadline: 99999,
                 resolve_timeout: 10,
+                lottery: None,
+                stream: None,
             };
 
             // first resolving by vouched result
@@ -563,6 +2527,8 @@ pub mod test_pay_resolver {
             let pay_request = ResolvePaymentConditionsRequest {
                 cond_pay: cond_pay,
                 hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
             };
 
             let (pay_id_2, amount_2, resolve_deadline_2) =
@@ -573,6 +2539,70 @@ pub mod test_pay_resolver {
         })
     }
 
+    #[test]
+    fn test_pass_prune_pay_info_after_prune_delay() {
+        ExtBuilder::build().execute_with(|| {
+            System::set_block_number(1);
+            MockStorageDeposits::set(0, 10);
+            let alice = account_key("Alice");
+            let bob = account_key("Bob");
+            let transfer_func = get_transfer_func(alice, 10, 0);
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_condition(0)],
+                transfer_func: transfer_func,
+                resolve_deadline: 5,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+            let encoded_cond_pay = encode_conditional_pay(cond_pay.clone());
+            let pay_id = calculate_pay_id::<TestRuntime>(hashing::blake2_256(&encoded_cond_pay).into());
+            let pay_request = ResolvePaymentConditionsRequest {
+                cond_pay: cond_pay.clone(),
+                hash_preimages: vec![H256::from_low_u64_be(1)],
+                oracle_outcomes: vec![],
+                lottery_secret: None,
+            };
+            assert_ok!(CelerPayModule::resolve_payment_by_conditions(Origin::signed(alice), pay_request.clone()));
+            assert_eq!(Balances::reserved_balance(alice), 10);
+
+            // pay info can be pruned after the resolve deadline of the pay
+            System::set_block_number(5);
+            assert_noop!(
+                CelerPayModule::prune_pay_info(Origin::signed(bob), cond_pay.clone()),
+                DispatchError::Other("Pay resolve deadline not passed")
+            );
+
+            // and after PayInfoPruneDelay from its onchain resolve deadline
+            System::set_block_number(11);
+            assert_noop!(
+                CelerPayModule::prune_pay_info(Origin::signed(bob), cond_pay.clone()),
+                DispatchError::Other("Pay info prune delay not passed")
+            );
+
+            System::set_block_number(12);
+            assert_ok!(CelerPayModule::prune_pay_info(Origin::signed(bob), cond_pay.clone()));
+            assert_eq!(CelerPayModule::pay_info_deposits(pay_id), None);
+            assert_eq!(Balances::reserved_balance(alice), 0);
+            assert_eq!(Balances::free_balance(alice), 999);
+            assert_eq!(Balances::free_balance(bob), 1001);
+
+            // pay info is removed, and the pay can not be resolved again after its resolve deadline
+            assert_eq!(CelerPayModule::info_map(pay_id), None);
+            assert_noop!(
+                CelerPayModule::resolve_payment_by_conditions(Origin::signed(alice), pay_request),
+                DispatchError::Other("Passed pay resolve deadline in cond_pay msg")
+            );
+            assert_noop!(
+                CelerPayModule::prune_pay_info(Origin::signed(bob), cond_pay),
+                Error::<TestRuntime>::PayInfoNotExist
+            );
+        })
+    }
+
     fn test_resolve_payment_by_vouched_result(amount: u64) {
         let transfer_func = get_transfer_func(account_key("Alice"), 100, 3);
         let shared_pay = ConditionalPay {
@@ -583,6 +2613,8 @@ pub mod test_pay_resolver {
             transfer_func: transfer_func,
             resolve_deadline: 99999,
             resolve_timeout: 10,
+            lottery: None,
+            stream: None,
         };
 
         let encoded_cond_pay = encode_conditional_pay(shared_pay.clone());
@@ -606,6 +2638,59 @@ pub mod test_pay_resolver {
         assert_eq!(_resolve_deadline, System::block_number() + 10);
     }
 
+    #[test]
+    fn test_pass_encode_conditional_pay_without_new_fields_as_initial_release() {
+        ExtBuilder::build().execute_with(|| {
+            let cond_pay = ConditionalPay {
+                pay_timestamp: Timestamp::get(),
+                src: account_key("src"),
+                dest: account_key("dest"),
+                conditions: vec![get_condition(0)],
+                transfer_func: get_transfer_func(account_key("Alice"), 10, 0),
+                resolve_deadline: 99999,
+                resolve_timeout: 10,
+                lottery: None,
+                stream: None,
+            };
+
+            // layout of the initial release
+            let condition = cond_pay.conditions[0].clone();
+            let mut encoded = cond_pay.pay_timestamp.encode();
+            encoded.extend(cond_pay.src.encode());
+            encoded.extend(cond_pay.dest.encode());
+            encoded.extend(condition.condition_type.encode());
+            encoded.extend(condition.hash_lock.encode());
+            encoded.extend(condition.call_is_finalized.encode());
+            encoded.extend(condition.call_get_outcome.encode());
+            encoded.extend(condition.numeric_app_num.encode());
+            encoded.extend(condition.numeric_session_id.encode());
+            encoded.extend(condition.args_query_finalzation.encode());
+            encoded.extend(condition.args_query_outcome.encode());
+            encoded.extend(cond_pay.transfer_func.logic_type.encode());
+            encoded.extend(cond_pay.transfer_func.max_transfer.token.token_type.encode());
+            encoded.extend(cond_pay.transfer_func.max_transfer.receiver.account.encode());
+            encoded.extend(cond_pay.transfer_func.max_transfer.receiver.amt.encode());
+            encoded.extend(cond_pay.resolve_deadline.encode());
+            encoded.extend(cond_pay.resolve_timeout.encode());
+            assert_eq!(
+                crate::pay_resolver::encode_conditional_pay::<TestRuntime>(cond_pay.clone()),
+                encoded
+            );
+
+            // setting a new field appends all new fields of the condition
+            let mut extended_pay = cond_pay;
+            extended_pay.conditions[0].hash_type = Some(HashType::Blake2_256);
+            assert_eq!(
+                crate::pay_resolver::encode_conditional_pay::<TestRuntime>(extended_pay.clone()).len(),
+                encoded.len() + 14
+            );
+            assert_eq!(
+                crate::pay_resolver::encode_conditional_pay::<TestRuntime>(extended_pay.clone()),
+                encode_conditional_pay(extended_pay)
+            );
+        })
+    }
+
     pub fn encode_conditional_pay(
         r#cond_pay: ConditionalPay<Moment, BlockNumber, AccountId, H256, MockCall, Balance>,
     ) -> std::vec::Vec<u8> {
@@ -622,6 +2707,23 @@ pub mod test_pay_resolver {
             encoded.extend(condition.numeric_session_id.encode());
             encoded.extend(condition.args_query_finalzation.encode());
             encoded.extend(condition.args_query_outcome.encode());
+            // fields added after the initial release are encoded only if any of them is set
+            let mut extension = condition.hash_type.encode();
+            extension.extend(condition.boolean_app_num.encode());
+            extension.extend(condition.boolean_session_id.encode());
+            extension.extend(condition.deployed_contract_address.encode());
+            extension.extend(condition.virtual_contract_address.encode());
+            extension.extend(condition.gas_limit.encode());
+            extension.extend(condition.oracle.encode());
+            extension.extend(condition.question_hash.encode());
+            extension.extend(condition.block_number.encode());
+            extension.extend(condition.timestamp.encode());
+            extension.extend(condition.dependent_pay_id.encode());
+            extension.extend(condition.boolean_app_id.encode());
+            extension.extend(condition.numeric_app_id.encode());
+            if extension.iter().any(|byte| *byte != 0) {
+                encoded.extend(extension);
+            }
         });
         encoded.extend(pay.transfer_func.logic_type.encode());
         encoded.extend(pay.transfer_func.max_transfer.token.token_type.encode());
@@ -629,102 +2731,388 @@ pub mod test_pay_resolver {
         encoded.extend(pay.transfer_func.max_transfer.receiver.amt.encode());
         encoded.extend(pay.resolve_deadline.encode());
         encoded.extend(pay.resolve_timeout.encode());
+        if pay.transfer_func.boolean_circuit.is_some() || pay.lottery.is_some() || pay.stream.is_some() {
+            encoded.extend(pay.transfer_func.boolean_circuit.encode());
+            encoded.extend(pay.lottery.encode());
+            encoded.extend(pay.stream.encode());
+        }
         
         return encoded;
     }
 
-    pub fn get_condition(r#type: u8) -> Condition<H256, MockCall> {
+    pub fn get_hash_lock_condition(preimage: H256, hash_type: HashType) -> Condition<Moment, BlockNumber, AccountId, H256, MockCall> {
+        let hash_lock: H256 = match hash_type {
+            HashType::Blake2_256 => hashing::blake2_256(preimage.as_ref()).into(),
+            HashType::Sha256 => hashing::sha2_256(preimage.as_ref()).into(),
+            HashType::Keccak256 => hashing::keccak_256(preimage.as_ref()).into(),
+        };
+        return Condition {
+            condition_type: ConditionType::HashLock,
+            hash_lock: Some(hash_lock),
+            hash_type: Some(hash_type),
+            call_is_finalized: None,
+            call_get_outcome: None,
+            boolean_app_num: None,
+            boolean_session_id: None,
+            numeric_app_num: None,
+            numeric_session_id: None,
+            args_query_finalzation: None,
+            args_query_outcome: None,
+            deployed_contract_address: None,
+            virtual_contract_address: None,
+            gas_limit: None,
+            oracle: None,
+            question_hash: None,
+            block_number: None,
+            timestamp: None,
+            dependent_pay_id: None,
+            boolean_app_id: None,
+            numeric_app_id: None,
+        };
+    }
+
+    pub fn get_deployed_contract_condition(
+        address: AccountId,
+        gas_limit: Option<u64>,
+    ) -> Condition<Moment, BlockNumber, AccountId, H256, MockCall> {
+        return Condition {
+            condition_type: ConditionType::DeployedContract,
+            hash_lock: None,
+            hash_type: None,
+            call_is_finalized: None,
+            call_get_outcome: None,
+            boolean_app_num: None,
+            boolean_session_id: None,
+            numeric_app_num: None,
+            numeric_session_id: None,
+            args_query_finalzation: None,
+            args_query_outcome: None,
+            deployed_contract_address: Some(address),
+            virtual_contract_address: None,
+            gas_limit: gas_limit,
+            oracle: None,
+            question_hash: None,
+            block_number: None,
+            timestamp: None,
+            dependent_pay_id: None,
+            boolean_app_id: None,
+            numeric_app_id: None,
+        };
+    }
+
+    pub fn get_virtual_contract_condition(
+        virt_addr: H256,
+        gas_limit: Option<u64>,
+    ) -> Condition<Moment, BlockNumber, AccountId, H256, MockCall> {
+        return Condition {
+            condition_type: ConditionType::VirtualContract,
+            hash_lock: None,
+            hash_type: None,
+            call_is_finalized: None,
+            call_get_outcome: None,
+            boolean_app_num: None,
+            boolean_session_id: None,
+            numeric_app_num: None,
+            numeric_session_id: None,
+            args_query_finalzation: None,
+            args_query_outcome: None,
+            deployed_contract_address: None,
+            virtual_contract_address: Some(virt_addr),
+            gas_limit: gas_limit,
+            oracle: None,
+            question_hash: None,
+            block_number: None,
+            timestamp: None,
+            dependent_pay_id: None,
+            boolean_app_id: None,
+            numeric_app_id: None,
+        };
+    }
+
+    pub fn get_oracle_condition(
+        oracle: AccountId,
+        question_hash: H256,
+    ) -> Condition<Moment, BlockNumber, AccountId, H256, MockCall> {
+        return Condition {
+            condition_type: ConditionType::OracleSigned,
+            hash_lock: None,
+            hash_type: None,
+            call_is_finalized: None,
+            call_get_outcome: None,
+            boolean_app_num: None,
+            boolean_session_id: None,
+            numeric_app_num: None,
+            numeric_session_id: None,
+            args_query_finalzation: None,
+            args_query_outcome: None,
+            deployed_contract_address: None,
+            virtual_contract_address: None,
+            gas_limit: None,
+            oracle: Some(oracle),
+            question_hash: Some(question_hash),
+            block_number: None,
+            timestamp: None,
+            dependent_pay_id: None,
+            boolean_app_id: None,
+            numeric_app_id: None,
+        };
+    }
+
+    pub fn get_oracle_outcome(
+        oracle: &str,
+        question_hash: H256,
+        result: OracleResult<Balance>,
+    ) -> OracleOutcome<H256, Balance, Signature> {
+        let mut encoded = question_hash.encode();
+        encoded.extend(result.encode());
+        return OracleOutcome {
+            question_hash: question_hash,
+            block_number: None,
+            timestamp: None,
+            dependent_pay_id: None,
+            boolean_app_id: None,
+            numeric_app_id: None,
+            result: result,
+            sig: account_pair(oracle).sign(&encoded),
+        };
+    }
+
+    pub fn get_time_condition(
+        condition_type: ConditionType,
+        block_number: Option<BlockNumber>,
+        timestamp: Option<Moment>,
+    ) -> Condition<Moment, BlockNumber, AccountId, H256, MockCall> {
+        return Condition {
+            condition_type: condition_type,
+            hash_lock: None,
+            hash_type: None,
+            call_is_finalized: None,
+            call_get_outcome: None,
+            boolean_app_num: None,
+            boolean_session_id: None,
+            numeric_app_num: None,
+            numeric_session_id: None,
+            args_query_finalzation: None,
+            args_query_outcome: None,
+            deployed_contract_address: None,
+            virtual_contract_address: None,
+            gas_limit: None,
+            oracle: None,
+            question_hash: None,
+            block_number: block_number,
+            timestamp: timestamp,
+            dependent_pay_id: None,
+            boolean_app_id: None,
+            numeric_app_id: None,
+        };
+    }
+
+    pub fn get_pay_result_condition(
+        pay_id: H256,
+        min_amount: Option<Balance>,
+    ) -> Condition<Moment, BlockNumber, AccountId, H256, MockCall> {
+        return Condition {
+            condition_type: ConditionType::PayResult,
+            hash_lock: None,
+            hash_type: None,
+            call_is_finalized: None,
+            call_get_outcome: None,
+            boolean_app_num: None,
+            boolean_session_id: None,
+            numeric_app_num: None,
+            numeric_session_id: None,
+            args_query_finalzation: None,
+            args_query_outcome: min_amount.map(|amount| amount.encode()),
+            deployed_contract_address: None,
+            virtual_contract_address: None,
+            gas_limit: None,
+            oracle: None,
+            question_hash: None,
+            block_number: None,
+            timestamp: None,
+            dependent_pay_id: Some(pay_id),
+            boolean_app_id: None,
+            numeric_app_id: None,
+        };
+    }
+
+    pub fn get_condition(r#type: u8) -> Condition<Moment, BlockNumber, AccountId, H256, MockCall> {
         if r#type == 0 {
             let condition_hash_lock = Condition {
                 condition_type: ConditionType::HashLock,
-                hash_lock: Some(H256::from_low_u64_be(1)),
+                hash_lock: Some(hashing::blake2_256(H256::from_low_u64_be(1).as_ref()).into()),
+                hash_type: None,
                 call_is_finalized: None,
                 call_get_outcome: None,
+                boolean_app_num: None,
+                boolean_session_id: None,
                 numeric_app_num: None,
                 numeric_session_id: None,
                 args_query_finalzation: None,
                 args_query_outcome: None,
+                deployed_contract_address: None,
+                virtual_contract_address: None,
+                gas_limit: None,
+                oracle: None,
+                question_hash: None,
+                block_number: None,
+                timestamp: None,
+                dependent_pay_id: None,
+                boolean_app_id: None,
+                numeric_app_id: None,
             };
             return condition_hash_lock;
         } else if r#type == 1 {
-            // this call return Ok(())
-            let call_is_finalized_true = Box::new(MockCall::MockBooleanCondition(
-                MockBooleanCall::is_finalized(
-                    H256::from_low_u64_be(1),
-                    1
-                ))
-            );
-            // this call return Ok(())
-            let call_get_outcome_true = Box::new(MockCall::MockBooleanCondition(
-                MockBooleanCall::get_outcome(
-                    H256::from_low_u64_be(1),
-                    1
-                ))
-            );
-            let condition_deployed_true = Condition {
-                condition_type: ConditionType::BooleanRuntimeModule,
-                hash_lock: None,
-                call_is_finalized: Some(call_is_finalized_true),
-                call_get_outcome: Some(call_get_outcome_true),
-                numeric_app_num: None,
-                numeric_session_id: None,
-                args_query_finalzation: None,
-                args_query_outcome: None,
-            };
-            return condition_deployed_true;
+            // registered boolean app with true outcome
+            return get_boolean_app_condition(true);
         } else if r#type == 2 {
-            // this call return Ok(())
-            let call_is_finalized_true = Box::new(MockCall::MockBooleanCondition(
-                MockBooleanCall::is_finalized(
-                    H256::from_low_u64_be(1),
-                    1
-                ))
-            );
-            // this call returns DispatchError::Other("FalseOutcome")
-            let call_get_outcome_false = Box::new(MockCall::MockBooleanCondition(
-                MockBooleanCall::get_outcome(
-                    H256::from_low_u64_be(1),
-                    0
-                ))
-            );
-            let condition_deployed_false = Condition {
-                condition_type: ConditionType::BooleanRuntimeModule,
-                hash_lock: None,
-                call_is_finalized: Some(call_is_finalized_true),
-                call_get_outcome: Some(call_get_outcome_false),
-                numeric_app_num: None,
-                numeric_session_id: None,
-                args_query_finalzation: None,
-                args_query_outcome: None,
-            };
-            return condition_deployed_false;
+            // registered boolean app with false outcome
+            return get_boolean_app_condition(false);
         } else if r#type == 3 {
             let condition_deployed_numeric_10 = Condition {
                 condition_type: ConditionType::NumericRuntimeModule,
                 hash_lock: None,
+                hash_type: None,
                 call_is_finalized: None,
                 call_get_outcome: None,
+                boolean_app_num: None,
+                boolean_session_id: None,
                 numeric_app_num: Some(0),
                 numeric_session_id: Some(H256::from_low_u64_be(1)),
                 args_query_finalzation: Some(1.encode()),
                 args_query_outcome: Some(10.encode()),
+                deployed_contract_address: None,
+                virtual_contract_address: None,
+                gas_limit: None,
+                oracle: None,
+                question_hash: None,
+                block_number: None,
+                timestamp: None,
+                dependent_pay_id: None,
+                boolean_app_id: None,
+                numeric_app_id: None,
             };
             return condition_deployed_numeric_10;
         } else {
             let condition_deployed_numeric_25 = Condition {
                 condition_type: ConditionType::NumericRuntimeModule,
                 hash_lock: None,
+                hash_type: None,
                 call_is_finalized: None,
                 call_get_outcome: None,
+                boolean_app_num: None,
+                boolean_session_id: None,
                 numeric_app_num: Some(0),
                 numeric_session_id: Some(H256::from_low_u64_be(1)),
                 args_query_finalzation: Some(1.encode()),
                 args_query_outcome: Some(25.encode()),
+                deployed_contract_address: None,
+                virtual_contract_address: None,
+                gas_limit: None,
+                oracle: None,
+                question_hash: None,
+                block_number: None,
+                timestamp: None,
+                dependent_pay_id: None,
+                boolean_app_id: None,
+                numeric_app_id: None,
             };
             return condition_deployed_numeric_25;
         }
     }
 
+    pub fn get_registered_boolean_condition(
+        app_num: u32,
+        is_finalized: bool,
+        outcome: bool,
+    ) -> Condition<Moment, BlockNumber, AccountId, H256, MockCall> {
+        return Condition {
+            condition_type: ConditionType::BooleanRuntimeModule,
+            hash_lock: None,
+            hash_type: None,
+            call_is_finalized: None,
+            call_get_outcome: None,
+            boolean_app_num: Some(app_num),
+            boolean_session_id: Some(H256::from_low_u64_be(1)),
+            numeric_app_num: None,
+            numeric_session_id: None,
+            args_query_finalzation: Some((is_finalized as u8).encode()),
+            args_query_outcome: Some((outcome as u8).encode()),
+            deployed_contract_address: None,
+            virtual_contract_address: None,
+            gas_limit: None,
+            oracle: None,
+            question_hash: None,
+            block_number: None,
+            timestamp: None,
+            dependent_pay_id: None,
+            boolean_app_id: None,
+            numeric_app_id: None,
+        };
+    }
+
+    pub fn get_boolean_app_condition(
+        outcome: bool,
+    ) -> Condition<Moment, BlockNumber, AccountId, H256, MockCall> {
+        let mut condition = get_registered_boolean_condition(0, true, outcome);
+        condition.boolean_app_num = None;
+        condition.boolean_app_id = Some(*b"mockbool");
+        return condition;
+    }
+
+    pub fn get_legacy_call_condition(
+        number: u8,
+    ) -> Condition<Moment, BlockNumber, AccountId, H256, MockCall> {
+        let mut condition = get_boolean_app_condition(number == 1);
+        condition.boolean_app_id = None;
+        condition.boolean_session_id = None;
+        condition.args_query_finalzation = None;
+        condition.args_query_outcome = None;
+        condition.call_is_finalized = Some(Box::new(MockCall::MockBooleanCondition(
+            MockBooleanCall::is_finalized(H256::from_low_u64_be(1), number)
+        )));
+        condition.call_get_outcome = Some(Box::new(MockCall::MockBooleanCondition(
+            MockBooleanCall::get_outcome(H256::from_low_u64_be(1), number)
+        )));
+        return condition;
+    }
+
+    pub fn get_call_index(call: MockBooleanCall<TestRuntime>) -> [u8; 2] {
+        let encoded_call = MockCall::MockBooleanCondition(call).encode();
+        return [encoded_call[0], encoded_call[1]];
+    }
+
+    pub fn get_boolean_circuit_transfer_func(
+        r#account: AccountId,
+        r#amount: Balance,
+        gates: Vec<BooleanGate>,
+    ) -> TransferFunction<AccountId, Balance> {
+        let mut transfer_func = get_transfer_func(r#account, r#amount, 2);
+        transfer_func.boolean_circuit = Some(gates.encode());
+        return transfer_func;
+    }
+
+    pub fn get_lottery_transfer_func(
+        r#account: AccountId,
+        r#amount: Balance,
+    ) -> TransferFunction<AccountId, Balance> {
+        let mut transfer_func = get_transfer_func(r#account, r#amount, 0);
+        transfer_func.logic_type = TransferFunctionType::Lottery;
+        return transfer_func;
+    }
+
+    pub fn get_lottery_ticket(
+        secret: H256,
+        win_prob: Perbill,
+        draw_block: BlockNumber,
+    ) -> LotteryTicket<H256, BlockNumber> {
+        return LotteryTicket {
+            win_prob: win_prob,
+            secret_commitment: hashing::blake2_256(secret.as_ref()).into(),
+            draw_block: draw_block,
+        };
+    }
+
     pub fn get_transfer_func(
         r#account: AccountId,
         r#amount: Balance,
@@ -745,6 +3133,7 @@ pub mod test_pay_resolver {
             let transfer_func = TransferFunction {
                 logic_type: TransferFunctionType::BooleanAnd,
                 max_transfer: token_transfer,
+                boolean_circuit: None,
             };
             return transfer_func;
         } else if r#type == 1 {
@@ -762,6 +3151,7 @@ pub mod test_pay_resolver {
             let transfer_func = TransferFunction {
                 logic_type: TransferFunctionType::BooleanOr,
                 max_transfer: token_transfer,
+                boolean_circuit: None,
             };
             return transfer_func;
         } else if r#type == 2 {
@@ -779,6 +3169,7 @@ pub mod test_pay_resolver {
             let transfer_func = TransferFunction {
                 logic_type: TransferFunctionType::BooleanCircut,
                 max_transfer: token_transfer,
+                boolean_circuit: None,
             };
             return transfer_func;
         } else if r#type == 3 {
@@ -796,6 +3187,7 @@ pub mod test_pay_resolver {
             let transfer_func = TransferFunction {
                 logic_type: TransferFunctionType::NumericAdd,
                 max_transfer: token_transfer,
+                boolean_circuit: None,
             };
             return transfer_func;
         } else if r#type == 4 {
@@ -813,6 +3205,7 @@ pub mod test_pay_resolver {
             let transfer_func = TransferFunction {
                 logic_type: TransferFunctionType::NumericMax,
                 max_transfer: token_transfer,
+                boolean_circuit: None,
             };
             return transfer_func;
         } else {
@@ -830,6 +3223,7 @@ pub mod test_pay_resolver {
             let transfer_func = TransferFunction {
                 logic_type: TransferFunctionType::NumericMin,
                 max_transfer: token_transfer,
+                boolean_circuit: None,
             };
             return transfer_func;
         }
@@ -4,22 +4,389 @@ use pallet_timestamp;
 use frame_support::{
     Parameter,
     dispatch::{PostDispatchInfo, IsSubType},
-    traits::{Currency, IsType},
-    weights::GetDispatchInfo,
+    traits::{Filter, Get, IsType, ReservableCurrency},
+    weights::{GetDispatchInfo, Weight},
 };
-use sp_runtime::traits::{IdentifyAccount, Member, Verify, Dispatchable};
-use mock_numeric_condition;
-use super::Event;
+use sp_runtime::traits::{IdentifyAccount, Member, Verify, Dispatchable, Zero};
+use sp_runtime::{DispatchResult, DispatchError, Perbill, RuntimeDebug};
+use sp_std::vec::Vec;
+use super::{BalanceOf, Event};
 use crate::Call;
+use crate::pay_resolver::AssetId;
 
-pub trait Trait: system::Trait + pallet_timestamp::Trait + mock_numeric_condition::Trait {
-    type Currency: Currency<Self::AccountId>;
+pub trait Trait: system::Trait + pallet_timestamp::Trait {
+    type Currency: ReservableCurrency<Self::AccountId>;
+    /// Custody of native token in Pool and wallets, which is fixed at genesis or at the first deposit.
+    /// Changing it in a runtime upgrade has no effect, since funds held in one mode are not moved.
+    type NativeCustody: Get<CustodyMode>;
+    /// The storage deposit reserved from the opener of a channel for the channel and its wallet
+    type ChannelDeposit: Get<BalanceOf<Self>>;
+    /// The storage deposit reserved from the resolver creating a pay info
+    type PayInfoDeposit: Get<BalanceOf<Self>>;
+    /// The number of blocks after which a closed channel can be pruned, which should be longer than
+    /// open deadlines of channel initializers so that a pruned channel can not be opened again
+    type ChannelPruneDelay: Get<Self::BlockNumber>;
+    /// The number of blocks after the onchain resolve deadline from which a pay info can be pruned,
+    /// which should be longer than the time channels take to clear pays and confirm settle
+    type PayInfoPruneDelay: Get<Self::BlockNumber>;
+    /// The storage deposit reserved from the revealer of a preimage
+    type PreimageDeposit: Get<BalanceOf<Self>>;
+    /// The number of blocks after the reveal block from which a revealed preimage can be pruned,
+    /// which should be longer than resolve timeouts of pays locked by it
+    type PreimagePruneDelay: Get<Self::BlockNumber>;
+    /// The portion of the storage deposit of a pruned entry rewarded to the pruner
+    type PruneReward: Get<Perbill>;
+    /// The number of blocks after last pay resolve deadlines for peers to clear pays before confirm settle,
+    /// which can be overridden for a channel by its initializer
+    type ClearPaysSafeMargin: Get<Self::BlockNumber>;
+    /// The maximum weight of automatic finalizations of settles and withdraw intents in a block,
+    /// zero disables scheduling the automatic finalizations
+    type AutoFinalizeWeight: Get<Weight>;
+    /// The maximum number of channels queued to be finalized automatically at a block
+    type MaxScheduledFinalizations: Get<u32>;
+    /// The fungible assets module backing non-native token channels
+    type Assets: Fungibles<Self::AccountId, BalanceOf<Self>>;
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
     type Public: IdentifyAccount<AccountId = Self::AccountId>;
     type Signature: Verify<Signer = <Self as Trait>::Public> + Member + Decode + Encode;
+    /// The maximum number of peers in a channel
+    type MaxChannelPeers: Get<u32>;
+    /// The maximum number of gates in a boolean circuit transfer function
+    type MaxBooleanCircuitGates: Get<u32>;
+    /// The maximum number of allowances expiring at a block
+    type MaxExpiringAllowances: Get<u32>;
+    /// Boolean condition apps keyed by app id, which must be unique
+    type BooleanConditions: BooleanConditions<Self::Hash>;
+    /// Numeric condition apps keyed by app id, which must be unique
+    type NumericConditions: NumericConditions<Self::Hash, BalanceOf<Self>>;
+    /// The contracts module executing contract conditions
+    type ConditionContracts: ConditionContracts<Self::AccountId, Self::Hash>;
+    /// Filter of calls of boolean runtime modules which can be referenced by legacy conditions
+    type ConditionCallFilter: Filter<<Self as Trait>::Call>;
+    /// Calls of boolean runtime modules referenced by legacy conditions, each with the id of the boolean
+    /// app answering it. Only the pallet and call indices of the calls are used, which are recorded in
+    /// runtime upgrades so that calls encoded by former runtimes keep their apps.
+    type LegacyConditionCalls: Get<Vec<(<Self as Trait>::Call, AppId)>>;
     /// The overarching call type
     type Call: Parameter + Dispatchable<Origin=Self::Origin, PostInfo=PostDispatchInfo>
 		+ GetDispatchInfo + From<frame_system::Call<Self>> + IsSubType<Call<Self>>
 		+ IsType<<Self as frame_system::Trait>::Call>;
 }
 
+/// Where native token deposited to Pool and wallets is held.
+/// Fungible assets are always held by the Pool and CelerWallet module accounts.
+#[derive(Clone, Copy, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+pub enum CustodyMode {
+    /// Transferred to the Pool and CelerWallet module accounts
+    ModuleAccount,
+    /// Reserved on the account of each owner
+    Reserve,
+}
+
+impl Default for CustodyMode {
+    // Chains started before the custody mode was introduced held funds in module accounts
+    fn default() -> Self {
+        CustodyMode::ModuleAccount
+    }
+}
+
+/// Abstraction over an `Assets`-style module holding balances of many fungible assets.
+pub trait Fungibles<AccountId, Balance> {
+    /// Return false if no assets are available, so that asset tokens are rejected
+    fn is_enabled() -> bool;
+
+    /// Return the free balance of `who` in asset `asset_id`
+    fn balance(asset_id: AssetId, who: &AccountId) -> Balance;
+
+    /// Transfer `amount` of asset `asset_id` from `source` to `dest`
+    fn transfer(
+        asset_id: AssetId,
+        source: &AccountId,
+        dest: &AccountId,
+        amount: Balance,
+    ) -> DispatchResult;
+}
+
+/// No fungible assets are available; every asset operation fails.
+impl<AccountId, Balance: Zero> Fungibles<AccountId, Balance> for () {
+    fn is_enabled() -> bool {
+        false
+    }
+
+    fn balance(_asset_id: AssetId, _who: &AccountId) -> Balance {
+        Zero::zero()
+    }
+
+    fn transfer(
+        _asset_id: AssetId,
+        _source: &AccountId,
+        _dest: &AccountId,
+        _amount: Balance,
+    ) -> DispatchResult {
+        Err(DispatchError::Other("Assets are not supported"))
+    }
+}
+
+/// Abstraction over a contracts module executing `DeployedContract` and `VirtualContract` conditions.
+pub trait ConditionContracts<AccountId, Hash> {
+    /// Return the deployed address of a virtual contract
+    fn resolve(virt_addr: &Hash) -> Result<AccountId, DispatchError>;
+
+    /// Call contract `dest` as `origin` with at most `gas_limit` gas and return the output data.
+    /// A reverted call is an error.
+    fn bare_call(
+        origin: AccountId,
+        dest: AccountId,
+        gas_limit: u64,
+        input_data: Vec<u8>,
+    ) -> Result<Vec<u8>, DispatchError>;
+}
+
+/// No contracts are available; every contract condition fails.
+impl<AccountId, Hash> ConditionContracts<AccountId, Hash> for () {
+    fn resolve(_virt_addr: &Hash) -> Result<AccountId, DispatchError> {
+        Err(DispatchError::Other("Contracts are not supported"))
+    }
+
+    fn bare_call(
+        _origin: AccountId,
+        _dest: AccountId,
+        _gas_limit: u64,
+        _input_data: Vec<u8>,
+    ) -> Result<Vec<u8>, DispatchError> {
+        Err(DispatchError::Other("Contracts are not supported"))
+    }
+}
+
+/// Stable identifier of a condition app, independent of the runtime layout.
+pub type AppId = [u8; 8];
+
+/// A boolean condition app referenced by `ConditionType::BooleanRuntimeModule`.
+pub trait BooleanCondition<Hash> {
+    /// Identifier of the app, which must never change once pays reference it
+    const APP_ID: AppId;
+
+    /// Return whether the session of the app is finalized
+    fn is_finalized(session_id: &Hash, query: Option<Vec<u8>>) -> Result<bool, DispatchError>;
+
+    /// Return the boolean outcome of the session
+    fn get_outcome(session_id: &Hash, query: Option<Vec<u8>>) -> Result<bool, DispatchError>;
+}
+
+/// A numeric condition app referenced by `ConditionType::NumericRuntimeModule`.
+pub trait NumericCondition<Hash, Balance> {
+    /// Identifier of the app, which must never change once pays reference it
+    const APP_ID: AppId;
+
+    /// Return whether the session of the app is finalized
+    fn is_finalized(session_id: &Hash, query: Option<Vec<u8>>) -> Result<bool, DispatchError>;
+
+    /// Return the numeric outcome of the session
+    fn get_outcome(session_id: &Hash, query: Option<Vec<u8>>) -> Result<Balance, DispatchError>;
+}
+
+/// Registry of boolean condition apps keyed by app id.
+///
+/// Implemented for tuples of `BooleanCondition`s. The legacy app number of an app is its position in the tuple.
+/// `None` is returned if no app is registered with the app id.
+pub trait BooleanConditions<Hash> {
+    /// Return the id of the app at position `app_num`
+    fn app_id(app_num: u32) -> Option<AppId>;
+
+    /// Return whether the session of app `app_id` is finalized
+    fn is_finalized(
+        app_id: &AppId,
+        session_id: &Hash,
+        query: Option<Vec<u8>>,
+    ) -> Option<Result<bool, DispatchError>>;
+
+    /// Return the boolean outcome of the session of app `app_id`
+    fn get_outcome(
+        app_id: &AppId,
+        session_id: &Hash,
+        query: Option<Vec<u8>>,
+    ) -> Option<Result<bool, DispatchError>>;
+}
+
+/// Registry of numeric condition apps keyed by app id.
+///
+/// Implemented for tuples of `NumericCondition`s. The legacy app number of an app is its position in the tuple.
+/// `None` is returned if no app is registered with the app id.
+pub trait NumericConditions<Hash, Balance> {
+    /// Return the id of the app at position `app_num`
+    fn app_id(app_num: u32) -> Option<AppId>;
+
+    /// Return whether the session of app `app_id` is finalized
+    fn is_finalized(
+        app_id: &AppId,
+        session_id: &Hash,
+        query: Option<Vec<u8>>,
+    ) -> Option<Result<bool, DispatchError>>;
+
+    /// Return the numeric outcome of the session of app `app_id`
+    fn get_outcome(
+        app_id: &AppId,
+        session_id: &Hash,
+        query: Option<Vec<u8>>,
+    ) -> Option<Result<Balance, DispatchError>>;
+}
+
+/// No condition app is registered.
+impl<Hash> BooleanConditions<Hash> for () {
+    fn app_id(_: u32) -> Option<AppId> {
+        None
+    }
+
+    fn is_finalized(_: &AppId, _: &Hash, _: Option<Vec<u8>>) -> Option<Result<bool, DispatchError>> {
+        None
+    }
+
+    fn get_outcome(_: &AppId, _: &Hash, _: Option<Vec<u8>>) -> Option<Result<bool, DispatchError>> {
+        None
+    }
+}
+
+/// No condition app is registered.
+impl<Hash, Balance> NumericConditions<Hash, Balance> for () {
+    fn app_id(_: u32) -> Option<AppId> {
+        None
+    }
+
+    fn is_finalized(_: &AppId, _: &Hash, _: Option<Vec<u8>>) -> Option<Result<bool, DispatchError>> {
+        None
+    }
+
+    fn get_outcome(_: &AppId, _: &Hash, _: Option<Vec<u8>>) -> Option<Result<Balance, DispatchError>> {
+        None
+    }
+}
+
+macro_rules! impl_conditions_for_tuples {
+    ($($app:ident),+) => {
+        impl<Hash, $($app: BooleanCondition<Hash>),+> BooleanConditions<Hash> for ($($app,)+) {
+            #[allow(unused_assignments)]
+            fn app_id(app_num: u32) -> Option<AppId> {
+                let mut index: u32 = 0;
+                $(
+                    if app_num == index {
+                        return Some($app::APP_ID);
+                    }
+                    index += 1;
+                )+
+                None
+            }
+
+            fn is_finalized(
+                app_id: &AppId,
+                session_id: &Hash,
+                query: Option<Vec<u8>>,
+            ) -> Option<Result<bool, DispatchError>> {
+                $(
+                    if *app_id == $app::APP_ID {
+                        return Some($app::is_finalized(session_id, query));
+                    }
+                )+
+                None
+            }
+
+            fn get_outcome(
+                app_id: &AppId,
+                session_id: &Hash,
+                query: Option<Vec<u8>>,
+            ) -> Option<Result<bool, DispatchError>> {
+                $(
+                    if *app_id == $app::APP_ID {
+                        return Some($app::get_outcome(session_id, query));
+                    }
+                )+
+                None
+            }
+        }
+
+        impl<Hash, Balance, $($app: NumericCondition<Hash, Balance>),+> NumericConditions<Hash, Balance> for ($($app,)+) {
+            #[allow(unused_assignments)]
+            fn app_id(app_num: u32) -> Option<AppId> {
+                let mut index: u32 = 0;
+                $(
+                    if app_num == index {
+                        return Some($app::APP_ID);
+                    }
+                    index += 1;
+                )+
+                None
+            }
+
+            fn is_finalized(
+                app_id: &AppId,
+                session_id: &Hash,
+                query: Option<Vec<u8>>,
+            ) -> Option<Result<bool, DispatchError>> {
+                $(
+                    if *app_id == $app::APP_ID {
+                        return Some($app::is_finalized(session_id, query));
+                    }
+                )+
+                None
+            }
+
+            fn get_outcome(
+                app_id: &AppId,
+                session_id: &Hash,
+                query: Option<Vec<u8>>,
+            ) -> Option<Result<Balance, DispatchError>> {
+                $(
+                    if *app_id == $app::APP_ID {
+                        return Some($app::get_outcome(session_id, query));
+                    }
+                )+
+                None
+            }
+        }
+    };
+}
+
+impl_conditions_for_tuples!(A);
+impl_conditions_for_tuples!(A, B);
+impl_conditions_for_tuples!(A, B, C);
+impl_conditions_for_tuples!(A, B, C, D);
+impl_conditions_for_tuples!(A, B, C, D, E);
+impl_conditions_for_tuples!(A, B, C, D, E, F);
+impl_conditions_for_tuples!(A, B, C, D, E, F, G);
+impl_conditions_for_tuples!(A, B, C, D, E, F, G, H);
+
+/// Return ids of the boolean apps in the order of their positions
+pub fn boolean_app_ids<Hash, C: BooleanConditions<Hash>>() -> Vec<AppId> {
+    let mut app_ids: Vec<AppId> = Vec::new();
+    while let Some(app_id) = C::app_id(app_ids.len() as u32) {
+        app_ids.push(app_id);
+    }
+    app_ids
+}
+
+/// Return ids of the numeric apps in the order of their positions
+pub fn numeric_app_ids<Hash, Balance, C: NumericConditions<Hash, Balance>>() -> Vec<AppId> {
+    let mut app_ids: Vec<AppId> = Vec::new();
+    while let Some(app_id) = C::app_id(app_ids.len() as u32) {
+        app_ids.push(app_id);
+    }
+    app_ids
+}
+
+/// Return pallet and call indices of legacy condition calls with the ids of the boolean apps answering them
+pub fn legacy_call_app_ids<T: Trait>() -> Vec<([u8; 2], AppId)> {
+    T::LegacyConditionCalls::get()
+        .into_iter()
+        .filter_map(|(call, app_id)| {
+            let encoded = call.encode();
+            if encoded.len() < 2 {
+                return None;
+            }
+            Some(([encoded[0], encoded[1]], app_id))
+        })
+        .collect()
+}
+
+/// Return true if an app id is registered more than once
+pub fn has_duplicate_app_ids(app_ids: &[AppId]) -> bool {
+    app_ids.iter().enumerate().any(|(i, app_id)| app_ids[..i].contains(app_id))
+}
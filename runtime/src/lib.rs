@@ -304,11 +304,157 @@ impl celer_contracts::Trait for Runtime {
 	type WeightPrice = pallet_transaction_payment::Module<Self>;
 }
 
+impl pallet_assets::Trait for Runtime {
+	type Event = Event;
+	type Balance = Balance;
+	type AssetId = u32;
+}
+
+/// Fungible assets of asset channels and pools are held by the assets module
+pub struct CelerPayAssets;
+impl celer_pay_module::Fungibles<AccountId, Balance> for CelerPayAssets {
+	fn is_enabled() -> bool {
+		true
+	}
+
+	fn balance(asset_id: u32, who: &AccountId) -> Balance {
+		Assets::balance(asset_id, who.clone())
+	}
+
+	fn transfer(
+		asset_id: u32,
+		source: &AccountId,
+		dest: &AccountId,
+		amount: Balance,
+	) -> sp_runtime::DispatchResult {
+		use frame_support::traits::UnfilteredDispatchable;
+		// the assets module exposes transfers only as a call, dispatched on behalf of `source`
+		pallet_assets::Call::<Runtime>::transfer(asset_id, dest.clone(), amount)
+			.dispatch_bypass_filter(frame_system::RawOrigin::Signed(source.clone()).into())
+			.map(|_| ())
+			.map_err(|e| e.error)
+	}
+}
+
+/// Boolean condition app whose session is finalized and true iff the query is 1,
+/// as the mock boolean condition module answers its calls
+pub struct MockBooleanConditionApp;
+impl celer_pay_module::BooleanCondition<Hash> for MockBooleanConditionApp {
+	const APP_ID: celer_pay_module::AppId = *b"mockbool";
+
+	fn is_finalized(_session_id: &Hash, query: Option<Vec<u8>>) -> Result<bool, sp_runtime::DispatchError> {
+		let number: u8 = codec::Decode::decode(&mut &query.unwrap_or_default()[..])
+			.map_err(|_| sp_runtime::DispatchError::Other("MustBeDecodable"))?;
+		Ok(number == 1)
+	}
+
+	fn get_outcome(_session_id: &Hash, query: Option<Vec<u8>>) -> Result<bool, sp_runtime::DispatchError> {
+		let number: u8 = codec::Decode::decode(&mut &query.unwrap_or_default()[..])
+			.map_err(|_| sp_runtime::DispatchError::Other("MustBeDecodable"))?;
+		Ok(number == 1)
+	}
+}
+
+/// Numeric condition app 0, backed by the mock numeric condition module
+pub struct MockNumericConditionApp;
+impl celer_pay_module::NumericCondition<Hash, Balance> for MockNumericConditionApp {
+	const APP_ID: celer_pay_module::AppId = *b"mock_num";
+
+	fn is_finalized(session_id: &Hash, query: Option<Vec<u8>>) -> Result<bool, sp_runtime::DispatchError> {
+		mock_numeric_condition::Module::<Runtime>::is_finalized(session_id, query)
+	}
+
+	fn get_outcome(session_id: &Hash, query: Option<Vec<u8>>) -> Result<Balance, sp_runtime::DispatchError> {
+		let outcome = mock_numeric_condition::Module::<Runtime>::get_outcome(session_id, query)?;
+		Ok(outcome.into())
+	}
+}
+
+/// Contract conditions are executed by the celer contracts module
+pub struct CelerContractsConditions;
+impl celer_pay_module::ConditionContracts<AccountId, Hash> for CelerContractsConditions {
+	fn resolve(virt_addr: &Hash) -> Result<AccountId, sp_runtime::DispatchError> {
+		CelerContracts::resolve(*virt_addr)
+			.map_err(|_| sp_runtime::DispatchError::Other("Virtual contract is not deployed"))
+	}
+
+	fn bare_call(
+		origin: AccountId,
+		dest: AccountId,
+		gas_limit: u64,
+		input_data: Vec<u8>,
+	) -> Result<Vec<u8>, sp_runtime::DispatchError> {
+		let (exec_result, _gas_consumed) =
+			CelerContracts::bare_call(origin, dest, 0, gas_limit, input_data);
+		match exec_result {
+			Ok(v) if v.is_success() => Ok(v.data),
+			Ok(_) => Err(sp_runtime::DispatchError::Other("Condition contract reverted")),
+			Err(e) => Err(e.error),
+		}
+	}
+}
+
+/// Only calls of the mock boolean condition module can be referenced by legacy conditions
+pub struct ConditionCallFilter;
+impl frame_support::traits::Filter<Call> for ConditionCallFilter {
+	fn filter(call: &Call) -> bool {
+		match call {
+			Call::MockBooleanCondition(_) => true,
+			_ => false,
+		}
+	}
+}
+
+parameter_types! {
+	/// Calls of the mock boolean condition module are answered by MockBooleanConditionApp
+	pub LegacyConditionCalls: Vec<(Call, celer_pay_module::AppId)> = vec![
+		(Call::MockBooleanCondition(mock_boolean_condition::Call::is_finalized(Default::default(), 0)), *b"mockbool"),
+		(Call::MockBooleanCondition(mock_boolean_condition::Call::get_outcome(Default::default(), 0)), *b"mockbool"),
+	];
+}
+
+parameter_types! {
+	pub const MaxChannelPeers: u32 = 8;
+	pub const MaxBooleanCircuitGates: u32 = 64;
+	pub const MaxExpiringAllowances: u32 = 128;
+	pub const NativeCustody: celer_pay_module::CustodyMode = celer_pay_module::CustodyMode::ModuleAccount;
+	pub const ChannelDeposit: Balance = 1 * CENTS;
+	pub const PayInfoDeposit: Balance = 10 * MILLICENTS;
+	pub const ChannelPruneDelay: BlockNumber = 7 * DAYS;
+	pub const PayInfoPruneDelay: BlockNumber = 7 * DAYS;
+	pub const PreimageDeposit: Balance = 10 * MILLICENTS;
+	pub const PreimagePruneDelay: BlockNumber = 7 * DAYS;
+	pub const PruneReward: Perbill = Perbill::from_percent(10);
+	pub const ClearPaysSafeMargin: BlockNumber = 10 * MINUTES;
+	pub AutoFinalizeWeight: Weight = Perbill::from_percent(10) * MaximumBlockWeight::get();
+	pub const MaxScheduledFinalizations: u32 = 256;
+}
+
 impl celer_pay_module::Trait for Runtime {
 	type Currency = pallet_balances::Module<Runtime>;
+	type NativeCustody = NativeCustody;
+	type ChannelDeposit = ChannelDeposit;
+	type PayInfoDeposit = PayInfoDeposit;
+	type ChannelPruneDelay = ChannelPruneDelay;
+	type PayInfoPruneDelay = PayInfoPruneDelay;
+	type PreimageDeposit = PreimageDeposit;
+	type PreimagePruneDelay = PreimagePruneDelay;
+	type PruneReward = PruneReward;
+	type ClearPaysSafeMargin = ClearPaysSafeMargin;
+	type AutoFinalizeWeight = AutoFinalizeWeight;
+	type MaxScheduledFinalizations = MaxScheduledFinalizations;
+	type Assets = CelerPayAssets;
 	type Event = Event;
 	type Public = MultiSigner;
 	type Signature = Signature;
+	type MaxChannelPeers = MaxChannelPeers;
+	type MaxBooleanCircuitGates = MaxBooleanCircuitGates;
+	type MaxExpiringAllowances = MaxExpiringAllowances;
+	type BooleanConditions = (MockBooleanConditionApp,);
+	type NumericConditions = (MockNumericConditionApp,);
+	type ConditionContracts = CelerContractsConditions;
+	type ConditionCallFilter = ConditionCallFilter;
+	type LegacyConditionCalls = LegacyConditionCalls;
 	type Call = Call;
 }
 
@@ -335,6 +481,8 @@ construct_runtime!(
 		CelerPayModule: celer_pay_module::{Module, Call, Storage, Event<T>},
 		MockBooleanCondition: mock_boolean_condition::{Module, Call},
 		MockNumericCondtion: mock_numeric_condition::{Module, Call},
+		// appended, so that the call indices of the modules above are kept
+		Assets: pallet_assets::{Module, Call, Storage, Event<T>},
 	}
 );
 
@@ -573,6 +721,10 @@ impl_runtime_apis! {
 			CelerPayModule::get_dispute_time_out(channel_id)
 		}
 
+		fn get_clear_safe_margin(channel_id: Hash) -> BlockNumber {
+			CelerPayModule::get_clear_safe_margin(channel_id)
+		}
+
 		fn get_state_seq_num_map(channel_id: Hash) -> (Vec<AccountId>, Vec<celer_pay_module_rpc_runtime_api::SeqNumInfo>) {
 			CelerPayModule::get_state_seq_num_map(channel_id)
 		}
@@ -632,6 +784,10 @@ impl_runtime_apis! {
 			CelerPayModule::get_wallet_balance(wallet_id)
 		}
 
+		fn get_wallet_account(wallet_id: Hash) -> AccountId {
+			CelerPayModule::get_wallet_account(&wallet_id)
+		}
+
 		fn get_pool_id() -> AccountId {
 			CelerPayModule::get_pool_id()
 		}
@@ -640,10 +796,24 @@ impl_runtime_apis! {
 			CelerPayModule::get_pool_balance(owner)
 		}
 
-		fn get_allowance(owner: AccountId, spender: AccountId) -> celer_pay_module_rpc_runtime_api::BalanceInfo<Balance> {
+		fn get_allowance(owner: AccountId, spender: AccountId) -> (
+			celer_pay_module_rpc_runtime_api::BalanceInfo<Balance>,
+			BlockNumber
+		) {
 			CelerPayModule::get_allowance(owner, spender)
 		}
 
+		fn get_asset_pool_balance(owner: AccountId, asset_id: u32) -> celer_pay_module_rpc_runtime_api::BalanceInfo<Balance> {
+			CelerPayModule::get_asset_pool_balance(owner, asset_id)
+		}
+
+		fn get_asset_allowance(owner: AccountId, spender: AccountId, asset_id: u32) -> (
+			celer_pay_module_rpc_runtime_api::BalanceInfo<Balance>,
+			BlockNumber
+		) {
+			CelerPayModule::get_asset_allowance(owner, spender, asset_id)
+		}
+
 		fn get_pay_resolver_id() -> AccountId {
 			CelerPayModule::get_pay_resolver_id()
 		}
@@ -654,6 +824,10 @@ impl_runtime_apis! {
 		) {
 			CelerPayModule::get_pay_info(pay_id)
 		}
+
+		fn get_preimage_reveal_info(hash_lock: Hash, hash_type: celer_pay_module_rpc_runtime_api::HashType) -> (bool, Hash, BlockNumber) {
+			CelerPayModule::get_preimage_reveal_info(hash_lock, hash_type)
+		}
 	}
 
 	#[cfg(feature = "runtime-benchmarks")]